@@ -0,0 +1,90 @@
+//! Call auctions, where orders accumulate without matching and are then uncrossed together at the
+//! single price which executes the most volume
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::order_result::OrderResult;
+
+/// The auction commands an `OrderBook` accepts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuctionAction {
+    /// Stop continuous trading and begin collecting orders for an auction
+    Start,
+    /// Execute the auction at its clearing price and resume continuous trading
+    Uncross
+}
+
+impl FromStr for AuctionAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(Self::Start),
+            "uncross" => Ok(Self::Uncross),
+            _ => Err(format!("Unknown auction action: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for AuctionAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Start => write!(f, "start"),
+            Self::Uncross => write!(f, "uncross")
+        }
+    }
+}
+
+/// The price an auction would uncross at if it ended now, with the volume it would execute and the
+/// quantity left unmatched at that price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IndicativeUncross {
+    pub price: Option<u64>,
+    pub matched_qty: u64,
+    pub imbalance_qty: u64,
+    /// The side with more quantity than can be matched at `price`
+    pub imbalance_side: Option<char>
+}
+
+impl IndicativeUncross {
+    /// Find the clearing price for buy and sell price levels, each given as a price and the total
+    /// quantity at that price. The clearing price executes the most volume; ties go to the price
+    /// leaving the smallest imbalance, and then to the highest price when buyers are left over or the
+    /// lowest price otherwise. There is no price when the book does not cross.
+    pub fn new(buy_levels: &[(u64, u64)], sell_levels: &[(u64, u64)]) -> IndicativeUncross {
+        let mut prices: Vec<u64> = buy_levels.iter().chain(sell_levels).map(|(price, _)| *price).collect();
+        prices.sort_unstable();
+        prices.dedup();
+
+        let mut best = IndicativeUncross { price: None, matched_qty: 0, imbalance_qty: 0, imbalance_side: None };
+        for price in prices {
+            let demand = buy_levels.iter().filter(|(buy_price, _)| *buy_price >= price).map(|(_, qty)| qty).sum::<u64>();
+            let supply = sell_levels.iter().filter(|(sell_price, _)| *sell_price <= price).map(|(_, qty)| qty).sum::<u64>();
+            let matched_qty = demand.min(supply);
+            let imbalance_qty = demand.abs_diff(supply);
+            let is_better = matched_qty > best.matched_qty ||
+                (matched_qty == best.matched_qty && imbalance_qty < best.imbalance_qty) ||
+                (matched_qty == best.matched_qty && imbalance_qty == best.imbalance_qty && demand > supply);
+            if matched_qty > 0 && is_better {
+                let imbalance_side = match demand.cmp(&supply) {
+                    std::cmp::Ordering::Greater => Some('B'),
+                    std::cmp::Ordering::Less => Some('S'),
+                    std::cmp::Ordering::Equal => None
+                };
+                best = IndicativeUncross { price: Some(price), matched_qty, imbalance_qty, imbalance_side };
+            }
+        }
+        best
+    }
+
+    /// Change `IndicativeUncross` into `OrderResult`
+    pub fn to_order_result(&self) -> OrderResult {
+        OrderResult::AuctionIndicative {
+            price: self.price,
+            matched_qty: self.matched_qty,
+            imbalance_qty: self.imbalance_qty,
+            imbalance_side: self.imbalance_side
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Unit tests for call auctions in `OrderBook` and `OrderBooks`
+
+#[cfg(test)]
+mod auction_tests {
+    use crate::auction::*;
+    use crate::order_book::OrderBook;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+    use crate::order_result::*;
+    use crate::trading_status::TradingStatus;
+    use chrono::Utc;
+
+    fn indicative(price: Option<u64>, matched_qty: u64, imbalance_qty: u64, imbalance_side: Option<char>) -> OrderResult {
+        OrderResult::AuctionIndicative { price, matched_qty, imbalance_qty, imbalance_side }
+    }
+
+    #[test]
+    fn parse_action() {
+        assert_eq!("start".parse::<AuctionAction>(), Ok(AuctionAction::Start));
+        assert_eq!("uncross".parse::<AuctionAction>(), Ok(AuctionAction::Uncross));
+        assert!("open".parse::<AuctionAction>().is_err());
+    }
+
+    #[test]
+    fn clearing_price_maximises_volume() {
+        let uncross = IndicativeUncross::new(&[(10, 200), (11, 100)], &[(9, 150), (10, 100), (12, 50)]);
+
+        assert_eq!(uncross, IndicativeUncross { price: Some(10), matched_qty: 250, imbalance_qty: 50, imbalance_side: Some('B') });
+    }
+
+    #[test]
+    fn clearing_price_ties() {
+        let balanced = IndicativeUncross::new(&[(11, 100)], &[(10, 100)]);
+        let buyers_left = IndicativeUncross::new(&[(11, 150)], &[(10, 100)]);
+        let sellers_left = IndicativeUncross::new(&[(11, 100)], &[(10, 150)]);
+
+        assert_eq!(balanced.price, Some(10));
+        assert_eq!(buyers_left.price, Some(11));
+        assert_eq!(sellers_left.price, Some(10));
+    }
+
+    #[test]
+    fn no_clearing_price_without_cross() {
+        let uncross = IndicativeUncross::new(&[(9, 100)], &[(10, 100)]);
+
+        assert_eq!(uncross, IndicativeUncross { price: None, matched_qty: 0, imbalance_qty: 0, imbalance_side: None });
+    }
+
+    #[test]
+    fn auction_collects_crossing_orders() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        assert_eq!(order_book.start_auction(Utc::now()), vec![
+            OrderResult::TradingStatusChange { status: TradingStatus::Auction },
+            indicative(None, 0, 0, None)
+        ]);
+        assert!(order_book.is_in_auction());
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 11, 100, 'B', 1));
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 1, user_order_id: 1 },
+            OrderResult::TopOfBookChange { side: 'B', price: "11".to_string(), total_quantity: "100".to_string() }
+        ]);
+
+        let order_results = order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 150, 'S', 2));
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 2, user_order_id: 2 },
+            OrderResult::TopOfBookChange { side: 'S', price: "10".to_string(), total_quantity: "150".to_string() },
+            indicative(Some(10), 100, 50, Some('S'))
+        ]);
+
+        let order_results = order_book.add_order(NewOrder::new_market(4, "AAPL".to_string(), 100, 'B', 4));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 4, user_order_id: 4 }]);
+
+        let order_results = order_book.cancel_order(&CancelOrder::new(1, 1));
+        assert_eq!(order_results[2], indicative(None, 0, 0, None));
+    }
+
+    #[test]
+    fn uncross_at_clearing_price() {
+        let mut order_book = OrderBook::new("AAPL", false);
+        order_book.start_auction(Utc::now());
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 11, 100, 'B', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 150, 'S', 2));
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 3));
+        assert_eq!(order_results.last(), Some(&indicative(Some(10), 150, 50, Some('B'))));
+
+        let time = Utc::now();
+        let order_results = order_book.uncross(time);
+
+        assert_eq!(order_results, vec![
+            OrderResult::TradingStatusChange { status: TradingStatus::Continuous },
+            OrderResult::Trade { trade_id: 0, symbol: "AAPL".to_string(), user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 100, aggressor_side: None, time },
+            OrderResult::Trade { trade_id: 0, symbol: "AAPL".to_string(), user_buy: 3, user_order_id_buy: 3, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 50, aggressor_side: None, time },
+            OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "50".to_string() },
+            OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() }
+        ]);
+        assert!(!order_book.is_in_auction());
+
+        let order_results = order_book.add_order(NewOrder::new(4, "AAPL".to_string(), 10, 50, 'S', 4));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 4, user_order_id: 4 }]);
+    }
+
+    #[test]
+    fn uncross_fills_iceberg_reserve() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.start_auction(Utc::now());
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 300, 'S', 1).with_display_qty(100));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 150, 'B', 2));
+
+        let order_results = order_book.uncross(Utc::now());
+
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 2, user_order_id_buy: 2, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 150, .. }));
+        let resting = &order_book.resting_orders('S')[&10][0];
+        assert_eq!((resting.qty, resting.reserve_qty), (100, 50));
+    }
+
+    #[test]
+    fn auction_through_order_books() {
+        let mut order_books = OrderBooks::new(true);
+
+        order_books.process(InputMessage::Auction(AuctionOrder::start("IBM")));
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 2)));
+        let results = order_books.process(InputMessage::Auction(AuctionOrder::uncross("IBM")));
+
+        assert_eq!(results[1].symbol, Some("IBM".to_string()));
+        assert!(matches!(&results[1].order_result, OrderResult::Trade { user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 100, .. }));
+    }
+
+    #[test]
+    fn empty_auction_book_survives_flush() {
+        let mut order_books = OrderBooks::new(true);
+
+        order_books.auction(AuctionOrder::start("IBM"));
+        order_books.flush(FlushOrder::all());
+
+        assert!(order_books.get_order_book("IBM").unwrap().is_in_auction());
+    }
+}
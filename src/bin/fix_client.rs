@@ -0,0 +1,57 @@
+//! A scripted FIX client for trying out the gateway. Each line of the script is a message written
+//! with '|' in place of SOH, such as `35=D|11=1|55=IBM|54=1|38=100|40=2|44=10`, which is sent with
+//! its header filled in; `wait <milliseconds>` waits that long for replies. Every message sent and
+//! received is printed, prefixed with '>' and '<'.
+
+use std::env;
+use std::fs;
+use std::time::Duration;
+use orderbook::fix::parse_readable;
+use orderbook::fix_gateway::FixClient;
+
+/// Holds options passed as command line arguments
+struct ClientConfig {
+    address: String,
+    comp_id: String,
+    target_comp_id: String,
+    script_file: String
+}
+
+/// Handles command line arguments
+fn parse_args(args: Vec<String>) -> ClientConfig {
+    let mut client_config = ClientConfig { address: "127.0.0.1:9878".to_string(), comp_id: "CLIENT1".to_string(), target_comp_id: "ORDERBOOK".to_string(), script_file: String::new() };
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--address" {
+            client_config.address = args.next().expect("No address supplied after --address");
+        } else if arg == "--comp-id" {
+            client_config.comp_id = args.next().expect("No CompID supplied after --comp-id");
+        } else if arg == "--target-comp-id" {
+            client_config.target_comp_id = args.next().expect("No CompID supplied after --target-comp-id");
+        } else {
+            client_config.script_file = arg;
+        }
+    }
+    client_config
+}
+
+fn main() {
+    let client_config = parse_args(env::args().collect());
+    let script = fs::read_to_string(&client_config.script_file).unwrap_or_else(|e| panic!("Failed to read {}: {}", client_config.script_file, e));
+    let mut client = FixClient::connect(&client_config.address, &client_config.comp_id, &client_config.target_comp_id)
+        .unwrap_or_else(|e| panic!("Failed to connect to {}: {}", client_config.address, e));
+
+    for line in script.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let wait = match line.strip_prefix("wait") {
+            Some(milliseconds) => Duration::from_millis(milliseconds.trim().parse().unwrap_or_else(|_| panic!("Invalid wait: \"{}\"", line))),
+            None => {
+                let message = parse_readable(line).unwrap_or_else(|e| panic!("{}", e));
+                println!("> {}", client.send(&message).expect("Failed to send"));
+                Duration::from_millis(100)
+            }
+        };
+        for message in client.receive_all(wait).unwrap_or_else(|e| panic!("{}", e)) {
+            println!("< {}", message);
+        }
+    }
+}
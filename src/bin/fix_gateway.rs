@@ -0,0 +1,60 @@
+//! Runs the FIX 4.4 order entry gateway on a local TCP port
+
+use std::env;
+use std::net::TcpListener;
+use orderbook::fix_gateway::{FixGateway, serve};
+use orderbook::order_books::OrderBooks;
+use orderbook::risk::load_risk_limits;
+use orderbook::self_trade_prevention::SelfTradePrevention;
+
+/// Holds options passed as command line arguments
+struct GatewayConfig {
+    port: u16,
+    /// The `CompID` clients send their messages to
+    comp_id: String,
+    trading_enabled: bool,
+    self_trade_prevention: SelfTradePrevention,
+    /// When set, orders are checked against the risk limits in this file
+    risk_limits_file: Option<String>,
+    /// Each client `CompID` allowed to log on, with the user it enters orders for
+    sessions: Vec<(String, u64)>
+}
+
+/// Handles command line arguments
+fn parse_args(args: Vec<String>) -> GatewayConfig {
+    let mut gateway_config = GatewayConfig { port: 9878, comp_id: "ORDERBOOK".to_string(), trading_enabled: false, self_trade_prevention: SelfTradePrevention::Allow, risk_limits_file: None, sessions: vec![] };
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-t" || arg == "--trading-enabled" {
+            gateway_config.trading_enabled = true;
+        } else if arg == "--port" {
+            gateway_config.port = args.next().expect("No port supplied after --port").parse().unwrap();
+        } else if arg == "--comp-id" {
+            gateway_config.comp_id = args.next().expect("No CompID supplied after --comp-id");
+        } else if arg == "--session" {
+            let session = args.next().expect("No session supplied after --session");
+            let (comp_id, user) = session.split_once(':').unwrap_or_else(|| panic!("Session must be COMPID:user, not \"{}\"", session));
+            gateway_config.sessions.push((comp_id.to_string(), user.parse().unwrap()));
+        } else if arg == "--stp" {
+            gateway_config.self_trade_prevention = args.next().expect("No mode supplied after --stp").parse().unwrap();
+        } else if arg == "--risk-limits" {
+            gateway_config.risk_limits_file = Some(args.next().expect("No file supplied after --risk-limits"));
+        }
+    }
+    gateway_config
+}
+
+fn main() {
+    let gateway_config = parse_args(env::args().collect());
+    let mut order_books = OrderBooks::new(gateway_config.trading_enabled);
+    order_books.set_self_trade_prevention(gateway_config.self_trade_prevention);
+    if let Some(risk_limits_file) = &gateway_config.risk_limits_file {
+        order_books.set_risk_manager(load_risk_limits(risk_limits_file).unwrap_or_else(|e| panic!("Failed to load {}: {}", risk_limits_file, e)));
+    }
+    let gateway = gateway_config.sessions.iter()
+        .fold(FixGateway::new(&gateway_config.comp_id, order_books), |gateway, (comp_id, user)| gateway.with_session(comp_id, *user));
+
+    let listener = TcpListener::bind(("127.0.0.1", gateway_config.port)).unwrap_or_else(|e| panic!("Failed to listen on port {}: {}", gateway_config.port, e));
+    println!("FIX gateway {} listening on {}", gateway_config.comp_id, listener.local_addr().unwrap());
+    serve(listener, gateway).expect("FIX gateway failed");
+}
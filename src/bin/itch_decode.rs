@@ -0,0 +1,55 @@
+//! Prints a binary market data feed as text, one message per line, reading it from a file or from
+//! the first connection to a local TCP port
+
+use std::env;
+use std::io::Read;
+use std::net::TcpListener;
+use orderbook::itch::FeedReader;
+
+/// Holds options passed as command line arguments
+struct DecoderConfig {
+    /// The feed file to read, when not listening
+    feed_file: Option<String>,
+    /// When set, the feed is read from the first connection to this port
+    port: Option<u16>
+}
+
+/// Handles command line arguments
+fn parse_args(args: Vec<String>) -> DecoderConfig {
+    let mut decoder_config = DecoderConfig { feed_file: None, port: None };
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--listen" {
+            decoder_config.port = Some(args.next().expect("No port supplied after --listen").parse().unwrap());
+        } else {
+            decoder_config.feed_file = Some(arg);
+        }
+    }
+    decoder_config
+}
+
+/// Print every message of the feed, stopping at the first that cannot be read
+fn print_feed<R: Read>(reader: FeedReader<R>) {
+    for feed_message in reader {
+        match feed_message {
+            Ok(feed_message) => println!("{}", feed_message),
+            Err(e) => {
+                eprintln!("Failed to read feed: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn main() {
+    let decoder_config = parse_args(env::args().collect());
+    if let Some(port) = decoder_config.port {
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| panic!("Failed to listen on port {}: {}", port, e));
+        eprintln!("Waiting for a feed on {}", listener.local_addr().unwrap());
+        let (stream, _) = listener.accept().expect("Failed to accept a connection");
+        print_feed(FeedReader::new(stream));
+    } else {
+        let feed_file = decoder_config.feed_file.expect("No feed file supplied");
+        print_feed(FeedReader::open(&feed_file).unwrap_or_else(|e| panic!("Failed to open {}: {}", feed_file, e)));
+    }
+}
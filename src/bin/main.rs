@@ -5,107 +5,598 @@ use std::thread;
 use std::sync::{mpsc, mpsc::Sender};
 use orderbook::order_books::OrderBooks;
 use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::TcpStream;
 use csv::StringRecord;
-use orderbook::order::{CancelOrder, NewOrder};
+use chrono::Duration;
+use orderbook::order::{AuctionOrder, CancelOrder, FlushOrder, InputMessage, MassCancelOrder, NewOrder, OrderStatusQuery, PostOnly, PriceBandOrder, RiskLimitOrder, TradingStatusOrder};
+use orderbook::auction::AuctionAction;
+use orderbook::order_result::{OrderResult, SequencedOrderResult};
+use orderbook::journal::{Journal, replay_records};
+use orderbook::fees::{FeeLedger, load_fee_schedule};
+use orderbook::itch::FeedWriter;
+use orderbook::market_data::MarketData;
+use orderbook::peg::{Peg, PegType};
+use orderbook::positions::{MarkPrice, PositionTracker};
+use orderbook::price_band::{CircuitBreaker, PriceBand};
+use orderbook::risk::{RiskLimit, load_risk_limits};
+use orderbook::self_trade_prevention::SelfTradePrevention;
+use orderbook::trading_status::{HaltPolicy, TradingStatus};
+use orderbook::snapshot::{load_snapshot, save_snapshot};
+use orderbook::scenario::{ScenarioSummary, parse_scenario_name, is_selected_scenario};
+
+/// A line of output sent from the reader thread to the writer thread
+enum Output {
+    /// Text copied from the input, such as a scenario name
+    Text(String),
+    Result(SequencedOrderResult)
+}
 
 /// Holds options passed as command line arguments
 struct RuntimeConfig {
     input_file: String,
-    trading_enabled: bool
+    trading_enabled: bool,
+    /// When set, only the scenario with this name (Ex. "7" or "scenario 7") is run
+    scenario: Option<String>,
+    /// When false, a flush only reports changes to the top of book rather than each removed order
+    report_flush_cancellations: bool,
+    self_trade_prevention: SelfTradePrevention,
+    /// Whether halted symbols reject or queue new orders
+    halt_policy: HaltPolicy,
+    /// When set, limit prices outside this band are rejected for every symbol
+    price_band: Option<PriceBand>,
+    /// When set, sudden price moves interrupt trading for every symbol
+    circuit_breaker: Option<CircuitBreaker>,
+    /// When set, each scenario starts with the risk limits in this file
+    risk_limits_file: Option<String>,
+    /// When set, each scenario starts from the `OrderBooks` in this snapshot file
+    restore_file: Option<String>,
+    /// When set, the final state of the `OrderBooks` is saved to this snapshot file
+    snapshot_file: Option<String>,
+    /// When set, every message is appended to this journal before it is processed
+    journal_file: Option<String>,
+    /// When set, each result is output with its global, per-symbol and input sequence numbers
+    sequenced: bool,
+    /// When set, every user's position and profit and loss, marked to this price, is reported once
+    /// the input is exhausted
+    positions: Option<MarkPrice>,
+    /// When set, fees are charged on every trade according to the schedule in this file
+    fee_schedule_file: Option<String>,
+    /// When set along with a fee schedule, the fees charged to each user are saved to this file
+    fee_ledger_file: Option<String>,
+    /// When set, OHLCV bars of every symbol are saved to this file
+    bars_file: Option<String>,
+    /// The length of each bar
+    bar_interval: Duration,
+    /// When set, every trade is saved to this file
+    tape_file: Option<String>,
+    /// When set, orders are forgotten this long after they are filled, cancelled, rejected or
+    /// expired
+    order_retention: Option<Duration>,
+    /// When set, every change to an order is followed by an execution report to its owner
+    execution_reports: bool,
+    /// When set, the public results are written to this file as a binary market data feed
+    itch_feed_file: Option<String>,
+    /// When set, the binary market data feed is sent to this TCP address (Ex. "127.0.0.1:9880")
+    itch_feed_address: Option<String>
 }
 
 impl RuntimeConfig {
     fn new(input_file: String, trading_enabled: bool) -> RuntimeConfig {
-        RuntimeConfig { input_file, trading_enabled }
+        RuntimeConfig {
+            input_file,
+            trading_enabled,
+            scenario: None,
+            report_flush_cancellations: true,
+            self_trade_prevention: SelfTradePrevention::Allow,
+            halt_policy: HaltPolicy::Reject,
+            price_band: None,
+            circuit_breaker: None,
+            risk_limits_file: None,
+            restore_file: None,
+            snapshot_file: None,
+            journal_file: None,
+            sequenced: false,
+            positions: None,
+            fee_schedule_file: None,
+            fee_ledger_file: None,
+            bars_file: None,
+            bar_interval: Duration::minutes(1),
+            tape_file: None,
+            order_retention: None,
+            execution_reports: false,
+            itch_feed_file: None,
+            itch_feed_address: None
+        }
     }
 }
 
 /// Handles command line arguments
 fn parse_args(args: Vec<String>) -> RuntimeConfig {
-    let mut trading_enabled = false;
-    let mut input_file = String::new();
-    for arg in args {        
+    let mut runtime_config = RuntimeConfig::new(String::new(), false);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
         if arg == "-t" || arg == "--trading-enabled" {
-            trading_enabled = true;
+            runtime_config.trading_enabled = true;
+        } else if arg == "-s" || arg == "--scenario" {
+            runtime_config.scenario = Some(args.next().expect("No scenario supplied after --scenario"));
+        } else if arg == "--no-flush-cancels" {
+            runtime_config.report_flush_cancellations = false;
+        } else if arg == "--stp" {
+            runtime_config.self_trade_prevention = args.next().expect("No mode supplied after --stp").parse().unwrap();
+        } else if arg == "--halt-policy" {
+            runtime_config.halt_policy = args.next().expect("No policy supplied after --halt-policy").parse().unwrap();
+        } else if arg == "--price-band" {
+            runtime_config.price_band = Some(args.next().expect("No band supplied after --price-band").parse().unwrap());
+        } else if arg == "--circuit-breaker" {
+            runtime_config.circuit_breaker = Some(args.next().expect("No breaker supplied after --circuit-breaker").parse().unwrap());
+        } else if arg == "--risk-limits" {
+            runtime_config.risk_limits_file = Some(args.next().expect("No file supplied after --risk-limits"));
+        } else if arg == "--restore" {
+            runtime_config.restore_file = Some(args.next().expect("No file supplied after --restore"));
+        } else if arg == "--snapshot" {
+            runtime_config.snapshot_file = Some(args.next().expect("No file supplied after --snapshot"));
+        } else if arg == "--sequenced" {
+            runtime_config.sequenced = true;
+        } else if arg == "--positions" {
+            runtime_config.positions = Some(args.next().expect("No mark price supplied after --positions").parse().unwrap());
+        } else if arg == "--fees" {
+            runtime_config.fee_schedule_file = Some(args.next().expect("No file supplied after --fees"));
+        } else if arg == "--fee-ledger" {
+            runtime_config.fee_ledger_file = Some(args.next().expect("No file supplied after --fee-ledger"));
+        } else if arg == "--bars" {
+            runtime_config.bars_file = Some(args.next().expect("No file supplied after --bars"));
+        } else if arg == "--bar-interval" {
+            let seconds = args.next().expect("No seconds supplied after --bar-interval").parse::<i64>().unwrap();
+            assert!(seconds > 0, "Bar interval must be at least one second");
+            runtime_config.bar_interval = Duration::seconds(seconds);
+        } else if arg == "--tape" {
+            runtime_config.tape_file = Some(args.next().expect("No file supplied after --tape"));
+        } else if arg == "--exec-reports" {
+            runtime_config.execution_reports = true;
+        } else if arg == "--order-retention" {
+            let seconds = args.next().expect("No seconds supplied after --order-retention").parse::<i64>().unwrap();
+            assert!(seconds >= 0, "Order retention must not be negative");
+            runtime_config.order_retention = Some(Duration::seconds(seconds));
+        } else if arg == "--journal" {
+            runtime_config.journal_file = Some(args.next().expect("No file supplied after --journal"));
+        } else if arg == "--itch-feed" {
+            runtime_config.itch_feed_file = Some(args.next().expect("No file supplied after --itch-feed"));
+        } else if arg == "--itch-address" {
+            runtime_config.itch_feed_address = Some(args.next().expect("No address supplied after --itch-address"));
         } else if arg.ends_with(".csv") {
-            input_file = arg;
+            runtime_config.input_file = arg;
         }
     }
-    if input_file.is_empty() {
+    if runtime_config.input_file.is_empty() {
         panic!("No input file supplied")
     }
-    RuntimeConfig::new(input_file, trading_enabled)
+    runtime_config
+}
+
+/// Holds the state of the reader thread as it works through the input CSV one scenario at a time
+struct ScenarioRunner {
+    order_books: OrderBooks,
+    runtime_config: RuntimeConfig,
+    journal: Option<Journal<File>>,
+    /// Set while the `OrderBooks` recovered from `--journal` have yet to process a message
+    recovered: bool,
+    /// Positions across every scenario run, kept when `--positions` was given
+    positions: Option<PositionTracker>,
+    /// Fees across every scenario run, charged when `--fees` was given
+    fees: Option<FeeLedger>,
+    /// Trades across every scenario run, kept when `--bars` or `--tape` was given
+    market_data: Option<MarketData>,
+    /// The binary market data feed, written when `--itch-feed` or `--itch-address` was given
+    feed: Option<FeedWriter<Box<dyn Write>>>,
+    current: Option<ScenarioSummary>,
+    summaries: Vec<ScenarioSummary>
+}
+
+impl ScenarioRunner {
+    /// When `--journal` names an existing journal, the `OrderBooks` are first recovered by replaying
+    /// it, and new messages are appended after it.
+    fn new(runtime_config: RuntimeConfig) -> ScenarioRunner {
+        let mut order_books = ScenarioRunner::fresh_order_books(&runtime_config);
+        let mut journal = None;
+        let mut recovered = false;
+        if let Some(journal_file) = &runtime_config.journal_file {
+            let (opened_journal, records) = Journal::open(journal_file).unwrap_or_else(|e| panic!("Failed to open {}: {}", journal_file, e));
+            if !records.is_empty() {
+                order_books = replay_records(&records, || ScenarioRunner::fresh_order_books(&runtime_config));
+                recovered = true;
+            }
+            journal = Some(opened_journal);
+        }
+        let positions = runtime_config.positions.map(PositionTracker::new);
+        let fees = runtime_config.fee_schedule_file.as_ref().map(|fee_schedule_file| {
+            FeeLedger::new(load_fee_schedule(fee_schedule_file).unwrap_or_else(|e| panic!("Failed to load {}: {}", fee_schedule_file, e)))
+        });
+        let market_data = (runtime_config.bars_file.is_some() || runtime_config.tape_file.is_some()).then(|| MarketData::new(runtime_config.bar_interval));
+        let feed = ScenarioRunner::open_feed(&runtime_config);
+        ScenarioRunner {
+            order_books,
+            runtime_config,
+            journal,
+            recovered,
+            positions,
+            fees,
+            market_data,
+            feed,
+            current: None,
+            summaries: vec![]
+        }
+    }
+
+    /// Process a message, journaling it first if `--journal` was given, and send its results to the
+    /// writer thread
+    fn process(&mut self, message: InputMessage, tx: &Sender<Output>) {
+        let order_results = match self.journal.as_mut() {
+            Some(journal) => journal.process(&mut self.order_books, message.clone()).unwrap_or_else(|e| panic!("Failed to journal message: {}", e)),
+            None => self.order_books.process(message.clone())
+        };
+        self.recovered = false;
+        if let Some(summary) = self.current.as_mut() {
+            let unsequenced: Vec<OrderResult> = order_results.iter().map(|sequenced_order_result| sequenced_order_result.order_result.clone()).collect();
+            summary.record(&message, &unsequenced);
+        }
+        if let Some(positions) = self.positions.as_mut() {
+            positions.record_all(&order_results);
+        }
+        if let Some(fees) = self.fees.as_mut() {
+            fees.record(&order_results);
+        }
+        if let Some(market_data) = self.market_data.as_mut() {
+            market_data.record(&order_results);
+        }
+        if let (Some(feed), Some(time)) = (self.feed.as_mut(), self.order_books.current_time()) {
+            feed.record(&message, &order_results, time).unwrap_or_else(|e| panic!("Failed to write market data feed: {}", e));
+        }
+        for order_result in order_results {
+            tx.send(Output::Result(order_result)).unwrap();
+        }
+    }
+
+    /// Open the binary market data feed to `--itch-address` if given, or else to `--itch-feed`
+    fn open_feed(runtime_config: &RuntimeConfig) -> Option<FeedWriter<Box<dyn Write>>> {
+        if let Some(address) = &runtime_config.itch_feed_address {
+            let stream = TcpStream::connect(address).unwrap_or_else(|e| panic!("Failed to connect to {}: {}", address, e));
+            Some(FeedWriter::new(Box::new(BufWriter::new(stream))))
+        } else {
+            runtime_config.itch_feed_file.as_ref().map(|itch_feed_file| {
+                FeedWriter::new(Box::new(BufWriter::new(File::create(itch_feed_file).unwrap_or_else(|e| panic!("Failed to create {}: {}", itch_feed_file, e)))) as Box<dyn Write>)
+            })
+        }
+    }
+
+    /// Make the `OrderBooks` a scenario starts with, either empty or restored from `--restore`.
+    /// A restored snapshot brings its own trading mode, self-trade prevention, halt policy, price
+    /// bands, circuit breaker, risk limits, order statuses and sequence numbers, though limits from
+    /// `--risk-limits` replace its risk limits. Terminal orders are kept for `--order-retention`.
+    fn fresh_order_books(runtime_config: &RuntimeConfig) -> OrderBooks {
+        let mut order_books = if let Some(restore_file) = &runtime_config.restore_file {
+            load_snapshot(restore_file).unwrap_or_else(|e| panic!("Failed to restore {}: {}", restore_file, e))
+        } else {
+            let mut order_books = OrderBooks::new(runtime_config.trading_enabled);
+            order_books.set_self_trade_prevention(runtime_config.self_trade_prevention);
+            order_books.set_halt_policy(runtime_config.halt_policy);
+            order_books.set_default_price_band(runtime_config.price_band);
+            order_books.set_circuit_breaker(runtime_config.circuit_breaker);
+            order_books
+        };
+        if let Some(risk_limits_file) = &runtime_config.risk_limits_file {
+            order_books.set_risk_manager(load_risk_limits(risk_limits_file).unwrap_or_else(|e| panic!("Failed to load {}: {}", risk_limits_file, e)));
+        }
+        order_books.set_order_retention(runtime_config.order_retention);
+        order_books.set_execution_reports(runtime_config.execution_reports);
+        order_books
+    }
+
+    /// Close out the current scenario and start the next one with fresh `OrderBooks`. Input resumed
+    /// from `--journal` must not start a scenario before its first message, as that would discard
+    /// the recovered `OrderBooks`.
+    fn start_scenario(&mut self, name: &str) {
+        assert!(!self.recovered, "Scenario \"{}\" would discard the order books recovered from the journal", name);
+        self.finish_scenario();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.reset(&mut self.order_books).unwrap_or_else(|e| panic!("Failed to journal reset: {}", e));
+        }
+        let mut order_books = ScenarioRunner::fresh_order_books(&self.runtime_config);
+        order_books.continue_sequences(&self.order_books);
+        self.order_books = order_books;
+        self.current = Some(ScenarioSummary::new(name));
+    }
+
+    /// Keep the summary of the current scenario if it was run
+    fn finish_scenario(&mut self) {
+        if let Some(summary) = self.current.take() {
+            if self.is_active(&summary) {
+                self.summaries.push(summary);
+            }
+        }
+    }
+
+    /// Whether rows in the current scenario should be run, based on `--scenario`
+    fn is_active(&self, summary: &ScenarioSummary) -> bool {
+        match &self.runtime_config.scenario {
+            Some(selected) => is_selected_scenario(&summary.name, selected),
+            None => true
+        }
+    }
+
+    /// Whether rows at the current position in the input should be run. Rows before the first
+    /// `#name:` are only run when no scenario was selected.
+    fn is_running(&self) -> bool {
+        match &self.current {
+            Some(summary) => self.is_active(summary),
+            None => self.runtime_config.scenario.is_none()
+        }
+    }
+}
+
+/// Get a trimmed field from `row`, treating a missing or blank field as `None`
+fn optional_field(row: &StringRecord, index: usize) -> Option<&str> {
+    row.get(index).map(|field| field.trim()).filter(|field| !field.is_empty())
+}
+
+/// Get the limit price at `index` in `row`, or `None` for the `M` which marks a market order
+fn limit_price(row: &StringRecord, index: usize) -> Option<u64> {
+    match row.get(index).unwrap().trim() {
+        "M" => None,
+        price => Some(price.parse::<u64>().unwrap())
+    }
+}
+
+/// Apply the space-separated order flags from a new or stop order row: `post-only`,
+/// `post-only-reprice` and `hidden`
+fn with_order_flags(new_order: NewOrder, flags: &str) -> NewOrder {
+    flags.split_whitespace().fold(new_order, |new_order, flag| match flag {
+        "post-only" => new_order.with_post_only(PostOnly::Reject),
+        "post-only-reprice" => new_order.with_post_only(PostOnly::Reprice),
+        "hidden" => new_order.with_hidden(),
+        _ => panic!("Invalid order flag: \"{}\"", flag)
+    })
 }
 
 /// Takes each row from the input CSV, outputs name or descr directly, and otherwise
 /// parses transaction input messages.
-fn handle_row(row: StringRecord, tx: &Sender<String>, order_books: &mut OrderBooks) {
-    if let Some(value) = row.get(0) {                          
+fn handle_row(row: StringRecord, tx: &Sender<Output>, runner: &mut ScenarioRunner) {
+    if let Some(value) = row.get(0) {
+        if let Some(name) = parse_scenario_name(value) {
+            runner.start_scenario(&name);
+        }
+        if !runner.is_running() {
+            return;
+        }
         if value.starts_with("#name: ") {
-            tx.send(row.as_slice().to_string()).unwrap();
+            tx.send(Output::Text(row.as_slice().to_string())).unwrap();
         } else if value.starts_with("#descr:") {
             let mut s = row.get(0).unwrap().to_string();
             if let Some(row1) = row.get(1) {
-                s.push_str(",");
+                s.push(',');
                 s.push_str(row1);
             }
-            tx.send(s).unwrap();
+            tx.send(Output::Text(s)).unwrap();
         } else {
             match value {
                 "N" => {
-                    assert_eq!(row.len(), 7, "Invalid New Order: \"{}\"", row.as_slice().to_string());
-                    let new_order = NewOrder::new(
+                    assert!(row.len() >= 7 && row.len() <= 10, "Invalid New Order: \"{}\"", row.as_slice());
+                    let user = row.get(1).unwrap().trim().parse::<u64>().unwrap();
+                    let symbol = row.get(2).unwrap().trim().to_string();
+                    let price = limit_price(&row, 3);
+                    let qty = row.get(4).unwrap().trim().parse::<u64>().unwrap();
+                    let side = row.get(5).unwrap().trim().chars().next().unwrap();
+                    let user_order_id = row.get(6).unwrap().trim().parse::<u64>().unwrap();
+                    let mut new_order = match price {
+                        Some(price) => NewOrder::new(user, symbol, price, qty, side, user_order_id),
+                        None => NewOrder::new_market(user, symbol, qty, side, user_order_id)
+                    };
+                    if let Some(self_trade_group) = optional_field(&row, 7) {
+                        new_order = new_order.with_self_trade_group(self_trade_group.parse::<u64>().unwrap());
+                    }
+                    if let Some(display_qty) = optional_field(&row, 8) {
+                        new_order = new_order.with_display_qty(display_qty.parse::<u64>().unwrap());
+                    }
+                    if let Some(flags) = optional_field(&row, 9) {
+                        new_order = with_order_flags(new_order, flags);
+                    }
+                    runner.process(InputMessage::New(new_order), tx);
+                },
+                "S" => {
+                    assert!(row.len() >= 8 && row.len() <= 11, "Invalid Stop Order: \"{}\"", row.as_slice());
+                    let user = row.get(1).unwrap().trim().parse::<u64>().unwrap();
+                    let symbol = row.get(2).unwrap().trim().to_string();
+                    let stop_price = row.get(3).unwrap().trim().parse::<u64>().unwrap();
+                    let price = limit_price(&row, 4);
+                    let qty = row.get(5).unwrap().trim().parse::<u64>().unwrap();
+                    let side = row.get(6).unwrap().trim().chars().next().unwrap();
+                    let user_order_id = row.get(7).unwrap().trim().parse::<u64>().unwrap();
+                    let mut stop_order = match price {
+                        Some(price) => NewOrder::new_stop_limit(user, symbol, stop_price, price, qty, side, user_order_id),
+                        None => NewOrder::new_stop(user, symbol, stop_price, qty, side, user_order_id)
+                    };
+                    if let Some(self_trade_group) = optional_field(&row, 8) {
+                        stop_order = stop_order.with_self_trade_group(self_trade_group.parse::<u64>().unwrap());
+                    }
+                    if let Some(display_qty) = optional_field(&row, 9) {
+                        stop_order = stop_order.with_display_qty(display_qty.parse::<u64>().unwrap());
+                    }
+                    if let Some(flags) = optional_field(&row, 10) {
+                        stop_order = with_order_flags(stop_order, flags);
+                    }
+                    runner.process(InputMessage::New(stop_order), tx);
+                },
+                "P" => {
+                    assert!(row.len() >= 9 && row.len() <= 12, "Invalid Pegged Order: \"{}\"", row.as_slice());
+                    let peg = Peg::new(
+                        row.get(3).unwrap().trim().parse::<PegType>().unwrap(),
+                        row.get(4).unwrap().trim().parse::<i64>().unwrap(),
+                        optional_field(&row, 5).map(|limit| limit.parse::<u64>().unwrap())
+                    );
+                    let mut pegged_order = NewOrder::new_pegged(
                         row.get(1).unwrap().trim().parse::<u64>().unwrap(),
                         row.get(2).unwrap().trim().to_string(),
-                        row.get(3).unwrap().trim().parse::<u64>().unwrap(),
-                        row.get(4).unwrap().trim().parse::<u64>().unwrap(),
-                        row.get(5).unwrap().trim().chars().nth(0).unwrap(),
-                        row.get(6).unwrap().trim().parse::<u64>().unwrap()
+                        peg,
+                        row.get(6).unwrap().trim().parse::<u64>().unwrap(),
+                        row.get(7).unwrap().trim().chars().next().unwrap(),
+                        row.get(8).unwrap().trim().parse::<u64>().unwrap()
                     );
-                    let order_results = order_books.add_order(new_order);
-                    for order_result in order_results {
-                        tx.send(order_result.to_string()).unwrap();
+                    if let Some(self_trade_group) = optional_field(&row, 9) {
+                        pegged_order = pegged_order.with_self_trade_group(self_trade_group.parse::<u64>().unwrap());
                     }
+                    if let Some(display_qty) = optional_field(&row, 10) {
+                        pegged_order = pegged_order.with_display_qty(display_qty.parse::<u64>().unwrap());
+                    }
+                    if let Some(flags) = optional_field(&row, 11) {
+                        pegged_order = with_order_flags(pegged_order, flags);
+                    }
+                    runner.process(InputMessage::New(pegged_order), tx);
                 },
                 "C" => {
-                    assert_eq!(row.len(), 3, "Invalid Cancel Order: \"{}\"", row.as_slice().to_string());
+                    assert_eq!(row.len(), 3, "Invalid Cancel Order: \"{}\"", row.as_slice());
                     let cancel_order = CancelOrder::new(
                         row.get(1).unwrap().trim().parse::<u64>().unwrap(),
                         row.get(2).unwrap().trim().parse::<u64>().unwrap()
                     );
-                    let order_results = order_books.cancel_order(cancel_order);
-                    for order_result in order_results {
-                        tx.send(order_result.to_string()).unwrap();
-                    }
+                    runner.process(InputMessage::Cancel(cancel_order), tx);
+                },
+                "M" => {
+                    assert!(row.len() >= 2 && row.len() <= 4, "Invalid Mass Cancel Order: \"{}\"", row.as_slice());
+                    let mass_cancel_order = MassCancelOrder::new(
+                        optional_field(&row, 1).map(|user| user.parse::<u64>().unwrap()),
+                        optional_field(&row, 2).map(|symbol| symbol.to_string()),
+                        optional_field(&row, 3).and_then(|side| side.chars().next())
+                    );
+                    runner.process(InputMessage::MassCancel(mass_cancel_order), tx);
+                },
+                "F" => {
+                    assert!(row.len() <= 3, "Invalid Flush: \"{}\"", row.as_slice());
+                    let flush_order = FlushOrder::new(
+                        optional_field(&row, 1).map(|symbol| symbol.to_string()),
+                        optional_field(&row, 2).map(|user| user.parse::<u64>().unwrap()),
+                        runner.runtime_config.report_flush_cancellations
+                    );
+                    runner.process(InputMessage::Flush(flush_order), tx);
+                },
+                "A" => {
+                    assert_eq!(row.len(), 3, "Invalid Auction: \"{}\"", row.as_slice());
+                    let auction_order = AuctionOrder::new(
+                        row.get(1).unwrap().trim(),
+                        row.get(2).unwrap().trim().parse::<AuctionAction>().unwrap()
+                    );
+                    runner.process(InputMessage::Auction(auction_order), tx);
+                },
+                "T" => {
+                    assert_eq!(row.len(), 3, "Invalid Trading Status: \"{}\"", row.as_slice());
+                    let trading_status_order = TradingStatusOrder::new(
+                        optional_field(&row, 1),
+                        row.get(2).unwrap().trim().parse::<TradingStatus>().unwrap()
+                    );
+                    runner.process(InputMessage::TradingStatus(trading_status_order), tx);
+                },
+                "L" => {
+                    assert!(row.len() >= 2 && row.len() <= 4, "Invalid Price Band: \"{}\"", row.as_slice());
+                    let price_band = optional_field(&row, 2).map(|percent| {
+                        let price_band = PriceBand::new(percent.parse::<u64>().unwrap());
+                        match optional_field(&row, 3) {
+                            Some(reference_price) => price_band.with_reference_price(reference_price.parse::<u64>().unwrap()),
+                            None => price_band
+                        }
+                    });
+                    runner.process(InputMessage::PriceBand(PriceBandOrder::new(optional_field(&row, 1), price_band)), tx);
+                },
+                "K" => {
+                    assert!(row.len() >= 3 && row.len() <= 4, "Invalid Risk Limit: \"{}\"", row.as_slice());
+                    let risk_limit_order = RiskLimitOrder::new(
+                        optional_field(&row, 1).map(|user| user.parse::<u64>().unwrap()),
+                        row.get(2).unwrap().trim().parse::<RiskLimit>().unwrap(),
+                        optional_field(&row, 3).map(|value| value.parse::<u64>().unwrap())
+                    );
+                    runner.process(InputMessage::RiskLimit(risk_limit_order), tx);
+                },
+                "Q" => {
+                    assert_eq!(row.len(), 3, "Invalid Order Status Query: \"{}\"", row.as_slice());
+                    let order_status_query = OrderStatusQuery::new(
+                        row.get(1).unwrap().trim().parse::<u64>().unwrap(),
+                        row.get(2).unwrap().trim().parse::<u64>().unwrap()
+                    );
+                    runner.process(InputMessage::OrderStatus(order_status_query), tx);
                 },
-                "F" => order_books.flush(),
                 _ => ()
             }
         }
     }
 }
 
-/// A function for running a reader thread which outputs results over `tx`
-fn reader_func(tx: Sender<String>, runtime_config: RuntimeConfig) {
-    let mut order_books = OrderBooks::new(runtime_config.trading_enabled);
-    if let Ok(mut reader) = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_path(&runtime_config.input_file) {
+/// A function for running a reader thread which outputs results over `tx`. Once the input is
+/// exhausted, a summary of each scenario run is printed to stderr so that stdout remains valid CSV,
+/// followed by the position report if `--positions` was given. The fee ledger is saved if
+/// `--fee-ledger` was given, the bars and tape if `--bars` or `--tape` were given, and the final
+/// `OrderBooks` if `--snapshot` was given. The market data feed is flushed.
+fn reader_func(tx: Sender<Output>, runtime_config: RuntimeConfig) {
+    let input_file = runtime_config.input_file.clone();
+    let mut runner = ScenarioRunner::new(runtime_config);
+    if let Ok(mut reader) = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_path(&input_file) {
         for line in reader.records() {
             match line {
                 Ok(row) => {
-                    handle_row(row, &tx, &mut order_books);
+                    handle_row(row, &tx, &mut runner);
                 },
                 Err(e) => println!("{e}")
             }
         }
     } else {
-        panic!("Failed to open {}", runtime_config.input_file);
+        panic!("Failed to open {}", input_file);
+    }
+    runner.finish_scenario();
+    for summary in &runner.summaries {
+        eprintln!("{summary}");
+    }
+    if let Some(positions) = &runner.positions {
+        for position_report in positions.report() {
+            eprintln!("{position_report}");
+        }
+    }
+    if let (Some(fees), Some(fee_ledger_file)) = (&runner.fees, &runner.runtime_config.fee_ledger_file) {
+        fees.save(fee_ledger_file).unwrap_or_else(|e| panic!("Failed to save {}: {}", fee_ledger_file, e));
+    }
+    if let (Some(market_data), Some(bars_file)) = (&runner.market_data, &runner.runtime_config.bars_file) {
+        market_data.save_bars(bars_file).unwrap_or_else(|e| panic!("Failed to save {}: {}", bars_file, e));
+    }
+    if let (Some(market_data), Some(tape_file)) = (&runner.market_data, &runner.runtime_config.tape_file) {
+        market_data.save_tape(tape_file).unwrap_or_else(|e| panic!("Failed to save {}: {}", tape_file, e));
+    }
+    if let Some(feed) = runner.feed.as_mut() {
+        feed.flush().unwrap_or_else(|e| panic!("Failed to write market data feed: {}", e));
+    }
+    if let Some(snapshot_file) = &runner.runtime_config.snapshot_file {
+        save_snapshot(&runner.order_books, snapshot_file).unwrap_or_else(|e| panic!("Failed to save {}: {}", snapshot_file, e));
     }
 }
 
-/// A function for running a writer thread which receives results over 'rx' and writes them to stdout
-fn writer_func(rx: Receiver<String>) {
+/// A function for running a writer thread which receives results over 'rx' and writes them to stdout.
+/// With `--sequenced`, each result is prefixed by its sequence numbers. A warning is printed to stderr
+/// if a result sequence number is skipped.
+fn writer_func(rx: Receiver<Output>, sequenced: bool) {
+    let mut next_sequence = None;
     for x in rx {
-        println!("{x}");
+        match x {
+            Output::Text(text) => println!("{text}"),
+            Output::Result(sequenced_order_result) => {
+                if let Some(expected) = next_sequence {
+                    if sequenced_order_result.sequence != expected {
+                        eprintln!("Result sequence gap: expected {} but received {}", expected, sequenced_order_result.sequence);
+                    }
+                }
+                next_sequence = Some(sequenced_order_result.sequence + 1);
+                if sequenced {
+                    println!("{sequenced_order_result}");
+                } else {
+                    println!("{}", sequenced_order_result.order_result);
+                }
+            }
+        }
     }
 }
 
@@ -115,16 +606,63 @@ fn writer_func(rx: Receiver<String>) {
 /// to finish before exiting.
 fn main() {
     let runtime_config = parse_args(env::args().collect());    
+    let sequenced = runtime_config.sequenced;
     let (tx, rx) = mpsc::channel();
     
     let reader_thread = thread::Builder::new().name("reader_thread".to_string()).spawn(|| {
         reader_func(tx, runtime_config)
     }).expect("Failed to start reader thread");
 
-    let writer_thread = thread::Builder::new().name("writer_thread".to_string()).spawn(|| {
-        writer_func(rx)
+    let writer_thread = thread::Builder::new().name("writer_thread".to_string()).spawn(move || {
+        writer_func(rx, sequenced)
     }).expect("Failed to start writer thread");
 
     reader_thread.join().unwrap();
     writer_thread.join().unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod main_tests {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn journal_with_order(name: &str) -> RuntimeConfig {
+        let journal_file = std::env::temp_dir().join(format!("orderbook_main_{}_{}.csv", name, std::process::id()));
+        let _ = std::fs::remove_file(&journal_file);
+        let (mut journal, _) = Journal::open(&journal_file).unwrap();
+        journal.process(&mut OrderBooks::new(false), InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1))).unwrap();
+
+        let mut runtime_config = RuntimeConfig::new("input.csv".to_string(), false);
+        runtime_config.journal_file = Some(journal_file.to_string_lossy().to_string());
+        runtime_config
+    }
+
+    #[test]
+    fn scenario_after_recovery_is_rejected() {
+        let runtime_config = journal_with_order("rejected");
+        let journal_file = runtime_config.journal_file.clone().unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let mut runner = ScenarioRunner::new(runtime_config);
+
+        let result = catch_unwind(AssertUnwindSafe(|| handle_row(StringRecord::from(vec!["#name: scenario 1"]), &tx, &mut runner)));
+        std::fs::remove_file(&journal_file).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(runner.order_books.symbols(), vec!["IBM".to_string()]);
+    }
+
+    #[test]
+    fn scenario_after_resumed_input() {
+        let runtime_config = journal_with_order("resumed");
+        let journal_file = runtime_config.journal_file.clone().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let mut runner = ScenarioRunner::new(runtime_config);
+
+        handle_row(StringRecord::from(vec!["C", "1", "1"]), &tx, &mut runner);
+        handle_row(StringRecord::from(vec!["#name: scenario 2"]), &tx, &mut runner);
+        std::fs::remove_file(&journal_file).unwrap();
+
+        assert!(matches!(rx.recv().unwrap(), Output::Result(sequenced_order_result) if sequenced_order_result.order_result.to_string() == "A, 1, 1"));
+        assert!(runner.order_books.symbols().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+//! Runs the binary order entry server on a local TCP port, optionally publishing its market data
+//! over UDP with a TCP replay service
+
+use std::env;
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use orderbook::order_books::OrderBooks;
+use orderbook::ouch_server::{OuchEngine, serve};
+use orderbook::risk::load_risk_limits;
+use orderbook::self_trade_prevention::SelfTradePrevention;
+use orderbook::udp_feed::{UdpPublisher, serve_replay};
+
+/// Holds options passed as command line arguments
+struct ServerConfig {
+    port: u16,
+    trading_enabled: bool,
+    self_trade_prevention: SelfTradePrevention,
+    /// When set, orders are checked against the risk limits in this file
+    risk_limits_file: Option<String>,
+    /// When set, market data is published to this multicast group or unicast address
+    udp_feed: Option<SocketAddr>,
+    /// The port of the replay service for the market data
+    replay_port: u16,
+    /// When set, every packet whose number is a multiple of this is left out of the market data
+    udp_drop_every: Option<u64>
+}
+
+/// Handles command line arguments
+fn parse_args(args: Vec<String>) -> ServerConfig {
+    let mut server_config = ServerConfig { port: 9879, trading_enabled: false, self_trade_prevention: SelfTradePrevention::Allow, risk_limits_file: None, udp_feed: None, replay_port: 9882, udp_drop_every: None };
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-t" || arg == "--trading-enabled" {
+            server_config.trading_enabled = true;
+        } else if arg == "--port" {
+            server_config.port = args.next().expect("No port supplied after --port").parse().unwrap();
+        } else if arg == "--stp" {
+            server_config.self_trade_prevention = args.next().expect("No mode supplied after --stp").parse().unwrap();
+        } else if arg == "--risk-limits" {
+            server_config.risk_limits_file = Some(args.next().expect("No file supplied after --risk-limits"));
+        } else if arg == "--udp-feed" {
+            server_config.udp_feed = Some(args.next().expect("No address supplied after --udp-feed").parse().unwrap());
+        } else if arg == "--replay-port" {
+            server_config.replay_port = args.next().expect("No port supplied after --replay-port").parse().unwrap();
+        } else if arg == "--udp-drop-every" {
+            server_config.udp_drop_every = Some(args.next().expect("No count supplied after --udp-drop-every").parse().unwrap());
+        }
+    }
+    server_config
+}
+
+fn main() {
+    let server_config = parse_args(env::args().collect());
+    let mut order_books = OrderBooks::new(server_config.trading_enabled);
+    order_books.set_self_trade_prevention(server_config.self_trade_prevention);
+    if let Some(risk_limits_file) = &server_config.risk_limits_file {
+        order_books.set_risk_manager(load_risk_limits(risk_limits_file).unwrap_or_else(|e| panic!("Failed to load {}: {}", risk_limits_file, e)));
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", server_config.port)).unwrap_or_else(|e| panic!("Failed to listen on port {}: {}", server_config.port, e));
+    println!("Order entry server listening on {}", listener.local_addr().unwrap());
+    let mut engine = OuchEngine::new(order_books);
+    let mut publisher = None;
+    if let Some(udp_feed) = server_config.udp_feed {
+        let mut udp_publisher = UdpPublisher::new(udp_feed).unwrap_or_else(|e| panic!("Failed to publish to {}: {}", udp_feed, e));
+        if let Some(udp_drop_every) = server_config.udp_drop_every {
+            udp_publisher = udp_publisher.with_dropped_packets(udp_drop_every);
+        }
+        let replay_listener = TcpListener::bind(("127.0.0.1", server_config.replay_port)).unwrap_or_else(|e| panic!("Failed to listen on port {}: {}", server_config.replay_port, e));
+        println!("Publishing market data to {} with replay on {}", udp_feed, replay_listener.local_addr().unwrap());
+        let history = udp_publisher.history();
+        thread::spawn(move || serve_replay(replay_listener, history).expect("Replay service failed"));
+        engine = engine.with_market_data();
+        publisher = Some(udp_publisher);
+    }
+    serve(listener, engine, publisher).expect("Order entry server failed");
+}
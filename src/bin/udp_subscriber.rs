@@ -0,0 +1,95 @@
+//! Subscribes to the UDP market data feed, recovering missed messages from the replay service, and
+//! prints each message as it is applied, followed by the rebuilt books once the feed goes quiet.
+//! Heartbeats do not count as activity, so a feed of only heartbeats is quiet.
+
+use std::env;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use orderbook::itch::FeedMessage;
+use orderbook::udp_feed::{FeedSubscriber, Packet, ReplayClient, subscribe};
+
+/// Holds options passed as command line arguments
+struct SubscriberConfig {
+    /// The multicast group or unicast address the feed is published to
+    listen: SocketAddr,
+    /// The address of the replay service
+    replay: SocketAddr,
+    /// Whether to start from a snapshot rather than retransmission of every message so far
+    snapshot: bool,
+    /// When set, the books are printed and the subscriber exits once no message arrives for this long
+    idle_exit: Option<Duration>
+}
+
+/// Handles command line arguments
+fn parse_args(args: Vec<String>) -> SubscriberConfig {
+    let mut subscriber_config = SubscriberConfig { listen: "127.0.0.1:9881".parse().unwrap(), replay: "127.0.0.1:9882".parse().unwrap(), snapshot: false, idle_exit: None };
+    let mut args = args.into_iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--listen" {
+            subscriber_config.listen = args.next().expect("No address supplied after --listen").parse().unwrap();
+        } else if arg == "--replay" {
+            subscriber_config.replay = args.next().expect("No address supplied after --replay").parse().unwrap();
+        } else if arg == "--snapshot" {
+            subscriber_config.snapshot = true;
+        } else if arg == "--idle-exit" {
+            let seconds = args.next().expect("No seconds supplied after --idle-exit").parse::<u64>().unwrap();
+            subscriber_config.idle_exit = Some(Duration::from_secs(seconds));
+        }
+    }
+    subscriber_config
+}
+
+/// Print `messages`, returning whether there were any
+fn print_messages(messages: Vec<FeedMessage>) -> bool {
+    for message in &messages {
+        println!("{}", message);
+    }
+    !messages.is_empty()
+}
+
+fn main() {
+    let subscriber_config = parse_args(env::args().collect());
+    let socket = subscribe(subscriber_config.listen).unwrap_or_else(|e| panic!("Failed to subscribe to {}: {}", subscriber_config.listen, e));
+    socket.set_read_timeout(subscriber_config.idle_exit).unwrap();
+    let mut last_message = Instant::now();
+    let mut replay = ReplayClient::connect(subscriber_config.replay).unwrap_or_else(|e| panic!("Failed to connect to {}: {}", subscriber_config.replay, e));
+    let mut subscriber = FeedSubscriber::new();
+    if subscriber_config.snapshot {
+        let (snapshot, next_sequence) = replay.snapshot().unwrap_or_else(|e| panic!("Failed to get a snapshot: {}", e));
+        eprintln!("Starting from a snapshot of {} messages before {}", snapshot.len(), next_sequence);
+        print_messages(subscriber.restore(&snapshot, next_sequence));
+        last_message = Instant::now();
+    }
+
+    let mut buffer = [0; 65536];
+    while subscriber_config.idle_exit.map_or(true, |idle_exit| last_message.elapsed() < idle_exit) {
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => panic!("Failed to receive: {}", e)
+        };
+        let packet = match Packet::decode(&buffer[..length]) {
+            Ok(packet) => packet,
+            Err(e) => {
+                eprintln!("Ignoring packet: {}", e);
+                continue;
+            }
+        };
+        let mut received = print_messages(subscriber.receive(&packet));
+        while let Some(gap) = subscriber.gap() {
+            eprintln!("Recovering messages {} to {}", gap.start, gap.end - 1);
+            let messages = replay.retransmit(gap).unwrap_or_else(|e| panic!("Failed to recover messages: {}", e));
+            if messages.is_empty() {
+                break;
+            }
+            received |= print_messages(subscriber.recover(messages));
+        }
+        if received {
+            last_message = Instant::now();
+        }
+    }
+
+    println!("# books before sequence {}", subscriber.next_sequence());
+    print_messages(subscriber.books().snapshot());
+}
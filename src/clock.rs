@@ -0,0 +1,43 @@
+//! The engine clock used by `OrderBooks` to timestamp each input message as it is processed
+
+use chrono::{DateTime, Duration, Utc};
+
+/// A source of engine time
+pub trait Clock: Send {
+    fn now(&mut self) -> DateTime<Utc>;
+}
+
+/// Reads the system time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&mut self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A deterministic clock which starts at a fixed time and moves forward by `step` each time it is
+/// read, so that repeated runs stamp identical times
+pub struct SimulatedClock {
+    current: DateTime<Utc>,
+    step: Duration
+}
+
+impl SimulatedClock {
+    pub fn new(start: DateTime<Utc>, step: Duration) -> SimulatedClock {
+        SimulatedClock { current: start, step }
+    }
+
+    /// Move the clock forward without reading it
+    pub fn advance(&mut self, duration: Duration) {
+        self.current += duration;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&mut self) -> DateTime<Utc> {
+        let now = self.current;
+        self.current += self.step;
+        now
+    }
+}
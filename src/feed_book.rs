@@ -0,0 +1,135 @@
+//! Order books rebuilt from nothing but the binary market data feed in `itch`, as a subscriber
+//! would. Each book holds the resting orders added and not yet cancelled or filled, the latest top
+//! of book on each side and the trading status, and can be written out as a snapshot of feed
+//! messages which rebuilds it.
+
+use std::collections::BTreeMap;
+
+use crate::itch::{FeedBody, FeedMessage};
+
+/// The book of one symbol, kept as the feed messages which describe it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedBook {
+    /// The `AddOrder` message of each resting order, in arrival order, with its quantity reduced by
+    /// the trades since
+    orders: Vec<FeedMessage>,
+    /// The latest `TopOfBook` message for each side
+    top_of_book: BTreeMap<char, FeedMessage>,
+    trading_status: Option<FeedMessage>
+}
+
+impl FeedBook {
+    /// The `AddOrder` message of each resting order, in arrival order, with its open quantity
+    pub fn orders(&self) -> impl Iterator<Item = &FeedMessage> {
+        self.orders.iter()
+    }
+
+    /// The open quantity of the order of `user` with `user_order_id`, if it is resting
+    pub fn open_qty(&self, user: u64, user_order_id: u64) -> Option<u64> {
+        self.orders.iter().find_map(|order| match order.body {
+            FeedBody::AddOrder { user: order_user, user_order_id: order_id, qty, .. } if order_user == user && order_id == user_order_id => Some(qty),
+            _ => None
+        })
+    }
+
+    /// The best price and total quantity at it on `side`, or `None` for both when the side is empty
+    /// or has not been reported
+    pub fn top_of_book(&self, side: char) -> (Option<u64>, Option<u64>) {
+        match self.top_of_book.get(&side).map(|message| &message.body) {
+            Some(FeedBody::TopOfBook { price, total_qty, .. }) => (*price, *total_qty),
+            _ => (None, None)
+        }
+    }
+
+    pub fn trading_status(&self) -> Option<&FeedMessage> {
+        self.trading_status.as_ref()
+    }
+
+    fn position(&self, user: u64, user_order_id: u64) -> Option<usize> {
+        self.orders.iter().position(|order| matches!(order.body, FeedBody::AddOrder { user: order_user, user_order_id: order_id, .. } if order_user == user && order_id == user_order_id))
+    }
+
+    /// Reduce the order of `user` with `user_order_id` by `qty`, removing it once it is filled
+    fn fill(&mut self, user: u64, user_order_id: u64, filled_qty: u64) {
+        if let Some(index) = self.position(user, user_order_id) {
+            if let FeedBody::AddOrder { qty, .. } = &mut self.orders[index].body {
+                *qty = qty.saturating_sub(filled_qty);
+                if *qty == 0 {
+                    self.orders.remove(index);
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, message: &FeedMessage) {
+        match &message.body {
+            FeedBody::AddOrder { user, user_order_id, .. } => {
+                if let Some(index) = self.position(*user, *user_order_id) {
+                    self.orders.remove(index);
+                }
+                self.orders.push(message.clone());
+            },
+            FeedBody::OrderCancel { user, user_order_id } => {
+                if let Some(index) = self.position(*user, *user_order_id) {
+                    self.orders.remove(index);
+                }
+            },
+            FeedBody::Trade { user_buy, user_order_id_buy, user_sell, user_order_id_sell, qty, .. } => {
+                self.fill(*user_buy, *user_order_id_buy, *qty);
+                self.fill(*user_sell, *user_order_id_sell, *qty);
+            },
+            FeedBody::TopOfBook { side, .. } => {
+                self.top_of_book.insert(*side, message.clone());
+            },
+            FeedBody::TradingStatus { .. } => self.trading_status = Some(message.clone())
+        }
+    }
+}
+
+/// The books of every symbol seen on a feed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedBooks {
+    books: BTreeMap<String, FeedBook>
+}
+
+impl FeedBooks {
+    pub fn new() -> FeedBooks {
+        FeedBooks { books: BTreeMap::new() }
+    }
+
+    /// Books rebuilt from `snapshot`, as written by `snapshot`
+    pub fn from_snapshot(snapshot: &[FeedMessage]) -> FeedBooks {
+        let mut feed_books = FeedBooks::new();
+        snapshot.iter().for_each(|message| feed_books.apply(message));
+        feed_books
+    }
+
+    /// Update the books with the next message of the feed. Messages about no one symbol, and cancels
+    /// and fills of orders which are not resting, are ignored.
+    pub fn apply(&mut self, message: &FeedMessage) {
+        let symbol = match &message.symbol {
+            Some(symbol) => symbol,
+            None => return
+        };
+        match message.body {
+            FeedBody::OrderCancel { .. } | FeedBody::Trade { .. } => if let Some(book) = self.books.get_mut(symbol) {
+                book.apply(message);
+            },
+            _ => self.books.entry(symbol.clone()).or_default().apply(message)
+        }
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&FeedBook> {
+        self.books.get(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &String> {
+        self.books.keys()
+    }
+
+    /// The messages which rebuild these books: for each symbol in order, its trading status, its
+    /// resting orders in arrival order and its top of book
+    pub fn snapshot(&self) -> Vec<FeedMessage> {
+        self.books.values().flat_map(|book| book.trading_status.iter().chain(book.orders.iter()).chain(book.top_of_book.values())).cloned().collect()
+    }
+}
@@ -0,0 +1,83 @@
+//! Unit tests for rebuilding order books from the binary market data feed
+
+#[cfg(test)]
+mod feed_book_tests {
+    use chrono::{DateTime, Utc};
+
+    use crate::feed_book::*;
+    use crate::itch::*;
+    use crate::order::*;
+    use crate::order_books::OrderBooks;
+    use crate::trading_status::TradingStatus;
+
+    /// The feed messages of processing `messages` with execution reports on
+    fn feed(messages: Vec<InputMessage>) -> (OrderBooks, Vec<FeedMessage>) {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_execution_reports(true);
+        let mut feed_messages = vec![];
+        for message in messages {
+            let order_results = order_books.process(message.clone());
+            let time = order_books.current_time().unwrap();
+            feed_messages.extend(order_results.iter().filter_map(|order_result| FeedMessage::from_result(order_result, Some(&message), time)));
+        }
+        (order_books, feed_messages)
+    }
+
+    fn messages() -> Vec<InputMessage> {
+        vec![
+            InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)),
+            InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 50, 'B', 2).with_display_qty(10)),
+            InputMessage::New(NewOrder::new(2, "IBM".to_string(), 12, 100, 'S', 3)),
+            InputMessage::New(NewOrder::new(3, "IBM".to_string(), 10, 100, 'S', 4)),
+            InputMessage::New(NewOrder::new(3, "IBM".to_string(), 10, 30, 'S', 5)),
+            InputMessage::New(NewOrder::new(1, "AAPL".to_string(), 20, 10, 'S', 6)),
+            InputMessage::New(NewOrder::new(2, "AAPL".to_string(), 20, 10, 'B', 7)),
+            InputMessage::New(NewOrder::new(2, "AAPL".to_string(), 19, 10, 'B', 8)),
+            InputMessage::Cancel(CancelOrder::new(2, 8)),
+            InputMessage::TradingStatus(TradingStatusOrder::new(Some("MSFT"), TradingStatus::Halted))
+        ]
+    }
+
+    #[test]
+    fn rebuild_matches_order_books() {
+        let (order_books, feed_messages) = feed(messages());
+        let mut feed_books = FeedBooks::new();
+        feed_messages.iter().for_each(|message| feed_books.apply(message));
+
+        let ibm = feed_books.book("IBM").unwrap();
+        for (user, user_order_id) in [(1, 2), (2, 3)] {
+            assert_eq!(ibm.open_qty(user, user_order_id), order_books.order_status(user, user_order_id).map(|status| status.open_qty));
+        }
+        assert_eq!(ibm.open_qty(1, 1), None);
+        assert_eq!(ibm.open_qty(3, 5), None);
+        assert_eq!(ibm.orders().count(), 2);
+        assert_eq!(ibm.top_of_book('B'), (Some(10), Some(10)));
+        assert_eq!(ibm.top_of_book('S'), (Some(12), Some(100)));
+        assert_eq!(feed_books.book("AAPL").unwrap().orders().count(), 0);
+        assert_eq!(feed_books.book("MSFT").unwrap().trading_status().map(|message| &message.body), Some(&FeedBody::TradingStatus { status: TradingStatus::Halted }));
+        assert_eq!(feed_books.symbols().collect::<Vec<&String>>(), vec!["AAPL", "IBM", "MSFT"]);
+    }
+
+    #[test]
+    fn snapshot_rebuilds_books() {
+        let (_, feed_messages) = feed(messages());
+        let mut feed_books = FeedBooks::new();
+        feed_messages.iter().for_each(|message| feed_books.apply(message));
+
+        let snapshot = feed_books.snapshot();
+        assert_eq!(snapshot.len(), 7);
+        assert_eq!(FeedBooks::from_snapshot(&snapshot), feed_books);
+    }
+
+    #[test]
+    fn ignores_unknown_orders() {
+        let time = "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let message = |body| FeedMessage { sequence: 1, time, symbol: Some("IBM".to_string()), body };
+        let mut feed_books = FeedBooks::new();
+        feed_books.apply(&message(FeedBody::OrderCancel { user: 1, user_order_id: 1 }));
+        feed_books.apply(&message(FeedBody::Trade { trade_id: 1, user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 100, aggressor_side: None }));
+        feed_books.apply(&FeedMessage { symbol: None, ..message(FeedBody::TradingStatus { status: TradingStatus::Closed }) });
+
+        assert_eq!(feed_books, FeedBooks::new());
+    }
+}
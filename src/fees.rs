@@ -0,0 +1,292 @@
+//! Maker and taker fees on trades, charged according to a `FeeSchedule` and accumulated for each
+//! user in a `FeeLedger`. Positive fees are charged to the user and negative fees are rebates.
+//!
+//! A schedule can be loaded from a CSV file with a `basis` row giving how rates apply and one `tier`
+//! row per volume tier, where a user's tier is the highest whose minimum volume they have traded:
+//! ```text
+//! basis, per-share|bps
+//! tier, min_volume, maker_rate, taker_rate
+//! ```
+//! For example, a rebate of 0.002 per share for adding liquidity and a fee of 0.003 per share for
+//! removing it, reduced for users who have traded a million shares:
+//! ```text
+//! basis, per-share
+//! tier, 0, -0.002, 0.003
+//! tier, 1000000, -0.0025, 0.0025
+//! ```
+
+use std::{collections::BTreeMap, fmt, fs::File, io::{Read, Write}, path::Path, str::FromStr};
+
+use csv::StringRecord;
+
+use crate::order_result::{OrderResult, SequencedOrderResult};
+
+/// How the rates of a `FeeSchedule` are applied to a trade
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeeBasis {
+    /// Rates are an amount per share traded
+    PerShare,
+    /// Rates are basis points of the traded price times quantity
+    BasisPoints
+}
+
+impl FromStr for FeeBasis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-share" => Ok(Self::PerShare),
+            "bps" => Ok(Self::BasisPoints),
+            _ => Err(format!("Unknown fee basis: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for FeeBasis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PerShare => write!(f, "per-share"),
+            Self::BasisPoints => write!(f, "bps")
+        }
+    }
+}
+
+/// Whether a user's side of a trade added liquidity by resting in the book, or removed it as the
+/// aggressor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Liquidity {
+    Maker,
+    Taker
+}
+
+impl fmt::Display for Liquidity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Maker => write!(f, "maker"),
+            Self::Taker => write!(f, "taker")
+        }
+    }
+}
+
+/// The rates charged to users who have traded at least `min_volume`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub maker_rate: f64,
+    pub taker_rate: f64
+}
+
+/// Maker and taker rates by volume tier. Without any tiers no fees are charged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeSchedule {
+    pub basis: FeeBasis,
+    /// Sorted by `min_volume`
+    tiers: Vec<FeeTier>
+}
+
+impl FeeSchedule {
+    pub fn new(basis: FeeBasis) -> FeeSchedule {
+        FeeSchedule { basis, tiers: vec![] }
+    }
+
+    /// Add a tier, replacing any tier with the same `min_volume`
+    pub fn with_tier(mut self, min_volume: u64, maker_rate: f64, taker_rate: f64) -> FeeSchedule {
+        self.tiers.retain(|tier| tier.min_volume != min_volume);
+        self.tiers.push(FeeTier { min_volume, maker_rate, taker_rate });
+        self.tiers.sort_by_key(|tier| tier.min_volume);
+        self
+    }
+
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    /// The tier of a user who has traded `volume`
+    pub fn tier(&self, volume: u64) -> Option<&FeeTier> {
+        self.tiers.iter().rev().find(|tier| tier.min_volume <= volume)
+    }
+
+    /// The fee for one side of a trade of `qty` at `price`, for a user who had already traded
+    /// `volume`
+    pub fn fee(&self, liquidity: Liquidity, price: u64, qty: u64, volume: u64) -> f64 {
+        let tier = match self.tier(volume) {
+            Some(tier) => tier,
+            None => return 0.0
+        };
+        let rate = match liquidity {
+            Liquidity::Maker => tier.maker_rate,
+            Liquidity::Taker => tier.taker_rate
+        };
+        match self.basis {
+            FeeBasis::PerShare => rate * qty as f64,
+            FeeBasis::BasisPoints => rate * price as f64 * qty as f64 / 10_000.0
+        }
+    }
+}
+
+/// The fee charged to one user for their side of a trade
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradeFee {
+    pub trade_id: u64,
+    pub user: u64,
+    pub user_order_id: u64,
+    pub symbol: String,
+    pub liquidity: Liquidity,
+    pub price: u64,
+    pub qty: u64,
+    pub fee: f64
+}
+
+/// The volume a user has traded and the fees charged to them so far
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UserFees {
+    pub volume: u64,
+    pub trades: u64,
+    pub maker_fees: f64,
+    pub taker_fees: f64
+}
+
+impl UserFees {
+    pub fn total(&self) -> f64 {
+        self.maker_fees + self.taker_fees
+    }
+}
+
+/// Charges fees on each trade published by `OrderBooks` and accumulates them by user
+pub struct FeeLedger {
+    fee_schedule: FeeSchedule,
+    user_fees: BTreeMap<u64, UserFees>,
+    trade_fees: Vec<TradeFee>
+}
+
+impl FeeLedger {
+    pub fn new(fee_schedule: FeeSchedule) -> FeeLedger {
+        FeeLedger { fee_schedule, user_fees: BTreeMap::new(), trade_fees: vec![] }
+    }
+
+    /// Charge fees on each trade among `sequenced_order_results`. The aggressor of a trade pays the
+    /// taker rate and the resting order the maker rate, while a trade without an aggressor, made by
+    /// an auction uncross, charges both sides the taker rate.
+    pub fn record(&mut self, sequenced_order_results: &[SequencedOrderResult]) {
+        for sequenced_order_result in sequenced_order_results {
+            if let OrderResult::Trade { trade_id, symbol, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, aggressor_side, .. } = &sequenced_order_result.order_result {
+                let liquidity = |side: char| if aggressor_side.map_or(true, |aggressor_side| aggressor_side == side) { Liquidity::Taker } else { Liquidity::Maker };
+                let trade_fee = |user: u64, user_order_id: u64, side: char| {
+                    TradeFee { trade_id: *trade_id, user, user_order_id, symbol: symbol.clone(), liquidity: liquidity(side), price: *price, qty: *qty, fee: 0.0 }
+                };
+                self.charge(trade_fee(*user_buy, *user_order_id_buy, 'B'));
+                self.charge(trade_fee(*user_sell, *user_order_id_sell, 'S'));
+            }
+        }
+    }
+
+    /// Charge the user of `trade_fee` for their side of a trade, at the rate of their tier before it
+    fn charge(&mut self, mut trade_fee: TradeFee) {
+        let user_fees = self.user_fees.entry(trade_fee.user).or_default();
+        trade_fee.fee = self.fee_schedule.fee(trade_fee.liquidity, trade_fee.price, trade_fee.qty, user_fees.volume);
+        user_fees.volume += trade_fee.qty;
+        user_fees.trades += 1;
+        match trade_fee.liquidity {
+            Liquidity::Maker => user_fees.maker_fees += trade_fee.fee,
+            Liquidity::Taker => user_fees.taker_fees += trade_fee.fee
+        }
+        self.trade_fees.push(trade_fee);
+    }
+
+    pub fn user_fees(&self, user: u64) -> Option<&UserFees> {
+        self.user_fees.get(&user)
+    }
+
+    /// Every fee charged, in the order the trades were made
+    pub fn trade_fees(&self) -> &[TradeFee] {
+        &self.trade_fees
+    }
+
+    /// Write one `user, volume, trades, maker_fees, taker_fees, total_fees` row per user, by user
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), FeeError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["user", "volume", "trades", "maker_fees", "taker_fees", "total_fees"])?;
+        for (user, user_fees) in &self.user_fees {
+            writer.write_record([
+                user.to_string(),
+                user_fees.volume.to_string(),
+                user_fees.trades.to_string(),
+                format_fee(user_fees.maker_fees),
+                format_fee(user_fees.taker_fees),
+                format_fee(user_fees.total())
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the ledger to the file at `path`, replacing it if it exists
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FeeError> {
+        self.write(File::create(path)?)
+    }
+}
+
+/// Format `fee` to four decimal places, enough for fractions of a cent per share
+fn format_fee(fee: f64) -> String {
+    format!("{:.4}", (fee * 10_000.0).round() / 10_000.0 + 0.0)
+}
+
+/// Reasons a fee schedule could not be loaded or a ledger could not be written
+#[derive(Debug)]
+pub enum FeeError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    InvalidRow(String)
+}
+
+impl fmt::Display for FeeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Fees I/O error: {}", e),
+            Self::Csv(e) => write!(f, "Fees CSV error: {}", e),
+            Self::InvalidRow(row) => write!(f, "Invalid fee schedule row: \"{}\"", row)
+        }
+    }
+}
+
+impl From<std::io::Error> for FeeError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<csv::Error> for FeeError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+/// Read a fee schedule from `reader`, which is per share unless it has a `basis` row
+pub fn read_fee_schedule<R: Read>(reader: R) -> Result<FeeSchedule, FeeError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let mut fee_schedule = FeeSchedule::new(FeeBasis::PerShare);
+    for record in reader.records() {
+        let row = record?;
+        let invalid = || FeeError::InvalidRow(row_to_string(&row));
+        match (row.get(0), row.len()) {
+            (Some("basis"), 2) => fee_schedule.basis = row.get(1).unwrap().parse().map_err(|_| invalid())?,
+            (Some("tier"), 4) => {
+                let min_volume = row.get(1).unwrap().parse::<u64>().map_err(|_| invalid())?;
+                let maker_rate = row.get(2).unwrap().parse::<f64>().map_err(|_| invalid())?;
+                let taker_rate = row.get(3).unwrap().parse::<f64>().map_err(|_| invalid())?;
+                fee_schedule = fee_schedule.with_tier(min_volume, maker_rate, taker_rate);
+            },
+            _ => return Err(invalid())
+        }
+    }
+    Ok(fee_schedule)
+}
+
+/// Read a fee schedule from the file at `path`
+pub fn load_fee_schedule<P: AsRef<Path>>(path: P) -> Result<FeeSchedule, FeeError> {
+    read_fee_schedule(File::open(path)?)
+}
+
+fn row_to_string(row: &StringRecord) -> String {
+    row.iter().collect::<Vec<&str>>().join(", ")
+}
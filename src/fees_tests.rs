@@ -0,0 +1,84 @@
+//! Unit tests for fee schedules and the fee ledger
+
+#[cfg(test)]
+mod fees_tests {
+    use crate::fees::*;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+
+    fn per_share_schedule() -> FeeSchedule {
+        FeeSchedule::new(FeeBasis::PerShare).with_tier(0, -0.002, 0.003).with_tier(150, -0.0025, 0.0025)
+    }
+
+    fn charged(order_books: &mut OrderBooks, fee_ledger: &mut FeeLedger, message: InputMessage) {
+        fee_ledger.record(&order_books.process(message));
+    }
+
+    #[test]
+    fn parse_basis() {
+        assert_eq!("bps".parse::<FeeBasis>(), Ok(FeeBasis::BasisPoints));
+        assert_eq!(FeeBasis::PerShare.to_string(), "per-share");
+        assert!("percent".parse::<FeeBasis>().is_err());
+    }
+
+    #[test]
+    fn tiers_by_volume() {
+        let fee_schedule = per_share_schedule();
+
+        assert_eq!(fee_schedule.tier(149).map(|tier| tier.min_volume), Some(0));
+        assert_eq!(fee_schedule.tier(150).map(|tier| tier.min_volume), Some(150));
+        assert_eq!(fee_schedule.fee(Liquidity::Taker, 10, 100, 0), 0.3);
+        assert_eq!(fee_schedule.fee(Liquidity::Maker, 10, 100, 200), -0.25);
+        assert_eq!(FeeSchedule::new(FeeBasis::BasisPoints).with_tier(0, 0.0, 5.0).fee(Liquidity::Taker, 20, 100, 0), 1.0);
+        assert_eq!(FeeSchedule::new(FeeBasis::PerShare).fee(Liquidity::Taker, 20, 100, 0), 0.0);
+    }
+
+    #[test]
+    fn aggressor_pays_taker_fee() {
+        let mut order_books = OrderBooks::new(true);
+        let mut fee_ledger = FeeLedger::new(per_share_schedule());
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1)));
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2)));
+
+        assert_eq!((fee_ledger.trade_fees()[0].user, fee_ledger.trade_fees()[0].liquidity), (2, Liquidity::Taker));
+        assert_eq!((fee_ledger.trade_fees()[1].user, fee_ledger.trade_fees()[1].liquidity), (1, Liquidity::Maker));
+        assert_eq!(fee_ledger.user_fees(1), Some(&UserFees { volume: 100, trades: 1, maker_fees: -0.2, taker_fees: 0.0 }));
+        assert_eq!(fee_ledger.user_fees(2), Some(&UserFees { volume: 100, trades: 1, maker_fees: 0.0, taker_fees: 0.3 }));
+    }
+
+    #[test]
+    fn triggered_stop_order_is_aggressor() {
+        let mut order_books = OrderBooks::new(true);
+        let mut fee_ledger = FeeLedger::new(per_share_schedule());
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new_stop(3, "IBM".to_string(), 10, 100, 'B', 3)));
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1)));
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(4, "IBM".to_string(), 11, 100, 'S', 4)));
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2)));
+
+        let trade_fees = fee_ledger.trade_fees();
+        assert_eq!(trade_fees.len(), 4);
+        assert_eq!((trade_fees[2].user, trade_fees[2].liquidity), (3, Liquidity::Taker));
+        assert_eq!((trade_fees[3].user, trade_fees[3].liquidity), (4, Liquidity::Maker));
+    }
+
+    #[test]
+    fn ledger_export() {
+        let mut order_books = OrderBooks::new(true);
+        let mut fee_ledger = FeeLedger::new(per_share_schedule());
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1)));
+        charged(&mut order_books, &mut fee_ledger, InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2)));
+        let mut buffer = vec![];
+        fee_ledger.write(&mut buffer).unwrap();
+
+        assert_eq!(String::from_utf8(buffer).unwrap(), "user,volume,trades,maker_fees,taker_fees,total_fees\n1,100,1,-0.2000,0.0000,-0.2000\n2,100,1,0.0000,0.3000,0.3000\n");
+    }
+
+    #[test]
+    fn read_schedule_file() {
+        let fee_schedule = read_fee_schedule("basis, bps\ntier, 1000, 0, 1\ntier, 0, -1, 2\n".as_bytes()).unwrap();
+
+        assert_eq!(fee_schedule.basis, FeeBasis::BasisPoints);
+        assert_eq!(fee_schedule.tiers()[0], FeeTier { min_volume: 0, maker_rate: -1.0, taker_rate: 2.0 });
+        assert!(matches!(read_fee_schedule("tier, 0, 1\n".as_bytes()), Err(FeeError::InvalidRow(_))));
+    }
+}
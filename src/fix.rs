@@ -0,0 +1,287 @@
+//! The FIX 4.4 tag=value message format used by the FIX gateway. A message is a list of
+//! `tag=value` fields, each ended by the SOH character, framed by a `BeginString` and `BodyLength`
+//! at the front and a `CheckSum` at the back:
+//! ```text
+//! 8=FIX.4.4|9=59|35=0|49=ORDERBOOK|56=CLIENT1|34=2|52=20220103-09:30:00.000|10=088|
+//! ```
+//! where '|' stands for SOH, as in `FixMessage`'s `Display`.
+
+use std::{fmt, str};
+
+use chrono::{DateTime, Utc};
+
+/// Ends every field
+pub const SOH: u8 = 0x01;
+
+/// The `BeginString` of every FIX 4.4 message
+pub const BEGIN_STRING: &str = "FIX.4.4";
+
+/// The largest `BodyLength` accepted, so that a client cannot make the gateway buffer without limit
+pub const MAX_BODY_LENGTH: usize = 8192;
+
+/// The tags used by the gateway
+pub mod tags {
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_TAG_ID: u32 = 371;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+}
+
+/// The message types used by the gateway
+pub mod msg_types {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+
+    /// Whether `msg_type` is a session-level message rather than an application message
+    pub fn is_admin(msg_type: &str) -> bool {
+        matches!(msg_type, HEARTBEAT | TEST_REQUEST | RESEND_REQUEST | REJECT | SEQUENCE_RESET | LOGOUT | LOGON)
+    }
+}
+
+/// Reasons bytes could not be read as a FIX message
+#[derive(Debug, PartialEq)]
+pub enum FixError {
+    /// The message does not start with `8=FIX.4.4` followed by a `BodyLength`
+    InvalidHeader(String),
+    /// A field is not `tag=value`
+    InvalidField(String),
+    /// The `CheckSum` does not match the message, which holds the expected and received sums
+    InvalidCheckSum { expected: u32, found: String },
+    /// The `BodyLength` is more than `MAX_BODY_LENGTH`
+    BodyTooLong(usize)
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader(header) => write!(f, "Invalid FIX header: \"{}\"", header),
+            Self::InvalidField(field) => write!(f, "Invalid FIX field: \"{}\"", field),
+            Self::InvalidCheckSum { expected, found } => write!(f, "Invalid FIX checksum: expected {:03} but found \"{}\"", expected, found),
+            Self::BodyTooLong(body_length) => write!(f, "FIX body length {} is more than {}", body_length, MAX_BODY_LENGTH)
+        }
+    }
+}
+
+/// A FIX message, holding every field after `BodyLength` and before `CheckSum` in order, starting
+/// with `MsgType`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> FixMessage {
+        FixMessage { fields: vec![(tags::MSG_TYPE, msg_type.to_string())] }
+    }
+
+    /// Set `tag` to `value`
+    pub fn with<T: ToString>(mut self, tag: u32, value: T) -> FixMessage {
+        self.set(tag, value);
+        self
+    }
+
+    /// Set `tag` to `value`, replacing its first value if it is already set
+    pub fn set<T: ToString>(&mut self, tag: u32, value: T) {
+        match self.fields.iter_mut().find(|(field_tag, _)| *field_tag == tag) {
+            Some(field) => field.1 = value.to_string(),
+            None => self.fields.push((tag, value.to_string()))
+        }
+    }
+
+    /// The first value of `tag`
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|(field_tag, _)| *field_tag == tag).map(|(_, value)| value.as_str())
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tags::MSG_TYPE).unwrap_or_default()
+    }
+
+    /// Every field after `BodyLength` and before `CheckSum`, starting with `MsgType`
+    pub fn fields(&self) -> &[(u32, String)] {
+        &self.fields
+    }
+
+    /// The `MsgSeqNum`, if it is present and a number
+    pub fn seq_num(&self) -> Option<u64> {
+        self.get(tags::MSG_SEQ_NUM).and_then(|seq_num| seq_num.parse().ok())
+    }
+
+    /// Frame the message with `BeginString`, `BodyLength` and `CheckSum`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = vec![];
+        for (tag, value) in &self.fields {
+            body.extend(format!("{}={}", tag, value).bytes());
+            body.push(SOH);
+        }
+        let mut bytes = format!("8={}\x019={}\x01", BEGIN_STRING, body.len()).into_bytes();
+        bytes.append(&mut body);
+        let check_sum = check_sum(&bytes);
+        bytes.extend(format!("10={:03}\x01", check_sum).bytes());
+        bytes
+    }
+
+    /// Read the first message in `buffer`, returning it with the number of bytes it took up, or
+    /// `None` if `buffer` does not yet hold a whole message
+    pub fn decode(buffer: &[u8]) -> Result<Option<(FixMessage, usize)>, FixError> {
+        let prefix = format!("8={}\x019=", BEGIN_STRING);
+        if buffer.len() < prefix.len() {
+            return if prefix.as_bytes().starts_with(buffer) { Ok(None) } else { Err(invalid_header(buffer)) };
+        }
+        if !buffer.starts_with(prefix.as_bytes()) {
+            return Err(invalid_header(buffer));
+        }
+        let max_digits = MAX_BODY_LENGTH.to_string().len();
+        let body_length_end = match buffer[prefix.len()..].iter().take(max_digits + 1).position(|byte| *byte == SOH) {
+            Some(position) => prefix.len() + position,
+            None if buffer.len() > prefix.len() + max_digits => return Err(invalid_header(buffer)),
+            None => return Ok(None)
+        };
+        let body_length = str::from_utf8(&buffer[prefix.len()..body_length_end]).ok()
+            .and_then(|body_length| body_length.parse::<usize>().ok())
+            .ok_or_else(|| invalid_header(buffer))?;
+        if body_length > MAX_BODY_LENGTH {
+            return Err(FixError::BodyTooLong(body_length));
+        }
+        let body_start = body_length_end + 1;
+        let (check_sum_start, check_sum_end) = body_start.checked_add(body_length)
+            .and_then(|check_sum_start| check_sum_start.checked_add("10=000\x01".len()).map(|check_sum_end| (check_sum_start, check_sum_end)))
+            .ok_or(FixError::BodyTooLong(body_length))?;
+        if buffer.len() < check_sum_end {
+            return Ok(None);
+        }
+
+        let check_sum_field = &buffer[check_sum_start..check_sum_end];
+        if !check_sum_field.starts_with(b"10=") || check_sum_field[check_sum_field.len() - 1] != SOH {
+            return Err(FixError::InvalidField(String::from_utf8_lossy(check_sum_field).to_string()));
+        }
+        let expected = check_sum(&buffer[..check_sum_start]);
+        let found = String::from_utf8_lossy(&check_sum_field[3..check_sum_field.len() - 1]).to_string();
+        if found.parse::<u32>().ok() != Some(expected) {
+            return Err(FixError::InvalidCheckSum { expected, found });
+        }
+
+        let mut fields = vec![];
+        for field in buffer[body_start..check_sum_start].split(|byte| *byte == SOH).filter(|field| !field.is_empty()) {
+            let field = String::from_utf8_lossy(field);
+            let (tag, value) = field.split_once('=')
+                .and_then(|(tag, value)| tag.parse::<u32>().ok().map(|tag| (tag, value.to_string())))
+                .ok_or_else(|| FixError::InvalidField(field.to_string()))?;
+            fields.push((tag, value));
+        }
+        if fields.first().map(|(tag, _)| *tag) != Some(tags::MSG_TYPE) {
+            return Err(FixError::InvalidField(fields.first().map_or(String::new(), |(tag, value)| format!("{}={}", tag, value))));
+        }
+        Ok(Some((FixMessage { fields }, check_sum_end)))
+    }
+}
+
+impl fmt::Display for FixMessage {
+    /// Write the framed message with '|' in place of SOH
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let encoded = self.encode();
+        write!(f, "{}", String::from_utf8_lossy(&encoded).replace('\x01', "|"))
+    }
+}
+
+/// Parse a message written with '|' in place of SOH, as by `Display`. `BeginString`, `BodyLength`
+/// and `CheckSum` may be left out, and are ignored if present.
+pub fn parse_readable(readable: &str) -> Result<FixMessage, FixError> {
+    let mut fields = vec![];
+    for field in readable.trim().split('|').filter(|field| !field.is_empty()) {
+        let (tag, value) = field.split_once('=')
+            .and_then(|(tag, value)| tag.trim().parse::<u32>().ok().map(|tag| (tag, value.to_string())))
+            .ok_or_else(|| FixError::InvalidField(field.to_string()))?;
+        if !matches!(tag, tags::BEGIN_STRING | tags::BODY_LENGTH | tags::CHECK_SUM) {
+            fields.push((tag, value));
+        }
+    }
+    match fields.iter().position(|(tag, _)| *tag == tags::MSG_TYPE) {
+        Some(index) => {
+            let msg_type = fields.remove(index);
+            fields.insert(0, msg_type);
+            Ok(FixMessage { fields })
+        },
+        None => Err(FixError::InvalidField(readable.to_string()))
+    }
+}
+
+/// `body` behind a standard header from `sender_comp_id` to `target_comp_id` numbered `seq_num`
+/// and sent at `time`, marked as a possible duplicate of a message first sent at `orig_sending_time`
+/// if set. Any header fields in `body` are left out.
+pub fn with_header(body: &FixMessage, sender_comp_id: &str, target_comp_id: &str, seq_num: u64, time: DateTime<Utc>, orig_sending_time: Option<&str>) -> FixMessage {
+    let mut message = FixMessage::new(body.msg_type())
+        .with(tags::SENDER_COMP_ID, sender_comp_id)
+        .with(tags::TARGET_COMP_ID, target_comp_id)
+        .with(tags::MSG_SEQ_NUM, seq_num)
+        .with(tags::SENDING_TIME, utc_timestamp(time));
+    if let Some(orig_sending_time) = orig_sending_time {
+        message.set(tags::POSS_DUP_FLAG, "Y");
+        message.set(tags::ORIG_SENDING_TIME, orig_sending_time);
+    }
+    let header_tags = [tags::MSG_TYPE, tags::SENDER_COMP_ID, tags::TARGET_COMP_ID, tags::MSG_SEQ_NUM, tags::SENDING_TIME, tags::POSS_DUP_FLAG, tags::ORIG_SENDING_TIME];
+    message.fields.extend(body.fields.iter().filter(|(tag, _)| !header_tags.contains(tag)).cloned());
+    message
+}
+
+/// `time` as a FIX `UTCTimestamp` with milliseconds
+pub fn utc_timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+/// The sum of `bytes` modulo 256
+fn check_sum(bytes: &[u8]) -> u32 {
+    bytes.iter().map(|byte| *byte as u32).sum::<u32>() % 256
+}
+
+fn invalid_header(buffer: &[u8]) -> FixError {
+    FixError::InvalidHeader(String::from_utf8_lossy(&buffer[..buffer.len().min(32)]).replace('\x01', "|"))
+}
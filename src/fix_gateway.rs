@@ -0,0 +1,815 @@
+//! A FIX 4.4 order entry gateway in front of `OrderBooks`. Each client logs on as a known
+//! `SenderCompID`, which the gateway maps to a user, and sends `NewOrderSingle`, `OrderCancelRequest`
+//! and `OrderCancelReplaceRequest` messages. These become `NewOrder` and `CancelOrder` messages, a
+//! replace being a cancel followed by a new order, and the execution reports they produce are sent
+//! back to the owner of each order as FIX `ExecutionReport` messages. Sessions keep their sequence
+//! numbers across reconnects, and answer resend requests from the messages they have sent.
+
+use std::{collections::{BTreeMap, HashMap}, io::{self, Read, Write}, net::{TcpListener, TcpStream, ToSocketAddrs}, sync::{Arc, Mutex}, thread, time};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{fix::{FixError, FixMessage, msg_types, tags, utc_timestamp, with_header}, order::{CancelOrder, InputMessage, NewOrder}, order_books::OrderBooks, order_result::{OrderResult, SequencedOrderResult}, order_status::{ExecType, OrderState}};
+
+/// How often a session exchanges heartbeats when the client's logon does not say
+const DEFAULT_HEARTBEAT_INTERVAL: i64 = 30;
+
+/// A message for the client logged on as `counterparty`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub counterparty: String,
+    pub message: FixMessage
+}
+
+/// A change requested to an order which is waiting for the order to be cancelled
+enum PendingChange {
+    /// An `OrderCancelRequest` with this `ClOrdID`
+    Cancel { cl_ord_id: String },
+    /// An `OrderCancelReplaceRequest` with this `ClOrdID`, placing `replacement` once the order is
+    /// cancelled
+    Replace { cl_ord_id: String, replacement: NewOrder }
+}
+
+/// An order entered through a session
+struct FixOrder {
+    cl_ord_id: String,
+    /// The `ClOrdID` of the order this one replaced
+    orig_cl_ord_id: Option<String>,
+    symbol: String,
+    /// 'B' or 'S'
+    side: char,
+    qty: u64,
+    /// `None` for a market order
+    price: Option<u64>,
+    pending: Option<PendingChange>
+}
+
+/// The state of one client's FIX session, kept between connections
+struct FixSession {
+    /// The gateway's `CompID`
+    comp_id: String,
+    /// The client's `CompID`
+    counterparty: String,
+    user: u64,
+    logged_on: bool,
+    heartbeat_interval: Duration,
+    next_incoming_seq: u64,
+    next_outgoing_seq: u64,
+    /// Whether a resend request for a gap in incoming sequence numbers is outstanding
+    awaiting_resend: bool,
+    /// Every message sent, by sequence number, without its header, with the time it was sent
+    sent: BTreeMap<u64, (FixMessage, DateTime<Utc>)>,
+    last_sent: DateTime<Utc>,
+    last_received: DateTime<Utc>,
+    /// When a test request was sent which has not yet been answered
+    test_request_sent: Option<DateTime<Utc>>,
+    /// Orders by user order id
+    orders: HashMap<u64, FixOrder>,
+    /// User order ids by `ClOrdID`
+    cl_ord_ids: HashMap<String, u64>,
+    next_user_order_id: u64,
+    next_exec_id: u64
+}
+
+impl FixSession {
+    fn new(comp_id: &str, counterparty: &str, user: u64) -> FixSession {
+        FixSession {
+            comp_id: comp_id.to_string(),
+            counterparty: counterparty.to_string(),
+            user,
+            logged_on: false,
+            heartbeat_interval: Duration::seconds(DEFAULT_HEARTBEAT_INTERVAL),
+            next_incoming_seq: 1,
+            next_outgoing_seq: 1,
+            awaiting_resend: false,
+            sent: BTreeMap::new(),
+            last_sent: Utc::now(),
+            last_received: Utc::now(),
+            test_request_sent: None,
+            orders: HashMap::new(),
+            cl_ord_ids: HashMap::new(),
+            next_user_order_id: 1,
+            next_exec_id: 1
+        }
+    }
+
+    /// Number `body` with the next outgoing sequence number and keep it for resending
+    fn send(&mut self, body: FixMessage, time: DateTime<Utc>) -> Delivery {
+        let seq_num = self.next_outgoing_seq;
+        self.next_outgoing_seq += 1;
+        self.last_sent = time;
+        let message = with_header(&body, &self.comp_id, &self.counterparty, seq_num, time, None);
+        self.sent.insert(seq_num, (body, time));
+        Delivery { counterparty: self.counterparty.clone(), message }
+    }
+
+    fn logout(&mut self, text: &str, time: DateTime<Utc>) -> Delivery {
+        self.logged_on = false;
+        self.send(FixMessage::new(msg_types::LOGOUT).with(tags::TEXT, text), time)
+    }
+
+    /// A session-level reject of `message` for `reason`, naming `tag` if one is at fault
+    fn reject(&mut self, message: &FixMessage, reason: u32, tag: Option<u32>, text: &str, time: DateTime<Utc>) -> Delivery {
+        let mut reject = FixMessage::new(msg_types::REJECT)
+            .with(tags::REF_SEQ_NUM, message.seq_num().unwrap_or_default());
+        if let Some(tag) = tag {
+            reject.set(tags::REF_TAG_ID, tag);
+        }
+        reject.set(tags::REF_MSG_TYPE, message.msg_type());
+        reject.set(tags::SESSION_REJECT_REASON, reason);
+        reject.set(tags::TEXT, text);
+        self.send(reject, time)
+    }
+
+    /// Resend every message numbered from `begin` to `end`, or to the last sent when `end` is zero.
+    /// Session-level messages are not resent, and each run of them is skipped by a gap fill.
+    fn resend(&mut self, begin: u64, end: u64, time: DateTime<Utc>) -> Vec<Delivery> {
+        let last = if end == 0 { self.next_outgoing_seq - 1 } else { end.min(self.next_outgoing_seq - 1) };
+        let mut deliveries = vec![];
+        let mut gap_start = None;
+        for seq_num in begin.max(1)..=last {
+            match self.sent.get(&seq_num) {
+                Some((body, sent)) if !msg_types::is_admin(body.msg_type()) => {
+                    if let Some(gap_start) = gap_start.take() {
+                        deliveries.push(self.gap_fill(gap_start, seq_num, time));
+                    }
+                    let message = with_header(body, &self.comp_id, &self.counterparty, seq_num, time, Some(&utc_timestamp(*sent)));
+                    deliveries.push(Delivery { counterparty: self.counterparty.clone(), message });
+                },
+                _ => {
+                    gap_start.get_or_insert(seq_num);
+                }
+            }
+        }
+        if let Some(gap_start) = gap_start {
+            deliveries.push(self.gap_fill(gap_start, last + 1, time));
+        }
+        self.last_sent = time;
+        deliveries
+    }
+
+    /// A gap fill numbered `seq_num`, telling the client the next message is `new_seq_no`
+    fn gap_fill(&self, seq_num: u64, new_seq_no: u64, time: DateTime<Utc>) -> Delivery {
+        let body = FixMessage::new(msg_types::SEQUENCE_RESET).with(tags::GAP_FILL_FLAG, "Y").with(tags::NEW_SEQ_NO, new_seq_no);
+        let message = with_header(&body, &self.comp_id, &self.counterparty, seq_num, time, Some(&utc_timestamp(time)));
+        Delivery { counterparty: self.counterparty.clone(), message }
+    }
+
+    /// An execution report of `order_result` for the order it names, unless it is the cancellation
+    /// of an order being replaced, which instead gives the replacement to place
+    fn execution_report(&mut self, order_result: &OrderResult, text: Option<String>, time: DateTime<Utc>) -> (Option<Delivery>, Option<NewOrder>) {
+        let (user_order_id, exec_type, state, last_qty, last_price, cum_qty, leaves_qty, average_price) = match order_result {
+            OrderResult::ExecutionReport { user_order_id, exec_type, state, last_qty, last_price, cum_qty, leaves_qty, average_price, .. } =>
+                (*user_order_id, *exec_type, *state, *last_qty, *last_price, *cum_qty, *leaves_qty, *average_price),
+            _ => return (None, None)
+        };
+        let order = match self.orders.get_mut(&user_order_id) {
+            Some(order) => order,
+            None => return (None, None)
+        };
+
+        let mut cl_ord_id = order.cl_ord_id.clone();
+        let mut orig_cl_ord_id = order.orig_cl_ord_id.clone();
+        let mut fix_exec_type = fix_exec_type(exec_type);
+        if orig_cl_ord_id.is_some() && exec_type == ExecType::New {
+            fix_exec_type = '5';
+        }
+        if exec_type == ExecType::Cancelled {
+            match order.pending.take() {
+                Some(PendingChange::Replace { replacement, .. }) => return (None, Some(replacement)),
+                Some(PendingChange::Cancel { cl_ord_id: cancel_cl_ord_id }) => {
+                    orig_cl_ord_id = Some(cl_ord_id);
+                    cl_ord_id = cancel_cl_ord_id;
+                },
+                None => ()
+            }
+        }
+
+        let mut report = FixMessage::new(msg_types::EXECUTION_REPORT)
+            .with(tags::ORDER_ID, format!("{}-{}", self.user, user_order_id))
+            .with(tags::CL_ORD_ID, cl_ord_id);
+        if let Some(orig_cl_ord_id) = orig_cl_ord_id {
+            report.set(tags::ORIG_CL_ORD_ID, orig_cl_ord_id);
+        }
+        report.set(tags::EXEC_ID, self.next_exec_id);
+        self.next_exec_id += 1;
+        report.set(tags::EXEC_TYPE, fix_exec_type);
+        report.set(tags::ORD_STATUS, fix_ord_status(state));
+        report.set(tags::SYMBOL, &order.symbol);
+        report.set(tags::SIDE, if order.side == 'B' { '1' } else { '2' });
+        report.set(tags::ORDER_QTY, order.qty);
+        if let Some(price) = order.price {
+            report.set(tags::PRICE, price);
+        }
+        report.set(tags::LAST_QTY, last_qty);
+        if let Some(last_price) = last_price {
+            report.set(tags::LAST_PX, last_price);
+        }
+        report.set(tags::LEAVES_QTY, leaves_qty);
+        report.set(tags::CUM_QTY, cum_qty);
+        report.set(tags::AVG_PX, if cum_qty == 0 { "0".to_string() } else { format!("{:.2}", average_price) });
+        report.set(tags::TRANSACT_TIME, utc_timestamp(time));
+        if let Some(text) = text {
+            report.set(tags::TEXT, text);
+        }
+        (Some(self.send(report, time)), None)
+    }
+
+    /// Reject a new order or replacement with `ClOrdID` `cl_ord_id` which never reached the book
+    fn reject_order(&mut self, message: &FixMessage, text: &str, time: DateTime<Utc>) -> Delivery {
+        let mut report = FixMessage::new(msg_types::EXECUTION_REPORT)
+            .with(tags::ORDER_ID, "NONE")
+            .with(tags::CL_ORD_ID, message.get(tags::CL_ORD_ID).unwrap_or_default());
+        if let Some(orig_cl_ord_id) = message.get(tags::ORIG_CL_ORD_ID) {
+            report.set(tags::ORIG_CL_ORD_ID, orig_cl_ord_id);
+        }
+        report.set(tags::EXEC_ID, self.next_exec_id);
+        self.next_exec_id += 1;
+        for (tag, value) in [(tags::EXEC_TYPE, "8"), (tags::ORD_STATUS, "8")] {
+            report.set(tag, value);
+        }
+        for tag in [tags::SYMBOL, tags::SIDE, tags::ORDER_QTY] {
+            report.set(tag, message.get(tag).unwrap_or_default());
+        }
+        for tag in [tags::LEAVES_QTY, tags::CUM_QTY, tags::AVG_PX] {
+            report.set(tag, 0);
+        }
+        report.set(tags::TEXT, text);
+        self.send(report, time)
+    }
+
+    /// Reject a cancel, or a replace when `is_replace` is set, for `reason`: 0 when the order is
+    /// too late to cancel and 1 when it is unknown
+    fn reject_cancel(&mut self, message: &FixMessage, ord_status: char, is_replace: bool, reason: u32, text: &str, time: DateTime<Utc>) -> Delivery {
+        let user_order_id = message.get(tags::ORIG_CL_ORD_ID).and_then(|orig_cl_ord_id| self.cl_ord_ids.get(orig_cl_ord_id));
+        let reject = FixMessage::new(msg_types::ORDER_CANCEL_REJECT)
+            .with(tags::ORDER_ID, user_order_id.map_or("NONE".to_string(), |user_order_id| format!("{}-{}", self.user, user_order_id)))
+            .with(tags::CL_ORD_ID, message.get(tags::CL_ORD_ID).unwrap_or_default())
+            .with(tags::ORIG_CL_ORD_ID, message.get(tags::ORIG_CL_ORD_ID).unwrap_or_default())
+            .with(tags::ORD_STATUS, ord_status)
+            .with(tags::CXL_REJ_RESPONSE_TO, if is_replace { 2 } else { 1 })
+            .with(tags::CXL_REJ_REASON, reason)
+            .with(tags::TEXT, text);
+        self.send(reject, time)
+    }
+}
+
+/// The FIX `ExecType` of `exec_type`
+fn fix_exec_type(exec_type: ExecType) -> char {
+    match exec_type {
+        ExecType::New => '0',
+        ExecType::Trade => 'F',
+        ExecType::Cancelled => '4',
+        ExecType::Rejected => '8',
+        ExecType::Expired => 'C',
+        ExecType::Restated => 'D'
+    }
+}
+
+/// The FIX `OrdStatus` of `state`
+fn fix_ord_status(state: OrderState) -> char {
+    match state {
+        OrderState::New => '0',
+        OrderState::PartiallyFilled => '1',
+        OrderState::Filled => '2',
+        OrderState::Cancelled => '4',
+        OrderState::Rejected => '8',
+        OrderState::Expired => 'C'
+    }
+}
+
+/// Why an order message could not be read, as a session reject reason and the tag at fault
+struct InvalidOrder {
+    reason: u32,
+    tag: u32,
+    text: String
+}
+
+fn required(message: &FixMessage, tag: u32) -> Result<&str, InvalidOrder> {
+    message.get(tag).filter(|value| !value.is_empty()).ok_or(InvalidOrder { reason: 1, tag, text: format!("Required tag missing: {}", tag) })
+}
+
+fn incorrect(tag: u32, value: &str) -> InvalidOrder {
+    InvalidOrder { reason: 5, tag, text: format!("Value is incorrect for tag {}: \"{}\"", tag, value) }
+}
+
+/// Read the symbol, side, quantity and limit price of a `NewOrderSingle` or
+/// `OrderCancelReplaceRequest`, where the price is in whole ticks and `None` for a market order
+fn parse_order(message: &FixMessage) -> Result<(String, char, u64, Option<u64>), InvalidOrder> {
+    required(message, tags::CL_ORD_ID)?;
+    let symbol = required(message, tags::SYMBOL)?.to_string();
+    let side = match required(message, tags::SIDE)? {
+        "1" => 'B',
+        "2" => 'S',
+        side => return Err(incorrect(tags::SIDE, side))
+    };
+    let qty = required(message, tags::ORDER_QTY)?;
+    let qty = qty.parse::<u64>().ok().filter(|qty| *qty > 0).ok_or_else(|| incorrect(tags::ORDER_QTY, qty))?;
+    let price = match required(message, tags::ORD_TYPE)? {
+        "1" => None,
+        "2" => {
+            let price = required(message, tags::PRICE)?;
+            Some(price.parse::<u64>().ok().filter(|price| *price > 0).ok_or_else(|| incorrect(tags::PRICE, price))?)
+        },
+        ord_type => return Err(incorrect(tags::ORD_TYPE, ord_type))
+    };
+    Ok((symbol, side, qty, price))
+}
+
+/// Turns FIX sessions into messages for `OrderBooks` and their results into FIX messages. It does no
+/// I/O itself: `serve` feeds it the messages read from each connection and writes out what it
+/// returns.
+pub struct FixGateway {
+    comp_id: String,
+    order_books: OrderBooks,
+    /// Sessions keyed by the client's `CompID`
+    sessions: HashMap<String, FixSession>,
+    /// The client `CompID` of each user with a session
+    counterparties: HashMap<u64, String>
+}
+
+impl FixGateway {
+    /// A gateway known to clients as `comp_id`, which turns on execution reports in `order_books`
+    pub fn new(comp_id: &str, mut order_books: OrderBooks) -> FixGateway {
+        order_books.set_execution_reports(true);
+        FixGateway { comp_id: comp_id.to_string(), order_books, sessions: HashMap::new(), counterparties: HashMap::new() }
+    }
+
+    /// Allow a client to log on as `counterparty`, entering orders as `user`
+    pub fn with_session(mut self, counterparty: &str, user: u64) -> FixGateway {
+        self.sessions.insert(counterparty.to_string(), FixSession::new(&self.comp_id, counterparty, user));
+        self.counterparties.insert(user, counterparty.to_string());
+        self
+    }
+
+    pub fn comp_id(&self) -> &str {
+        &self.comp_id
+    }
+
+    pub fn order_books(&self) -> &OrderBooks {
+        &self.order_books
+    }
+
+    pub fn is_logged_on(&self, counterparty: &str) -> bool {
+        self.sessions.get(counterparty).map_or(false, |session| session.logged_on)
+    }
+
+    /// The sequence numbers the session with `counterparty` expects to receive and send next
+    pub fn next_seq_nums(&self, counterparty: &str) -> Option<(u64, u64)> {
+        self.sessions.get(counterparty).map(|session| (session.next_incoming_seq, session.next_outgoing_seq))
+    }
+
+    /// Handle `message` from a client, received at `time`, returning the messages to send. A client
+    /// which is not logged on once this returns should be disconnected.
+    pub fn receive(&mut self, message: FixMessage, time: DateTime<Utc>) -> Vec<Delivery> {
+        let counterparty = message.get(tags::SENDER_COMP_ID).unwrap_or_default().to_string();
+        let session = match self.sessions.get_mut(&counterparty) {
+            Some(session) if message.get(tags::TARGET_COMP_ID) == Some(self.comp_id.as_str()) => session,
+            _ => {
+                let logout = FixMessage::new(msg_types::LOGOUT).with(tags::TEXT, format!("Unknown session: \"{}\" to \"{}\"", counterparty, message.get(tags::TARGET_COMP_ID).unwrap_or_default()));
+                return vec![Delivery { counterparty: counterparty.clone(), message: with_header(&logout, &self.comp_id, &counterparty, 1, time, None) }];
+            }
+        };
+        session.last_received = time;
+        session.test_request_sent = None;
+        let msg_type = message.msg_type().to_string();
+
+        if !session.logged_on {
+            if msg_type != msg_types::LOGON {
+                return vec![session.logout("First message must be Logon", time)];
+            }
+            if message.get(tags::RESET_SEQ_NUM_FLAG) == Some("Y") {
+                session.next_incoming_seq = 1;
+                session.next_outgoing_seq = 1;
+                session.sent.clear();
+            }
+        }
+
+        let seq_num = match message.seq_num() {
+            Some(seq_num) => seq_num,
+            None => return vec![session.logout("MsgSeqNum missing", time)]
+        };
+        if msg_type == msg_types::SEQUENCE_RESET && message.get(tags::GAP_FILL_FLAG) != Some("Y") {
+            return match message.get(tags::NEW_SEQ_NO).and_then(|new_seq_no| new_seq_no.parse::<u64>().ok()) {
+                Some(new_seq_no) if new_seq_no >= session.next_incoming_seq => {
+                    session.next_incoming_seq = new_seq_no;
+                    session.awaiting_resend = false;
+                    vec![]
+                },
+                _ => vec![session.reject(&message, 5, Some(tags::NEW_SEQ_NO), "NewSeqNo must not go back", time)]
+            };
+        }
+        if seq_num < session.next_incoming_seq {
+            if message.get(tags::POSS_DUP_FLAG) == Some("Y") {
+                return vec![];
+            }
+            let text = format!("MsgSeqNum too low, expecting {} but received {}", session.next_incoming_seq, seq_num);
+            return vec![session.logout(&text, time)];
+        }
+
+        let mut deliveries = vec![];
+        if seq_num > session.next_incoming_seq {
+            // Answer a logon or resend request at once, but leave every other message to be resent
+            // in order after the gap
+            if msg_type == msg_types::LOGON {
+                deliveries.append(&mut self.logon(&counterparty, &message, time));
+            } else if msg_type == msg_types::RESEND_REQUEST {
+                deliveries.append(&mut self.resend_request(&counterparty, &message, time));
+            }
+            let session = self.sessions.get_mut(&counterparty).unwrap();
+            if !session.awaiting_resend {
+                session.awaiting_resend = true;
+                let resend_request = FixMessage::new(msg_types::RESEND_REQUEST)
+                    .with(tags::BEGIN_SEQ_NO, session.next_incoming_seq)
+                    .with(tags::END_SEQ_NO, 0);
+                deliveries.push(session.send(resend_request, time));
+            }
+            return deliveries;
+        }
+
+        session.next_incoming_seq += 1;
+        session.awaiting_resend = false;
+        match msg_type.as_str() {
+            msg_types::LOGON => deliveries.append(&mut self.logon(&counterparty, &message, time)),
+            msg_types::HEARTBEAT | msg_types::REJECT => (),
+            msg_types::TEST_REQUEST => {
+                let mut heartbeat = FixMessage::new(msg_types::HEARTBEAT);
+                if let Some(test_req_id) = message.get(tags::TEST_REQ_ID) {
+                    heartbeat.set(tags::TEST_REQ_ID, test_req_id);
+                }
+                deliveries.push(session.send(heartbeat, time));
+            },
+            msg_types::RESEND_REQUEST => deliveries.append(&mut self.resend_request(&counterparty, &message, time)),
+            msg_types::SEQUENCE_RESET => {
+                if let Some(new_seq_no) = message.get(tags::NEW_SEQ_NO).and_then(|new_seq_no| new_seq_no.parse::<u64>().ok()) {
+                    session.next_incoming_seq = session.next_incoming_seq.max(new_seq_no);
+                }
+            },
+            msg_types::LOGOUT => {
+                session.logged_on = false;
+                deliveries.push(session.send(FixMessage::new(msg_types::LOGOUT), time));
+            },
+            msg_types::NEW_ORDER_SINGLE => deliveries.append(&mut self.new_order_single(&counterparty, &message, time)),
+            msg_types::ORDER_CANCEL_REQUEST => deliveries.append(&mut self.cancel_request(&counterparty, &message, false, time)),
+            msg_types::ORDER_CANCEL_REPLACE_REQUEST => deliveries.append(&mut self.cancel_request(&counterparty, &message, true, time)),
+            _ => deliveries.push(session.reject(&message, 11, Some(tags::MSG_TYPE), &format!("Unsupported MsgType: \"{}\"", msg_type), time))
+        }
+        deliveries
+    }
+
+    /// Check the heartbeats of the session with `counterparty` at `time`: a heartbeat is sent after
+    /// an interval without sending, a test request after an interval without receiving, and a
+    /// logout when that goes unanswered for another interval
+    pub fn tick(&mut self, counterparty: &str, time: DateTime<Utc>) -> Vec<Delivery> {
+        let session = match self.sessions.get_mut(counterparty) {
+            Some(session) if session.logged_on && session.heartbeat_interval > Duration::zero() => session,
+            _ => return vec![]
+        };
+        let interval = session.heartbeat_interval;
+        let mut deliveries = vec![];
+        match session.test_request_sent {
+            Some(sent) if time - sent >= interval => return vec![session.logout("Test request not answered", time)],
+            None if time - session.last_received >= interval + interval / 5 => {
+                session.test_request_sent = Some(time);
+                deliveries.push(session.send(FixMessage::new(msg_types::TEST_REQUEST).with(tags::TEST_REQ_ID, utc_timestamp(time)), time));
+            },
+            _ => ()
+        }
+        if time - session.last_sent >= interval {
+            deliveries.push(session.send(FixMessage::new(msg_types::HEARTBEAT), time));
+        }
+        deliveries
+    }
+
+    /// Note that the connection of `counterparty` has closed, ending its session until it logs on
+    /// again
+    pub fn disconnect(&mut self, counterparty: &str) {
+        if let Some(session) = self.sessions.get_mut(counterparty) {
+            session.logged_on = false;
+        }
+    }
+
+    fn logon(&mut self, counterparty: &str, message: &FixMessage, time: DateTime<Utc>) -> Vec<Delivery> {
+        let session = self.sessions.get_mut(counterparty).unwrap();
+        let heartbeat_interval = match message.get(tags::HEART_BT_INT).map(|seconds| seconds.parse::<i64>()) {
+            Some(Ok(seconds)) if seconds >= 0 => seconds,
+            None => DEFAULT_HEARTBEAT_INTERVAL,
+            _ => return vec![session.logout("HeartBtInt must be a number of seconds", time)]
+        };
+        session.heartbeat_interval = Duration::seconds(heartbeat_interval);
+        session.logged_on = true;
+        let mut logon = FixMessage::new(msg_types::LOGON)
+            .with(tags::ENCRYPT_METHOD, 0)
+            .with(tags::HEART_BT_INT, heartbeat_interval);
+        if message.get(tags::RESET_SEQ_NUM_FLAG) == Some("Y") {
+            logon.set(tags::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        vec![session.send(logon, time)]
+    }
+
+    fn resend_request(&mut self, counterparty: &str, message: &FixMessage, time: DateTime<Utc>) -> Vec<Delivery> {
+        let session = self.sessions.get_mut(counterparty).unwrap();
+        let begin = message.get(tags::BEGIN_SEQ_NO).and_then(|begin| begin.parse::<u64>().ok());
+        let end = message.get(tags::END_SEQ_NO).and_then(|end| end.parse::<u64>().ok());
+        match (begin, end) {
+            (Some(begin), Some(end)) => session.resend(begin, end, time),
+            (None, _) => vec![session.reject(message, 1, Some(tags::BEGIN_SEQ_NO), "Required tag missing: 7", time)],
+            (_, None) => vec![session.reject(message, 1, Some(tags::END_SEQ_NO), "Required tag missing: 16", time)]
+        }
+    }
+
+    fn new_order_single(&mut self, counterparty: &str, message: &FixMessage, time: DateTime<Utc>) -> Vec<Delivery> {
+        let session = self.sessions.get_mut(counterparty).unwrap();
+        let (symbol, side, qty, price) = match parse_order(message) {
+            Ok(order) => order,
+            Err(invalid) => return vec![session.reject(message, invalid.reason, Some(invalid.tag), &invalid.text, time)]
+        };
+        let cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or_default();
+        if session.cl_ord_ids.contains_key(cl_ord_id) {
+            return vec![session.reject_order(message, &format!("Duplicate ClOrdID: \"{}\"", cl_ord_id), time)];
+        }
+        let new_order = session.place(cl_ord_id, None, symbol, side, qty, price);
+        let order_results = self.order_books.process(InputMessage::New(new_order));
+        self.route(order_results, time)
+    }
+
+    /// Cancel the order named by an `OrderCancelRequest`, or by an `OrderCancelReplaceRequest` when
+    /// `is_replace` is set, placing its replacement once it is cancelled
+    fn cancel_request(&mut self, counterparty: &str, message: &FixMessage, is_replace: bool, time: DateTime<Utc>) -> Vec<Delivery> {
+        let session = self.sessions.get_mut(counterparty).unwrap();
+        let replacement = if is_replace { Some(parse_order(message)) } else { None };
+        let checked = [tags::ORIG_CL_ORD_ID, tags::CL_ORD_ID, tags::SYMBOL, tags::SIDE].iter()
+            .try_for_each(|tag| required(message, *tag).map(|_| ()))
+            .and(replacement.transpose());
+        let replacement = match checked {
+            Ok(replacement) => replacement,
+            Err(invalid) => return vec![session.reject(message, invalid.reason, Some(invalid.tag), &invalid.text, time)]
+        };
+        let cl_ord_id = message.get(tags::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig_cl_ord_id = message.get(tags::ORIG_CL_ORD_ID).unwrap_or_default().to_string();
+        if session.cl_ord_ids.contains_key(&cl_ord_id) {
+            return vec![session.reject_cancel(message, '8', is_replace, 99, &format!("Duplicate ClOrdID: \"{}\"", cl_ord_id), time)];
+        }
+        let user_order_id = match session.cl_ord_ids.get(&orig_cl_ord_id) {
+            Some(user_order_id) => *user_order_id,
+            None => return vec![session.reject_cancel(message, '8', is_replace, 1, "Unknown order", time)]
+        };
+        let status = self.order_books.order_status(session.user, user_order_id).map(|status| status.state);
+        if status.map_or(true, |state| state.is_terminal()) {
+            let ord_status = status.map_or('8', fix_ord_status);
+            return vec![session.reject_cancel(message, ord_status, is_replace, 0, "Too late to cancel", time)];
+        }
+        let order = &session.orders[&user_order_id];
+        if let Some((symbol, side, _, _)) = &replacement {
+            if *symbol != order.symbol || *side != order.side {
+                return vec![session.reject_cancel(message, fix_ord_status(status.unwrap()), is_replace, 99, "Symbol and side cannot be replaced", time)];
+            }
+        }
+
+        let pending = match replacement {
+            Some((symbol, side, qty, price)) => {
+                let replacement = session.place(&cl_ord_id, Some(orig_cl_ord_id), symbol, side, qty, price);
+                PendingChange::Replace { cl_ord_id, replacement }
+            },
+            None => PendingChange::Cancel { cl_ord_id }
+        };
+        session.orders.get_mut(&user_order_id).unwrap().pending = Some(pending);
+        let user = session.user;
+        let order_results = self.order_books.process(InputMessage::Cancel(CancelOrder::new(user, user_order_id)));
+        let mut deliveries = self.route(order_results, time);
+
+        let session = self.sessions.get_mut(counterparty).unwrap();
+        if let Some(pending) = session.orders.get_mut(&user_order_id).and_then(|order| order.pending.take()) {
+            if let PendingChange::Replace { cl_ord_id, replacement } = pending {
+                session.orders.remove(&replacement.user_order_id);
+                session.cl_ord_ids.remove(&cl_ord_id);
+            }
+            let ord_status = self.order_books.order_status(user, user_order_id).map_or('8', |status| fix_ord_status(status.state));
+            deliveries.push(session.reject_cancel(message, ord_status, is_replace, 0, "Order could not be cancelled", time));
+        }
+        deliveries
+    }
+
+    /// Turn the execution reports among `order_results` into FIX messages for the owners of their
+    /// orders, placing the replacement of any order whose cancellation completes a replace
+    fn route(&mut self, order_results: Vec<SequencedOrderResult>, time: DateTime<Utc>) -> Vec<Delivery> {
+        let mut deliveries = vec![];
+        let mut reasons = HashMap::new();
+        let mut replacements = vec![];
+        for sequenced_order_result in order_results {
+            match &sequenced_order_result.order_result {
+                OrderResult::RiskRejection { user, user_order_id, reason } => {
+                    reasons.insert((*user, *user_order_id), format!("Risk limit breached: {}", reason));
+                },
+                OrderResult::ExecutionReport { user, user_order_id, .. } => {
+                    let sessions = &mut self.sessions;
+                    let session = match self.counterparties.get(user).and_then(|counterparty| sessions.get_mut(counterparty)) {
+                        Some(session) => session,
+                        None => continue
+                    };
+                    let text = reasons.remove(&(*user, *user_order_id));
+                    let (delivery, replacement) = session.execution_report(&sequenced_order_result.order_result, text, time);
+                    deliveries.extend(delivery);
+                    replacements.extend(replacement);
+                },
+                _ => ()
+            }
+        }
+        for replacement in replacements {
+            let order_results = self.order_books.process(InputMessage::New(replacement));
+            deliveries.append(&mut self.route(order_results, time));
+        }
+        deliveries
+    }
+}
+
+impl FixSession {
+    /// Record a new order with `cl_ord_id`, replacing the order with `orig_cl_ord_id` if set, and
+    /// return the `NewOrder` placing it
+    fn place(&mut self, cl_ord_id: &str, orig_cl_ord_id: Option<String>, symbol: String, side: char, qty: u64, price: Option<u64>) -> NewOrder {
+        let user_order_id = self.next_user_order_id;
+        self.next_user_order_id += 1;
+        self.cl_ord_ids.insert(cl_ord_id.to_string(), user_order_id);
+        self.orders.insert(user_order_id, FixOrder { cl_ord_id: cl_ord_id.to_string(), orig_cl_ord_id, symbol: symbol.clone(), side, qty, price, pending: None });
+        match price {
+            Some(price) => NewOrder::new(self.user, symbol, price, qty, side, user_order_id),
+            None => NewOrder::new_market(self.user, symbol, qty, side, user_order_id)
+        }
+    }
+}
+
+/// A gateway shared by every connection, with the connection of each client logged on
+struct Server {
+    gateway: FixGateway,
+    connections: HashMap<String, TcpStream>
+}
+
+impl Server {
+    /// Write each delivery to its client's connection, if it has one
+    fn deliver(&mut self, deliveries: Vec<Delivery>) {
+        for delivery in deliveries {
+            if let Some(stream) = self.connections.get_mut(&delivery.counterparty) {
+                if stream.write_all(&delivery.message.encode()).is_err() {
+                    self.connections.remove(&delivery.counterparty);
+                }
+            }
+        }
+    }
+}
+
+/// Accept FIX connections on `listener` for `gateway`, each handled by its own thread, until the
+/// listener fails
+pub fn serve(listener: TcpListener, gateway: FixGateway) -> io::Result<()> {
+    let server = Arc::new(Mutex::new(Server { gateway, connections: HashMap::new() }));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = server.clone();
+        thread::Builder::new().name("fix_connection".to_string()).spawn(move || {
+            let _ = handle_connection(stream, server);
+        })?;
+    }
+    Ok(())
+}
+
+/// Read messages from one client until it disconnects, is logged out or fails, then close its
+/// session. Its first message names the session, which must not already be connected.
+fn handle_connection(stream: TcpStream, server: Arc<Mutex<Server>>) -> io::Result<()> {
+    let mut counterparty: Option<String> = None;
+    let result = read_messages(stream, &server, &mut counterparty);
+    if let Some(counterparty) = counterparty {
+        close(&mut server.lock().unwrap(), &counterparty);
+    }
+    result
+}
+
+/// Read messages from one client into the gateway until it disconnects or is logged out, naming
+/// `counterparty` once its session is connected
+fn read_messages(mut stream: TcpStream, server: &Mutex<Server>, counterparty: &mut Option<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(time::Duration::from_millis(500)))?;
+    let mut buffer = vec![];
+    let mut read_buffer = [0; 4096];
+    loop {
+        let read = match stream.read(&mut read_buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                if let Some(counterparty) = counterparty.as_deref() {
+                    let mut server = server.lock().unwrap();
+                    let deliveries = server.gateway.tick(counterparty, Utc::now());
+                    server.deliver(deliveries);
+                    if !server.gateway.is_logged_on(counterparty) {
+                        break;
+                    }
+                }
+                continue;
+            },
+            Err(error) => return Err(error)
+        };
+        buffer.extend_from_slice(&read_buffer[..read]);
+
+        loop {
+            let message = match FixMessage::decode(&buffer) {
+                Ok(Some((message, consumed))) => {
+                    buffer.drain(..consumed);
+                    message
+                },
+                Ok(None) => break,
+                Err(error) => {
+                    // Garbled messages are ignored, skipping to the next which looks like a message
+                    eprintln!("{}", error);
+                    skip_garbled(&mut buffer);
+                    continue;
+                }
+            };
+            let mut server = server.lock().unwrap();
+            if counterparty.is_none() {
+                let sender_comp_id = message.get(tags::SENDER_COMP_ID).unwrap_or_default().to_string();
+                if server.connections.contains_key(&sender_comp_id) {
+                    return Ok(());
+                }
+                server.connections.insert(sender_comp_id.clone(), stream.try_clone()?);
+                *counterparty = Some(sender_comp_id);
+            }
+            let deliveries = server.gateway.receive(message, Utc::now());
+            server.deliver(deliveries);
+            if !server.gateway.is_logged_on(counterparty.as_deref().unwrap()) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn close(server: &mut Server, counterparty: &str) {
+    server.gateway.disconnect(counterparty);
+    server.connections.remove(counterparty);
+}
+
+/// Drop bytes from the front of `buffer` up to the next `BeginString`, or all of them if there is
+/// none
+fn skip_garbled(buffer: &mut Vec<u8>) {
+    let begin_string = b"8=FIX.4.4\x01";
+    let next = buffer.windows(begin_string.len()).skip(1).position(|window| window == begin_string).map(|position| position + 1);
+    buffer.drain(..next.unwrap_or(buffer.len()));
+}
+
+/// A FIX client, which numbers and stamps the messages it sends, as used to script a session
+/// against the gateway
+pub struct FixClient {
+    stream: TcpStream,
+    comp_id: String,
+    target_comp_id: String,
+    next_seq: u64,
+    buffer: Vec<u8>
+}
+
+impl FixClient {
+    /// Connect to the gateway at `address` as `comp_id`, where the gateway is `target_comp_id`
+    pub fn connect<A: ToSocketAddrs>(address: A, comp_id: &str, target_comp_id: &str) -> io::Result<FixClient> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(FixClient { stream, comp_id: comp_id.to_string(), target_comp_id: target_comp_id.to_string(), next_seq: 1, buffer: vec![] })
+    }
+
+    /// Send `body` behind a header numbered with the next sequence number, or with its own
+    /// `MsgSeqNum` if it has one, returning the message sent
+    pub fn send(&mut self, body: &FixMessage) -> io::Result<FixMessage> {
+        let seq_num = body.seq_num().unwrap_or(self.next_seq);
+        self.next_seq = self.next_seq.max(seq_num + 1);
+        let orig_sending_time = body.get(tags::ORIG_SENDING_TIME).map(|orig_sending_time| orig_sending_time.to_string())
+            .or_else(|| (body.get(tags::POSS_DUP_FLAG) == Some("Y")).then(|| utc_timestamp(Utc::now())));
+        let message = with_header(body, &self.comp_id, &self.target_comp_id, seq_num, Utc::now(), orig_sending_time.as_deref());
+        self.stream.write_all(&message.encode())?;
+        Ok(message)
+    }
+
+    /// Read the next message, waiting at most `timeout` for it, or `None` if none arrives or the
+    /// gateway disconnects
+    pub fn receive(&mut self, timeout: time::Duration) -> Result<Option<FixMessage>, FixError> {
+        let deadline = time::Instant::now() + timeout;
+        let mut read_buffer = [0; 4096];
+        loop {
+            if let Some((message, consumed)) = FixMessage::decode(&self.buffer)? {
+                self.buffer.drain(..consumed);
+                return Ok(Some(message));
+            }
+            let now = time::Instant::now();
+            if now >= deadline || self.stream.set_read_timeout(Some(deadline - now)).is_err() {
+                return Ok(None);
+            }
+            match self.stream.read(&mut read_buffer) {
+                Ok(0) | Err(_) => return Ok(None),
+                Ok(read) => self.buffer.extend_from_slice(&read_buffer[..read])
+            }
+        }
+    }
+
+    /// Read messages until none arrives for `timeout`
+    pub fn receive_all(&mut self, timeout: time::Duration) -> Result<Vec<FixMessage>, FixError> {
+        let mut messages = vec![];
+        while let Some(message) = self.receive(timeout)? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
@@ -0,0 +1,189 @@
+//! Unit tests for the FIX order entry gateway, driving sessions directly and over localhost
+
+#[cfg(test)]
+mod fix_gateway_tests {
+    use std::{net::TcpListener, thread, time};
+
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::fix::*;
+    use crate::fix_gateway::*;
+    use crate::order_books::OrderBooks;
+
+    fn start() -> DateTime<Utc> {
+        "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn gateway() -> FixGateway {
+        FixGateway::new("ORDERBOOK", OrderBooks::new(true)).with_session("CLIENT1", 1).with_session("CLIENT2", 2)
+    }
+
+    /// `body` from `counterparty` numbered `seq_num`
+    fn from(counterparty: &str, seq_num: u64, body: FixMessage) -> FixMessage {
+        with_header(&body, counterparty, "ORDERBOOK", seq_num, start(), None)
+    }
+
+    fn logon(gateway: &mut FixGateway, counterparty: &str) -> Vec<Delivery> {
+        gateway.receive(from(counterparty, 1, FixMessage::new(msg_types::LOGON).with(tags::ENCRYPT_METHOD, 0).with(tags::HEART_BT_INT, 30)), start())
+    }
+
+    fn new_order_single(cl_ord_id: &str, side: &str, qty: u64, price: u64) -> FixMessage {
+        FixMessage::new(msg_types::NEW_ORDER_SINGLE)
+            .with(tags::CL_ORD_ID, cl_ord_id)
+            .with(tags::SYMBOL, "IBM")
+            .with(tags::SIDE, side)
+            .with(tags::ORDER_QTY, qty)
+            .with(tags::ORD_TYPE, 2)
+            .with(tags::PRICE, price)
+    }
+
+    /// The `MsgType` and each of `fields` of every delivery
+    fn summary(deliveries: &[Delivery], fields: &[u32]) -> Vec<String> {
+        deliveries.iter().map(|delivery| {
+            let mut values = vec![delivery.counterparty.clone(), delivery.message.msg_type().to_string()];
+            values.extend(fields.iter().map(|tag| delivery.message.get(*tag).unwrap_or("-").to_string()));
+            values.join(" ")
+        }).collect()
+    }
+
+    #[test]
+    fn logon_and_heartbeats() {
+        let mut gateway = gateway();
+        let deliveries = logon(&mut gateway, "CLIENT1");
+        assert_eq!(summary(&deliveries, &[tags::MSG_SEQ_NUM, tags::HEART_BT_INT]), vec!["CLIENT1 A 1 30"]);
+        assert!(gateway.is_logged_on("CLIENT1"));
+
+        let deliveries = gateway.receive(from("CLIENT1", 2, FixMessage::new(msg_types::TEST_REQUEST).with(tags::TEST_REQ_ID, "ping")), start());
+        assert_eq!(summary(&deliveries, &[tags::TEST_REQ_ID]), vec!["CLIENT1 0 ping"]);
+
+        assert!(gateway.tick("CLIENT1", start() + Duration::seconds(10)).is_empty());
+        assert_eq!(summary(&gateway.tick("CLIENT1", start() + Duration::seconds(30)), &[]), vec!["CLIENT1 0"]);
+        assert_eq!(summary(&gateway.tick("CLIENT1", start() + Duration::seconds(36)), &[]), vec!["CLIENT1 1"]);
+        assert_eq!(summary(&gateway.tick("CLIENT1", start() + Duration::seconds(66)), &[]), vec!["CLIENT1 5"]);
+        assert!(!gateway.is_logged_on("CLIENT1"));
+    }
+
+    #[test]
+    fn logon_refused() {
+        let mut gateway = gateway();
+        let deliveries = gateway.receive(from("CLIENT9", 1, FixMessage::new(msg_types::LOGON)), start());
+        assert_eq!(summary(&deliveries, &[]), vec!["CLIENT9 5"]);
+
+        let deliveries = gateway.receive(from("CLIENT1", 1, FixMessage::new(msg_types::HEARTBEAT)), start());
+        assert_eq!(summary(&deliveries, &[tags::TEXT]), vec!["CLIENT1 5 First message must be Logon"]);
+        assert!(!gateway.is_logged_on("CLIENT1"));
+    }
+
+    #[test]
+    fn orders_trade_and_report_to_both_sides() {
+        let mut gateway = gateway();
+        logon(&mut gateway, "CLIENT1");
+        logon(&mut gateway, "CLIENT2");
+
+        let deliveries = gateway.receive(from("CLIENT1", 2, new_order_single("buy-1", "1", 100, 10)), start());
+        assert_eq!(summary(&deliveries, &[tags::CL_ORD_ID, tags::ORDER_ID, tags::EXEC_TYPE, tags::ORD_STATUS, tags::LEAVES_QTY]), vec!["CLIENT1 8 buy-1 1-1 0 0 100"]);
+
+        let deliveries = gateway.receive(from("CLIENT2", 2, new_order_single("sell-1", "2", 100, 10)), start());
+        assert_eq!(summary(&deliveries, &[tags::CL_ORD_ID, tags::EXEC_TYPE, tags::ORD_STATUS, tags::LAST_QTY, tags::LAST_PX, tags::CUM_QTY, tags::AVG_PX]), vec![
+            "CLIENT2 8 sell-1 0 0 0 - 0 0",
+            "CLIENT1 8 buy-1 F 2 100 10 100 10.00",
+            "CLIENT2 8 sell-1 F 2 100 10 100 10.00"
+        ]);
+    }
+
+    #[test]
+    fn cancel_and_replace() {
+        let mut gateway = gateway();
+        logon(&mut gateway, "CLIENT1");
+        gateway.receive(from("CLIENT1", 2, new_order_single("1", "1", 100, 10)), start());
+
+        let replace = FixMessage::new(msg_types::ORDER_CANCEL_REPLACE_REQUEST)
+            .with(tags::ORIG_CL_ORD_ID, "1")
+            .with(tags::CL_ORD_ID, "2")
+            .with(tags::SYMBOL, "IBM")
+            .with(tags::SIDE, 1)
+            .with(tags::ORDER_QTY, 50)
+            .with(tags::ORD_TYPE, 2)
+            .with(tags::PRICE, 11);
+        let deliveries = gateway.receive(from("CLIENT1", 3, replace), start());
+        assert_eq!(summary(&deliveries, &[tags::CL_ORD_ID, tags::ORIG_CL_ORD_ID, tags::EXEC_TYPE, tags::ORDER_QTY, tags::PRICE]), vec!["CLIENT1 8 2 1 5 50 11"]);
+
+        let cancel = FixMessage::new(msg_types::ORDER_CANCEL_REQUEST).with(tags::ORIG_CL_ORD_ID, "2").with(tags::CL_ORD_ID, "3").with(tags::SYMBOL, "IBM").with(tags::SIDE, 1);
+        let deliveries = gateway.receive(from("CLIENT1", 4, cancel.clone()), start());
+        assert_eq!(summary(&deliveries, &[tags::CL_ORD_ID, tags::ORIG_CL_ORD_ID, tags::EXEC_TYPE, tags::ORD_STATUS]), vec!["CLIENT1 8 3 2 4 4"]);
+
+        let deliveries = gateway.receive(from("CLIENT1", 5, cancel.with(tags::CL_ORD_ID, "4")), start());
+        assert_eq!(summary(&deliveries, &[tags::CXL_REJ_RESPONSE_TO, tags::CXL_REJ_REASON, tags::ORD_STATUS]), vec!["CLIENT1 9 1 0 4"]);
+        let unknown = FixMessage::new(msg_types::ORDER_CANCEL_REQUEST).with(tags::ORIG_CL_ORD_ID, "9").with(tags::CL_ORD_ID, "5").with(tags::SYMBOL, "IBM").with(tags::SIDE, 1);
+        let deliveries = gateway.receive(from("CLIENT1", 6, unknown), start());
+        assert_eq!(summary(&deliveries, &[tags::CXL_REJ_REASON, tags::TEXT]), vec!["CLIENT1 9 1 Unknown order"]);
+    }
+
+    #[test]
+    fn invalid_orders_rejected() {
+        let mut gateway = gateway();
+        logon(&mut gateway, "CLIENT1");
+
+        let deliveries = gateway.receive(from("CLIENT1", 2, FixMessage::new(msg_types::NEW_ORDER_SINGLE).with(tags::CL_ORD_ID, "1")), start());
+        assert_eq!(summary(&deliveries, &[tags::REF_SEQ_NUM, tags::REF_TAG_ID, tags::SESSION_REJECT_REASON]), vec!["CLIENT1 3 2 55 1"]);
+        let deliveries = gateway.receive(from("CLIENT1", 3, new_order_single("1", "7", 100, 10)), start());
+        assert_eq!(summary(&deliveries, &[tags::REF_TAG_ID, tags::SESSION_REJECT_REASON]), vec!["CLIENT1 3 54 5"]);
+
+        gateway.receive(from("CLIENT1", 4, new_order_single("1", "1", 100, 10)), start());
+        let deliveries = gateway.receive(from("CLIENT1", 5, new_order_single("1", "1", 100, 10)), start());
+        assert_eq!(summary(&deliveries, &[tags::EXEC_TYPE, tags::ORD_STATUS, tags::TEXT]), vec!["CLIENT1 8 8 8 Duplicate ClOrdID: \"1\""]);
+        let deliveries = gateway.receive(from("CLIENT1", 6, FixMessage::new("Z")), start());
+        assert_eq!(summary(&deliveries, &[tags::SESSION_REJECT_REASON]), vec!["CLIENT1 3 11"]);
+    }
+
+    #[test]
+    fn sequence_gaps_and_resends() {
+        let mut gateway = gateway();
+        logon(&mut gateway, "CLIENT1");
+        gateway.receive(from("CLIENT1", 2, new_order_single("1", "1", 100, 10)), start());
+
+        let deliveries = gateway.receive(from("CLIENT1", 5, new_order_single("2", "1", 100, 10)), start());
+        assert_eq!(summary(&deliveries, &[tags::BEGIN_SEQ_NO, tags::END_SEQ_NO]), vec!["CLIENT1 2 3 0"]);
+        assert_eq!(gateway.next_seq_nums("CLIENT1"), Some((3, 4)));
+        let gap_fill = FixMessage::new(msg_types::SEQUENCE_RESET).with(tags::GAP_FILL_FLAG, "Y").with(tags::NEW_SEQ_NO, 5);
+        assert!(gateway.receive(from("CLIENT1", 3, gap_fill), start()).is_empty());
+        assert_eq!(gateway.next_seq_nums("CLIENT1"), Some((5, 4)));
+
+        let resend_request = FixMessage::new(msg_types::RESEND_REQUEST).with(tags::BEGIN_SEQ_NO, 1).with(tags::END_SEQ_NO, 0);
+        let deliveries = gateway.receive(from("CLIENT1", 5, resend_request), start());
+        assert_eq!(summary(&deliveries, &[tags::MSG_SEQ_NUM, tags::POSS_DUP_FLAG, tags::NEW_SEQ_NO]), vec![
+            "CLIENT1 4 1 Y 2",
+            "CLIENT1 8 2 Y -",
+            "CLIENT1 4 3 Y 4"
+        ]);
+
+        let deliveries = gateway.receive(from("CLIENT1", 2, FixMessage::new(msg_types::HEARTBEAT)), start());
+        assert_eq!(summary(&deliveries, &[tags::TEXT]), vec!["CLIENT1 5 MsgSeqNum too low, expecting 6 but received 2"]);
+    }
+
+    #[test]
+    fn scripted_client_over_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, gateway()));
+        let timeout = time::Duration::from_millis(200);
+
+        let mut buyer = FixClient::connect(address, "CLIENT1", "ORDERBOOK").unwrap();
+        buyer.send(&FixMessage::new(msg_types::LOGON).with(tags::ENCRYPT_METHOD, 0).with(tags::HEART_BT_INT, 30)).unwrap();
+        assert_eq!(buyer.receive(timeout).unwrap().unwrap().msg_type(), msg_types::LOGON);
+        buyer.send(&new_order_single("1", "1", 100, 10)).unwrap();
+        assert_eq!(buyer.receive(timeout).unwrap().unwrap().get(tags::EXEC_TYPE), Some("0"));
+
+        let mut seller = FixClient::connect(address, "CLIENT2", "ORDERBOOK").unwrap();
+        seller.send(&FixMessage::new(msg_types::LOGON).with(tags::HEART_BT_INT, 30)).unwrap();
+        seller.send(&new_order_single("1", "2", 100, 10)).unwrap();
+        let exec_types: Vec<String> = seller.receive_all(timeout).unwrap().iter().map(|message| message.msg_type().to_string() + message.get(tags::EXEC_TYPE).unwrap_or_default()).collect();
+        assert_eq!(exec_types, vec!["A", "80", "8F"]);
+        let fill = buyer.receive(timeout).unwrap().unwrap();
+        assert_eq!((fill.get(tags::EXEC_TYPE), fill.get(tags::ORD_STATUS), fill.get(tags::LAST_PX)), (Some("F"), Some("2"), Some("10")));
+
+        buyer.send(&FixMessage::new(msg_types::LOGOUT)).unwrap();
+        assert_eq!(buyer.receive(timeout).unwrap().unwrap().msg_type(), msg_types::LOGOUT);
+        assert_eq!(buyer.receive(timeout).unwrap(), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Unit tests for the FIX message format
+
+#[cfg(test)]
+mod fix_tests {
+    use chrono::{DateTime, Utc};
+
+    use crate::fix::*;
+
+    #[test]
+    fn encode_then_decode() {
+        let message = FixMessage::new(msg_types::HEARTBEAT).with(tags::SENDER_COMP_ID, "ORDERBOOK").with(tags::TARGET_COMP_ID, "CLIENT1").with(tags::MSG_SEQ_NUM, 2);
+        let encoded = message.encode();
+
+        assert_eq!(String::from_utf8_lossy(&encoded), "8=FIX.4.4\x019=34\x0135=0\x0149=ORDERBOOK\x0156=CLIENT1\x0134=2\x0110=151\x01");
+        assert_eq!(FixMessage::decode(&encoded), Ok(Some((message.clone(), encoded.len()))));
+        assert_eq!(message.seq_num(), Some(2));
+        assert_eq!(message.to_string(), "8=FIX.4.4|9=34|35=0|49=ORDERBOOK|56=CLIENT1|34=2|10=151|");
+    }
+
+    #[test]
+    fn decode_partial_and_consecutive_messages() {
+        let mut encoded = FixMessage::new(msg_types::HEARTBEAT).encode();
+        let first_length = encoded.len();
+        encoded.extend(FixMessage::new(msg_types::LOGOUT).encode());
+
+        assert_eq!(FixMessage::decode(&encoded[..5]), Ok(None));
+        assert_eq!(FixMessage::decode(&encoded[..first_length - 1]), Ok(None));
+        let (message, consumed) = FixMessage::decode(&encoded).unwrap().unwrap();
+        assert_eq!((message.msg_type(), consumed), (msg_types::HEARTBEAT, first_length));
+        let (message, _) = FixMessage::decode(&encoded[consumed..]).unwrap().unwrap();
+        assert_eq!(message.msg_type(), msg_types::LOGOUT);
+    }
+
+    #[test]
+    fn decode_rejects_bad_messages() {
+        let mut encoded = FixMessage::new(msg_types::HEARTBEAT).encode();
+        let check_sum_start = encoded.len() - 4;
+        encoded[check_sum_start] = b'9';
+
+        assert!(matches!(FixMessage::decode(&encoded), Err(FixError::InvalidCheckSum { .. })));
+        assert!(matches!(FixMessage::decode(b"8=FIX.4.2\x019=5\x01"), Err(FixError::InvalidHeader(_))));
+        assert!(matches!(FixMessage::decode(b"garbage"), Err(FixError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn decode_rejects_oversized_body_length() {
+        assert!(matches!(FixMessage::decode(b"8=FIX.4.4\x019=18446744073709551615\x0135=0\x01"), Err(FixError::InvalidHeader(_))));
+        assert!(matches!(FixMessage::decode(b"8=FIX.4.4\x019=99999999999999999999999"), Err(FixError::InvalidHeader(_))));
+        assert!(matches!(FixMessage::decode(format!("8=FIX.4.4\x019={}\x01", MAX_BODY_LENGTH + 1).as_bytes()), Err(FixError::BodyTooLong(_))));
+        assert!(matches!(FixMessage::decode(b"8=FIX.4.4\x019=8192\x0135=0\x01"), Ok(None)));
+    }
+
+    #[test]
+    fn readable_messages() {
+        let message = parse_readable("8=FIX.4.4|9=99|11=1|35=D|55=IBM|10=000|").unwrap();
+
+        assert_eq!(message.fields(), &[(35, "D".to_string()), (11, "1".to_string()), (55, "IBM".to_string())]);
+        assert!(parse_readable("11=1|55=IBM").is_err());
+        assert!(parse_readable("35=D|X=1").is_err());
+    }
+
+    #[test]
+    fn header_comes_before_body() {
+        let body = FixMessage::new(msg_types::NEW_ORDER_SINGLE).with(tags::CL_ORD_ID, "1").with(tags::MSG_SEQ_NUM, 7);
+        let message = with_header(&body, "CLIENT1", "ORDERBOOK", 3, "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), Some("20220103-09:29:00.000"));
+
+        let tags: Vec<u32> = message.fields().iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, vec![35, 49, 56, 34, 52, 43, 122, 11]);
+        assert_eq!(message.get(tags::SENDING_TIME), Some("20220103-09:30:00.000"));
+        assert_eq!(message.seq_num(), Some(3));
+    }
+}
@@ -0,0 +1,349 @@
+//! A binary market data feed of the public results of `OrderBooks`, in a fixed layout modelled on
+//! ITCH. Every message is framed by a two byte big-endian length and starts with a header of a one
+//! byte message type, the result's global sequence number (u64), the engine time in nanoseconds
+//! since the Unix epoch (u64) and the symbol, eight ASCII bytes padded with spaces, or all spaces for
+//! a result about no one symbol. The fields after the header are big-endian:
+//! ```text
+//! 'A' AddOrder       user u64, user order id u64, side u8, qty u64, price u64 (u64::MAX for a market
+//!                    or pegged order, as zero is a limit price)
+//! 'X' OrderCancel    user u64, user order id u64
+//! 'E' Trade          trade id u64, buy user u64, buy user order id u64, sell user u64,
+//!                    sell user order id u64, price u64, qty u64, aggressor side u8 (' ' for none)
+//! 'Q' TopOfBook      side u8, price u64, total qty u64 (both 0 for an empty side)
+//! 'H' TradingStatus  status u8: 'P' pre-open, 'A' auction, 'T' continuous, 'H' halted, 'C' closed
+//! ```
+//! Each message also reads back as a line of text, such as
+//! `5, 2022-01-03T09:30:00.000000000Z, IBM, T, 1, 1, 1, 2, 1, 10, 100, S`.
+
+use std::{convert::{TryFrom, TryInto}, fmt, fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+
+use crate::{order::{InputMessage, OrderType}, order_result::{OrderResult, SequencedOrderResult}, order_status::ExecType, trading_status::TradingStatus};
+
+/// The length of the header every message starts with
+const HEADER_LENGTH: usize = 25;
+
+/// The price of an added market or pegged order, so that zero stays a valid limit price
+const NO_PRICE: u64 = u64::MAX;
+
+/// What a feed message reports, after its header
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeedBody {
+    /// A new order was accepted, with a price of `None` for a market or pegged order
+    AddOrder { user: u64, user_order_id: u64, side: char, qty: u64, price: Option<u64> },
+    /// An order left the book without trading, whether cancelled by its user or by the engine, or
+    /// expired
+    OrderCancel { user: u64, user_order_id: u64 },
+    Trade { trade_id: u64, user_buy: u64, user_order_id_buy: u64, user_sell: u64, user_order_id_sell: u64, price: u64, qty: u64, aggressor_side: Option<char> },
+    /// The best price and total quantity at it on one side, or `None` for both when the side is empty
+    TopOfBook { side: char, price: Option<u64>, total_qty: Option<u64> },
+    TradingStatus { status: TradingStatus }
+}
+
+/// One message of the feed
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeedMessage {
+    /// The global sequence number of the result the message reports
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    /// `None` for a result about no one symbol, such as a trading status change for every symbol
+    pub symbol: Option<String>,
+    pub body: FeedBody
+}
+
+/// Reasons a feed could not be read
+#[derive(Debug)]
+pub enum FeedError {
+    Io(io::Error),
+    UnknownType(u8),
+    /// The message is the wrong length for its type, holding the type and length
+    InvalidLength(u8, usize),
+    /// A field holds a value that cannot be decoded
+    InvalidField(String),
+    /// A packet or other frame holding messages cannot be decoded
+    InvalidPacket(String)
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::UnknownType(message_type) => write!(f, "Unknown message type: {:?}", *message_type as char),
+            Self::InvalidLength(message_type, length) => write!(f, "Invalid length for message type {:?}: {}", *message_type as char, length),
+            Self::InvalidField(field) => write!(f, "Invalid field: {}", field),
+            Self::InvalidPacket(reason) => write!(f, "Invalid packet: {}", reason)
+        }
+    }
+}
+
+impl From<io::Error> for FeedError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Nanoseconds since the epoch, or `None` before the epoch or too far after it to fit
+fn time_to_nanos(time: DateTime<Utc>) -> Option<u64> {
+    let nanos = time.timestamp().checked_mul(1_000_000_000)?.checked_add(i64::from(time.timestamp_subsec_nanos()))?;
+    u64::try_from(nanos).ok()
+}
+
+fn nanos_to_time(nanos: u64) -> Option<DateTime<Utc>> {
+    let nanos = i64::try_from(nanos).ok()?;
+    Utc.timestamp_opt(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32).single()
+}
+
+fn status_code(status: TradingStatus) -> u8 {
+    match status {
+        TradingStatus::PreOpen => b'P',
+        TradingStatus::Auction => b'A',
+        TradingStatus::Continuous => b'T',
+        TradingStatus::Halted => b'H',
+        TradingStatus::Closed => b'C'
+    }
+}
+
+fn status_from_code(code: u8) -> Option<TradingStatus> {
+    match code {
+        b'P' => Some(TradingStatus::PreOpen),
+        b'A' => Some(TradingStatus::Auction),
+        b'T' => Some(TradingStatus::Continuous),
+        b'H' => Some(TradingStatus::Halted),
+        b'C' => Some(TradingStatus::Closed),
+        _ => None
+    }
+}
+
+impl FeedMessage {
+    /// The feed message reporting `order_result`, produced at `time` by `message`, if it is a public
+    /// result. An acknowledgement reports the order added by a new order message, or the order
+    /// removed by a cancel message, and is otherwise left out. Execution reports are left out too,
+    /// except that an order which expired, which no other result reports, is reported as cancelled.
+    pub fn from_result(sequenced_order_result: &SequencedOrderResult, message: Option<&InputMessage>, time: DateTime<Utc>) -> Option<FeedMessage> {
+        let body = match (&sequenced_order_result.order_result, message) {
+            (OrderResult::Acknowledgement { user, user_order_id }, Some(InputMessage::New(new_order))) if new_order.user == *user && new_order.user_order_id == *user_order_id => {
+                let has_price = new_order.peg.is_none() && !matches!(new_order.order_type, OrderType::Market);
+                FeedBody::AddOrder { user: *user, user_order_id: *user_order_id, side: new_order.side, qty: new_order.qty, price: Some(new_order.price).filter(|_| has_price) }
+            },
+            (OrderResult::Acknowledgement { user, user_order_id }, Some(InputMessage::Cancel(cancel_order))) if cancel_order.user == *user && cancel_order.user_order_id == *user_order_id =>
+                FeedBody::OrderCancel { user: *user, user_order_id: *user_order_id },
+            (OrderResult::Cancellation { user, user_order_id }, _) | (OrderResult::SelfTradeCancellation { user, user_order_id }, _)
+                | (OrderResult::ExecutionReport { user, user_order_id, exec_type: ExecType::Expired, .. }, _) =>
+                FeedBody::OrderCancel { user: *user, user_order_id: *user_order_id },
+            (OrderResult::Trade { trade_id, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, aggressor_side, .. }, _) =>
+                FeedBody::Trade { trade_id: *trade_id, user_buy: *user_buy, user_order_id_buy: *user_order_id_buy, user_sell: *user_sell, user_order_id_sell: *user_order_id_sell, price: *price, qty: *qty, aggressor_side: *aggressor_side },
+            (OrderResult::TopOfBookChange { side, price, total_quantity }, _) =>
+                FeedBody::TopOfBook { side: *side, price: price.parse().ok(), total_qty: total_quantity.parse().ok() },
+            (OrderResult::TradingStatusChange { status }, _) => FeedBody::TradingStatus { status: *status },
+            _ => return None
+        };
+        let time = match &sequenced_order_result.order_result {
+            OrderResult::Trade { time, .. } => *time,
+            _ => time
+        };
+        Some(FeedMessage { sequence: sequenced_order_result.sequence, time, symbol: sequenced_order_result.symbol.clone(), body })
+    }
+
+    /// The message without its length prefix, failing if its time is not representable in nanoseconds since the epoch
+    pub fn encode(&self) -> Result<Vec<u8>, FeedError> {
+        let message_type = match self.body {
+            FeedBody::AddOrder { .. } => b'A',
+            FeedBody::OrderCancel { .. } => b'X',
+            FeedBody::Trade { .. } => b'E',
+            FeedBody::TopOfBook { .. } => b'Q',
+            FeedBody::TradingStatus { .. } => b'H'
+        };
+        let mut bytes = vec![message_type];
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        let nanos = time_to_nanos(self.time).ok_or_else(|| FeedError::InvalidField(format!("time {}", self.time.to_rfc3339_opts(SecondsFormat::Nanos, true))))?;
+        bytes.extend_from_slice(&nanos.to_be_bytes());
+        let mut symbol = [b' '; 8];
+        for (byte, symbol_byte) in symbol.iter_mut().zip(self.symbol.as_deref().unwrap_or_default().bytes()) {
+            *byte = symbol_byte;
+        }
+        bytes.extend_from_slice(&symbol);
+
+        let mut u64s = |values: &[u64]| values.iter().for_each(|value| bytes.extend_from_slice(&value.to_be_bytes()));
+        match &self.body {
+            FeedBody::AddOrder { user, user_order_id, side, qty, price } => {
+                u64s(&[*user, *user_order_id]);
+                bytes.push(*side as u8);
+                bytes.extend_from_slice(&qty.to_be_bytes());
+                bytes.extend_from_slice(&price.unwrap_or(NO_PRICE).to_be_bytes());
+            },
+            FeedBody::OrderCancel { user, user_order_id } => u64s(&[*user, *user_order_id]),
+            FeedBody::Trade { trade_id, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, aggressor_side } => {
+                u64s(&[*trade_id, *user_buy, *user_order_id_buy, *user_sell, *user_order_id_sell, *price, *qty]);
+                bytes.push(aggressor_side.unwrap_or(' ') as u8);
+            },
+            FeedBody::TopOfBook { side, price, total_qty } => {
+                bytes.push(*side as u8);
+                bytes.extend_from_slice(&price.unwrap_or(0).to_be_bytes());
+                bytes.extend_from_slice(&total_qty.unwrap_or(0).to_be_bytes());
+            },
+            FeedBody::TradingStatus { status } => bytes.push(status_code(*status))
+        }
+        Ok(bytes)
+    }
+
+    /// Read a message without its length prefix
+    pub fn decode(payload: &[u8]) -> Result<FeedMessage, FeedError> {
+        let message_type = *payload.first().ok_or(FeedError::InvalidLength(0, 0))?;
+        let length = HEADER_LENGTH + match message_type {
+            b'A' => 33,
+            b'X' => 16,
+            b'E' => 57,
+            b'Q' => 17,
+            b'H' => 1,
+            _ => return Err(FeedError::UnknownType(message_type))
+        };
+        if payload.len() != length {
+            return Err(FeedError::InvalidLength(message_type, payload.len()));
+        }
+
+        let u64_at = |offset: usize| u64::from_be_bytes(payload[offset..offset + 8].try_into().unwrap());
+        let sequence = u64_at(1);
+        let nanos = u64_at(9);
+        let time = nanos_to_time(nanos).ok_or_else(|| FeedError::InvalidField(format!("time {}", nanos)))?;
+        let symbol_field = &payload[17..25];
+        if !symbol_field.is_ascii() {
+            return Err(FeedError::InvalidField(format!("symbol {:?}", symbol_field)));
+        }
+        let symbol = Some(String::from_utf8_lossy(symbol_field).trim_end().to_string()).filter(|symbol| !symbol.is_empty());
+
+        let field = |offset: usize| u64_at(HEADER_LENGTH + offset);
+        let byte = |offset: usize| payload[HEADER_LENGTH + offset];
+        let body = match message_type {
+            b'A' => FeedBody::AddOrder { user: field(0), user_order_id: field(8), side: byte(16) as char, qty: field(17), price: Some(field(25)).filter(|price| *price != NO_PRICE) },
+            b'X' => FeedBody::OrderCancel { user: field(0), user_order_id: field(8) },
+            b'E' => FeedBody::Trade {
+                trade_id: field(0),
+                user_buy: field(8),
+                user_order_id_buy: field(16),
+                user_sell: field(24),
+                user_order_id_sell: field(32),
+                price: field(40),
+                qty: field(48),
+                aggressor_side: Some(byte(56) as char).filter(|side| *side != ' ')
+            },
+            b'Q' => {
+                let (price, total_qty) = (field(1), field(9));
+                let empty = price == 0 && total_qty == 0;
+                FeedBody::TopOfBook { side: byte(0) as char, price: Some(price).filter(|_| !empty), total_qty: Some(total_qty).filter(|_| !empty) }
+            },
+            _ => FeedBody::TradingStatus { status: status_from_code(byte(0)).ok_or_else(|| FeedError::InvalidField(format!("trading status {:?}", byte(0) as char)))? }
+        };
+        Ok(FeedMessage { sequence, time, symbol, body })
+    }
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+impl fmt::Display for FeedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}, {}, ", self.sequence, self.time.to_rfc3339_opts(SecondsFormat::Nanos, true), self.symbol.as_deref().unwrap_or("-"))?;
+        match &self.body {
+            FeedBody::AddOrder { user, user_order_id, side, qty, price } => write!(f, "A, {}, {}, {}, {}, {}", user, user_order_id, side, qty, or_dash(*price)),
+            FeedBody::OrderCancel { user, user_order_id } => write!(f, "C, {}, {}", user, user_order_id),
+            FeedBody::Trade { trade_id, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, aggressor_side } =>
+                write!(f, "T, {}, {}, {}, {}, {}, {}, {}, {}", trade_id, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, or_dash(*aggressor_side)),
+            FeedBody::TopOfBook { side, price, total_qty } => write!(f, "B, {}, {}, {}", side, or_dash(*price), or_dash(*total_qty)),
+            FeedBody::TradingStatus { status } => write!(f, "S, {}", status)
+        }
+    }
+}
+
+/// Write `payload` framed by its two byte big-endian length
+pub fn write_payload<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&(payload.len() as u16).to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+/// Read the next payload framed by its two byte big-endian length, or `None` if `reader` ends
+/// before it starts
+pub fn read_payload<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 2];
+    match reader.read_exact(&mut length) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e)
+    }
+    let mut payload = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Writes the public results of `OrderBooks` as a framed binary feed, such as to a file or socket
+pub struct FeedWriter<W: Write> {
+    writer: W
+}
+
+impl FeedWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<FeedWriter<BufWriter<File>>> {
+        Ok(FeedWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> FeedWriter<W> {
+    pub fn new(writer: W) -> FeedWriter<W> {
+        FeedWriter { writer }
+    }
+
+    /// Write a message for each public result among `order_results`, produced at `time` by `message`
+    pub fn record(&mut self, message: &InputMessage, order_results: &[SequencedOrderResult], time: DateTime<Utc>) -> Result<(), FeedError> {
+        for sequenced_order_result in order_results {
+            if let Some(feed_message) = FeedMessage::from_result(sequenced_order_result, Some(message), time) {
+                self.write(&feed_message)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, feed_message: &FeedMessage) -> Result<(), FeedError> {
+        Ok(write_payload(&mut self.writer, &feed_message.encode()?)?)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a framed binary feed, one message at a time
+pub struct FeedReader<R: Read> {
+    reader: R
+}
+
+impl FeedReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FeedReader<BufReader<File>>> {
+        Ok(FeedReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> FeedReader<R> {
+    pub fn new(reader: R) -> FeedReader<R> {
+        FeedReader { reader }
+    }
+
+    /// The next message, or `None` at the end of the feed
+    pub fn next_message(&mut self) -> Result<Option<FeedMessage>, FeedError> {
+        match read_payload(&mut self.reader)? {
+            Some(payload) => FeedMessage::decode(&payload).map(Some),
+            None => Ok(None)
+        }
+    }
+}
+
+impl<R: Read> Iterator for FeedReader<R> {
+    type Item = Result<FeedMessage, FeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
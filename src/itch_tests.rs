@@ -0,0 +1,157 @@
+//! Unit tests for the binary market data feed
+
+#[cfg(test)]
+mod itch_tests {
+    use std::io::Cursor;
+
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::clock::SimulatedClock;
+    use crate::itch::*;
+    use crate::order::*;
+    use crate::order_books::OrderBooks;
+    use crate::order_result::*;
+    use crate::trading_status::TradingStatus;
+
+    fn feed_message(sequence: u64, symbol: Option<&str>, body: FeedBody) -> FeedMessage {
+        FeedMessage { sequence, time: "2022-01-03T09:30:00.000001500Z".parse::<DateTime<Utc>>().unwrap(), symbol: symbol.map(str::to_string), body }
+    }
+
+    #[test]
+    fn round_trip() {
+        let messages = vec![
+            feed_message(1, Some("IBM"), FeedBody::AddOrder { user: 1, user_order_id: 2, side: 'B', qty: 100, price: Some(10) }),
+            feed_message(2, Some("AAPL"), FeedBody::AddOrder { user: 1, user_order_id: 3, side: 'S', qty: 50, price: None }),
+            feed_message(3, Some("IBM"), FeedBody::OrderCancel { user: 1, user_order_id: 2 }),
+            feed_message(4, Some("IBM"), FeedBody::Trade { trade_id: 1, user_buy: 1, user_order_id_buy: 2, user_sell: 3, user_order_id_sell: 4, price: 10, qty: 100, aggressor_side: Some('S') }),
+            feed_message(5, Some("IBM"), FeedBody::Trade { trade_id: 2, user_buy: 1, user_order_id_buy: 2, user_sell: 3, user_order_id_sell: 4, price: 10, qty: 100, aggressor_side: None }),
+            feed_message(6, Some("IBM"), FeedBody::TopOfBook { side: 'B', price: Some(10), total_qty: Some(100) }),
+            feed_message(7, Some("IBM"), FeedBody::TopOfBook { side: 'S', price: None, total_qty: None }),
+            feed_message(8, None, FeedBody::TradingStatus { status: TradingStatus::Halted })
+        ];
+        for message in messages {
+            assert_eq!(FeedMessage::decode(&message.encode().unwrap()).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn add_order_layout() {
+        let encoded = feed_message(1, Some("IBM"), FeedBody::AddOrder { user: 1, user_order_id: 2, side: 'B', qty: 100, price: Some(10) }).encode().unwrap();
+
+        assert_eq!(encoded.len(), 58);
+        assert_eq!(&encoded[..9], &[b'A', 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(&encoded[17..25], b"IBM     ");
+        assert_eq!(encoded[41], b'B');
+        assert_eq!(&encoded[50..], &[0, 0, 0, 0, 0, 0, 0, 10]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(matches!(FeedMessage::decode(b"Z"), Err(FeedError::UnknownType(b'Z'))));
+        assert!(matches!(FeedMessage::decode(b"X123"), Err(FeedError::InvalidLength(b'X', 4))));
+        let mut encoded = feed_message(1, None, FeedBody::TradingStatus { status: TradingStatus::Closed }).encode().unwrap();
+        *encoded.last_mut().unwrap() = b'?';
+        assert!(matches!(FeedMessage::decode(&encoded), Err(FeedError::InvalidField(_))));
+        encoded[9..17].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(FeedMessage::decode(&encoded), Err(FeedError::InvalidField(_))));
+    }
+
+    #[test]
+    fn unrepresentable_time() {
+        let mut before_epoch = feed_message(1, None, FeedBody::TradingStatus { status: TradingStatus::Closed });
+        before_epoch.time = "1969-12-31T23:59:59Z".parse::<DateTime<Utc>>().unwrap();
+        let mut after_2262 = feed_message(1, None, FeedBody::TradingStatus { status: TradingStatus::Closed });
+        after_2262.time = "2263-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert!(matches!(before_epoch.encode(), Err(FeedError::InvalidField(_))));
+        assert!(matches!(after_2262.encode(), Err(FeedError::InvalidField(_))));
+        assert!(matches!(FeedWriter::new(Vec::new()).write(&after_2262), Err(FeedError::InvalidField(_))));
+    }
+
+    #[test]
+    fn zero_price_is_limit() {
+        let limit = feed_message(1, Some("IBM"), FeedBody::AddOrder { user: 1, user_order_id: 2, side: 'B', qty: 100, price: Some(0) });
+        let market = feed_message(2, Some("IBM"), FeedBody::AddOrder { user: 1, user_order_id: 3, side: 'B', qty: 100, price: None });
+
+        assert_eq!(&limit.encode().unwrap()[50..], &[0; 8]);
+        assert_eq!(&market.encode().unwrap()[50..], &[0xff; 8]);
+        assert_eq!(FeedMessage::decode(&limit.encode().unwrap()).unwrap(), limit);
+        assert_eq!(FeedMessage::decode(&market.encode().unwrap()).unwrap(), market);
+    }
+
+    fn sequenced(sequence: u64, order_result: OrderResult) -> SequencedOrderResult {
+        SequencedOrderResult { sequence, symbol: Some("IBM".to_string()), symbol_sequence: Some(sequence), input_sequence: 1, order_result }
+    }
+
+    #[test]
+    fn only_public_results() {
+        let time = "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let new_order = InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        let acknowledgement = sequenced(1, OrderResult::Acknowledgement { user: 1, user_order_id: 1 });
+        let rejection = sequenced(2, OrderResult::Rejection { user: 1, user_order_id: 1 });
+
+        assert_eq!(FeedMessage::from_result(&acknowledgement, Some(&new_order), time).map(|message| message.body),
+            Some(FeedBody::AddOrder { user: 1, user_order_id: 1, side: 'B', qty: 100, price: Some(10) }));
+        assert_eq!(FeedMessage::from_result(&acknowledgement, Some(&InputMessage::Cancel(CancelOrder::new(1, 1))), time).map(|message| message.body),
+            Some(FeedBody::OrderCancel { user: 1, user_order_id: 1 }));
+        assert_eq!(FeedMessage::from_result(&acknowledgement, None, time), None);
+        assert_eq!(FeedMessage::from_result(&rejection, Some(&new_order), time), None);
+    }
+
+    #[test]
+    fn writer_to_reader() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_clock(Box::new(SimulatedClock::new("2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), Duration::microseconds(1))));
+        let mut writer = FeedWriter::new(vec![]);
+        let messages = vec![
+            InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)),
+            InputMessage::New(NewOrder::new(1, "IBM".to_string(), 9, 50, 'B', 2)),
+            InputMessage::New(NewOrder::new_market(2, "IBM".to_string(), 100, 'S', 3)),
+            InputMessage::Cancel(CancelOrder::new(1, 2))
+        ];
+        for message in messages {
+            let order_results = order_books.process(message.clone());
+            writer.record(&message, &order_results, order_books.current_time().unwrap()).unwrap();
+        }
+
+        let lines: Vec<String> = FeedReader::new(Cursor::new(writer.into_inner())).map(|message| message.unwrap().to_string()).collect();
+        assert_eq!(lines, vec![
+            "1, 2022-01-03T09:30:00.000000000Z, IBM, A, 1, 1, B, 100, 10",
+            "2, 2022-01-03T09:30:00.000000000Z, IBM, B, B, 10, 100",
+            "3, 2022-01-03T09:30:00.000001000Z, IBM, A, 1, 2, B, 50, 9",
+            "4, 2022-01-03T09:30:00.000002000Z, IBM, A, 2, 3, S, 100, -",
+            "5, 2022-01-03T09:30:00.000002000Z, IBM, T, 1, 1, 1, 2, 3, 10, 100, S",
+            "6, 2022-01-03T09:30:00.000002000Z, IBM, B, B, 9, 50",
+            "7, 2022-01-03T09:30:00.000003000Z, IBM, C, 1, 2",
+            "8, 2022-01-03T09:30:00.000003000Z, IBM, B, B, -, -"
+        ]);
+    }
+
+    #[test]
+    fn expired_orders_are_cancelled() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_execution_reports(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        let message = InputMessage::New(NewOrder::new(2, "IBM".to_string(), 9, 40, 'S', 2));
+        let order_results = order_books.process(message.clone());
+
+        let bodies: Vec<FeedBody> = order_results.iter().filter_map(|order_result| FeedMessage::from_result(order_result, Some(&message), Utc::now())).map(|message| message.body).collect();
+        assert_eq!(bodies, vec![
+            FeedBody::AddOrder { user: 2, user_order_id: 2, side: 'S', qty: 40, price: Some(9) },
+            FeedBody::OrderCancel { user: 2, user_order_id: 2 }
+        ]);
+    }
+
+    #[test]
+    fn truncated_feed() {
+        let mut writer = FeedWriter::new(vec![]);
+        writer.write(&feed_message(1, None, FeedBody::TradingStatus { status: TradingStatus::Continuous })).unwrap();
+        let mut bytes = writer.into_inner();
+        bytes.extend_from_slice(&[0, 42, b'A']);
+
+        let mut reader = FeedReader::new(Cursor::new(bytes));
+        assert_eq!(reader.next_message().unwrap().map(|message| message.body), Some(FeedBody::TradingStatus { status: TradingStatus::Continuous }));
+        assert!(matches!(reader.next_message(), Err(FeedError::Io(_))));
+        assert!(reader.next_message().unwrap().is_none());
+    }
+}
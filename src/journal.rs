@@ -0,0 +1,349 @@
+//! A write-ahead journal of every input message accepted by `OrderBooks`, and recovery by replaying
+//! it.
+//!
+//! Each message is appended with a sequence number and its engine time, and flushed, before it is
+//! processed, so results are never published for a message that could be lost in a crash. A
+//! journal begins with a version row and holds one row per entry:
+//! ```text
+//! JOURNAL, 1
+//! sequence, time, N, user, symbol, price, qty, side, user_order_id, self_trade_group, order_type, display_qty, post_only, hidden, peg
+//! sequence, time, C, user, user_order_id
+//! sequence, time, M, user, symbol, side
+//! sequence, time, F, symbol, user, report_cancellations
+//! sequence, time, A, symbol, auction_action
+//! sequence, time, T, symbol, trading_status
+//! sequence, time, L, symbol, price_band
+//! sequence, time, K, user, risk_limit, value
+//! sequence, time, Q, user, user_order_id
+//! sequence, time, RESET
+//! ```
+//! where '-' marks an absent optional field. `RESET` marks a return to the starting `OrderBooks`,
+//! as when the CSV runner begins a new scenario.
+
+use std::{fmt, fs::{File, OpenOptions}, io::{Read, Write}, path::Path};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use csv::StringRecord;
+
+use crate::{order::{AuctionOrder, CancelOrder, FlushOrder, InputMessage, MassCancelOrder, NewOrder, OrderStatusQuery, PriceBandOrder, RiskLimitOrder, TradingStatusOrder}, order_books::OrderBooks, order_result::SequencedOrderResult};
+
+/// The journal format version written by `Journal`
+pub const JOURNAL_VERSION: u32 = 1;
+
+/// Reasons a journal could not be written, read or replayed
+#[derive(Debug)]
+pub enum JournalError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    UnsupportedVersion(String),
+    InvalidRow(String),
+    SequenceGap { expected: u64, found: u64 }
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Journal I/O error: {}", e),
+            Self::Csv(e) => write!(f, "Journal CSV error: {}", e),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported journal version: \"{}\"", version),
+            Self::InvalidRow(row) => write!(f, "Invalid journal row: \"{}\"", row),
+            Self::SequenceGap { expected, found } => write!(f, "Journal sequence gap: expected {} but found {}", expected, found)
+        }
+    }
+}
+
+impl From<std::io::Error> for JournalError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<csv::Error> for JournalError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+/// One entry in the journal
+#[derive(Clone)]
+pub enum JournalEntry {
+    Message(InputMessage),
+    Reset
+}
+
+/// A journal entry with its sequence number and engine time
+pub struct JournalRecord {
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    pub entry: JournalEntry
+}
+
+/// Appends entries to a journal, numbering them from `next_sequence`
+pub struct Journal<W: Write> {
+    writer: csv::Writer<W>,
+    next_sequence: u64
+}
+
+impl Journal<File> {
+    /// Open the journal at `path` for appending, creating it if needed. Returns the journal along
+    /// with the records already in it, which can be replayed with `replay_records`. A journal written
+    /// in another format version is never appended to.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<(Journal<File>, Vec<JournalRecord>), JournalError> {
+        let records = match File::open(&path) {
+            Ok(file) => read_journal(file)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into())
+        };
+        let is_new = !path.as_ref().exists() || std::fs::metadata(&path)?.len() == 0;
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let next_sequence = records.last().map_or(1, |record| record.sequence + 1);
+        let mut journal = Journal::new(file, next_sequence);
+        if is_new {
+            journal.write_version()?;
+        }
+        Ok((journal, records))
+    }
+}
+
+impl<W: Write> Journal<W> {
+    /// Start a journal on `writer`. The version row is only written by `write_version`, so that an
+    /// existing journal can be continued.
+    pub fn new(writer: W, next_sequence: u64) -> Journal<W> {
+        Journal { writer: csv::WriterBuilder::new().flexible(true).from_writer(writer), next_sequence }
+    }
+
+    /// Write the version row which begins a new journal
+    pub fn write_version(&mut self) -> Result<(), JournalError> {
+        self.writer.write_record(["JOURNAL", &JOURNAL_VERSION.to_string()])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// The sequence number the next entry will be given
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Append `entry` at engine time `time` and flush it, returning its sequence number
+    pub fn append(&mut self, time: DateTime<Utc>, entry: &JournalEntry) -> Result<u64, JournalError> {
+        let sequence = self.next_sequence;
+        let mut row = vec![sequence.to_string(), time.to_rfc3339_opts(SecondsFormat::Nanos, true)];
+        row.extend(entry_to_fields(entry));
+        self.writer.write_record(&row)?;
+        self.writer.flush()?;
+        self.next_sequence += 1;
+        Ok(sequence)
+    }
+
+    /// Journal `message` at the engine time of `order_books`, then process it, so that results are
+    /// only returned once the message is durable. The journal sequence number becomes the input
+    /// sequence number of the results.
+    pub fn process(&mut self, order_books: &mut OrderBooks, message: InputMessage) -> Result<Vec<SequencedOrderResult>, JournalError> {
+        let time = order_books.now();
+        let sequence = self.append(time, &JournalEntry::Message(message.clone()))?;
+        Ok(order_books.process_at(sequence, time, message))
+    }
+
+    /// Journal a return to the starting `OrderBooks`
+    pub fn reset(&mut self, order_books: &mut OrderBooks) -> Result<u64, JournalError> {
+        let time = order_books.now();
+        self.append(time, &JournalEntry::Reset)
+    }
+}
+
+/// Read every record in a journal, checking that sequence numbers have no gaps
+pub fn read_journal<R: Read>(reader: R) -> Result<Vec<JournalRecord>, JournalError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let mut rows = reader.records();
+
+    let version_row = match rows.next() {
+        Some(row) => row?,
+        None => return Ok(vec![])
+    };
+    if version_row.get(0) != Some("JOURNAL") {
+        return Err(JournalError::InvalidRow(row_to_string(&version_row)));
+    }
+    if version_row.get(1) != Some(JOURNAL_VERSION.to_string().as_str()) {
+        return Err(JournalError::UnsupportedVersion(version_row.get(1).unwrap_or_default().to_string()));
+    }
+
+    let mut records: Vec<JournalRecord> = vec![];
+    for row in rows {
+        let record = parse_record(&row?)?;
+        let expected = records.last().map_or(record.sequence, |last| last.sequence + 1);
+        if record.sequence != expected {
+            return Err(JournalError::SequenceGap { expected, found: record.sequence });
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Rebuild `OrderBooks` by replaying `records` in order, starting from `fresh_order_books()` and
+/// again after each `RESET`. Result sequence numbers carry on across each `RESET`.
+pub fn replay_records<F: FnMut() -> OrderBooks>(records: &[JournalRecord], mut fresh_order_books: F) -> OrderBooks {
+    let mut order_books = fresh_order_books();
+    for record in records {
+        match &record.entry {
+            JournalEntry::Message(message) => {
+                order_books.process_at(record.sequence, record.time, message.clone());
+            },
+            JournalEntry::Reset => {
+                let mut next_order_books = fresh_order_books();
+                next_order_books.continue_sequences(&order_books);
+                order_books = next_order_books;
+            }
+        }
+    }
+    order_books
+}
+
+/// Rebuild `OrderBooks` from the journal at `path`
+pub fn recover<P: AsRef<Path>, F: FnMut() -> OrderBooks>(path: P, fresh_order_books: F) -> Result<OrderBooks, JournalError> {
+    let records = read_journal(File::open(path)?)?;
+    Ok(replay_records(&records, fresh_order_books))
+}
+
+fn optional_to_field<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or("-".to_string(), |value| value.to_string())
+}
+
+fn entry_to_fields(entry: &JournalEntry) -> Vec<String> {
+    match entry {
+        JournalEntry::Message(InputMessage::New(new_order)) => vec![
+            "N".to_string(),
+            new_order.user.to_string(),
+            new_order.symbol.clone(),
+            new_order.price.to_string(),
+            new_order.qty.to_string(),
+            new_order.side.to_string(),
+            new_order.user_order_id.to_string(),
+            optional_to_field(&new_order.self_trade_group),
+            new_order.order_type.to_string(),
+            optional_to_field(&new_order.display_qty),
+            optional_to_field(&new_order.post_only),
+            new_order.hidden.to_string(),
+            optional_to_field(&new_order.peg)
+        ],
+        JournalEntry::Message(InputMessage::Cancel(cancel_order)) => vec![
+            "C".to_string(),
+            cancel_order.user.to_string(),
+            cancel_order.user_order_id.to_string()
+        ],
+        JournalEntry::Message(InputMessage::MassCancel(mass_cancel_order)) => vec![
+            "M".to_string(),
+            optional_to_field(&mass_cancel_order.user),
+            optional_to_field(&mass_cancel_order.symbol),
+            optional_to_field(&mass_cancel_order.side)
+        ],
+        JournalEntry::Message(InputMessage::Flush(flush_order)) => vec![
+            "F".to_string(),
+            optional_to_field(&flush_order.symbol),
+            optional_to_field(&flush_order.user),
+            flush_order.report_cancellations.to_string()
+        ],
+        JournalEntry::Message(InputMessage::Auction(auction_order)) => vec![
+            "A".to_string(),
+            auction_order.symbol.clone(),
+            auction_order.action.to_string()
+        ],
+        JournalEntry::Message(InputMessage::TradingStatus(trading_status_order)) => vec![
+            "T".to_string(),
+            optional_to_field(&trading_status_order.symbol),
+            trading_status_order.status.to_string()
+        ],
+        JournalEntry::Message(InputMessage::PriceBand(price_band_order)) => vec![
+            "L".to_string(),
+            optional_to_field(&price_band_order.symbol),
+            optional_to_field(&price_band_order.price_band)
+        ],
+        JournalEntry::Message(InputMessage::RiskLimit(risk_limit_order)) => vec![
+            "K".to_string(),
+            optional_to_field(&risk_limit_order.user),
+            risk_limit_order.limit.to_string(),
+            optional_to_field(&risk_limit_order.value)
+        ],
+        JournalEntry::Message(InputMessage::OrderStatus(order_status_query)) => vec![
+            "Q".to_string(),
+            order_status_query.user.to_string(),
+            order_status_query.user_order_id.to_string()
+        ],
+        JournalEntry::Reset => vec!["RESET".to_string()]
+    }
+}
+
+fn parse_record(row: &StringRecord) -> Result<JournalRecord, JournalError> {
+    let invalid = || JournalError::InvalidRow(row_to_string(row));
+    let sequence = parse_field::<u64>(row, 0)?;
+    let time = DateTime::parse_from_rfc3339(row.get(1).ok_or_else(invalid)?).map_err(|_| invalid())?.with_timezone(&Utc);
+    let entry = match (row.get(2), row.len()) {
+        (Some("N"), 15) => {
+            let mut new_order = NewOrder::new(
+                parse_field(row, 3)?,
+                row.get(4).unwrap().to_string(),
+                parse_field(row, 5)?,
+                parse_field(row, 6)?,
+                parse_field(row, 7)?,
+                parse_field(row, 8)?
+            );
+            new_order.time_received = time;
+            new_order.self_trade_group = parse_optional_field(row, 9)?;
+            new_order.order_type = parse_field(row, 10)?;
+            new_order.display_qty = parse_optional_field(row, 11)?;
+            new_order.post_only = parse_optional_field(row, 12)?;
+            new_order.hidden = parse_field(row, 13)?;
+            new_order.peg = parse_optional_field(row, 14)?;
+            JournalEntry::Message(InputMessage::New(new_order))
+        },
+        (Some("C"), 5) => JournalEntry::Message(InputMessage::Cancel(CancelOrder::new(parse_field(row, 3)?, parse_field(row, 4)?))),
+        (Some("M"), 6) => {
+            let user = parse_optional_field(row, 3)?;
+            let symbol = parse_optional_field(row, 4)?;
+            let side = parse_optional_field(row, 5)?;
+            if (user.is_none() && symbol.is_none()) || !(side.is_none() || side == Some('B') || side == Some('S')) {
+                return Err(invalid());
+            }
+            JournalEntry::Message(InputMessage::MassCancel(MassCancelOrder::new(user, symbol, side)))
+        },
+        (Some("F"), 6) => JournalEntry::Message(InputMessage::Flush(FlushOrder::new(
+            parse_optional_field(row, 3)?,
+            parse_optional_field(row, 4)?,
+            parse_field(row, 5)?
+        ))),
+        (Some("A"), 5) => JournalEntry::Message(InputMessage::Auction(AuctionOrder::new(row.get(3).unwrap(), parse_field(row, 4)?))),
+        (Some("T"), 5) => {
+            let symbol: Option<String> = parse_optional_field(row, 3)?;
+            JournalEntry::Message(InputMessage::TradingStatus(TradingStatusOrder::new(symbol.as_deref(), parse_field(row, 4)?)))
+        },
+        (Some("L"), 5) => {
+            let symbol: Option<String> = parse_optional_field(row, 3)?;
+            JournalEntry::Message(InputMessage::PriceBand(PriceBandOrder::new(symbol.as_deref(), parse_optional_field(row, 4)?)))
+        },
+        (Some("K"), 6) => JournalEntry::Message(InputMessage::RiskLimit(RiskLimitOrder::new(
+            parse_optional_field(row, 3)?,
+            parse_field(row, 4)?,
+            parse_optional_field(row, 5)?
+        ))),
+        (Some("Q"), 5) => JournalEntry::Message(InputMessage::OrderStatus(OrderStatusQuery::new(parse_field(row, 3)?, parse_field(row, 4)?))),
+        (Some("RESET"), 3) => JournalEntry::Reset,
+        _ => return Err(invalid())
+    };
+    Ok(JournalRecord { sequence, time, entry })
+}
+
+fn parse_field<T: std::str::FromStr>(row: &StringRecord, index: usize) -> Result<T, JournalError> {
+    row.get(index)
+        .and_then(|field| field.parse::<T>().ok())
+        .ok_or_else(|| JournalError::InvalidRow(row_to_string(row)))
+}
+
+fn parse_optional_field<T: std::str::FromStr>(row: &StringRecord, index: usize) -> Result<Option<T>, JournalError> {
+    match row.get(index) {
+        Some("-") => Ok(None),
+        _ => parse_field(row, index).map(Some)
+    }
+}
+
+fn row_to_string(row: &StringRecord) -> String {
+    row.iter().collect::<Vec<&str>>().join(", ")
+}
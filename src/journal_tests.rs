@@ -0,0 +1,282 @@
+//! Unit tests for the write-ahead journal and recovery of `OrderBooks`
+
+#[cfg(test)]
+mod journal_tests {
+    use crate::clock::SimulatedClock;
+    use crate::journal::*;
+    use crate::peg::*;
+    use crate::order_books::OrderBooks;
+    use crate::snapshot::write_snapshot;
+    use crate::order::*;
+    use crate::order_result::*;
+    use crate::trading_status::TradingStatus;
+    use crate::price_band::PriceBand;
+    use crate::risk::RiskLimit;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn simulated_order_books() -> OrderBooks {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_clock(Box::new(SimulatedClock::new("2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), Duration::microseconds(1))));
+        order_books
+    }
+
+    fn snapshot_to_string(order_books: &OrderBooks) -> String {
+        let mut buffer = vec![];
+        write_snapshot(order_books, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn messages() -> Vec<InputMessage> {
+        vec![
+            InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)),
+            InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 101).with_self_trade_group(7)),
+            InputMessage::New(NewOrder::new(2, "IBM".to_string(), 12, 100, 'S', 102)),
+            InputMessage::New(NewOrder::new(1, "MSFT".to_string(), 20, 50, 'S', 2)),
+            InputMessage::New(NewOrder::new(3, "MSFT".to_string(), 20, 50, 'B', 201)),
+            InputMessage::Cancel(CancelOrder::new(1, 1)),
+            InputMessage::MassCancel(MassCancelOrder::by_user_and_side(2, "IBM", 'S')),
+            InputMessage::Flush(FlushOrder::new(Some("AAPL".to_string()), None, false))
+        ]
+    }
+
+    #[test]
+    fn journal_format() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        for message in messages() {
+            journal.process(&mut order_books, message).unwrap();
+        }
+        assert_eq!(journal.next_sequence(), 9);
+        drop(journal);
+
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "JOURNAL,1");
+        assert_eq!(lines[1], "1,2022-01-03T09:30:00.000000000Z,N,1,IBM,10,100,B,1,-,limit,-,-,false,-");
+        assert_eq!(lines[2], "2,2022-01-03T09:30:00.000001000Z,N,2,IBM,10,100,B,101,7,limit,-,-,false,-");
+        assert_eq!(lines[6], "6,2022-01-03T09:30:00.000005000Z,C,1,1");
+        assert_eq!(lines[7], "7,2022-01-03T09:30:00.000006000Z,M,2,IBM,S");
+        assert_eq!(lines[8], "8,2022-01-03T09:30:00.000007000Z,F,AAPL,-,false");
+    }
+
+    #[test]
+    fn recovery_matches_original() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        let mut original_results = vec![];
+        for message in messages() {
+            original_results.extend(journal.process(&mut order_books, message).unwrap().into_iter().map(|sequenced_order_result| sequenced_order_result.order_result));
+        }
+        drop(journal);
+
+        let records = read_journal(buffer.as_slice()).unwrap();
+        let recovered = replay_records(&records, || OrderBooks::new(true));
+
+        assert_eq!(records.len(), 8);
+        assert!(original_results.iter().any(|order_result| matches!(order_result, OrderResult::Trade { user_buy: 3, user_order_id_buy: 201, user_sell: 1, user_order_id_sell: 2, price: 20, qty: 50, .. })));
+        assert_eq!(snapshot_to_string(&recovered), snapshot_to_string(&order_books));
+    }
+
+    #[test]
+    fn order_types_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new_market(1, "IBM".to_string(), 100, 'B', 1))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new_stop(1, "IBM".to_string(), 12, 100, 'B', 2))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new_stop_limit(1, "IBM".to_string(), 8, 7, 100, 'S', 3))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 20, 300, 'S', 4).with_display_qty(100))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 21, 300, 'S', 5).with_post_only(PostOnly::Reject).with_hidden())).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new_pegged(1, "IBM".to_string(), Peg::new(PegType::Primary, 1, None), 300, 'S', 6))).unwrap();
+        drop(journal);
+
+        let new_orders: Vec<NewOrder> = read_journal(buffer.as_slice()).unwrap().into_iter().map(|record| match record.entry {
+            JournalEntry::Message(InputMessage::New(new_order)) => new_order,
+            _ => panic!("Expected a new order")
+        }).collect();
+
+        assert_eq!(new_orders[0].order_type, OrderType::Market);
+        assert_eq!(new_orders[1].order_type, OrderType::Stop { stop_price: 12 });
+        assert_eq!(new_orders[2].order_type, OrderType::StopLimit { stop_price: 8 });
+        assert_eq!(new_orders[3].display_qty, Some(100));
+        assert_eq!(new_orders[4].post_only, Some(PostOnly::Reject));
+        assert!(new_orders[4].hidden);
+        assert_eq!(new_orders[5].peg, Some(Peg::new(PegType::Primary, 1, None)));
+        assert!(new_orders[..5].iter().all(|new_order| new_order.peg.is_none()));
+    }
+
+    #[test]
+    fn auction_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::Auction(AuctionOrder::start("IBM"))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1))).unwrap();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(2, "IBM".to_string(), 9, 100, 'S', 2))).unwrap();
+        drop(journal);
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().nth(1), Some("1,2022-01-03T09:30:00.000000000Z,A,IBM,start"));
+
+        let mut recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+        assert!(matches!(&recovered.auction(AuctionOrder::uncross("IBM"))[1], OrderResult::Trade { user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 9, qty: 100, .. }));
+    }
+
+    #[test]
+    fn trading_status_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::TradingStatus(TradingStatusOrder::all(TradingStatus::PreOpen))).unwrap();
+        journal.process(&mut order_books, InputMessage::TradingStatus(TradingStatusOrder::new(Some("IBM"), TradingStatus::Halted))).unwrap();
+        drop(journal);
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().nth(1), Some("1,2022-01-03T09:30:00.000000000Z,T,-,pre-open"));
+        assert_eq!(text.lines().nth(2), Some("2,2022-01-03T09:30:00.000001000Z,T,IBM,halted"));
+
+        let recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+        assert_eq!(recovered.session_status(), TradingStatus::PreOpen);
+        assert_eq!(recovered.get_order_book("IBM").unwrap().trading_status(), TradingStatus::Halted);
+    }
+
+    #[test]
+    fn price_band_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::PriceBand(PriceBandOrder::new(Some("IBM"), Some(PriceBand::new(10).with_reference_price(100))))).unwrap();
+        journal.process(&mut order_books, InputMessage::PriceBand(PriceBandOrder::new(None, None))).unwrap();
+        drop(journal);
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().nth(1), Some("1,2022-01-03T09:30:00.000000000Z,L,IBM,10:100"));
+        assert_eq!(text.lines().nth(2), Some("2,2022-01-03T09:30:00.000001000Z,L,-,-"));
+
+        let recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+        assert_eq!(recovered.get_order_book("IBM").unwrap().price_band(), None);
+    }
+
+    #[test]
+    fn risk_limit_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::RiskLimit(RiskLimitOrder::new(None, RiskLimit::MaxOrderQty, Some(100)))).unwrap();
+        journal.process(&mut order_books, InputMessage::RiskLimit(RiskLimitOrder::new(Some(1), RiskLimit::MaxOrderQty, None))).unwrap();
+        drop(journal);
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().nth(1), Some("1,2022-01-03T09:30:00.000000000Z,K,-,max-order-qty,100"));
+        assert_eq!(text.lines().nth(2), Some("2,2022-01-03T09:30:00.000001000Z,K,1,max-order-qty,-"));
+
+        let recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+        assert_eq!(recovered.risk_manager().limits(2).get(RiskLimit::MaxOrderQty), Some(100));
+    }
+
+    #[test]
+    fn order_status_query_round_trip() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1))).unwrap();
+        let order_results = journal.process(&mut order_books, InputMessage::OrderStatus(OrderStatusQuery::new(1, 1))).unwrap();
+        drop(journal);
+
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text.lines().nth(2), Some("2,2022-01-03T09:30:00.000001000Z,Q,1,1"));
+        assert_eq!(order_results[0].order_result.to_string(), "O, 1, 1, IBM, B, new, 100, 0, 100, -");
+
+        let recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+        assert_eq!(snapshot_to_string(&recovered), snapshot_to_string(&order_books));
+    }
+
+    #[test]
+    fn recovery_after_reset() {
+        let mut buffer = vec![];
+        let mut order_books = simulated_order_books();
+        let mut journal = Journal::new(&mut buffer, 1);
+        journal.write_version().unwrap();
+
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1))).unwrap();
+        journal.reset(&mut order_books).unwrap();
+        order_books = simulated_order_books();
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1))).unwrap();
+        drop(journal);
+
+        let recovered = replay_records(&read_journal(buffer.as_slice()).unwrap(), || OrderBooks::new(true));
+
+        assert_eq!(recovered.symbols(), vec!["AAPL".to_string()]);
+    }
+
+    #[test]
+    fn sequence_gap() {
+        let journal = "JOURNAL,1\n1,2022-01-03T09:30:00Z,C,1,1\n3,2022-01-03T09:30:01Z,C,1,2\n";
+
+        let result = read_journal(journal.as_bytes());
+
+        assert!(matches!(result, Err(JournalError::SequenceGap { expected: 2, found: 3 })));
+    }
+
+    #[test]
+    fn invalid_row() {
+        let journal = "JOURNAL,1\n1,2022-01-03T09:30:00Z,N,1,IBM,10\n";
+
+        assert!(matches!(read_journal(journal.as_bytes()), Err(JournalError::InvalidRow(_))));
+    }
+
+    #[test]
+    fn open_continues_existing_journal() {
+        let path = std::env::temp_dir().join(format!("orderbook_journal_test_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (mut journal, records) = Journal::open(&path).unwrap();
+        let mut order_books = simulated_order_books();
+        assert!(records.is_empty());
+        journal.process(&mut order_books, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1))).unwrap();
+        drop(journal);
+
+        let (mut journal, records) = Journal::open(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(journal.next_sequence(), 2);
+        journal.process(&mut order_books, InputMessage::Cancel(CancelOrder::new(1, 1))).unwrap();
+        drop(journal);
+
+        let recovered = recover(&path, || OrderBooks::new(true)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshot_to_string(&recovered), snapshot_to_string(&order_books));
+    }
+
+    #[test]
+    fn open_refuses_other_version() {
+        let path = std::env::temp_dir().join(format!("orderbook_journal_version_test_{}.csv", std::process::id()));
+        std::fs::write(&path, "JOURNAL,0\n1,2022-01-03T09:30:00Z,C,1,1\n").unwrap();
+
+        let result = Journal::open(&path);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(JournalError::UnsupportedVersion(version)) if version == "0"));
+        assert_eq!(contents, "JOURNAL,0\n1,2022-01-03T09:30:00Z,C,1,1\n");
+    }
+}
@@ -1,9 +1,53 @@
 //! The orderbook library, which contains all datastructures used to hold buy and sell order books grouped by symbol
 
+// Test modules share the name of the file they live in
+#![allow(clippy::module_inception)]
+
 pub mod order_result;
+pub mod clock;
 pub mod order;
 pub mod order_book;
 pub mod order_books;
+pub mod scenario;
+pub mod self_trade_prevention;
+pub mod peg;
+pub mod auction;
+pub mod trading_status;
+pub mod price_band;
+pub mod risk;
+pub mod order_status;
+pub mod positions;
+pub mod fees;
+pub mod market_data;
+pub mod snapshot;
+pub mod journal;
+pub mod fix;
+pub mod fix_gateway;
+pub mod ouch;
+pub mod ouch_server;
+pub mod itch;
+pub mod feed_book;
+pub mod udp_feed;
 pub mod orderbooks_tests;
 pub mod orderbook_tests;
-pub mod order_result_tests;
\ No newline at end of file
+pub mod order_result_tests;
+pub mod scenario_tests;
+pub mod self_trade_prevention_tests;
+pub mod peg_tests;
+pub mod auction_tests;
+pub mod trading_status_tests;
+pub mod price_band_tests;
+pub mod risk_tests;
+pub mod order_status_tests;
+pub mod positions_tests;
+pub mod fees_tests;
+pub mod market_data_tests;
+pub mod snapshot_tests;
+pub mod journal_tests;
+pub mod fix_tests;
+pub mod fix_gateway_tests;
+pub mod ouch_tests;
+pub mod ouch_server_tests;
+pub mod itch_tests;
+pub mod feed_book_tests;
+pub mod udp_feed_tests;
\ No newline at end of file
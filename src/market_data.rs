@@ -0,0 +1,256 @@
+//! Market data built from the trades published by `OrderBooks`: a tape of every trade, and for each
+//! symbol its last price, cumulative volume, VWAP and OHLCV bars. Bars are bucketed by the engine
+//! time of each trade, so a run with a `SimulatedClock` produces identical bars every time.
+//!
+//! Bars can be saved as CSV, one row per symbol and interval with at least one trade:
+//! ```text
+//! symbol,start,open,high,low,close,volume,trades,vwap
+//! IBM,2022-01-03T09:30:00Z,10,12,10,11,300,3,11.0000
+//! ```
+
+use std::{collections::BTreeMap, fmt, fs::File, io::Write, path::Path};
+
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+
+use crate::order_result::{OrderResult, SequencedOrderResult};
+
+/// One trade on the tape
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradePrint {
+    pub trade_id: u64,
+    pub time: DateTime<Utc>,
+    pub symbol: String,
+    pub price: u64,
+    pub qty: u64,
+    /// The side of the incoming order, or `None` for a trade made by an auction uncross
+    pub aggressor_side: Option<char>
+}
+
+/// The open, high, low and close prices and the volume traded in a symbol during one interval
+/// starting at `start`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bar {
+    pub symbol: String,
+    pub start: DateTime<Utc>,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+    pub trades: u64,
+    /// The sum of price times quantity of the trades in the bar
+    pub notional: u128
+}
+
+impl Bar {
+    fn new(trade_print: &TradePrint, start: DateTime<Utc>) -> Bar {
+        Bar {
+            symbol: trade_print.symbol.clone(),
+            start,
+            open: trade_print.price,
+            high: trade_print.price,
+            low: trade_print.price,
+            close: trade_print.price,
+            volume: 0,
+            trades: 0,
+            notional: 0
+        }
+    }
+
+    fn add(&mut self, trade_print: &TradePrint) {
+        self.high = self.high.max(trade_print.price);
+        self.low = self.low.min(trade_print.price);
+        self.close = trade_print.price;
+        self.volume += trade_print.qty;
+        self.trades += 1;
+        self.notional += trade_print.price as u128 * trade_print.qty as u128;
+    }
+
+    pub fn vwap(&self) -> f64 {
+        self.notional as f64 / self.volume as f64
+    }
+}
+
+/// The running totals of every trade in a symbol
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SymbolStats {
+    pub last_price: Option<u64>,
+    pub volume: u64,
+    pub trades: u64,
+    /// The sum of price times quantity of every trade
+    pub notional: u128
+}
+
+impl SymbolStats {
+    /// The volume weighted average price of every trade, or `None` if there have been none
+    pub fn vwap(&self) -> Option<f64> {
+        if self.volume == 0 {
+            None
+        } else {
+            Some(self.notional as f64 / self.volume as f64)
+        }
+    }
+}
+
+/// Maintains the tape, symbol statistics and bars from the results of `OrderBooks` passed to
+/// `record`
+pub struct MarketData {
+    bar_interval: Duration,
+    tape: Vec<TradePrint>,
+    stats: BTreeMap<String, SymbolStats>,
+    /// The bars of each symbol, oldest first
+    bars: BTreeMap<String, Vec<Bar>>
+}
+
+impl MarketData {
+    /// `bar_interval` must be at least a millisecond
+    pub fn new(bar_interval: Duration) -> MarketData {
+        assert!(bar_interval >= Duration::milliseconds(1), "Bar interval must be at least a millisecond");
+        MarketData { bar_interval, tape: vec![], stats: BTreeMap::new(), bars: BTreeMap::new() }
+    }
+
+    pub fn bar_interval(&self) -> Duration {
+        self.bar_interval
+    }
+
+    /// Add each trade among `sequenced_order_results` to the tape, statistics and bars
+    pub fn record(&mut self, sequenced_order_results: &[SequencedOrderResult]) {
+        for sequenced_order_result in sequenced_order_results {
+            if let OrderResult::Trade { trade_id, symbol, price, qty, aggressor_side, time, .. } = &sequenced_order_result.order_result {
+                self.add(TradePrint { trade_id: *trade_id, time: *time, symbol: symbol.clone(), price: *price, qty: *qty, aggressor_side: *aggressor_side });
+            }
+        }
+    }
+
+    fn add(&mut self, trade_print: TradePrint) {
+        let stats = self.stats.entry(trade_print.symbol.clone()).or_default();
+        stats.last_price = Some(trade_print.price);
+        stats.volume += trade_print.qty;
+        stats.trades += 1;
+        stats.notional += trade_print.price as u128 * trade_print.qty as u128;
+
+        let start = self.bar_start(trade_print.time);
+        let bars = self.bars.entry(trade_print.symbol.clone()).or_default();
+        if bars.last().map_or(true, |bar| bar.start != start) {
+            bars.push(Bar::new(&trade_print, start));
+        }
+        bars.last_mut().unwrap().add(&trade_print);
+        self.tape.push(trade_print);
+    }
+
+    /// The start of the interval containing `time`, counting intervals from the Unix epoch
+    fn bar_start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let millis = time.timestamp_millis();
+        Utc.timestamp_millis_opt(millis - millis.rem_euclid(self.bar_interval.num_milliseconds())).unwrap()
+    }
+
+    /// Every trade, in the order they were made
+    pub fn tape(&self) -> &[TradePrint] {
+        &self.tape
+    }
+
+    pub fn stats(&self, symbol: &str) -> Option<&SymbolStats> {
+        self.stats.get(symbol)
+    }
+
+    pub fn last_price(&self, symbol: &str) -> Option<u64> {
+        self.stats(symbol).and_then(|stats| stats.last_price)
+    }
+
+    pub fn volume(&self, symbol: &str) -> u64 {
+        self.stats(symbol).map_or(0, |stats| stats.volume)
+    }
+
+    pub fn vwap(&self, symbol: &str) -> Option<f64> {
+        self.stats(symbol).and_then(SymbolStats::vwap)
+    }
+
+    /// The bars of `symbol`, oldest first, with one bar for each interval it traded in
+    pub fn bars(&self, symbol: &str) -> &[Bar] {
+        self.bars.get(symbol).map_or(&[], |bars| bars.as_slice())
+    }
+
+    /// Write one `symbol, start, open, high, low, close, volume, trades, vwap` row per bar, by
+    /// symbol and then start
+    pub fn write_bars<W: Write>(&self, writer: W) -> Result<(), MarketDataError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["symbol", "start", "open", "high", "low", "close", "volume", "trades", "vwap"])?;
+        for bar in self.bars.values().flatten() {
+            writer.write_record([
+                bar.symbol.clone(),
+                bar.start.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                bar.open.to_string(),
+                bar.high.to_string(),
+                bar.low.to_string(),
+                bar.close.to_string(),
+                bar.volume.to_string(),
+                bar.trades.to_string(),
+                format_price(bar.vwap())
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the bars to the file at `path`, replacing it if it exists
+    pub fn save_bars<P: AsRef<Path>>(&self, path: P) -> Result<(), MarketDataError> {
+        self.write_bars(File::create(path)?)
+    }
+
+    /// Write one `trade_id, time, symbol, price, qty, aggressor_side` row per trade, in the order
+    /// they were made, with `-` for a trade without an aggressor
+    pub fn write_tape<W: Write>(&self, writer: W) -> Result<(), MarketDataError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["trade_id", "time", "symbol", "price", "qty", "aggressor_side"])?;
+        for trade_print in &self.tape {
+            writer.write_record([
+                trade_print.trade_id.to_string(),
+                trade_print.time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+                trade_print.symbol.clone(),
+                trade_print.price.to_string(),
+                trade_print.qty.to_string(),
+                trade_print.aggressor_side.map_or("-".to_string(), |side| side.to_string())
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the tape to the file at `path`, replacing it if it exists
+    pub fn save_tape<P: AsRef<Path>>(&self, path: P) -> Result<(), MarketDataError> {
+        self.write_tape(File::create(path)?)
+    }
+}
+
+/// Format an average `price` to four decimal places
+fn format_price(price: f64) -> String {
+    format!("{:.4}", price)
+}
+
+/// Reasons market data could not be written
+#[derive(Debug)]
+pub enum MarketDataError {
+    Io(std::io::Error),
+    Csv(csv::Error)
+}
+
+impl fmt::Display for MarketDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Market data I/O error: {}", e),
+            Self::Csv(e) => write!(f, "Market data CSV error: {}", e)
+        }
+    }
+}
+
+impl From<std::io::Error> for MarketDataError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<csv::Error> for MarketDataError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
@@ -0,0 +1,99 @@
+//! Unit tests for the trade tape and OHLCV bars
+
+#[cfg(test)]
+mod market_data_tests {
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::market_data::*;
+    use crate::clock::SimulatedClock;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+
+    /// `OrderBooks` whose engine clock starts at 09:30:00 and moves 15 seconds per message
+    fn order_books() -> OrderBooks {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_clock(Box::new(SimulatedClock::new("2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), Duration::seconds(15))));
+        order_books
+    }
+
+    fn recorded(order_books: &mut OrderBooks, market_data: &mut MarketData, new_order: NewOrder) {
+        market_data.record(&order_books.process(InputMessage::New(new_order)));
+    }
+
+    /// Trade 100 at 10 at 09:30:15, 200 at 12 at 09:30:45, and 100 at 11 at 09:31:15
+    fn three_trades(order_books: &mut OrderBooks, market_data: &mut MarketData) {
+        recorded(order_books, market_data, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        recorded(order_books, market_data, NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 1));
+        recorded(order_books, market_data, NewOrder::new(1, "IBM".to_string(), 12, 200, 'S', 2));
+        recorded(order_books, market_data, NewOrder::new(2, "IBM".to_string(), 12, 200, 'B', 2));
+        recorded(order_books, market_data, NewOrder::new(1, "IBM".to_string(), 11, 100, 'B', 3));
+        recorded(order_books, market_data, NewOrder::new(2, "IBM".to_string(), 11, 100, 'S', 3));
+    }
+
+    #[test]
+    fn tape_and_stats() {
+        let mut order_books = order_books();
+        let mut market_data = MarketData::new(Duration::minutes(1));
+        three_trades(&mut order_books, &mut market_data);
+
+        assert_eq!(market_data.tape().len(), 3);
+        assert_eq!(market_data.tape()[2], TradePrint { trade_id: 3, time: "2022-01-03T09:31:15Z".parse::<DateTime<Utc>>().unwrap(), symbol: "IBM".to_string(), price: 11, qty: 100, aggressor_side: Some('S') });
+        assert_eq!(market_data.last_price("IBM"), Some(11));
+        assert_eq!(market_data.volume("IBM"), 400);
+        assert_eq!(market_data.vwap("IBM"), Some(11.25));
+        assert_eq!(market_data.vwap("AAPL"), None);
+    }
+
+    #[test]
+    fn bars_by_engine_time() {
+        let mut order_books = order_books();
+        let mut market_data = MarketData::new(Duration::minutes(1));
+        three_trades(&mut order_books, &mut market_data);
+        let bars = market_data.bars("IBM");
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].start, bars[0].open, bars[0].high, bars[0].low, bars[0].close, bars[0].volume, bars[0].trades), ("2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), 10, 12, 10, 12, 300, 2));
+        assert_eq!(bars[0].vwap(), 34.0 / 3.0);
+        assert_eq!((bars[1].start, bars[1].open, bars[1].close, bars[1].volume), ("2022-01-03T09:31:00Z".parse::<DateTime<Utc>>().unwrap(), 11, 11, 100));
+        assert!(market_data.bars("AAPL").is_empty());
+    }
+
+    #[test]
+    fn bars_per_symbol() {
+        let mut order_books = order_books();
+        let mut market_data = MarketData::new(Duration::hours(1));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(1, "AAPL".to_string(), 20, 50, 'S', 2));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(2, "AAPL".to_string(), 20, 50, 'B', 1));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(market_data.bars("AAPL").len(), 1);
+        assert_eq!((market_data.bars("AAPL")[0].volume, market_data.bars("IBM")[0].volume), (50, 100));
+        assert_eq!(market_data.tape().iter().map(|trade_print| trade_print.symbol.as_str()).collect::<Vec<&str>>(), vec!["AAPL", "IBM"]);
+    }
+
+    #[test]
+    fn bars_export() {
+        let mut order_books = order_books();
+        let mut market_data = MarketData::new(Duration::minutes(1));
+        three_trades(&mut order_books, &mut market_data);
+        let mut buffer = vec![];
+        market_data.write_bars(&mut buffer).unwrap();
+
+        assert_eq!(String::from_utf8(buffer).unwrap(), "symbol,start,open,high,low,close,volume,trades,vwap\n\
+            IBM,2022-01-03T09:30:00Z,10,12,10,12,300,2,11.3333\n\
+            IBM,2022-01-03T09:31:00Z,11,11,11,11,100,1,11.0000\n");
+    }
+
+    #[test]
+    fn tape_export() {
+        let mut order_books = order_books();
+        let mut market_data = MarketData::new(Duration::minutes(1));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        recorded(&mut order_books, &mut market_data, NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 1));
+        let mut buffer = vec![];
+        market_data.write_tape(&mut buffer).unwrap();
+
+        assert_eq!(String::from_utf8(buffer).unwrap(), "trade_id,time,symbol,price,qty,aggressor_side\n1,2022-01-03T09:30:15.000000000Z,IBM,10,100,B\n");
+    }
+}
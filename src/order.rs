@@ -1,8 +1,81 @@
 //! Order structs corresponding to New Order and Cancel Order transaction requests, as well as the ExistingOrder struct
 //! for storing orders in memory
 
+use std::{fmt, str::FromStr};
+
 use chrono::{DateTime, Utc};
 
+use crate::{auction::AuctionAction, peg::Peg, price_band::PriceBand, risk::RiskLimit, trading_status::TradingStatus};
+
+/// How a `NewOrder` is priced and when it becomes active
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderType {
+    /// Rests at `price` if it cannot trade
+    Limit,
+    /// Trades at any price, and is cancelled rather than resting if it cannot trade
+    Market,
+    /// Held until a trade reaches `stop_price`, then enters as a market order
+    Stop { stop_price: u64 },
+    /// Held until a trade reaches `stop_price`, then enters as a limit order at `price`
+    StopLimit { stop_price: u64 }
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Limit => write!(f, "limit"),
+            Self::Market => write!(f, "market"),
+            Self::Stop { stop_price } => write!(f, "stop:{}", stop_price),
+            Self::StopLimit { stop_price } => write!(f, "stop-limit:{}", stop_price)
+        }
+    }
+}
+
+impl FromStr for OrderType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Unknown order type: \"{}\"", s);
+        match s.split_once(':') {
+            None if s == "limit" => Ok(Self::Limit),
+            None if s == "market" => Ok(Self::Market),
+            Some(("stop", stop_price)) => Ok(Self::Stop { stop_price: stop_price.parse().map_err(|_| invalid())? }),
+            Some(("stop-limit", stop_price)) => Ok(Self::StopLimit { stop_price: stop_price.parse().map_err(|_| invalid())? }),
+            _ => Err(invalid())
+        }
+    }
+}
+
+/// What happens to a post-only order that would cross the book and take liquidity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostOnly {
+    /// The order is rejected
+    Reject,
+    /// The order is repriced one tick behind the best opposite price so that it rests instead
+    Reprice
+}
+
+impl fmt::Display for PostOnly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reject => write!(f, "reject"),
+            Self::Reprice => write!(f, "reprice")
+        }
+    }
+}
+
+impl FromStr for PostOnly {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "reprice" => Ok(Self::Reprice),
+            _ => Err(format!("Unknown post-only mode: \"{}\"", s))
+        }
+    }
+}
+
 /// A request to place a new order into OrderBooks
 #[derive(Clone)]
 pub struct NewOrder {
@@ -12,12 +85,100 @@ pub struct NewOrder {
     pub qty: u64,
     pub side: char,
     pub user_order_id: u64,
-    pub time_received: DateTime<Utc>
+    pub time_received: DateTime<Utc>,
+    /// Orders sharing a self-trade group are prevented from trading with each other, as are orders
+    /// from the same user
+    pub self_trade_group: Option<u64>,
+    pub order_type: OrderType,
+    /// For an iceberg order, the quantity shown in the book at any one time, with the rest held in
+    /// reserve. `None` shows the whole quantity.
+    pub display_qty: Option<u64>,
+    /// Set for an order which must only add liquidity, never take it
+    pub post_only: Option<PostOnly>,
+    /// A hidden order can be matched but never appears in the top of book
+    pub hidden: bool,
+    /// Set for a pegged order, whose `price` is set from the top of book when it is placed and
+    /// follows it afterwards
+    pub peg: Option<Peg>
 }
 
 impl NewOrder {
     pub fn new(user: u64, symbol: String, price: u64, qty: u64, side: char, user_order_id: u64) -> NewOrder {
-        NewOrder { user, symbol, price, qty, side, user_order_id, time_received: Utc::now() }
+        NewOrder { user, symbol, price, qty, side, user_order_id, time_received: Utc::now(), self_trade_group: None, order_type: OrderType::Limit, display_qty: None, post_only: None, hidden: false, peg: None }
+    }
+
+    /// A market order, which has no price of its own
+    pub fn new_market(user: u64, symbol: String, qty: u64, side: char, user_order_id: u64) -> NewOrder {
+        NewOrder { order_type: OrderType::Market, ..NewOrder::new(user, symbol, 0, qty, side, user_order_id) }
+    }
+
+    /// A pegged order, priced from the top of book by `peg`
+    pub fn new_pegged(user: u64, symbol: String, peg: Peg, qty: u64, side: char, user_order_id: u64) -> NewOrder {
+        NewOrder { peg: Some(peg), ..NewOrder::new(user, symbol, 0, qty, side, user_order_id) }
+    }
+
+    /// A stop order, which becomes a market order once a trade reaches `stop_price`
+    pub fn new_stop(user: u64, symbol: String, stop_price: u64, qty: u64, side: char, user_order_id: u64) -> NewOrder {
+        NewOrder { order_type: OrderType::Stop { stop_price }, ..NewOrder::new(user, symbol, 0, qty, side, user_order_id) }
+    }
+
+    /// A stop-limit order, which becomes a limit order at `price` once a trade reaches `stop_price`
+    pub fn new_stop_limit(user: u64, symbol: String, stop_price: u64, price: u64, qty: u64, side: char, user_order_id: u64) -> NewOrder {
+        NewOrder { order_type: OrderType::StopLimit { stop_price }, ..NewOrder::new(user, symbol, price, qty, side, user_order_id) }
+    }
+
+    /// Whether this order may trade against a resting order at `price`
+    pub fn accepts_price(&self, price: u64) -> bool {
+        match (self.order_type, self.side) {
+            (OrderType::Market, _) | (OrderType::Stop { .. }, _) => true,
+            (_, 'B') => price <= self.price,
+            _ => price >= self.price
+        }
+    }
+
+    /// Whether a trade at `price` reaches the stop price of this order. Buy stops are reached by
+    /// trades at or above the stop price, and sell stops by trades at or below it.
+    pub fn is_stop_reached(&self, price: u64) -> bool {
+        match self.order_type {
+            OrderType::Stop { stop_price } | OrderType::StopLimit { stop_price } =>
+                (self.side == 'B' && price >= stop_price) || (self.side == 'S' && price <= stop_price),
+            _ => false
+        }
+    }
+
+    /// The order that enters the book once the stop price is reached
+    pub fn triggered(&self) -> NewOrder {
+        let order_type = match self.order_type {
+            OrderType::Stop { .. } => OrderType::Market,
+            OrderType::StopLimit { .. } => OrderType::Limit,
+            order_type => order_type
+        };
+        NewOrder { order_type, ..self.clone() }
+    }
+
+    /// Place this order into a self-trade group
+    pub fn with_self_trade_group(mut self, self_trade_group: u64) -> NewOrder {
+        self.self_trade_group = Some(self_trade_group);
+        self
+    }
+
+    /// Make this an iceberg order which shows at most `display_qty` in the book
+    pub fn with_display_qty(mut self, display_qty: u64) -> NewOrder {
+        assert!(display_qty > 0, "Invalid New Order. Display quantity must be greater than 0.");
+        self.display_qty = Some(display_qty);
+        self
+    }
+
+    /// Make this a post-only order, which is handled by `post_only` instead of crossing the book
+    pub fn with_post_only(mut self, post_only: PostOnly) -> NewOrder {
+        self.post_only = Some(post_only);
+        self
+    }
+
+    /// Make this a hidden order, which is left out of the top of book
+    pub fn with_hidden(mut self) -> NewOrder {
+        self.hidden = true;
+        self
     }
 }
 
@@ -26,20 +187,47 @@ impl NewOrder {
 pub struct ExistingOrder {
     pub user: u64,
     pub price: u64,
+    /// The quantity shown in the book, which for an iceberg order is its current slice
     pub qty: u64,
     pub user_order_id: u64,
-    pub time_received: DateTime<Utc>
+    pub time_received: DateTime<Utc>,
+    pub self_trade_group: Option<u64>,
+    /// The size of each slice of an iceberg order
+    pub display_qty: Option<u64>,
+    /// The quantity of an iceberg order held back from the book
+    pub reserve_qty: u64,
+    /// Left out of the top of book, although it can still be matched
+    pub hidden: bool,
+    /// How a pegged order follows the top of book
+    pub peg: Option<Peg>
 }
 
 impl ExistingOrder {
     pub fn new(new_order: NewOrder) -> ExistingOrder {
+        let qty = new_order.display_qty.map_or(new_order.qty, |display_qty| display_qty.min(new_order.qty));
         ExistingOrder {
             user: new_order.user,
             price: new_order.price,
-            qty: new_order.qty,
+            qty,
             user_order_id: new_order.user_order_id,
-            time_received: new_order.time_received
+            time_received: new_order.time_received,
+            self_trade_group: new_order.self_trade_group,
+            display_qty: new_order.display_qty,
+            reserve_qty: new_order.qty - qty,
+            hidden: new_order.hidden,
+            peg: new_order.peg
+        }
+    }
+
+    /// The next slice of an iceberg order whose shown quantity has been used up, taking fresh time
+    /// priority at `time_received`. Returns `None` once the reserve is empty.
+    pub fn replenished(&self, time_received: DateTime<Utc>) -> Option<ExistingOrder> {
+        let display_qty = self.display_qty?;
+        if self.reserve_qty == 0 {
+            return None;
         }
+        let qty = display_qty.min(self.reserve_qty);
+        Some(ExistingOrder { qty, time_received, reserve_qty: self.reserve_qty - qty, ..*self })
     }
 }
 
@@ -54,4 +242,153 @@ impl CancelOrder {
     pub fn new(user: u64, user_order_id: u64) -> CancelOrder {
         CancelOrder { user, user_order_id }
     }
+}
+
+/// The format of a request to flush orders, optionally limited to one symbol and/or one user
+#[derive(Clone)]
+pub struct FlushOrder {
+    pub symbol: Option<String>,
+    pub user: Option<u64>,
+    /// When false, removed orders do not each produce an `OrderResult::Cancellation`
+    pub report_cancellations: bool
+}
+
+impl FlushOrder {
+    pub fn new(symbol: Option<String>, user: Option<u64>, report_cancellations: bool) -> FlushOrder {
+        FlushOrder { symbol, user, report_cancellations }
+    }
+
+    /// Flush every order in every `OrderBook`, reporting each cancellation
+    pub fn all() -> FlushOrder {
+        FlushOrder::new(None, None, true)
+    }
+}
+
+/// The format of a request to cancel every order matching the given user, symbol and side. At
+/// least one of `user` or `symbol` must be given.
+#[derive(Clone)]
+pub struct MassCancelOrder {
+    pub user: Option<u64>,
+    pub symbol: Option<String>,
+    pub side: Option<char>
+}
+
+impl MassCancelOrder {
+    pub fn new(user: Option<u64>, symbol: Option<String>, side: Option<char>) -> MassCancelOrder {
+        assert!(user.is_some() || symbol.is_some(), "Invalid Mass Cancel Order. Mass cancel must name a user or a symbol.");
+        assert!(side.is_none() || side == Some('B') || side == Some('S'), "Invalid Mass Cancel Order. Side must be B or S.");
+        MassCancelOrder { user, symbol, side }
+    }
+
+    /// Cancel all orders for `user`
+    pub fn by_user(user: u64) -> MassCancelOrder {
+        MassCancelOrder::new(Some(user), None, None)
+    }
+
+    /// Cancel all orders for `symbol`
+    pub fn by_symbol(symbol: &str) -> MassCancelOrder {
+        MassCancelOrder::new(None, Some(symbol.to_string()), None)
+    }
+
+    /// Cancel all orders for `user` on one `side` of `symbol`
+    pub fn by_user_and_side(user: u64, symbol: &str, side: char) -> MassCancelOrder {
+        MassCancelOrder::new(Some(user), Some(symbol.to_string()), Some(side))
+    }
+}
+
+/// A request to start or uncross a call auction for one symbol
+#[derive(Clone)]
+pub struct AuctionOrder {
+    pub symbol: String,
+    pub action: AuctionAction
+}
+
+impl AuctionOrder {
+    pub fn new(symbol: &str, action: AuctionAction) -> AuctionOrder {
+        AuctionOrder { symbol: symbol.to_string(), action }
+    }
+
+    /// Begin collecting orders for an auction on `symbol`
+    pub fn start(symbol: &str) -> AuctionOrder {
+        AuctionOrder::new(symbol, AuctionAction::Start)
+    }
+
+    /// Uncross the auction on `symbol` and resume continuous trading
+    pub fn uncross(symbol: &str) -> AuctionOrder {
+        AuctionOrder::new(symbol, AuctionAction::Uncross)
+    }
+}
+
+/// A request to move one symbol, or every symbol when `symbol` is `None`, to a new trading status
+#[derive(Clone)]
+pub struct TradingStatusOrder {
+    pub symbol: Option<String>,
+    pub status: TradingStatus
+}
+
+impl TradingStatusOrder {
+    pub fn new(symbol: Option<&str>, status: TradingStatus) -> TradingStatusOrder {
+        TradingStatusOrder { symbol: symbol.map(|symbol| symbol.to_string()), status }
+    }
+
+    /// Move every symbol, including those without an `OrderBook` yet, to `status`
+    pub fn all(status: TradingStatus) -> TradingStatusOrder {
+        TradingStatusOrder::new(None, status)
+    }
+}
+
+/// A request to set the price band of one symbol, or of every symbol when `symbol` is `None`,
+/// removing it when `price_band` is `None`
+#[derive(Clone)]
+pub struct PriceBandOrder {
+    pub symbol: Option<String>,
+    pub price_band: Option<PriceBand>
+}
+
+impl PriceBandOrder {
+    pub fn new(symbol: Option<&str>, price_band: Option<PriceBand>) -> PriceBandOrder {
+        PriceBandOrder { symbol: symbol.map(|symbol| symbol.to_string()), price_band }
+    }
+}
+
+/// A request to set one risk limit of `user`, or the default limit when `user` is `None`, removing
+/// it when `value` is `None`
+#[derive(Clone)]
+pub struct RiskLimitOrder {
+    pub user: Option<u64>,
+    pub limit: RiskLimit,
+    pub value: Option<u64>
+}
+
+impl RiskLimitOrder {
+    pub fn new(user: Option<u64>, limit: RiskLimit, value: Option<u64>) -> RiskLimitOrder {
+        RiskLimitOrder { user, limit, value }
+    }
+}
+
+/// A request for the lifecycle status of the order of `user` with `user_order_id`
+#[derive(Clone)]
+pub struct OrderStatusQuery {
+    pub user: u64,
+    pub user_order_id: u64
+}
+
+impl OrderStatusQuery {
+    pub fn new(user: u64, user_order_id: u64) -> OrderStatusQuery {
+        OrderStatusQuery { user, user_order_id }
+    }
+}
+
+/// Any transaction message accepted by `OrderBooks::process`
+#[derive(Clone)]
+pub enum InputMessage {
+    New(NewOrder),
+    Cancel(CancelOrder),
+    MassCancel(MassCancelOrder),
+    Flush(FlushOrder),
+    Auction(AuctionOrder),
+    TradingStatus(TradingStatusOrder),
+    PriceBand(PriceBandOrder),
+    RiskLimit(RiskLimitOrder),
+    OrderStatus(OrderStatusQuery)
 }
\ No newline at end of file
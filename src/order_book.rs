@@ -1,8 +1,10 @@
 //! An order book for one symbol, managing both buy and sell orders
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
-use crate::{order_result::OrderResult, order::{ExistingOrder, NewOrder, CancelOrder}};
+use chrono::{DateTime, Utc};
+
+use crate::{auction::IndicativeUncross, price_band::{BreakerAction, CircuitBreaker, PriceBand}, risk::Exposure, trading_status::{HaltPolicy, TradingStatus}, order_result::OrderResult, order::{ExistingOrder, NewOrder, CancelOrder, OrderType, PostOnly}, self_trade_prevention::{SelfTradePrevention, is_self_trade}};
 
 /// Describe the top of the current `OrderBook` for a particular side
 #[derive(PartialEq)]
@@ -45,33 +47,224 @@ impl OrderBookLocation {
 
 /// Maintains a buy and sell book for a given symbol
 pub struct OrderBook {
-    _symbol: String,
+    symbol: String,
     /// key is price; `Vec<ExistingOrder>` is sorted by time_received
     buy_orders: BTreeMap<u64, Vec<ExistingOrder>>,
     /// key is price; `Vec<ExistingOrder>` is sorted by time_received
     sell_orders: BTreeMap<u64, Vec<ExistingOrder>>,
-    trading_enabled: bool
+    /// Stop and stop-limit orders waiting for a trade to reach their stop price, in the order
+    /// they were received
+    stop_orders: Vec<NewOrder>,
+    /// The time the latest new order was received, which gives repriced pegged orders their place
+    /// at the back of their new price level
+    latest_time_received: Option<DateTime<Utc>>,
+    /// The engine time of the latest new order or trading status change, at which any trades it
+    /// causes are executed
+    current_time: DateTime<Utc>,
+    /// The phase of the trading session, which decides whether new orders are matched, collected
+    /// for an auction, queued or rejected
+    trading_status: TradingStatus,
+    halt_policy: HaltPolicy,
+    /// New orders received before the book opened or while it was halted, in the order they were
+    /// received, waiting to be entered when trading resumes
+    queued_orders: Vec<NewOrder>,
+    /// The indicative uncross most recently published during the current auction
+    published_indicative: Option<IndicativeUncross>,
+    /// Limit prices outside this band are rejected
+    price_band: Option<PriceBand>,
+    circuit_breaker: Option<CircuitBreaker>,
+    /// The price of the most recent trade, which is the reference price of the price band
+    last_trade_price: Option<u64>,
+    /// The time and price of recent trades, oldest first, kept for the circuit breaker window
+    recent_trades: VecDeque<(DateTime<Utc>, u64)>,
+    /// When the interruption caused by a tripped circuit breaker ends
+    breaker_resumes_at: Option<DateTime<Utc>>,
+    /// The quantity each user has bought less the quantity they have sold, keyed by user
+    positions: HashMap<u64, i64>,
+    trading_enabled: bool,
+    self_trade_prevention: SelfTradePrevention
 }
 
 impl OrderBook {
     pub fn new(symbol: &str, trading_enabled: bool) -> OrderBook {
         OrderBook {
-            _symbol: symbol.to_string(),
+            symbol: symbol.to_string(),
             buy_orders: BTreeMap::new(),
             sell_orders: BTreeMap::new(),
-            trading_enabled
+            stop_orders: vec![],
+            latest_time_received: None,
+            current_time: Utc::now(),
+            trading_status: TradingStatus::Continuous,
+            halt_policy: HaltPolicy::Reject,
+            queued_orders: vec![],
+            published_indicative: None,
+            price_band: None,
+            circuit_breaker: None,
+            last_trade_price: None,
+            recent_trades: VecDeque::new(),
+            breaker_resumes_at: None,
+            positions: HashMap::new(),
+            trading_enabled,
+            self_trade_prevention: SelfTradePrevention::Allow
         }
     }
 
+    /// The symbol this `OrderBook` holds orders for
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The resting orders on one side of the book, keyed by price
+    pub(crate) fn resting_orders(&self, side: char) -> &BTreeMap<u64, Vec<ExistingOrder>> {
+        if side == 'B' { &self.buy_orders } else { &self.sell_orders }
+    }
+
+    /// Place an order directly at the back of its price level without matching or producing
+    /// results, as when restoring a snapshot whose levels are already in priority order
+    pub(crate) fn restore_order(&mut self, side: char, existing_order: ExistingOrder) {
+        let orders = if side == 'B' { &mut self.buy_orders } else { &mut self.sell_orders };
+        orders.entry(existing_order.price).or_default().push(existing_order);
+    }
+
+    /// The stop and stop-limit orders waiting to be triggered, in the order they were received
+    pub(crate) fn stop_orders(&self) -> &[NewOrder] {
+        &self.stop_orders
+    }
+
+    /// Place a stop or stop-limit order directly at the back of the waiting stop orders, as when
+    /// restoring a snapshot
+    pub(crate) fn restore_stop_order(&mut self, stop_order: NewOrder) {
+        self.stop_orders.push(stop_order);
+    }
+
+    /// New orders waiting for trading to resume, in the order they were received
+    pub(crate) fn queued_orders(&self) -> &[NewOrder] {
+        &self.queued_orders
+    }
+
+    /// Place an order directly at the back of the queued orders, as when restoring a snapshot
+    pub(crate) fn restore_queued_order(&mut self, queued_order: NewOrder) {
+        self.queued_orders.push(queued_order);
+    }
+
+    /// Whether the order of `user` with `user_order_id` is resting in the book, waiting to be
+    /// triggered or queued
+    pub fn holds_order(&self, user: u64, user_order_id: u64) -> bool {
+        let is_order = |new_order: &NewOrder| new_order.user == user && new_order.user_order_id == user_order_id;
+        self.find_order_by_id(user, user_order_id).is_some() || self.stop_orders.iter().any(is_order) || self.queued_orders.iter().any(is_order)
+    }
+
+    /// Set the trading status without publishing anything or entering queued orders, as when
+    /// restoring a snapshot or creating an `OrderBook` during a trading session
+    pub(crate) fn restore_trading_status(&mut self, trading_status: TradingStatus) {
+        self.trading_status = trading_status;
+        self.published_indicative = None;
+    }
+
+    /// The phase of the trading session this `OrderBook` is in
+    pub fn trading_status(&self) -> TradingStatus {
+        self.trading_status
+    }
+
+    /// Whether a call auction is collecting orders for this `OrderBook`
+    pub fn is_in_auction(&self) -> bool {
+        self.trading_status == TradingStatus::Auction
+    }
+
+    /// Reject limit orders priced outside `price_band`, or accept any price when `None`
+    pub fn set_price_band(&mut self, price_band: Option<PriceBand>) {
+        self.price_band = price_band;
+    }
+
+    pub fn price_band(&self) -> Option<PriceBand> {
+        self.price_band
+    }
+
+    /// Interrupt continuous trading when trade prices move too far too quickly, or never when `None`
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: Option<CircuitBreaker>) {
+        self.circuit_breaker = circuit_breaker;
+    }
+
+    /// The price of the most recent trade
+    pub fn last_trade_price(&self) -> Option<u64> {
+        self.last_trade_price
+    }
+
+    /// When continuous trading resumes after a tripped circuit breaker, if it has tripped
+    pub fn breaker_resumes_at(&self) -> Option<DateTime<Utc>> {
+        self.breaker_resumes_at
+    }
+
+    /// Set the last trade price and the end of any circuit breaker interruption, as when restoring a
+    /// snapshot. Trades within the circuit breaker window are not restored.
+    pub(crate) fn restore_market_state(&mut self, last_trade_price: Option<u64>, breaker_resumes_at: Option<DateTime<Utc>>) {
+        self.last_trade_price = last_trade_price;
+        self.breaker_resumes_at = breaker_resumes_at;
+    }
+
+    /// The quantity `user` has bought less the quantity they have sold in this `OrderBook`
+    pub fn position(&self, user: u64) -> i64 {
+        self.positions.get(&user).copied().unwrap_or_default()
+    }
+
+    /// Every user with a position other than zero, in user order, with their position
+    pub(crate) fn positions(&self) -> Vec<(u64, i64)> {
+        let mut positions: Vec<(u64, i64)> = self.positions.iter().filter(|(_, position)| **position != 0).map(|(user, position)| (*user, *position)).collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Set the position of `user`, as when restoring a snapshot
+    pub(crate) fn restore_position(&mut self, user: u64, position: i64) {
+        self.positions.insert(user, position);
+    }
+
+    /// The open orders of `user` in this `OrderBook`, including stop and queued orders, with their
+    /// position and the full open quantity on each side. `open_orders` counts only this `OrderBook`.
+    pub fn exposure(&self, user: u64) -> Exposure {
+        let mut exposure = Exposure { net_position: self.position(user), ..Exposure::default() };
+        let resting_orders = self.buy_orders.values().flatten().map(|existing_order| ('B', existing_order.user, existing_order.qty + existing_order.reserve_qty))
+            .chain(self.sell_orders.values().flatten().map(|existing_order| ('S', existing_order.user, existing_order.qty + existing_order.reserve_qty)));
+        let waiting_orders = self.stop_orders.iter().chain(self.queued_orders.iter()).map(|new_order| (new_order.side, new_order.user, new_order.qty));
+        for (side, _, qty) in resting_orders.chain(waiting_orders).filter(|(_, order_user, _)| *order_user == user) {
+            exposure.open_orders += 1;
+            if side == 'B' {
+                exposure.open_buy_qty += qty;
+            } else {
+                exposure.open_sell_qty += qty;
+            }
+        }
+        exposure
+    }
+
+    /// The price used to value `new_order` for risk checks: its limit price, or its stop price for a
+    /// stop order, or the limit of a pegged order, and otherwise the best opposite price it would
+    /// trade against, or zero if none
+    pub fn risk_price(&self, new_order: &NewOrder) -> u64 {
+        let best_opposite_price = if new_order.side == 'B' { self.sell_orders.keys().next() } else { self.buy_orders.keys().next_back() };
+        match (new_order.order_type, new_order.peg) {
+            (OrderType::Limit | OrderType::StopLimit { .. }, None) => new_order.price,
+            (OrderType::Stop { stop_price }, _) => stop_price,
+            (_, Some(peg)) => peg.limit.or(best_opposite_price.copied()).unwrap_or_default(),
+            _ => best_opposite_price.copied().unwrap_or_default()
+        }
+    }
+
+    /// Choose whether new orders are rejected or queued while this `OrderBook` is halted
+    pub fn set_halt_policy(&mut self, halt_policy: HaltPolicy) {
+        self.halt_policy = halt_policy;
+    }
+
+    /// Choose how matches between orders from the same user or self-trade group are handled
+    pub fn set_self_trade_prevention(&mut self, self_trade_prevention: SelfTradePrevention) {
+        self.self_trade_prevention = self_trade_prevention;
+    }
+
     /// Identify whether a new sell order is below the highest buy or a new buy order is above
     /// the highest sell
     fn crosses_book(&self, new_order: &NewOrder) -> bool {
-        if new_order.side == 'B' && self.is_above_lowest_sell_price(new_order.price) {
-            return true;
-        } else if new_order.side == 'S' && self.is_below_highest_buy_price(new_order.price) {
-            return true;
-        }
-        false
+        (new_order.side == 'B' && self.is_above_lowest_sell_price(new_order.price)) ||
+            (new_order.side == 'S' && self.is_below_highest_buy_price(new_order.price))
     }
 
     /// Is `buy_price` above lowest price in `sell_orders`
@@ -92,11 +285,17 @@ impl OrderBook {
         }
     }
 
-    /// Get the highest price and quantity in `buy_orders`
+    /// The quantity shown at a price level, leaving out hidden orders
+    fn displayed_quantity(existing_orders: &[ExistingOrder]) -> u64 {
+        existing_orders.iter().filter(|existing_order| !existing_order.hidden).map(|existing_order| existing_order.qty).sum::<u64>()
+    }
+
+    /// Get the highest price and quantity in `buy_orders`, skipping levels holding only hidden orders
     fn get_top_of_buy_book(&self) -> TopOfBook {
-        if let Some(top) = self.buy_orders.iter().rev().nth(0) {
-            let price = *top.0;
-            let total_quantity = top.1.iter().map(|existing_order| existing_order.qty).sum::<u64>();
+        let top = self.buy_orders.iter().rev()
+            .map(|(price, existing_orders)| (*price, OrderBook::displayed_quantity(existing_orders)))
+            .find(|(_, total_quantity)| *total_quantity > 0);
+        if let Some((price, total_quantity)) = top {
             TopOfBook::new('B', Some(price), Some(total_quantity))
         } else {
             TopOfBook::new('B', None, None)
@@ -108,11 +307,10 @@ impl OrderBook {
         let current_top = self.get_top_of_buy_book();
         
         let mut order_results = vec![];
-        order_results.push(OrderResult::Acknowledgement{user: new_order.user, user_order_id: new_order.user_order_id});
         
         if let Some(v) = self.buy_orders.get_mut(&new_order.price) {
             v.push(ExistingOrder::new(new_order));
-            v.sort_by(|a, b| a.time_received.cmp(&b.time_received));
+            v.sort_by_key(|existing_order| existing_order.time_received);
         } else {
             self.buy_orders.insert(new_order.price, vec![ExistingOrder::new(new_order)]);
         }
@@ -125,11 +323,12 @@ impl OrderBook {
         order_results
     }
 
-    /// Get the lowest price and quantity in `sell_orders`
+    /// Get the lowest price and quantity in `sell_orders`, skipping levels holding only hidden orders
     fn get_top_of_sell_book(&self) -> TopOfBook {
-        if let Some(top) = self.sell_orders.iter().nth(0) {
-            let price = *top.0;
-            let total_quantity = top.1.iter().map(|existing_order| existing_order.qty).sum::<u64>();
+        let top = self.sell_orders.iter()
+            .map(|(price, existing_orders)| (*price, OrderBook::displayed_quantity(existing_orders)))
+            .find(|(_, total_quantity)| *total_quantity > 0);
+        if let Some((price, total_quantity)) = top {
             TopOfBook::new('S', Some(price), Some(total_quantity))
         } else {
             TopOfBook::new('S', None, None)
@@ -141,11 +340,10 @@ impl OrderBook {
         let current_top = self.get_top_of_sell_book();
         
         let mut order_results = vec![];
-        order_results.push(OrderResult::Acknowledgement{user: new_order.user, user_order_id: new_order.user_order_id});
 
         if let Some(v) = self.sell_orders.get_mut(&new_order.price) {
             v.push(ExistingOrder::new(new_order));
-            v.sort_by(|a, b| a.time_received.cmp(&b.time_received));
+            v.sort_by_key(|existing_order| existing_order.time_received);
         } else {
             self.sell_orders.insert(new_order.price, vec![ExistingOrder::new(new_order)]);
         }    
@@ -160,81 +358,601 @@ impl OrderBook {
 
     /// Adds order to proper side (buy or sell) of book. When trading is disabled, attempting
     /// to cross the book results in rejection. When trading is enabled, crossing the book will
-    /// result in a matched trade.
+    /// result in a matched trade. Stop and stop-limit orders are held aside until a trade reaches
+    /// their stop price, and every trade is checked against the waiting stop orders. Pegged orders
+    /// are then repriced to follow any change to the top of book. During an auction, orders are
+    /// added by `add_auction_order` instead. Before the book opens, and while it is halted with
+    /// `HaltPolicy::Queue`, orders are acknowledged and queued until trading resumes; once closed,
+    /// or while halted with `HaltPolicy::Reject`, they are rejected. Limit and stop-limit orders
+    /// priced outside the price band are rejected whatever the trading status.
     pub fn add_order(&mut self, new_order: NewOrder) -> Vec<OrderResult> {
         assert!(new_order.side == 'B' || new_order.side == 'S', "Invalid New Order. New order must be B or S.");
-        if self.crosses_book(&new_order) {
-            if self.trading_enabled {
-                self.attempt_order_match(new_order)
-            } else {
+        self.latest_time_received = Some(new_order.time_received);
+        self.current_time = new_order.time_received;
+        if !self.is_within_price_band(&new_order) {
+            return vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }];
+        }
+        match (self.trading_status, self.halt_policy) {
+            (TradingStatus::PreOpen, _) | (TradingStatus::Halted, HaltPolicy::Queue) => {
+                let acknowledgement = OrderResult::Acknowledgement { user: new_order.user, user_order_id: new_order.user_order_id };
+                self.queued_orders.push(new_order);
+                vec![acknowledgement]
+            },
+            (TradingStatus::Halted, HaltPolicy::Reject) | (TradingStatus::Closed, _) => {
                 vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }]
+            },
+            (TradingStatus::Auction, _) | (TradingStatus::Continuous, _) => self.enter_order(new_order, true)
+        }
+    }
+
+    /// Whether the limit price of `new_order`, if it has one, is inside the price band. Market,
+    /// stop and pegged orders have no limit price to check.
+    fn is_within_price_band(&self, new_order: &NewOrder) -> bool {
+        match (self.price_band, new_order.order_type, new_order.peg) {
+            (Some(price_band), OrderType::Limit | OrderType::StopLimit { .. }, None) => price_band.allows(new_order.price, self.last_trade_price),
+            _ => true
+        }
+    }
+
+    /// Enter an order into an open book, acknowledging it unless it was acknowledged when queued
+    fn enter_order(&mut self, new_order: NewOrder, acknowledge: bool) -> Vec<OrderResult> {
+        let time_received = new_order.time_received;
+        if self.trading_status == TradingStatus::Auction {
+            return self.add_auction_order(new_order, acknowledge);
+        }
+        let mut order_results = match new_order.order_type {
+            OrderType::Stop { .. } | OrderType::StopLimit { .. } => {
+                let mut order_results = vec![];
+                if acknowledge {
+                    order_results.push(OrderResult::Acknowledgement { user: new_order.user, user_order_id: new_order.user_order_id });
+                }
+                self.stop_orders.push(new_order);
+                order_results
+            },
+            _ => self.add_active_order(new_order, acknowledge)
+        };
+        self.trigger_stop_orders(&mut order_results, time_received);
+        self.reprice_pegged_orders(&mut order_results);
+        self.record_trades(&mut order_results, time_received);
+        order_results
+    }
+
+    /// Add a limit or market order, matching it if it crosses the book. Triggered stop orders were
+    /// acknowledged when first received, so are not acknowledged again. A post-only order that
+    /// would cross is rejected or repriced whether or not trading is enabled. A pegged order is
+    /// priced from the top of book, and rejected if the prices it follows are missing.
+    fn add_active_order(&mut self, mut new_order: NewOrder, acknowledge: bool) -> Vec<OrderResult> {
+        if let Some(peg) = new_order.peg {
+            let (best_bid, best_ask) = self.reference_prices();
+            match peg.price(new_order.side, best_bid, best_ask) {
+                Some(price) => new_order.price = price,
+                None => return vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }]
+            }
+        }
+        let is_market_order = new_order.order_type == OrderType::Market;
+        if let Some(post_only) = new_order.post_only {
+            if is_market_order || self.crosses_book(&new_order) {
+                match (post_only, self.price_behind_opposite_book(&new_order)) {
+                    (PostOnly::Reprice, Some(price)) if !is_market_order => new_order.price = price,
+                    _ => return vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }]
+                }
             }
+        }
+        if (is_market_order || self.crosses_book(&new_order)) && !self.trading_enabled {
+            return vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }];
+        }
+
+        let mut order_results = vec![];
+        if acknowledge {
+            order_results.push(OrderResult::Acknowledgement { user: new_order.user, user_order_id: new_order.user_order_id });
+        }
+        if is_market_order || self.crosses_book(&new_order) {
+            order_results.append(&mut self.attempt_order_match(new_order));
         } else if new_order.side == 'B' {
-            self.add_buy_order(new_order)
+            order_results.append(&mut self.add_buy_order(new_order));
         } else if new_order.side == 'S' {
-            self.add_sell_order(new_order)
+            order_results.append(&mut self.add_sell_order(new_order));
+        }
+        order_results
+    }
+
+    /// The price one tick behind the best opposite price, where `new_order` would rest without
+    /// crossing the book
+    fn price_behind_opposite_book(&self, new_order: &NewOrder) -> Option<u64> {
+        if new_order.side == 'B' {
+            self.sell_orders.keys().next().and_then(|lowest_sell_price| lowest_sell_price.checked_sub(1)).filter(|price| *price > 0)
         } else {
-            vec![]
+            self.buy_orders.keys().next_back().map(|highest_buy_price| highest_buy_price + 1)
         }
     }
 
-    /// Find a matching order for `new_order` on the other side of the `OrderBook`
-    fn attempt_order_match(&mut self, new_order: NewOrder) -> Vec<OrderResult> {
+    /// Add an order during an auction. Limit and pegged orders rest in the book even when they cross
+    /// it, whether or not trading is enabled, and stop orders wait as usual. Market orders cannot
+    /// rest, so are rejected. The indicative uncross is published if the order changed it.
+    fn add_auction_order(&mut self, mut new_order: NewOrder, acknowledge: bool) -> Vec<OrderResult> {
+        let rejection = vec![OrderResult::Rejection { user: new_order.user, user_order_id: new_order.user_order_id }];
         let mut order_results = vec![];
-
-        order_results.push(OrderResult::Acknowledgement { user: new_order.user, user_order_id: new_order.user_order_id });
-        
+        if acknowledge {
+            order_results.push(OrderResult::Acknowledgement { user: new_order.user, user_order_id: new_order.user_order_id });
+        }
+        match new_order.order_type {
+            OrderType::Market => return rejection,
+            OrderType::Stop { .. } | OrderType::StopLimit { .. } => {
+                self.stop_orders.push(new_order);
+                return order_results;
+            },
+            OrderType::Limit => ()
+        }
+        if let Some(peg) = new_order.peg {
+            let (best_bid, best_ask) = self.reference_prices();
+            match peg.price(new_order.side, best_bid, best_ask) {
+                Some(price) => new_order.price = price,
+                None => return rejection
+            }
+        }
         if new_order.side == 'B' {
-            let current_top = self.get_top_of_sell_book();
-            if let Some(order_book_location) = self.match_order(&new_order) {
-                let existing_order = self.remove_order(order_book_location);
-                order_results.push(OrderResult::Trade { 
-                    user_buy: new_order.user, 
-                    user_order_id_buy: new_order.user_order_id, 
-                    user_sell: existing_order.user, 
-                    user_order_id_sell: existing_order.user_order_id, 
-                    price: existing_order.price, 
-                    qty: existing_order.qty });
-                let new_top = self.get_top_of_sell_book();
-                if new_top != current_top {
-                    order_results.push(new_top.to_order_result());
+            order_results.append(&mut self.add_buy_order(new_order));
+        } else {
+            order_results.append(&mut self.add_sell_order(new_order));
+        }
+        self.publish_indicative(&mut order_results);
+        order_results
+    }
+
+    /// Move to `trading_status`, publishing the change, or do nothing if already there. Entering an
+    /// auction publishes the indicative uncross of the orders already resting. Entering continuous
+    /// trading first executes any orders left crossing by an auction, as `uncross` does, with
+    /// time priority `time`. Either way, queued orders are then entered in the order they were
+    /// received. Closing the book cancels its queued orders.
+    pub fn set_trading_status(&mut self, trading_status: TradingStatus, time: DateTime<Utc>) -> Vec<OrderResult> {
+        let mut order_results = vec![];
+        if trading_status == self.trading_status {
+            return order_results;
+        }
+        self.trading_status = trading_status;
+        self.current_time = time;
+        self.published_indicative = None;
+        self.recent_trades.clear();
+        self.breaker_resumes_at = None;
+        order_results.push(OrderResult::TradingStatusChange { status: trading_status });
+
+        match trading_status {
+            TradingStatus::Auction => {
+                self.publish_indicative(&mut order_results);
+                self.enter_queued_orders(&mut order_results);
+            },
+            TradingStatus::Continuous => {
+                self.latest_time_received = Some(time);
+                self.execute_uncross(&mut order_results);
+                self.trigger_stop_orders(&mut order_results, time);
+                self.reprice_pegged_orders(&mut order_results);
+                self.record_trades(&mut order_results, time);
+                self.enter_queued_orders(&mut order_results);
+            },
+            TradingStatus::Closed => {
+                for queued_order in self.queued_orders.drain(..) {
+                    order_results.push(OrderResult::Cancellation { user: queued_order.user, user_order_id: queued_order.user_order_id });
                 }
+            },
+            TradingStatus::PreOpen | TradingStatus::Halted => ()
+        }
+        order_results
+    }
+
+    /// Enter queued orders in the order they were received, stopping if a tripped circuit breaker
+    /// interrupts trading
+    fn enter_queued_orders(&mut self, order_results: &mut Vec<OrderResult>) {
+        while self.trading_status == TradingStatus::Auction || self.trading_status == TradingStatus::Continuous {
+            if self.queued_orders.is_empty() {
+                break;
             }
-        } else if new_order.side == 'S' {
-            let current_top = self.get_top_of_buy_book();
-            if let Some(order_book_location) = self.match_order(&new_order) {
-                let existing_order = self.remove_order(order_book_location);
-                order_results.push(OrderResult::Trade { 
-                    user_buy: existing_order.user, 
-                    user_order_id_buy: existing_order.user_order_id, 
-                    user_sell: new_order.user, 
-                    user_order_id_sell: new_order.user_order_id, 
-                    price: existing_order.price, 
-                    qty: existing_order.qty });
-                let new_top = self.get_top_of_buy_book();
-                if new_top != current_top {
-                    order_results.push(new_top.to_order_result());
+            let queued_order = self.queued_orders.remove(0);
+            self.latest_time_received = Some(queued_order.time_received);
+            order_results.append(&mut self.enter_order(queued_order, false));
+        }
+    }
+
+    /// Note the price of each trade in `order_results`, made at `time`, and update the positions of
+    /// both users. During continuous trading, a
+    /// trade which moves too far from a trade within the circuit breaker window trips the breaker,
+    /// halting the book or starting a volatility auction until the cooldown ends.
+    fn record_trades(&mut self, order_results: &mut Vec<OrderResult>, time: DateTime<Utc>) {
+        let trades: Vec<(u64, u64, u64, u64)> = order_results.iter().filter_map(|order_result| match order_result {
+            OrderResult::Trade { user_buy, user_sell, price, qty, .. } => Some((*user_buy, *user_sell, *price, *qty)),
+            _ => None
+        }).collect();
+        for (user_buy, user_sell, price, qty) in trades {
+            *self.positions.entry(user_buy).or_default() += qty as i64;
+            *self.positions.entry(user_sell).or_default() -= qty as i64;
+            self.last_trade_price = Some(price);
+            let circuit_breaker = match self.circuit_breaker {
+                Some(circuit_breaker) => circuit_breaker,
+                None => continue
+            };
+            while self.recent_trades.front().map_or(false, |(trade_time, _)| *trade_time < time - circuit_breaker.window) {
+                self.recent_trades.pop_front();
+            }
+            let is_tripped = self.trading_status == TradingStatus::Continuous &&
+                self.recent_trades.iter().any(|(_, earlier_price)| circuit_breaker.is_tripped_by(price, *earlier_price));
+            self.recent_trades.push_back((time, price));
+            if is_tripped {
+                let trading_status = match circuit_breaker.action {
+                    BreakerAction::Halt => TradingStatus::Halted,
+                    BreakerAction::Auction => TradingStatus::Auction
+                };
+                order_results.append(&mut self.set_trading_status(trading_status, time));
+                self.breaker_resumes_at = Some(time + circuit_breaker.cooldown);
+            }
+        }
+    }
+
+    /// Resume continuous trading if the interruption caused by a tripped circuit breaker has ended
+    /// by `time`, uncrossing a volatility auction
+    pub fn resume_after_breaker(&mut self, time: DateTime<Utc>) -> Vec<OrderResult> {
+        match self.breaker_resumes_at {
+            Some(breaker_resumes_at) if time >= breaker_resumes_at => self.set_trading_status(TradingStatus::Continuous, time),
+            _ => vec![]
+        }
+    }
+
+    /// Stop continuous trading and begin collecting orders for a call auction, publishing the
+    /// indicative uncross of the orders already resting. Queued orders entered as the auction
+    /// begins take time priority `time`.
+    pub fn start_auction(&mut self, time: DateTime<Utc>) -> Vec<OrderResult> {
+        self.set_trading_status(TradingStatus::Auction, time)
+    }
+
+    /// The price the current auction would uncross at if it ended now, counting the full quantity
+    /// of hidden and iceberg orders
+    pub fn indicative_uncross(&self) -> IndicativeUncross {
+        let levels = |orders: &BTreeMap<u64, Vec<ExistingOrder>>| -> Vec<(u64, u64)> {
+            orders.iter().map(|(price, existing_orders)| (*price, existing_orders.iter().map(|existing_order| existing_order.qty + existing_order.reserve_qty).sum())).collect()
+        };
+        IndicativeUncross::new(&levels(&self.buy_orders), &levels(&self.sell_orders))
+    }
+
+    /// Publish the indicative uncross during an auction, if it differs from the last one published
+    fn publish_indicative(&mut self, order_results: &mut Vec<OrderResult>) {
+        if self.trading_status != TradingStatus::Auction {
+            return;
+        }
+        let indicative = self.indicative_uncross();
+        if self.published_indicative != Some(indicative) {
+            order_results.push(indicative.to_order_result());
+            self.published_indicative = Some(indicative);
+        }
+    }
+
+    /// End the auction by moving to continuous trading, which executes every order that can trade
+    /// at the clearing price, in price and then time priority. Every trade is at the clearing price, and
+    /// orders may be partly filled, with any remainder resting at its own price. Self-trade
+    /// prevention does not apply to the uncross. Stop orders reached by the clearing price are then
+    /// triggered with time priority `time`, and pegged orders repriced. Does nothing unless an
+    /// auction is collecting orders.
+    pub fn uncross(&mut self, time: DateTime<Utc>) -> Vec<OrderResult> {
+        if self.trading_status != TradingStatus::Auction {
+            return vec![];
+        }
+        self.set_trading_status(TradingStatus::Continuous, time)
+    }
+
+    /// Execute every order that can trade at the clearing price of the orders resting in the book,
+    /// if any cross
+    fn execute_uncross(&mut self, order_results: &mut Vec<OrderResult>) {
+        if let Some(price) = self.indicative_uncross().price {
+            let current_tops = (self.get_top_of_buy_book(), self.get_top_of_sell_book());
+            let buy_fills = OrderBook::auction_fills(self.buy_orders.range(price..).rev());
+            let sell_fills = OrderBook::auction_fills(self.sell_orders.range(..=price));
+
+            let (mut buy_index, mut sell_index) = (0, 0);
+            let mut buy_remaining: Vec<u64> = buy_fills.iter().map(|(_, _, _, qty)| *qty).collect();
+            let mut sell_remaining: Vec<u64> = sell_fills.iter().map(|(_, _, _, qty)| *qty).collect();
+            while buy_index < buy_fills.len() && sell_index < sell_fills.len() {
+                let qty = buy_remaining[buy_index].min(sell_remaining[sell_index]);
+                let (_, user_buy, user_order_id_buy, _) = buy_fills[buy_index];
+                let (_, user_sell, user_order_id_sell, _) = sell_fills[sell_index];
+                order_results.push(OrderResult::Trade {
+                    trade_id: 0,
+                    symbol: self.symbol.clone(),
+                    user_buy,
+                    user_order_id_buy,
+                    user_sell,
+                    user_order_id_sell,
+                    price,
+                    qty,
+                    aggressor_side: None,
+                    time: self.current_time
+                });
+                buy_remaining[buy_index] -= qty;
+                sell_remaining[sell_index] -= qty;
+                if buy_remaining[buy_index] == 0 {
+                    buy_index += 1;
+                }
+                if sell_remaining[sell_index] == 0 {
+                    sell_index += 1;
                 }
             }
+            OrderBook::apply_auction_fills(&mut self.buy_orders, &buy_fills, &buy_remaining);
+            OrderBook::apply_auction_fills(&mut self.sell_orders, &sell_fills, &sell_remaining);
+
+            let (new_buy_top, new_sell_top) = (self.get_top_of_buy_book(), self.get_top_of_sell_book());
+            if new_buy_top != current_tops.0 {
+                order_results.push(new_buy_top.to_order_result());
+            }
+            if new_sell_top != current_tops.1 {
+                order_results.push(new_sell_top.to_order_result());
+            }
+        }
+    }
+
+    /// List the orders of the given price levels in priority order as price, user, user_order_id
+    /// and full quantity
+    fn auction_fills<'a, I: Iterator<Item = (&'a u64, &'a Vec<ExistingOrder>)>>(levels: I) -> Vec<(u64, u64, u64, u64)> {
+        levels.flat_map(|(price, existing_orders)| existing_orders.iter().map(move |existing_order|
+            (*price, existing_order.user, existing_order.user_order_id, existing_order.qty + existing_order.reserve_qty)
+        )).collect()
+    }
+
+    /// Reduce each order listed in `fills` to its `remaining` quantity, showing an iceberg order's
+    /// remainder in slices as usual, and remove orders with nothing remaining
+    fn apply_auction_fills(orders: &mut BTreeMap<u64, Vec<ExistingOrder>>, fills: &[(u64, u64, u64, u64)], remaining: &[u64]) {
+        for ((price, user, user_order_id, _), remaining_qty) in fills.iter().zip(remaining) {
+            let existing_orders = orders.get_mut(price).unwrap();
+            let index = existing_orders.iter().position(|existing_order| existing_order.user == *user && existing_order.user_order_id == *user_order_id).unwrap();
+            if *remaining_qty == 0 {
+                existing_orders.remove(index);
+                if existing_orders.is_empty() {
+                    orders.remove(price);
+                }
+            } else {
+                let existing_order = &mut existing_orders[index];
+                existing_order.qty = existing_order.display_qty.map_or(*remaining_qty, |display_qty| display_qty.min(*remaining_qty));
+                existing_order.reserve_qty = remaining_qty - existing_order.qty;
+            }
+        }
+    }
+
+    /// The best bid and best ask that pegged orders follow: the best shown prices of orders that are
+    /// not pegged themselves
+    fn reference_prices(&self) -> (Option<u64>, Option<u64>) {
+        let is_reference = |existing_order: &ExistingOrder| existing_order.peg.is_none() && !existing_order.hidden;
+        let best_bid = self.buy_orders.iter().rev().find(|(_, existing_orders)| existing_orders.iter().any(is_reference)).map(|(price, _)| *price);
+        let best_ask = self.sell_orders.iter().find(|(_, existing_orders)| existing_orders.iter().any(is_reference)).map(|(price, _)| *price);
+        (best_bid, best_ask)
+    }
+
+    /// Move every resting pegged order whose price no longer follows the top of book, best price
+    /// first. A moved order goes to the back of its new price level, and is held one tick behind
+    /// the opposite side rather than crossing the book. Each move produces a result, followed by any
+    /// change to the top of book.
+    fn reprice_pegged_orders(&mut self, order_results: &mut Vec<OrderResult>) {
+        if self.trading_status != TradingStatus::Continuous {
+            return;
+        }
+        let (best_bid, best_ask) = self.reference_prices();
+        let mut moves = vec![];
+        for (side, orders) in [('B', &self.buy_orders), ('S', &self.sell_orders)] {
+            let levels: Vec<(&u64, &Vec<ExistingOrder>)> = if side == 'B' { orders.iter().rev().collect() } else { orders.iter().collect() };
+            for (price, existing_orders) in levels {
+                for existing_order in existing_orders {
+                    let new_price = existing_order.peg.and_then(|peg| peg.price(side, best_bid, best_ask));
+                    if let Some(new_price) = new_price.filter(|new_price| new_price != price) {
+                        moves.push((side, existing_order.user, existing_order.user_order_id, new_price));
+                    }
+                }
+            }
+        }
+        if moves.is_empty() {
+            return;
+        }
+
+        let current_tops = (self.get_top_of_buy_book(), self.get_top_of_sell_book());
+        for (side, user, user_order_id, new_price) in moves {
+            let new_price = if side == 'B' {
+                match self.sell_orders.keys().next() {
+                    Some(lowest_sell_price) if new_price >= *lowest_sell_price => lowest_sell_price.saturating_sub(1),
+                    _ => new_price
+                }
+            } else {
+                match self.buy_orders.keys().next_back() {
+                    Some(highest_buy_price) if new_price <= *highest_buy_price => highest_buy_price + 1,
+                    _ => new_price
+                }
+            };
+            let order_book_location = self.find_order_by_id(user, user_order_id).unwrap();
+            if new_price == 0 || new_price == order_book_location.price {
+                continue;
+            }
+            let mut existing_order = self.remove_order(order_book_location);
+            existing_order.price = new_price;
+            existing_order.time_received = self.latest_time_received.unwrap_or(existing_order.time_received);
+            self.restore_order(side, existing_order);
+            order_results.push(OrderResult::PeggedOrderRepriced { user, user_order_id, price: new_price });
+        }
+        let (new_buy_top, new_sell_top) = (self.get_top_of_buy_book(), self.get_top_of_sell_book());
+        if new_buy_top != current_tops.0 {
+            order_results.push(new_buy_top.to_order_result());
+        }
+        if new_sell_top != current_tops.1 {
+            order_results.push(new_sell_top.to_order_result());
+        }
+    }
+
+    /// Check each trade in `order_results` against the waiting stop orders. Stop orders reached by a
+    /// trade are triggered in the order they were received and enter the book with the time
+    /// priority of the order that caused the trade. Their results are appended, so any trades they
+    /// make can trigger further stop orders.
+    fn trigger_stop_orders(&mut self, order_results: &mut Vec<OrderResult>, time_received: DateTime<Utc>) {
+        let mut index = 0;
+        while index < order_results.len() {
+            if let OrderResult::Trade { price, .. } = order_results[index] {
+                let (triggered, waiting): (Vec<NewOrder>, Vec<NewOrder>) = self.stop_orders.drain(..)
+                    .partition(|stop_order| stop_order.is_stop_reached(price));
+                self.stop_orders = waiting;
+                for stop_order in triggered {
+                    order_results.push(OrderResult::StopTriggered { user: stop_order.user, user_order_id: stop_order.user_order_id });
+                    let mut active_order = stop_order.triggered();
+                    active_order.time_received = time_received;
+                    order_results.append(&mut self.add_active_order(active_order, false));
+                }
+            }
+            index += 1;
+        }
+    }
+
+    /// Find a matching order for `new_order` on the other side of the `OrderBook`. If the match
+    /// would be a self-trade, `self_trade_prevention` decides which orders are cancelled or reduced,
+    /// and matching continues for as long as the incoming order remains. A market order which does
+    /// not trade is cancelled.
+    fn attempt_order_match(&mut self, mut new_order: NewOrder) -> Vec<OrderResult> {
+        let mut order_results = vec![];
+
+        let current_top = self.get_top_of_opposite_book(new_order.side);
+        let mut is_live = true;
+        while let Some(order_book_location) = self.match_order(&new_order) {
+            let resting_order = self.get_order(&order_book_location);
+            if self.self_trade_prevention == SelfTradePrevention::Allow ||
+                !is_self_trade(new_order.user, new_order.self_trade_group, resting_order.user, resting_order.self_trade_group) {
+                let side = order_book_location.side;
+                let existing_order = self.remove_order(order_book_location);
+                order_results.push(self.trade(&new_order, &existing_order));
+                self.replenish(side, &existing_order, new_order.time_received);
+                is_live = false;
+                break;
+            }
+            is_live = self.prevent_self_trade(&mut new_order, order_book_location, &mut order_results);
+            if !is_live {
+                break;
+            }
+        }
+        if is_live && new_order.order_type == OrderType::Market {
+            order_results.push(OrderResult::Cancellation { user: new_order.user, user_order_id: new_order.user_order_id });
+        }
+        let new_top = self.get_top_of_opposite_book(new_order.side);
+        if new_top != current_top {
+            order_results.push(new_top.to_order_result());
         }
 
         order_results
     }
 
+    /// Build the trade between an incoming order, which is the aggressor, and the resting order it
+    /// matched. Its trade id is left for `OrderBooks` to number.
+    fn trade(&self, new_order: &NewOrder, existing_order: &ExistingOrder) -> OrderResult {
+        let (buy, sell) = if new_order.side == 'B' {
+            ((new_order.user, new_order.user_order_id), (existing_order.user, existing_order.user_order_id))
+        } else {
+            ((existing_order.user, existing_order.user_order_id), (new_order.user, new_order.user_order_id))
+        };
+        OrderResult::Trade {
+            trade_id: 0,
+            symbol: self.symbol.clone(),
+            user_buy: buy.0,
+            user_order_id_buy: buy.1,
+            user_sell: sell.0,
+            user_order_id_sell: sell.1,
+            price: existing_order.price,
+            qty: existing_order.qty,
+            aggressor_side: Some(new_order.side),
+            time: self.current_time
+        }
+    }
+
+    /// Apply `self_trade_prevention` to a new order and the resting order at `order_book_location`.
+    /// Returns whether the new order is still live and should continue matching.
+    fn prevent_self_trade(&mut self, new_order: &mut NewOrder, order_book_location: OrderBookLocation, order_results: &mut Vec<OrderResult>) -> bool {
+        match self.self_trade_prevention {
+            SelfTradePrevention::Allow => true,
+            SelfTradePrevention::CancelNewest => {
+                order_results.push(OrderResult::SelfTradeCancellation { user: new_order.user, user_order_id: new_order.user_order_id });
+                false
+            },
+            SelfTradePrevention::CancelOldest => {
+                let existing_order = self.remove_order(order_book_location);
+                order_results.push(OrderResult::SelfTradeCancellation { user: existing_order.user, user_order_id: existing_order.user_order_id });
+                true
+            },
+            SelfTradePrevention::CancelBoth => {
+                let existing_order = self.remove_order(order_book_location);
+                order_results.push(OrderResult::SelfTradeCancellation { user: existing_order.user, user_order_id: existing_order.user_order_id });
+                order_results.push(OrderResult::SelfTradeCancellation { user: new_order.user, user_order_id: new_order.user_order_id });
+                false
+            },
+            SelfTradePrevention::DecrementAndCancel => {
+                let resting_order = self.get_order(&order_book_location);
+                let (user, user_order_id, resting_qty) = (resting_order.user, resting_order.user_order_id, resting_order.qty);
+                let decrement = resting_qty.min(new_order.qty);
+                if resting_qty == decrement {
+                    let side = order_book_location.side;
+                    let existing_order = self.remove_order(order_book_location);
+                    if self.replenish(side, &existing_order, new_order.time_received) {
+                        order_results.push(OrderResult::SelfTradeDecrement { user, user_order_id, remaining_qty: existing_order.reserve_qty });
+                    } else {
+                        order_results.push(OrderResult::SelfTradeCancellation { user, user_order_id });
+                    }
+                } else {
+                    self.get_order_mut(&order_book_location).qty -= decrement;
+                    order_results.push(OrderResult::SelfTradeDecrement { user, user_order_id, remaining_qty: resting_qty - decrement });
+                }
+                new_order.qty -= decrement;
+                if new_order.qty == 0 {
+                    order_results.push(OrderResult::SelfTradeCancellation { user: new_order.user, user_order_id: new_order.user_order_id });
+                    false
+                } else {
+                    order_results.push(OrderResult::SelfTradeDecrement { user: new_order.user, user_order_id: new_order.user_order_id, remaining_qty: new_order.qty });
+                    true
+                }
+            }
+        }
+    }
+
+    /// Show the next slice of an iceberg order whose shown quantity was used up, at the back of its
+    /// price level. Returns whether any reserve remained.
+    fn replenish(&mut self, side: char, existing_order: &ExistingOrder, time_received: DateTime<Utc>) -> bool {
+        match existing_order.replenished(time_received) {
+            Some(next_slice) => {
+                self.restore_order(side, next_slice);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Get the top of the side of the book that a `side` order would match against
+    fn get_top_of_opposite_book(&self, side: char) -> TopOfBook {
+        if side == 'B' {
+            self.get_top_of_sell_book()
+        } else {
+            self.get_top_of_buy_book()
+        }
+    }
+
+    /// Get the order at a given `OrderBookLocation`
+    fn get_order(&self, order_book_location: &OrderBookLocation) -> &ExistingOrder {
+        let orders = if order_book_location.side == 'B' { &self.buy_orders } else { &self.sell_orders };
+        &orders[&order_book_location.price][order_book_location.index]
+    }
+
+    /// Get the order at a given `OrderBookLocation` for modification
+    fn get_order_mut(&mut self, order_book_location: &OrderBookLocation) -> &mut ExistingOrder {
+        let orders = if order_book_location.side == 'B' { &mut self.buy_orders } else { &mut self.sell_orders };
+        &mut orders.get_mut(&order_book_location.price).unwrap()[order_book_location.index]
+    }
+
     /// Remove an order at a given `OrderBookLocation`, and then delete the key for that price if
     /// `Vec<ExistingOrder>` at that price is now empty.
     fn remove_order(&mut self, order_book_location: OrderBookLocation) -> ExistingOrder {
         if order_book_location.side == 'B' {
             let vec = self.buy_orders.get_mut(&order_book_location.price).unwrap();
             let existing_order = vec.remove(order_book_location.index);
-            if vec.len() == 0 {
+            if vec.is_empty() {
                 self.buy_orders.remove(&order_book_location.price);
             }
             existing_order
         } else {
             let vec = self.sell_orders.get_mut(&order_book_location.price).unwrap();
             let existing_order = vec.remove(order_book_location.index);
-            if vec.len() == 0 {
+            if vec.is_empty() {
                 self.sell_orders.remove(&order_book_location.price);
             }
             existing_order
@@ -246,7 +964,7 @@ impl OrderBook {
     fn match_order(&self, new_order: &NewOrder) -> Option<OrderBookLocation> {
         if new_order.side == 'B' {
             for (price, existing_orders) in self.sell_orders.iter() {
-                if *price <= new_order.price {
+                if new_order.accepts_price(*price) {
                     for (index, existing_order) in existing_orders.iter().enumerate() {
                         if existing_order.qty == new_order.qty {
                             return Some(OrderBookLocation::new('S', *price, index));
@@ -256,7 +974,7 @@ impl OrderBook {
             }
         } else if new_order.side == 'S' {
             for (price, existing_orders) in self.buy_orders.iter() {
-                if *price >= new_order.price {
+                if new_order.accepts_price(*price) {
                     for (index, existing_order) in existing_orders.iter().enumerate() {
                         if existing_order.qty == new_order.qty {
                             return Some(OrderBookLocation::new('B', *price, index));
@@ -288,12 +1006,20 @@ impl OrderBook {
         None
     }
 
-    /// Search for order matching `cancel_order` in this `OrderBook` and remove the
-    /// order if found.
+    /// Search for order matching `cancel_order` in this `OrderBook`, including stop orders waiting
+    /// to be triggered and queued orders, and remove the order if found. Pegged orders are then repriced to follow
+    /// any change to the top of book.
     pub fn cancel_order(&mut self, cancel_order: &CancelOrder) -> Vec<OrderResult> {
         let mut order_results = vec![];
 
-        if let Some(order_book_location) = self.find_order_by_id(cancel_order.user, cancel_order.user_order_id) {
+        let is_cancelled = |new_order: &NewOrder| new_order.user == cancel_order.user && new_order.user_order_id == cancel_order.user_order_id;
+        if let Some(index) = self.stop_orders.iter().position(is_cancelled) {
+            self.stop_orders.remove(index);
+            order_results.push(OrderResult::Acknowledgement { user: cancel_order.user, user_order_id: cancel_order.user_order_id });
+        } else if let Some(index) = self.queued_orders.iter().position(is_cancelled) {
+            self.queued_orders.remove(index);
+            order_results.push(OrderResult::Acknowledgement { user: cancel_order.user, user_order_id: cancel_order.user_order_id });
+        } else if let Some(order_book_location) = self.find_order_by_id(cancel_order.user, cancel_order.user_order_id) {
             order_results.push(OrderResult::Acknowledgement { user: cancel_order.user, user_order_id: cancel_order.user_order_id });
             if order_book_location.side == 'B' {
                 let current_top = self.get_top_of_buy_book();
@@ -310,8 +1036,99 @@ impl OrderBook {
                     order_results.push(new_top.to_order_result());
                 }
             }
+            self.reprice_pegged_orders(&mut order_results);
+            self.publish_indicative(&mut order_results);
         }
 
         order_results
     }
-}
\ No newline at end of file
+
+    /// Remove every order in this `OrderBook`, or only those belonging to `user`. Each removed
+    /// order produces a cancellation when `report_cancellations` is set, followed by a change to the
+    /// top of book for each side that changed.
+    pub fn flush(&mut self, user: Option<u64>, report_cancellations: bool) -> Vec<OrderResult> {
+        self.remove_orders(user, None, report_cancellations)
+    }
+
+    /// Cancel every order belonging to `user` and/or on `side`, producing a cancellation for each
+    /// removed order followed by any change to the top of book
+    pub fn mass_cancel(&mut self, user: Option<u64>, side: Option<char>) -> Vec<OrderResult> {
+        self.remove_orders(user, side, true)
+    }
+
+    /// Remove orders matching `user` and `side`, where `None` matches any, followed by any matching
+    /// stop orders and then queued orders. Pegged orders left behind are then repriced.
+    fn remove_orders(&mut self, user: Option<u64>, side: Option<char>, report_cancellations: bool) -> Vec<OrderResult> {
+        let mut order_results = vec![];
+
+        if side.is_none() || side == Some('B') {
+            let current_top = self.get_top_of_buy_book();
+            let removed_orders = OrderBook::remove_orders_from_side(&mut self.buy_orders, user, true);
+            OrderBook::push_cancellations(&mut order_results, removed_orders, report_cancellations);
+            let new_top = self.get_top_of_buy_book();
+            if new_top != current_top {
+                order_results.push(new_top.to_order_result());
+            }
+        }
+
+        if side.is_none() || side == Some('S') {
+            let current_top = self.get_top_of_sell_book();
+            let removed_orders = OrderBook::remove_orders_from_side(&mut self.sell_orders, user, false);
+            OrderBook::push_cancellations(&mut order_results, removed_orders, report_cancellations);
+            let new_top = self.get_top_of_sell_book();
+            if new_top != current_top {
+                order_results.push(new_top.to_order_result());
+            }
+        }
+
+        for waiting_orders in [&mut self.stop_orders, &mut self.queued_orders] {
+            let (removed_orders, waiting): (Vec<NewOrder>, Vec<NewOrder>) = waiting_orders.drain(..)
+                .partition(|waiting_order| (user.is_none() || user == Some(waiting_order.user)) && (side.is_none() || side == Some(waiting_order.side)));
+            *waiting_orders = waiting;
+            if report_cancellations {
+                for removed_order in removed_orders {
+                    order_results.push(OrderResult::Cancellation { user: removed_order.user, user_order_id: removed_order.user_order_id });
+                }
+            }
+        }
+        self.reprice_pegged_orders(&mut order_results);
+        self.publish_indicative(&mut order_results);
+
+        order_results
+    }
+
+    /// Remove matching orders from one side of the book, best price first, deleting any price
+    /// levels left empty
+    fn remove_orders_from_side(orders: &mut BTreeMap<u64, Vec<ExistingOrder>>, user: Option<u64>, best_price_is_highest: bool) -> Vec<ExistingOrder> {
+        let mut prices: Vec<u64> = orders.keys().copied().collect();
+        if best_price_is_highest {
+            prices.reverse();
+        }
+
+        let mut removed_orders = vec![];
+        for price in prices {
+            let existing_orders = orders.get_mut(&price).unwrap();
+            let (removed, kept): (Vec<ExistingOrder>, Vec<ExistingOrder>) = existing_orders.drain(..)
+                .partition(|existing_order| user.is_none() || user == Some(existing_order.user));
+            *existing_orders = kept;
+            if existing_orders.is_empty() {
+                orders.remove(&price);
+            }
+            removed_orders.extend(removed);
+        }
+        removed_orders
+    }
+
+    fn push_cancellations(order_results: &mut Vec<OrderResult>, removed_orders: Vec<ExistingOrder>, report_cancellations: bool) {
+        if report_cancellations {
+            for existing_order in removed_orders {
+                order_results.push(OrderResult::Cancellation { user: existing_order.user, user_order_id: existing_order.user_order_id });
+            }
+        }
+    }
+
+    /// Whether this `OrderBook` holds no orders on either side and no waiting stop or queued orders
+    pub fn is_empty(&self) -> bool {
+        self.buy_orders.is_empty() && self.sell_orders.is_empty() && self.stop_orders.is_empty() && self.queued_orders.is_empty()
+    }
+}
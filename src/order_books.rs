@@ -2,50 +2,614 @@
 
 use std::collections::HashMap;
 
-use crate::{order_book::OrderBook, order::{NewOrder, CancelOrder}, order_result::OrderResult};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{order_book::OrderBook, clock::{Clock, SystemClock}, self_trade_prevention::SelfTradePrevention, order::{NewOrder, CancelOrder, FlushOrder, MassCancelOrder, AuctionOrder, TradingStatusOrder, PriceBandOrder, RiskLimitOrder, OrderStatusQuery, InputMessage}, order_status::{OrderState, OrderStatus, OrderStatusTracker}, auction::AuctionAction, price_band::{CircuitBreaker, PriceBand}, risk::{RiskLimit, RiskManager}, trading_status::{HaltPolicy, TradingStatus}, order_result::{OrderResult, SequencedOrderResult}};
+
+/// Results tagged with the symbol of the `OrderBook` that produced them, or `None` for a result about
+/// the request as a whole
+type SymbolOrderResults = Vec<(Option<String>, OrderResult)>;
 
 /// Hold a colection of orderbooks in a hashmap and track whether trading mode is enabled
 pub struct OrderBooks {
     /// A hashmap where the key is a stock symbol (Ex. AAPL) and the value is an `OrderBook`
     all_orders: HashMap<String, OrderBook>,
-    trading_enabled: bool
+    trading_enabled: bool,
+    self_trade_prevention: SelfTradePrevention,
+    halt_policy: HaltPolicy,
+    /// The trading status of the session, given to each new `OrderBook`
+    session_status: TradingStatus,
+    /// The price band given to each new `OrderBook`, as last set for every symbol
+    default_price_band: Option<PriceBand>,
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Checks each new order against its user's risk limits before it reaches an `OrderBook`
+    risk_manager: RiskManager,
+    /// The lifecycle of every order handled by `process`
+    order_statuses: OrderStatusTracker,
+    /// Whether `process` publishes an execution report for every change to an order
+    execution_reports: bool,
+    /// Stamps each message handled by `process`
+    clock: Box<dyn Clock>,
+    /// The engine time of the message most recently handled by `process` or `process_at`
+    current_time: Option<DateTime<Utc>>,
+    /// The sequence number given to the next result from `process`
+    next_sequence: u64,
+    /// The sequence number given to the next result from each `OrderBook`, keyed by symbol
+    next_symbol_sequences: HashMap<String, u64>,
+    /// The input sequence number `process` gives to the next message
+    next_input_sequence: u64,
+    /// The id given to the next trade in any `OrderBook`
+    next_trade_id: u64
 }
 
 impl OrderBooks {
     pub fn new(trading_enabled: bool) -> OrderBooks {
         OrderBooks {
             all_orders: HashMap::new(),
-            trading_enabled
+            trading_enabled,
+            self_trade_prevention: SelfTradePrevention::Allow,
+            halt_policy: HaltPolicy::Reject,
+            session_status: TradingStatus::Continuous,
+            default_price_band: None,
+            circuit_breaker: None,
+            risk_manager: RiskManager::new(),
+            order_statuses: OrderStatusTracker::new(),
+            execution_reports: false,
+            clock: Box::new(SystemClock),
+            current_time: None,
+            next_sequence: 1,
+            next_symbol_sequences: HashMap::new(),
+            next_input_sequence: 1,
+            next_trade_id: 1
+        }
+    }
+
+    /// Replace the engine clock, such as with a `SimulatedClock` for repeatable runs
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Read the engine clock
+    pub fn now(&mut self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// The engine time of the message most recently processed
+    pub fn current_time(&self) -> Option<DateTime<Utc>> {
+        self.current_time
+    }
+
+    /// Stamp `message` with the engine clock and the next input sequence number, and process it
+    pub fn process(&mut self, message: InputMessage) -> Vec<SequencedOrderResult> {
+        let time = self.now();
+        self.process_at(self.next_input_sequence, time, message)
+    }
+
+    /// Process `message` as input number `input_sequence`, as though received at `time`, which
+    /// becomes the `time_received` of a new order. Replaying the same messages at the same times
+    /// rebuilds identical `OrderBooks`. Each result is given the next global sequence number, and
+    /// the next sequence number for its symbol if it came from an `OrderBook`. Any circuit breaker
+    /// interruption which has ended by `time` is ended first, and the status of every order named
+    /// by the results is updated.
+    pub fn process_at(&mut self, input_sequence: u64, time: DateTime<Utc>, message: InputMessage) -> Vec<SequencedOrderResult> {
+        self.current_time = Some(time);
+        self.next_input_sequence = input_sequence + 1;
+        self.order_statuses.purge(time);
+        let mut symbol_order_results = self.resume_after_breakers(time);
+        let resumed = !symbol_order_results.is_empty();
+        let new_order_key = match &message {
+            InputMessage::New(new_order) => Some((new_order.user, new_order.user_order_id)),
+            _ => None
+        };
+        let is_cancel = matches!(message, InputMessage::Cancel(_));
+        let sweep_state = match message {
+            InputMessage::Flush(_) => Some(OrderState::Cancelled),
+            InputMessage::Auction(_) | InputMessage::TradingStatus(_) => Some(OrderState::Expired),
+            _ if resumed => Some(OrderState::Expired),
+            _ => None
+        };
+        symbol_order_results.append(&mut match message {
+            InputMessage::New(mut new_order) => {
+                new_order.time_received = time;
+                self.order_statuses.open(&new_order, time);
+                self.add_order_by_symbol(new_order)
+            },
+            InputMessage::Cancel(cancel_order) => self.cancel_order_by_symbol(cancel_order),
+            InputMessage::MassCancel(mass_cancel_order) => self.mass_cancel_by_symbol(mass_cancel_order),
+            InputMessage::Flush(flush_order) => self.flush_by_symbol(flush_order),
+            InputMessage::Auction(auction_order) => self.auction_by_symbol(auction_order),
+            InputMessage::TradingStatus(trading_status_order) => self.set_trading_status_by_symbol(trading_status_order),
+            InputMessage::PriceBand(price_band_order) => self.set_price_band_by_symbol(price_band_order),
+            InputMessage::RiskLimit(risk_limit_order) => self.set_risk_limit_by_symbol(risk_limit_order),
+            InputMessage::OrderStatus(order_status_query) => self.order_status_by_symbol(order_status_query)
+        });
+        let symbol_order_results = self.track_order_statuses(symbol_order_results, new_order_key, is_cancel, sweep_state, time);
+
+        symbol_order_results.into_iter().map(|(symbol, order_result)| {
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            let symbol_sequence = symbol.as_ref().map(|symbol| {
+                let next_symbol_sequence = self.next_symbol_sequences.entry(symbol.clone()).or_insert(1);
+                *next_symbol_sequence += 1;
+                *next_symbol_sequence - 1
+            });
+            SequencedOrderResult { sequence, symbol, symbol_sequence, input_sequence, order_result }
+        }).collect()
+    }
+
+    /// Update order statuses from the results of one message, following each result with the
+    /// execution reports it caused when they are enabled. Orders which have left their `OrderBook`
+    /// without a result saying why become `Expired`: the new order and any triggered stop orders,
+    /// and when `sweep_state` is given, every working order, which becomes `sweep_state`.
+    fn track_order_statuses(&mut self, symbol_order_results: SymbolOrderResults, new_order_key: Option<(u64, u64)>, is_cancel: bool, sweep_state: Option<OrderState>, time: DateTime<Utc>) -> SymbolOrderResults {
+        let mut candidates: Vec<(u64, u64, OrderState)> = new_order_key.into_iter()
+            .chain(symbol_order_results.iter().filter_map(|(_, order_result)| match order_result {
+                OrderResult::StopTriggered { user, user_order_id } => Some((*user, *user_order_id)),
+                _ => None
+            }))
+            .map(|(user, user_order_id)| (user, user_order_id, OrderState::Expired))
+            .collect();
+        let mut tracked_order_results: SymbolOrderResults = vec![];
+        for (symbol, order_result) in symbol_order_results {
+            let execution_reports = self.order_statuses.record(&order_result, is_cancel, time);
+            tracked_order_results.push((symbol, order_result));
+            self.push_execution_reports(&mut tracked_order_results, execution_reports);
+        }
+
+        if let Some(sweep_state) = sweep_state {
+            candidates.extend(self.order_statuses.working_orders().into_iter().map(|(user, user_order_id, _)| (user, user_order_id, sweep_state)));
+        }
+        for (user, user_order_id, state) in candidates {
+            let symbol = match self.order_statuses.status(user, user_order_id) {
+                Some(status) if !status.state.is_terminal() => status.symbol.clone(),
+                _ => continue
+            };
+            if !self.all_orders.get(&symbol).map_or(false, |order_book| order_book.holds_order(user, user_order_id)) {
+                let execution_reports = self.order_statuses.finish(user, user_order_id, state, time).into_iter().collect();
+                self.push_execution_reports(&mut tracked_order_results, execution_reports);
+            }
+        }
+        tracked_order_results
+    }
+
+    /// Add `execution_reports` to `symbol_order_results`, each tagged with the symbol of its order,
+    /// if execution reports are enabled
+    fn push_execution_reports(&self, symbol_order_results: &mut SymbolOrderResults, execution_reports: Vec<OrderResult>) {
+        if !self.execution_reports {
+            return;
+        }
+        for execution_report in execution_reports {
+            if let OrderResult::ExecutionReport { user, user_order_id, .. } = execution_report {
+                let symbol = self.order_statuses.status(user, user_order_id).map(|status| status.symbol.clone());
+                symbol_order_results.push((symbol, execution_report));
+            }
+        }
+    }
+
+    /// Choose whether `process` follows each result that changes an order with an execution report
+    /// to its owner
+    pub fn set_execution_reports(&mut self, execution_reports: bool) {
+        self.execution_reports = execution_reports;
+    }
+
+    pub fn execution_reports(&self) -> bool {
+        self.execution_reports
+    }
+
+    /// The status of the order of `user` with `user_order_id`, if it has been seen by `process`
+    /// and, once terminal, is still retained
+    pub fn order_status(&self, user: u64, user_order_id: u64) -> Option<&OrderStatus> {
+        self.order_statuses.status(user, user_order_id)
+    }
+
+    pub fn order_statuses(&self) -> &OrderStatusTracker {
+        &self.order_statuses
+    }
+
+    /// Keep an order status, as when restoring a snapshot
+    pub(crate) fn restore_order_status(&mut self, order_status: OrderStatus) {
+        self.order_statuses.restore(order_status);
+    }
+
+    /// Choose how long orders are kept once filled, cancelled, rejected or expired, or for good
+    /// when `None`
+    pub fn set_order_retention(&mut self, retention: Option<Duration>) {
+        self.order_statuses.set_retention(retention);
+    }
+
+    /// Answer a status query from the order statuses
+    pub fn order_status_query(&mut self, order_status_query: OrderStatusQuery) -> Vec<OrderResult> {
+        without_symbols(self.order_status_by_symbol(order_status_query))
+    }
+
+    fn order_status_by_symbol(&mut self, order_status_query: OrderStatusQuery) -> SymbolOrderResults {
+        let status = self.order_statuses.status(order_status_query.user, order_status_query.user_order_id).cloned();
+        vec![(None, OrderResult::OrderStatusReport { user: order_status_query.user, user_order_id: order_status_query.user_order_id, status })]
+    }
+
+    /// Carry on the global, per-symbol and input sequence numbers and the trade ids from `previous`,
+    /// so that they keep increasing when one `OrderBooks` replaces another
+    pub fn continue_sequences(&mut self, previous: &OrderBooks) {
+        self.next_sequence = previous.next_sequence;
+        self.next_symbol_sequences = previous.next_symbol_sequences.clone();
+        self.next_input_sequence = previous.next_input_sequence;
+        self.next_trade_id = previous.next_trade_id;
+    }
+
+    /// The id the next trade will be given
+    pub fn next_trade_id(&self) -> u64 {
+        self.next_trade_id
+    }
+
+    /// Continue numbering trades from `next_trade_id`, as when restoring a snapshot
+    pub fn restore_next_trade_id(&mut self, next_trade_id: u64) {
+        self.next_trade_id = next_trade_id;
+    }
+
+    /// Give each trade in `order_results` the next trade id
+    fn number_trades(&mut self, mut order_results: Vec<OrderResult>) -> Vec<OrderResult> {
+        for order_result in order_results.iter_mut() {
+            if let OrderResult::Trade { trade_id, .. } = order_result {
+                *trade_id = self.next_trade_id;
+                self.next_trade_id += 1;
+            }
+        }
+        order_results
+    }
+
+    /// The sequence number the next result will be given
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// The input sequence number `process` will give the next message
+    pub fn next_input_sequence(&self) -> u64 {
+        self.next_input_sequence
+    }
+
+    /// The sequence number the next result for each symbol that has had any will be given, in
+    /// symbol order
+    pub fn next_symbol_sequences(&self) -> Vec<(String, u64)> {
+        let mut next_symbol_sequences: Vec<(String, u64)> = self.next_symbol_sequences.iter().map(|(symbol, sequence)| (symbol.clone(), *sequence)).collect();
+        next_symbol_sequences.sort();
+        next_symbol_sequences
+    }
+
+    /// Continue numbering results and input messages from `next_sequence` and
+    /// `next_input_sequence`, as when restoring a snapshot
+    pub fn restore_sequences(&mut self, next_sequence: u64, next_input_sequence: u64) {
+        self.next_sequence = next_sequence;
+        self.next_input_sequence = next_input_sequence;
+    }
+
+    /// Continue numbering the results for `symbol` from `next_symbol_sequence`, as when restoring a
+    /// snapshot
+    pub fn restore_symbol_sequence(&mut self, symbol: &str, next_symbol_sequence: u64) {
+        self.next_symbol_sequences.insert(symbol.to_string(), next_symbol_sequence);
+    }
+
+    /// Choose how matches between orders from the same user or self-trade group are handled, for
+    /// existing and future `OrderBook`s
+    pub fn set_self_trade_prevention(&mut self, self_trade_prevention: SelfTradePrevention) {
+        self.self_trade_prevention = self_trade_prevention;
+        for order_book in self.all_orders.values_mut() {
+            order_book.set_self_trade_prevention(self_trade_prevention);
+        }
+    }
+
+    /// Choose whether halted `OrderBook`s reject or queue new orders, for existing and future
+    /// `OrderBook`s
+    pub fn set_halt_policy(&mut self, halt_policy: HaltPolicy) {
+        self.halt_policy = halt_policy;
+        for order_book in self.all_orders.values_mut() {
+            order_book.set_halt_policy(halt_policy);
+        }
+    }
+
+    pub fn halt_policy(&self) -> HaltPolicy {
+        self.halt_policy
+    }
+
+    /// The trading status new `OrderBook`s start in, as last set for every symbol
+    pub fn session_status(&self) -> TradingStatus {
+        self.session_status
+    }
+
+    /// Set the trading status new `OrderBook`s start in without publishing anything, as when
+    /// restoring a snapshot
+    pub(crate) fn restore_session_status(&mut self, session_status: TradingStatus) {
+        self.session_status = session_status;
+    }
+
+    /// Set the price band of every existing and future `OrderBook`, or remove it when `None`
+    pub fn set_default_price_band(&mut self, price_band: Option<PriceBand>) {
+        self.default_price_band = price_band;
+        for order_book in self.all_orders.values_mut() {
+            order_book.set_price_band(price_band);
         }
     }
 
+    /// The price band new `OrderBook`s start with
+    pub fn default_price_band(&self) -> Option<PriceBand> {
+        self.default_price_band
+    }
+
+    /// Choose when trading is interrupted by sudden price moves, for existing and future
+    /// `OrderBook`s, or never when `None`
+    pub fn set_circuit_breaker(&mut self, circuit_breaker: Option<CircuitBreaker>) {
+        self.circuit_breaker = circuit_breaker;
+        for order_book in self.all_orders.values_mut() {
+            order_book.set_circuit_breaker(circuit_breaker);
+        }
+    }
+
+    pub fn circuit_breaker(&self) -> Option<CircuitBreaker> {
+        self.circuit_breaker
+    }
+
+    /// Replace every risk limit, such as with limits loaded from a file
+    pub fn set_risk_manager(&mut self, risk_manager: RiskManager) {
+        self.risk_manager = risk_manager;
+    }
+
+    pub fn risk_manager(&self) -> &RiskManager {
+        &self.risk_manager
+    }
+
+    pub fn trading_enabled(&self) -> bool {
+        self.trading_enabled
+    }
+
+    pub fn self_trade_prevention(&self) -> SelfTradePrevention {
+        self.self_trade_prevention
+    }
+
+    /// Get the `OrderBook` for `symbol`, if it holds or has held any orders
+    pub fn get_order_book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.all_orders.get(symbol)
+    }
+
+    /// Get the `OrderBook` for `symbol`, creating it if not already existing
+    pub(crate) fn get_or_create_order_book(&mut self, symbol: &str) -> &mut OrderBook {
+        let trading_enabled = self.trading_enabled;
+        let self_trade_prevention = self.self_trade_prevention;
+        let halt_policy = self.halt_policy;
+        let session_status = self.session_status;
+        let price_band = self.default_price_band;
+        let circuit_breaker = self.circuit_breaker;
+        self.all_orders.entry(symbol.to_string()).or_insert_with(|| {
+            let mut new_order_book = OrderBook::new(symbol, trading_enabled);
+            new_order_book.set_self_trade_prevention(self_trade_prevention);
+            new_order_book.set_halt_policy(halt_policy);
+            new_order_book.restore_trading_status(session_status);
+            new_order_book.set_price_band(price_band);
+            new_order_book.set_circuit_breaker(circuit_breaker);
+            new_order_book
+        })
+    }
+
     /// Locate the proper `OrderBook` for the new order or create if not already existing for that 
-    /// symbol. 
+    /// symbol. An order breaching one of its user's risk limits is rejected before reaching the
+    /// `OrderBook`, and leaves no `OrderBook` behind for a symbol that had none.
     pub fn add_order(&mut self, new_order: NewOrder) -> Vec<OrderResult> {
-        if let Some(v) = self.all_orders.get_mut(&new_order.symbol) {
-            v.add_order(new_order)
-        } else {
-            let new_symbol = new_order.symbol.clone();
-            let mut new_order_book = OrderBook::new(&new_symbol, self.trading_enabled);
-            let order_results = new_order_book.add_order(new_order);
-            self.all_orders.insert(new_symbol, new_order_book);
-            order_results
+        without_symbols(self.add_order_by_symbol(new_order))
+    }
+
+    fn add_order_by_symbol(&mut self, new_order: NewOrder) -> SymbolOrderResults {
+        let symbol = new_order.symbol.clone();
+        let is_new_symbol = !self.all_orders.contains_key(&symbol);
+        self.get_or_create_order_book(&symbol);
+        if let Err(reason) = self.check_risk(&new_order) {
+            if is_new_symbol {
+                self.all_orders.remove(&symbol);
+            }
+            return with_symbol(&symbol, vec![OrderResult::RiskRejection { user: new_order.user, user_order_id: new_order.user_order_id, reason }]);
+        }
+        let order_results = self.all_orders.get_mut(&symbol).unwrap().add_order(new_order);
+        with_symbol(&symbol, self.number_trades(order_results))
+    }
+
+    /// Check `new_order` against its user's risk limits, counting it towards their message rate,
+    /// and return the first limit it breaches. Its `OrderBook` must already exist.
+    fn check_risk(&mut self, new_order: &NewOrder) -> Result<(), RiskLimit> {
+        if !self.risk_manager.record_message(new_order.user, new_order.time_received) {
+            return Err(RiskLimit::MaxMessagesPerSecond);
+        }
+        let limits = self.risk_manager.limits(new_order.user);
+        if limits.is_empty() {
+            return Ok(());
         }
+        let order_book = &self.all_orders[&new_order.symbol];
+        let mut exposure = order_book.exposure(new_order.user);
+        exposure.open_orders = self.all_orders.values().map(|order_book| order_book.exposure(new_order.user).open_orders).sum();
+        limits.check(new_order, order_book.risk_price(new_order), &exposure)
     }
 
     /// Search through each `OrderBook` attempting to cancel an existing order
     pub fn cancel_order(&mut self, cancel_order: CancelOrder) -> Vec<OrderResult> {
-        let mut order_results: Vec<OrderResult> = vec![];
+        without_symbols(self.cancel_order_by_symbol(cancel_order))
+    }
+
+    fn cancel_order_by_symbol(&mut self, cancel_order: CancelOrder) -> SymbolOrderResults {
+        let mut order_results: SymbolOrderResults = vec![];
+        self.risk_manager.record_message(cancel_order.user, self.current_time.unwrap_or_else(Utc::now));
         
-        for order_book in self.all_orders.values_mut() {
-            order_results.append(&mut order_book.cancel_order(&cancel_order));
+        for (symbol, order_book) in self.all_orders.iter_mut() {
+            order_results.append(&mut with_symbol(symbol, order_book.cancel_order(&cancel_order)));
+        }
+
+        order_results
+    }
+
+    /// List the symbols of all `OrderBook`s in order
+    pub fn symbols(&self) -> Vec<String> {
+        self.sorted_symbols(None)
+    }
+
+    /// List the symbols of all `OrderBook`s in order, or only `symbol` if given and present
+    fn sorted_symbols(&self, symbol: Option<&str>) -> Vec<String> {
+        let mut symbols: Vec<String> = self.all_orders.keys()
+            .filter(|key| symbol.is_none() || symbol == Some(key.as_str()))
+            .cloned()
+            .collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// Cancel every order matching `mass_cancel_order` across the `OrderBook`s it names, in symbol
+    /// order. Each cancelled order produces a cancellation, followed by a single report of how many
+    /// orders were cancelled. Any `OrderBook` left with nothing worth keeping is dropped, as by
+    /// `flush`.
+    pub fn mass_cancel(&mut self, mass_cancel_order: MassCancelOrder) -> Vec<OrderResult> {
+        without_symbols(self.mass_cancel_by_symbol(mass_cancel_order))
+    }
+
+    fn mass_cancel_by_symbol(&mut self, mass_cancel_order: MassCancelOrder) -> SymbolOrderResults {
+        let mut order_results: SymbolOrderResults = vec![];
+        if let Some(user) = mass_cancel_order.user {
+            self.risk_manager.record_message(user, self.current_time.unwrap_or_else(Utc::now));
         }
 
+        for symbol in self.sorted_symbols(mass_cancel_order.symbol.as_deref()) {
+            let order_book = self.all_orders.get_mut(&symbol).unwrap();
+            order_results.append(&mut with_symbol(&symbol, order_book.mass_cancel(mass_cancel_order.user, mass_cancel_order.side)));
+            self.remove_if_empty(&symbol);
+        }
+
+        let total_cancelled = order_results.iter()
+            .filter(|(_, order_result)| matches!(order_result, OrderResult::Cancellation { .. }))
+            .count() as u64;
+        order_results.push((None, OrderResult::MassCancelReport {
+            user: mass_cancel_order.user,
+            symbol: mass_cancel_order.symbol,
+            side: mass_cancel_order.side,
+            total_cancelled
+        }));
+
         order_results
     }
 
-    /// Flush all `OrderBook`s
-    pub fn flush(&mut self) {
-        self.all_orders.clear()
+    /// Flush every `OrderBook`, or only the one for `flush_order.symbol`, removing all orders or only
+    /// those belonging to `flush_order.user`. `OrderBook`s are flushed in symbol order so that
+    /// results are repeatable, and any `OrderBook` left with nothing worth keeping is dropped.
+    pub fn flush(&mut self, flush_order: FlushOrder) -> Vec<OrderResult> {
+        without_symbols(self.flush_by_symbol(flush_order))
     }
-}
\ No newline at end of file
+
+    fn flush_by_symbol(&mut self, flush_order: FlushOrder) -> SymbolOrderResults {
+        let mut order_results: SymbolOrderResults = vec![];
+
+        for symbol in self.sorted_symbols(flush_order.symbol.as_deref()) {
+            let order_book = self.all_orders.get_mut(&symbol).unwrap();
+            order_results.append(&mut with_symbol(&symbol, order_book.flush(flush_order.user, flush_order.report_cancellations)));
+            self.remove_if_empty(&symbol);
+        }
+
+        order_results
+    }
+
+    /// Drop the `OrderBook` for `symbol` if it holds nothing worth keeping: no orders, no positions,
+    /// no last trade price, no circuit breaker interruption and the trading status and price band a
+    /// new `OrderBook` would be given
+    fn remove_if_empty(&mut self, symbol: &str) {
+        let (session_status, default_price_band) = (self.session_status, self.default_price_band);
+        let is_unused = |order_book: &OrderBook| order_book.is_empty()
+            && order_book.trading_status() == session_status
+            && order_book.price_band() == default_price_band
+            && order_book.positions().is_empty()
+            && order_book.last_trade_price().is_none()
+            && order_book.breaker_resumes_at().is_none();
+        if self.all_orders.get(symbol).map_or(false, is_unused) {
+            self.all_orders.remove(symbol);
+        }
+    }
+
+    /// Start or uncross a call auction for `auction_order.symbol`, creating its `OrderBook` if
+    /// needed. Either happens at the engine time of the message being processed.
+    pub fn auction(&mut self, auction_order: AuctionOrder) -> Vec<OrderResult> {
+        without_symbols(self.auction_by_symbol(auction_order))
+    }
+
+    fn auction_by_symbol(&mut self, auction_order: AuctionOrder) -> SymbolOrderResults {
+        let time = self.current_time.unwrap_or_else(Utc::now);
+        let order_book = self.get_or_create_order_book(&auction_order.symbol);
+        let order_results = match auction_order.action {
+            AuctionAction::Start => order_book.start_auction(time),
+            AuctionAction::Uncross => order_book.uncross(time)
+        };
+        with_symbol(&auction_order.symbol, self.number_trades(order_results))
+    }
+
+    /// Move `trading_status_order.symbol` to a new trading status, creating its `OrderBook` if
+    /// needed, or move every `OrderBook` in symbol order when no symbol is given, along with the
+    /// status new `OrderBook`s start in. Continuous trading resumes at the engine time of the
+    /// message being processed.
+    pub fn set_trading_status(&mut self, trading_status_order: TradingStatusOrder) -> Vec<OrderResult> {
+        without_symbols(self.set_trading_status_by_symbol(trading_status_order))
+    }
+
+    fn set_trading_status_by_symbol(&mut self, trading_status_order: TradingStatusOrder) -> SymbolOrderResults {
+        let time = self.current_time.unwrap_or_else(Utc::now);
+        let status = trading_status_order.status;
+        let symbols = match trading_status_order.symbol {
+            Some(symbol) => vec![symbol],
+            None => {
+                self.session_status = status;
+                self.sorted_symbols(None)
+            }
+        };
+
+        let mut order_results: SymbolOrderResults = vec![];
+        for symbol in symbols {
+            let status_order_results = self.get_or_create_order_book(&symbol).set_trading_status(status, time);
+            order_results.append(&mut with_symbol(&symbol, self.number_trades(status_order_results)));
+        }
+        order_results
+    }
+
+    /// Set the price band of `price_band_order.symbol`, creating its `OrderBook` if needed, or of
+    /// every existing and future `OrderBook` when no symbol is given
+    pub fn set_price_band(&mut self, price_band_order: PriceBandOrder) -> Vec<OrderResult> {
+        without_symbols(self.set_price_band_by_symbol(price_band_order))
+    }
+
+    fn set_price_band_by_symbol(&mut self, price_band_order: PriceBandOrder) -> SymbolOrderResults {
+        match price_band_order.symbol {
+            Some(symbol) => self.get_or_create_order_book(&symbol).set_price_band(price_band_order.price_band),
+            None => self.set_default_price_band(price_band_order.price_band)
+        }
+        vec![]
+    }
+
+    /// Set one risk limit of `risk_limit_order.user`, or the default limit when no user is given
+    pub fn set_risk_limit(&mut self, risk_limit_order: RiskLimitOrder) -> Vec<OrderResult> {
+        without_symbols(self.set_risk_limit_by_symbol(risk_limit_order))
+    }
+
+    fn set_risk_limit_by_symbol(&mut self, risk_limit_order: RiskLimitOrder) -> SymbolOrderResults {
+        self.risk_manager.set_limit(risk_limit_order.user, risk_limit_order.limit, risk_limit_order.value);
+        vec![]
+    }
+
+    /// Resume continuous trading, in symbol order, in every `OrderBook` whose circuit breaker
+    /// interruption has ended by `time`
+    fn resume_after_breakers(&mut self, time: DateTime<Utc>) -> SymbolOrderResults {
+        let mut order_results: SymbolOrderResults = vec![];
+        if self.all_orders.values().all(|order_book| order_book.breaker_resumes_at().is_none()) {
+            return order_results;
+        }
+        for symbol in self.sorted_symbols(None) {
+            let resumed_order_results = self.all_orders.get_mut(&symbol).unwrap().resume_after_breaker(time);
+            order_results.append(&mut with_symbol(&symbol, self.number_trades(resumed_order_results)));
+        }
+        order_results
+    }
+}
+
+fn with_symbol(symbol: &str, order_results: Vec<OrderResult>) -> SymbolOrderResults {
+    order_results.into_iter().map(|order_result| (Some(symbol.to_string()), order_result)).collect()
+}
+
+fn without_symbols(symbol_order_results: SymbolOrderResults) -> Vec<OrderResult> {
+    symbol_order_results.into_iter().map(|(_, order_result)| order_result).collect()
+}
@@ -1,20 +1,109 @@
 //! All possible results after a transaction message is processed inside OrderBooks
-#[derive(Debug, PartialEq)]
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::{order_status::{ExecType, OrderState, OrderStatus}, risk::RiskLimit, trading_status::TradingStatus};
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum OrderResult {
     Acknowledgement { user: u64, user_order_id: u64 },
     Rejection { user: u64, user_order_id: u64 },
+    /// A new order was rejected for breaching one of its user's risk limits
+    RiskRejection { user: u64, user_order_id: u64, reason: RiskLimit },
+    /// An existing order was removed from the book without a cancel order from its user, such as by a flush
+    Cancellation { user: u64, user_order_id: u64 },
+    /// A stop or stop-limit order was triggered by a trade reaching its stop price
+    StopTriggered { user: u64, user_order_id: u64 },
+    /// A pegged order moved to `price` to follow the top of book
+    PeggedOrderRepriced { user: u64, user_order_id: u64, price: u64 },
+    /// An order was cancelled by self-trade prevention
+    SelfTradeCancellation { user: u64, user_order_id: u64 },
+    /// An order was reduced by self-trade prevention and remains with `remaining_qty`
+    SelfTradeDecrement { user: u64, user_order_id: u64, remaining_qty: u64 },
+    /// Summarises a mass cancel, echoing its criteria with the number of orders cancelled
+    MassCancelReport { user: Option<u64>, symbol: Option<String>, side: Option<char>, total_cancelled: u64 },
+    /// While an auction is collecting orders, the price it would uncross at now, the volume that
+    /// would execute and the quantity left unmatched on `imbalance_side`
+    AuctionIndicative { price: Option<u64>, matched_qty: u64, imbalance_qty: u64, imbalance_side: Option<char> },
+    /// The trading status of the symbol changed
+    TradingStatusChange { status: TradingStatus },
     TopOfBookChange { side: char, price: String, total_quantity: String },
-    Trade { user_buy: u64, user_order_id_buy: u64, user_sell: u64, user_order_id_sell: u64, price: u64, qty: u64 }
+    /// Sent to the owner of an order for every change to it: the event, the order's state after it,
+    /// the quantity and price of the fill for a trade, and the quantity filled so far, the quantity
+    /// still working and the average fill price
+    ExecutionReport { user: u64, user_order_id: u64, exec_type: ExecType, state: OrderState, last_qty: u64, last_price: Option<u64>, cum_qty: u64, leaves_qty: u64, average_price: f64 },
+    /// The answer to a status query, or `None` for an order that is unknown or no longer retained
+    OrderStatusReport { user: u64, user_order_id: u64, status: Option<OrderStatus> },
+    /// A trade between a buy and a sell order. `trade_id` is unique across all symbols, numbered by
+    /// `OrderBooks` from 1, while an `OrderBook` used on its own leaves it 0. `aggressor_side` is the
+    /// side of the incoming order that traded with a resting order, or `None` for a trade made by an
+    /// auction uncross, and `time` is the engine time the trade was executed. It is displayed with
+    /// the trade id, aggressor side and symbol after the quantity, though not the time, so that the
+    /// output of a run depends only on its input.
+    Trade { trade_id: u64, symbol: String, user_buy: u64, user_order_id_buy: u64, user_sell: u64, user_order_id_sell: u64, price: u64, qty: u64, aggressor_side: Option<char>, time: DateTime<Utc> }
 }
 
-impl ToString for OrderResult {
-    fn to_string(&self) -> String {
+impl fmt::Display for OrderResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Acknowledgement { user, user_order_id } => format!("A, {}, {}", user, user_order_id),
-            Self::Rejection { user, user_order_id } => format!("R, {}, {}", user, user_order_id),
-            Self::TopOfBookChange { side, price, total_quantity} => format!("B, {}, {}, {}", side, price, total_quantity),
-            Self::Trade { user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty } =>
-                format!("T, {}, {}, {}, {}, {}, {}", user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty)
+            Self::Acknowledgement { user, user_order_id } => write!(f, "A, {}, {}", user, user_order_id),
+            Self::Rejection { user, user_order_id } => write!(f, "R, {}, {}", user, user_order_id),
+            Self::RiskRejection { user, user_order_id, reason } => write!(f, "R, {}, {}, {}", user, user_order_id, reason),
+            Self::Cancellation { user, user_order_id } => write!(f, "C, {}, {}", user, user_order_id),
+            Self::StopTriggered { user, user_order_id } => write!(f, "G, {}, {}", user, user_order_id),
+            Self::PeggedOrderRepriced { user, user_order_id, price } => write!(f, "E, {}, {}, {}", user, user_order_id, price),
+            Self::SelfTradeCancellation { user, user_order_id } => write!(f, "P, {}, {}", user, user_order_id),
+            Self::SelfTradeDecrement { user, user_order_id, remaining_qty } => write!(f, "D, {}, {}, {}", user, user_order_id, remaining_qty),
+            Self::MassCancelReport { user, symbol, side, total_cancelled } => {
+                let user = user.map_or("-".to_string(), |user| user.to_string());
+                let symbol = symbol.as_deref().unwrap_or("-");
+                let side = side.map_or("-".to_string(), |side| side.to_string());
+                write!(f, "M, {}, {}, {}, {}", user, symbol, side, total_cancelled)
+            },
+            Self::AuctionIndicative { price, matched_qty, imbalance_qty, imbalance_side } => {
+                let price = price.map_or("-".to_string(), |price| price.to_string());
+                let imbalance_side = imbalance_side.map_or("-".to_string(), |side| side.to_string());
+                write!(f, "I, {}, {}, {}, {}", price, matched_qty, imbalance_qty, imbalance_side)
+            },
+            Self::TradingStatusChange { status } => write!(f, "S, {}", status),
+            Self::TopOfBookChange { side, price, total_quantity} => write!(f, "B, {}, {}, {}", side, price, total_quantity),
+            Self::ExecutionReport { user, user_order_id, exec_type, state, last_qty, last_price, cum_qty, leaves_qty, average_price } => {
+                let last_price = last_price.map_or("-".to_string(), |last_price| last_price.to_string());
+                let average_price = if *cum_qty == 0 { "-".to_string() } else { format!("{:.2}", average_price) };
+                write!(f, "X, {}, {}, {}, {}, {}, {}, {}, {}, {}", user, user_order_id, exec_type, state, last_qty, last_price, cum_qty, leaves_qty, average_price)
+            },
+            Self::OrderStatusReport { user, user_order_id, status } => match status {
+                Some(status) => write!(f, "O, {}, {}, {}", user, user_order_id, status),
+                None => write!(f, "O, {}, {}, unknown", user, user_order_id)
+            },
+            Self::Trade { trade_id, symbol, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, aggressor_side, .. } => {
+                let aggressor_side = aggressor_side.map_or("-".to_string(), |aggressor_side| aggressor_side.to_string());
+                write!(f, "T, {}, {}, {}, {}, {}, {}, {}, {}, {}", user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, trade_id, aggressor_side, symbol)
+            }
         }
     }
+}
+
+/// An `OrderResult` numbered by `OrderBooks`, so that consumers can detect gaps and tie each result
+/// back to the input message that caused it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequencedOrderResult {
+    /// Increases by one with every result from `OrderBooks`
+    pub sequence: u64,
+    /// The symbol of the `OrderBook` that produced this result, if any
+    pub symbol: Option<String>,
+    /// Increases by one with every result for `symbol`
+    pub symbol_sequence: Option<u64>,
+    /// The sequence number of the input message that produced this result
+    pub input_sequence: u64,
+    pub order_result: OrderResult
+}
+
+impl fmt::Display for SequencedOrderResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = self.symbol.as_deref().unwrap_or("-");
+        let symbol_sequence = self.symbol_sequence.map_or("-".to_string(), |symbol_sequence| symbol_sequence.to_string());
+        write!(f, "{}, {}, {}, {}, {}", self.sequence, symbol, symbol_sequence, self.input_sequence, self.order_result)
+    }
 }
\ No newline at end of file
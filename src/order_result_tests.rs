@@ -3,6 +3,9 @@
 #[cfg(test)]
 mod order_result_tests {
     use crate::order_result::*;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+    use chrono::Utc;
 
     #[test]
     fn acknowledgement() {
@@ -16,6 +19,49 @@ mod order_result_tests {
         assert_eq!(rejection.to_string(), "R, 1, 1");
     }
 
+    #[test]
+    fn cancellation() {
+        let cancellation = OrderResult::Cancellation { user: 1, user_order_id: 1 };
+        assert_eq!(cancellation.to_string(), "C, 1, 1");
+    }
+
+    #[test]
+    fn stop_triggered() {
+        let order_result = OrderResult::StopTriggered { user: 1, user_order_id: 2 };
+        assert_eq!(order_result.to_string(), "G, 1, 2");
+    }
+
+    #[test]
+    fn pegged_order_repriced() {
+        let order_result = OrderResult::PeggedOrderRepriced { user: 1, user_order_id: 2, price: 10 };
+        assert_eq!(order_result.to_string(), "E, 1, 2, 10");
+    }
+
+    #[test]
+    fn self_trade_prevention() {
+        let cancellation = OrderResult::SelfTradeCancellation { user: 1, user_order_id: 1 };
+        let decrement = OrderResult::SelfTradeDecrement { user: 1, user_order_id: 1, remaining_qty: 50 };
+        assert_eq!(cancellation.to_string(), "P, 1, 1");
+        assert_eq!(decrement.to_string(), "D, 1, 1, 50");
+    }
+
+    #[test]
+    fn mass_cancel_report() {
+        let by_user = OrderResult::MassCancelReport { user: Some(1), symbol: None, side: None, total_cancelled: 3 };
+        let by_user_and_side = OrderResult::MassCancelReport { user: Some(1), symbol: Some("IBM".to_string()), side: Some('B'), total_cancelled: 0 };
+        assert_eq!(by_user.to_string(), "M, 1, -, -, 3");
+        assert_eq!(by_user_and_side.to_string(), "M, 1, IBM, B, 0");
+    }
+
+    #[test]
+    fn auction_indicative() {
+        let order_result = OrderResult::AuctionIndicative { price: Some(10), matched_qty: 150, imbalance_qty: 50, imbalance_side: Some('B') };
+        assert_eq!(order_result.to_string(), "I, 10, 150, 50, B");
+
+        let order_result = OrderResult::AuctionIndicative { price: None, matched_qty: 0, imbalance_qty: 0, imbalance_side: None };
+        assert_eq!(order_result.to_string(), "I, -, 0, 0, -");
+    }
+
     #[test]
     fn top_of_book_change() {
         let top_of_book_change = OrderResult::TopOfBookChange { side: 'B', price: 10.to_string(), total_quantity: 100.to_string() };
@@ -24,7 +70,31 @@ mod order_result_tests {
 
     #[test]
     fn trade() {
-        let trade = OrderResult::Trade { user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 101, price: 10, qty: 100 };
-        assert_eq!(trade.to_string(), "T, 1, 1, 2, 101, 10, 100");
+        let trade = OrderResult::Trade { trade_id: 1, symbol: "IBM".to_string(), user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 101, price: 10, qty: 100, aggressor_side: Some('B'), time: Utc::now() };
+        assert_eq!(trade.to_string(), "T, 1, 1, 2, 101, 10, 100, 1, B, IBM");
+    }
+
+    #[test]
+    fn auction_trade() {
+        let trade = OrderResult::Trade { trade_id: 7, symbol: "IBM".to_string(), user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 101, price: 10, qty: 100, aggressor_side: None, time: Utc::now() };
+        assert_eq!(trade.to_string(), "T, 1, 1, 2, 101, 10, 100, 7, -, IBM");
+    }
+
+    #[test]
+    fn trade_output() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1)));
+
+        let output: Vec<String> = order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2))).iter().map(|sequenced_order_result| sequenced_order_result.to_string()).collect();
+
+        assert_eq!(output, vec!["3, IBM, 3, 2, A, 2, 2", "4, IBM, 4, 2, T, 2, 2, 1, 1, 10, 100, 1, B, IBM", "5, IBM, 5, 2, B, S, -, -"]);
+    }
+
+    #[test]
+    fn sequenced_order_result() {
+        let from_order_book = SequencedOrderResult { sequence: 7, symbol: Some("IBM".to_string()), symbol_sequence: Some(3), input_sequence: 4, order_result: OrderResult::Acknowledgement { user: 1, user_order_id: 1 } };
+        let from_order_books = SequencedOrderResult { sequence: 8, symbol: None, symbol_sequence: None, input_sequence: 5, order_result: OrderResult::MassCancelReport { user: Some(1), symbol: None, side: None, total_cancelled: 0 } };
+        assert_eq!(from_order_book.to_string(), "7, IBM, 3, 4, A, 1, 1");
+        assert_eq!(from_order_books.to_string(), "8, -, -, 5, M, 1, -, -, 0");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,315 @@
+//! The lifecycle of every order, tracked by `OrderBooks` from the results it publishes, so that a
+//! user can ask what happened to an order after it has left the book. Orders in a terminal state
+//! are kept for a configurable retention period of engine time, or for good by default.
+
+use std::{collections::{HashMap, VecDeque}, fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{order::NewOrder, order_result::OrderResult};
+
+/// The stage an order has reached in its lifecycle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderState {
+    /// Accepted, and resting, waiting to be triggered or queued without having traded
+    New,
+    /// Traded some but not all of its quantity, and still working the rest
+    PartiallyFilled,
+    Filled,
+    /// Removed by its user, a mass cancel, a flush, self-trade prevention or a market order finding
+    /// nothing to trade with
+    Cancelled,
+    Rejected,
+    /// Left the book without trading, resting or being cancelled, as a crossing limit order does
+    /// when no resting order matches its quantity
+    Expired
+}
+
+impl OrderState {
+    /// Whether the order can no longer change
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::New | Self::PartiallyFilled)
+    }
+}
+
+impl FromStr for OrderState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(Self::New),
+            "partially-filled" => Ok(Self::PartiallyFilled),
+            "filled" => Ok(Self::Filled),
+            "cancelled" => Ok(Self::Cancelled),
+            "rejected" => Ok(Self::Rejected),
+            "expired" => Ok(Self::Expired),
+            _ => Err(format!("Unknown order state: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for OrderState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::New => write!(f, "new"),
+            Self::PartiallyFilled => write!(f, "partially-filled"),
+            Self::Filled => write!(f, "filled"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Rejected => write!(f, "rejected"),
+            Self::Expired => write!(f, "expired")
+        }
+    }
+}
+
+/// The event an execution report describes, following FIX `ExecType`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExecType {
+    /// The order was accepted
+    New,
+    /// The order traded
+    Trade,
+    Cancelled,
+    Rejected,
+    Expired,
+    /// The open quantity was reduced by self-trade prevention
+    Restated
+}
+
+impl FromStr for ExecType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(Self::New),
+            "trade" => Ok(Self::Trade),
+            "cancelled" => Ok(Self::Cancelled),
+            "rejected" => Ok(Self::Rejected),
+            "expired" => Ok(Self::Expired),
+            "restated" => Ok(Self::Restated),
+            _ => Err(format!("Unknown exec type: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for ExecType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::New => write!(f, "new"),
+            Self::Trade => write!(f, "trade"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Rejected => write!(f, "rejected"),
+            Self::Expired => write!(f, "expired"),
+            Self::Restated => write!(f, "restated")
+        }
+    }
+}
+
+/// Where an order is in its lifecycle, with what it has traded so far
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderStatus {
+    pub user: u64,
+    pub user_order_id: u64,
+    pub symbol: String,
+    pub side: char,
+    pub state: OrderState,
+    pub original_qty: u64,
+    pub filled_qty: u64,
+    /// The quantity still working, which is zero once the order is terminal
+    pub open_qty: u64,
+    /// The average price of every fill, or zero before the first fill
+    pub average_price: f64,
+    /// The engine time of the latest change
+    pub updated: DateTime<Utc>
+}
+
+impl OrderStatus {
+    pub fn new(new_order: &NewOrder, time: DateTime<Utc>) -> OrderStatus {
+        OrderStatus {
+            user: new_order.user,
+            user_order_id: new_order.user_order_id,
+            symbol: new_order.symbol.clone(),
+            side: new_order.side,
+            state: OrderState::New,
+            original_qty: new_order.qty,
+            filled_qty: 0,
+            open_qty: new_order.qty,
+            average_price: 0.0,
+            updated: time
+        }
+    }
+
+    /// Apply a fill of `qty` at `price`
+    fn fill(&mut self, price: u64, qty: u64) {
+        self.average_price = (self.average_price * self.filled_qty as f64 + price as f64 * qty as f64) / (self.filled_qty + qty) as f64;
+        self.filled_qty += qty;
+        self.open_qty = self.open_qty.saturating_sub(qty);
+        self.state = if self.open_qty == 0 { OrderState::Filled } else { OrderState::PartiallyFilled };
+    }
+}
+
+impl OrderStatus {
+    /// An execution report of this status after an event of `exec_type`, with the quantity and
+    /// price of the fill if it was a trade
+    pub fn execution_report(&self, exec_type: ExecType, last_fill: Option<(u64, u64)>) -> OrderResult {
+        OrderResult::ExecutionReport {
+            user: self.user,
+            user_order_id: self.user_order_id,
+            exec_type,
+            state: self.state,
+            last_qty: last_fill.map_or(0, |(qty, _)| qty),
+            last_price: last_fill.map(|(_, price)| price),
+            cum_qty: self.filled_qty,
+            leaves_qty: self.open_qty,
+            average_price: self.average_price
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let average_price = if self.filled_qty == 0 { "-".to_string() } else { format!("{:.2}", self.average_price) };
+        write!(f, "{}, {}, {}, {}, {}, {}, {}", self.symbol, self.side, self.state, self.original_qty, self.filled_qty, self.open_qty, average_price)
+    }
+}
+
+/// Keeps the status of each order, keyed by user and user order id, where a new order reusing the
+/// id of an earlier one replaces its status
+pub struct OrderStatusTracker {
+    /// How long terminal orders are kept, or for good when `None`
+    retention: Option<Duration>,
+    statuses: HashMap<(u64, u64), OrderStatus>,
+    /// Orders in the order they became terminal, with the time they did
+    terminal: VecDeque<(DateTime<Utc>, (u64, u64))>
+}
+
+impl Default for OrderStatusTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderStatusTracker {
+    pub fn new() -> OrderStatusTracker {
+        OrderStatusTracker { retention: None, statuses: HashMap::new(), terminal: VecDeque::new() }
+    }
+
+    pub fn set_retention(&mut self, retention: Option<Duration>) {
+        self.retention = retention;
+    }
+
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention
+    }
+
+    pub fn status(&self, user: u64, user_order_id: u64) -> Option<&OrderStatus> {
+        self.statuses.get(&(user, user_order_id))
+    }
+
+    /// Every status, by user and then user order id
+    pub fn statuses(&self) -> Vec<&OrderStatus> {
+        let mut statuses: Vec<&OrderStatus> = self.statuses.values().collect();
+        statuses.sort_by_key(|status| (status.user, status.user_order_id));
+        statuses
+    }
+
+    /// Keep `status` as it is, as when restoring a snapshot
+    pub(crate) fn restore(&mut self, status: OrderStatus) {
+        let key = (status.user, status.user_order_id);
+        if status.state.is_terminal() {
+            let index = self.terminal.partition_point(|(finished, _)| *finished <= status.updated);
+            self.terminal.insert(index, (status.updated, key));
+        }
+        self.statuses.insert(key, status);
+    }
+
+    /// Start tracking `new_order`, received at `time`
+    pub(crate) fn open(&mut self, new_order: &NewOrder, time: DateTime<Utc>) {
+        self.statuses.insert((new_order.user, new_order.user_order_id), OrderStatus::new(new_order, time));
+    }
+
+    /// Update the order named by `order_result`, produced at `time`, returning an execution report
+    /// for each order it changed. An acknowledgement confirms a cancel order when `is_cancel` is
+    /// set, and otherwise the acceptance of a new order.
+    pub(crate) fn record(&mut self, order_result: &OrderResult, is_cancel: bool, time: DateTime<Utc>) -> Vec<OrderResult> {
+        match order_result {
+            OrderResult::Acknowledgement { user, user_order_id } if is_cancel => self.finish(*user, *user_order_id, OrderState::Cancelled, time).into_iter().collect(),
+            OrderResult::Acknowledgement { user, user_order_id } => self.status(*user, *user_order_id)
+                .filter(|status| status.state == OrderState::New)
+                .map(|status| status.execution_report(ExecType::New, None))
+                .into_iter().collect(),
+            OrderResult::Rejection { user, user_order_id } | OrderResult::RiskRejection { user, user_order_id, .. } =>
+                self.finish(*user, *user_order_id, OrderState::Rejected, time).into_iter().collect(),
+            OrderResult::Cancellation { user, user_order_id } | OrderResult::SelfTradeCancellation { user, user_order_id } =>
+                self.finish(*user, *user_order_id, OrderState::Cancelled, time).into_iter().collect(),
+            OrderResult::SelfTradeDecrement { user, user_order_id, remaining_qty } => match self.statuses.get_mut(&(*user, *user_order_id)) {
+                Some(status) if !status.state.is_terminal() => {
+                    status.open_qty = *remaining_qty;
+                    status.updated = time;
+                    vec![status.execution_report(ExecType::Restated, None)]
+                },
+                _ => vec![]
+            },
+            OrderResult::Trade { user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, .. } => {
+                self.fill(*user_buy, *user_order_id_buy, *price, *qty, time).into_iter()
+                    .chain(self.fill(*user_sell, *user_order_id_sell, *price, *qty, time))
+                    .collect()
+            },
+            _ => vec![]
+        }
+    }
+
+    fn fill(&mut self, user: u64, user_order_id: u64, price: u64, qty: u64, time: DateTime<Utc>) -> Option<OrderResult> {
+        let status = self.statuses.get_mut(&(user, user_order_id)).filter(|status| !status.state.is_terminal())?;
+        status.fill(price, qty);
+        status.updated = time;
+        let execution_report = status.execution_report(ExecType::Trade, Some((qty, price)));
+        if status.state.is_terminal() {
+            self.terminal.push_back((time, (user, user_order_id)));
+        }
+        Some(execution_report)
+    }
+
+    /// Move a working order to the terminal `state` at `time`, returning its execution report.
+    /// Terminal orders are left as they are.
+    pub(crate) fn finish(&mut self, user: u64, user_order_id: u64, state: OrderState, time: DateTime<Utc>) -> Option<OrderResult> {
+        let status = self.statuses.get_mut(&(user, user_order_id)).filter(|status| !status.state.is_terminal())?;
+        status.state = state;
+        status.open_qty = 0;
+        status.updated = time;
+        let exec_type = match state {
+            OrderState::Rejected => ExecType::Rejected,
+            OrderState::Expired => ExecType::Expired,
+            _ => ExecType::Cancelled
+        };
+        let execution_report = status.execution_report(exec_type, None);
+        self.terminal.push_back((time, (user, user_order_id)));
+        Some(execution_report)
+    }
+
+    /// The orders still working, by user and then user order id
+    pub(crate) fn working_orders(&self) -> Vec<(u64, u64, String)> {
+        let mut working_orders: Vec<(u64, u64, String)> = self.statuses.values()
+            .filter(|status| !status.state.is_terminal())
+            .map(|status| (status.user, status.user_order_id, status.symbol.clone()))
+            .collect();
+        working_orders.sort();
+        working_orders
+    }
+
+    /// Forget terminal orders whose retention period has passed by `time`
+    pub(crate) fn purge(&mut self, time: DateTime<Utc>) {
+        let retention = match self.retention {
+            Some(retention) => retention,
+            None => return
+        };
+        while let Some((finished, key)) = self.terminal.front().cloned() {
+            if finished + retention > time {
+                break;
+            }
+            self.terminal.pop_front();
+            if self.statuses.get(&key).map_or(false, |status| status.state.is_terminal() && status.updated == finished) {
+                self.statuses.remove(&key);
+            }
+        }
+    }
+}
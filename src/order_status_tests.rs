@@ -0,0 +1,147 @@
+//! Unit tests for order lifecycle tracking and status queries
+
+#[cfg(test)]
+mod order_status_tests {
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::order_status::*;
+    use crate::clock::SimulatedClock;
+    use crate::order_books::OrderBooks;
+    use crate::order_result::{OrderResult, SequencedOrderResult};
+    use crate::order::*;
+
+    fn state(order_books: &OrderBooks, user: u64, user_order_id: u64) -> Option<OrderState> {
+        order_books.order_status(user, user_order_id).map(|status| status.state)
+    }
+
+    #[test]
+    fn parse_state() {
+        assert_eq!("partially-filled".parse::<OrderState>(), Ok(OrderState::PartiallyFilled));
+        assert_eq!(OrderState::Expired.to_string(), "expired");
+        assert!("done".parse::<OrderState>().is_err());
+        assert!(OrderState::Filled.is_terminal() && !OrderState::New.is_terminal());
+    }
+
+    #[test]
+    fn new_then_filled() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1)));
+        assert_eq!(state(&order_books, 1, 1), Some(OrderState::New));
+
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 11, 100, 'B', 1)));
+        let status = order_books.order_status(2, 1).unwrap();
+        assert_eq!((status.state, status.original_qty, status.filled_qty, status.open_qty, status.average_price), (OrderState::Filled, 100, 100, 0, 10.0));
+        assert_eq!(state(&order_books, 1, 1), Some(OrderState::Filled));
+    }
+
+    #[test]
+    fn iceberg_partially_filled() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 300, 'S', 1).with_display_qty(100)));
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        let status = order_books.order_status(1, 1).unwrap();
+        assert_eq!((status.state, status.filled_qty, status.open_qty), (OrderState::PartiallyFilled, 100, 200));
+    }
+
+    #[test]
+    fn cancelled_rejected_and_expired() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::Cancel(CancelOrder::new(1, 1)));
+        assert_eq!(state(&order_books, 1, 1), Some(OrderState::Cancelled));
+
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2).with_post_only(PostOnly::Reject)));
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 50, 'S', 1).with_post_only(PostOnly::Reject)));
+        assert_eq!(state(&order_books, 2, 1), Some(OrderState::Rejected));
+
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 9, 50, 'S', 2)));
+        assert_eq!(state(&order_books, 2, 2), Some(OrderState::Expired));
+
+        order_books.process(InputMessage::New(NewOrder::new_market(2, "AAPL".to_string(), 50, 'S', 3)));
+        assert_eq!(state(&order_books, 2, 3), Some(OrderState::Cancelled));
+    }
+
+    #[test]
+    fn flush_without_reports_cancels() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::New(NewOrder::new_stop(1, "IBM".to_string(), 12, 100, 'B', 2)));
+        let order_results = order_books.process(InputMessage::Flush(FlushOrder::new(None, None, false)));
+
+        assert!(order_results.iter().all(|sequenced_order_result| !matches!(sequenced_order_result.order_result, OrderResult::Cancellation { .. })));
+        assert_eq!(state(&order_books, 1, 1), Some(OrderState::Cancelled));
+        assert_eq!(state(&order_books, 1, 2), Some(OrderState::Cancelled));
+    }
+
+    #[test]
+    fn status_query() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        let order_results = order_books.process(InputMessage::OrderStatus(OrderStatusQuery::new(1, 1)));
+        assert_eq!(order_results[0].order_result.to_string(), "O, 1, 1, IBM, B, new, 100, 0, 100, -");
+        assert_eq!(order_results[0].symbol, None);
+        assert_eq!(order_books.order_status_query(OrderStatusQuery::new(9, 9))[0].to_string(), "O, 9, 9, unknown");
+    }
+
+    #[test]
+    fn terminal_orders_retained_for_period() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_clock(Box::new(SimulatedClock::new("2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(), Duration::seconds(1))));
+        order_books.set_order_retention(Some(Duration::seconds(2)));
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::Cancel(CancelOrder::new(1, 1)));
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2)));
+        assert_eq!(state(&order_books, 1, 1), Some(OrderState::Cancelled));
+
+        order_books.process(InputMessage::OrderStatus(OrderStatusQuery::new(1, 1)));
+        assert_eq!(state(&order_books, 1, 1), None);
+        assert_eq!(state(&order_books, 1, 2), Some(OrderState::New));
+    }
+
+    fn reports(order_results: &[SequencedOrderResult]) -> Vec<String> {
+        order_results.iter().map(|sequenced_order_result| sequenced_order_result.order_result.to_string()).collect()
+    }
+
+    #[test]
+    fn execution_reports_follow_results() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_execution_reports(true);
+        let order_results = order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 300, 'S', 1).with_display_qty(100)));
+        assert_eq!(reports(&order_results), vec!["A, 1, 1", "X, 1, 1, new, new, 0, -, 0, 300, -", "B, S, 10, 100"]);
+        assert_eq!(order_results[1].symbol.as_deref(), Some("IBM"));
+
+        let order_results = order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 1)));
+        assert_eq!(reports(&order_results)[..5], [
+            "A, 2, 1".to_string(),
+            "X, 2, 1, new, new, 0, -, 0, 100, -".to_string(),
+            "T, 2, 1, 1, 1, 10, 100, 1, B, IBM".to_string(),
+            "X, 2, 1, trade, filled, 100, 10, 100, 0, 10.00".to_string(),
+            "X, 1, 1, trade, partially-filled, 100, 10, 100, 200, 10.00".to_string()
+        ]);
+    }
+
+    #[test]
+    fn execution_reports_for_terminal_states() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_execution_reports(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        assert_eq!(reports(&order_books.process(InputMessage::Cancel(CancelOrder::new(1, 1))))[1], "X, 1, 1, cancelled, cancelled, 0, -, 0, 0, -");
+        let order_results = order_books.process(InputMessage::New(NewOrder::new_market(1, "IBM".to_string(), 100, 'B', 2).with_post_only(PostOnly::Reject)));
+        assert_eq!(reports(&order_results), vec!["R, 1, 2", "X, 1, 2, rejected, rejected, 0, -, 0, 0, -"]);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 3)));
+        let order_results = order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 9, 50, 'S', 1)));
+        assert_eq!(reports(&order_results).last().unwrap(), "X, 2, 1, expired, expired, 0, -, 0, 0, -");
+    }
+
+    #[test]
+    fn execution_reports_off_by_default() {
+        let mut order_books = OrderBooks::new(true);
+        let order_results = order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        assert!(!order_books.execution_reports());
+        assert!(order_results.iter().all(|sequenced_order_result| !matches!(sequenced_order_result.order_result, OrderResult::ExecutionReport { .. })));
+    }
+}
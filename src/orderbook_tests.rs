@@ -83,7 +83,207 @@ mod orderbook_tests {
         assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 1 });
         assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "10".to_string(), total_quantity: "100".to_string() });
         assert_eq!(order_results[2], OrderResult::Acknowledgement { user: 2, user_order_id: 101 });
-        assert_eq!(order_results[3], OrderResult::Trade { user_buy: 2, user_order_id_buy: 101, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100 });
+        assert!(matches!(&order_results[3], OrderResult::Trade { user_buy: 2, user_order_id_buy: 101, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
         assert_eq!(order_results[4], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn order_book_flush() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'B', 101));
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 12, 100, 'S', 2));
+        let order_results = order_book.flush(None, true);
+
+        assert_eq!(order_results.len(), 5);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 2, user_order_id: 101 });
+        assert_eq!(order_results[1], OrderResult::Cancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[2], OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_results[3], OrderResult::Cancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[4], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert!(order_book.is_empty());
+    }
+
+    #[test]
+    fn order_book_flush_without_cancellations() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+        let order_results = order_book.flush(None, false);
+
+        assert_eq!(order_results.len(), 1);
+        assert_eq!(order_results[0], OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() });
+    }
+
+    #[test]
+    fn order_book_flush_user() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'B', 101));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 12, 100, 'S', 102));
+        let order_results = order_book.flush(Some(1), true);
+
+        assert_eq!(order_results.len(), 2);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "100".to_string() });
+        assert!(!order_book.is_empty());
+    }
+
+    #[test]
+    fn order_book_stop_order_waits_for_trade() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        let order_results = order_book.add_order(NewOrder::new_stop(1, "AAPL".to_string(), 11, 100, 'B', 1));
+        assert_eq!(order_results, vec![OrderResult::Acknowledgement { user: 1, user_order_id: 1 }]);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        let order_results = order_book.cancel_order(&CancelOrder::new(1, 1));
+
+        assert_eq!(order_results, vec![OrderResult::Acknowledgement { user: 1, user_order_id: 1 }]);
+        assert_eq!(order_book.resting_orders('S').keys().collect::<Vec<&u64>>(), vec![&11]);
+    }
+
+    #[test]
+    fn order_book_stop_order_triggered_by_trade() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new_stop(1, "AAPL".to_string(), 11, 100, 'B', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 12, 100, 'S', 102));
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 11, 100, 'B', 201));
+
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 3, user_order_id_buy: 201, user_sell: 2, user_order_id_sell: 101, price: 11, qty: 100, .. }));
+        assert_eq!(order_results[3], OrderResult::StopTriggered { user: 1, user_order_id: 1 });
+        assert!(matches!(&order_results[4], OrderResult::Trade { user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 102, price: 12, qty: 100, .. }));
+        assert!(order_book.is_empty());
+    }
+
+    #[test]
+    fn order_book_stop_orders_cascade() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new_stop(1, "AAPL".to_string(), 10, 100, 'S', 1));
+        order_book.add_order(NewOrder::new_stop_limit(1, "AAPL".to_string(), 9, 8, 50, 'S', 2));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'B', 101));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 9, 100, 'B', 102));
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'S', 201));
+
+        assert!(order_results.contains(&OrderResult::StopTriggered { user: 1, user_order_id: 1 }));
+        assert!(order_results.iter().any(|order_result| matches!(order_result, OrderResult::Trade { user_buy: 2, user_order_id_buy: 102, user_sell: 1, user_order_id_sell: 1, price: 9, qty: 100, .. })));
+        assert!(order_results.contains(&OrderResult::StopTriggered { user: 1, user_order_id: 2 }));
+        assert_eq!(order_book.resting_orders('S').keys().collect::<Vec<&u64>>(), vec![&8]);
+    }
+
+    #[test]
+    fn order_book_unmatched_market_order_cancelled() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 50, 'S', 101));
+        let order_results = order_book.add_order(NewOrder::new_market(1, "AAPL".to_string(), 100, 'B', 1));
+
+        assert_eq!(order_results, vec![OrderResult::Acknowledgement { user: 1, user_order_id: 1 }, OrderResult::Cancellation { user: 1, user_order_id: 1 }]);
+        assert_eq!(order_book.resting_orders('S').keys().collect::<Vec<&u64>>(), vec![&11]);
+    }
+
+    #[test]
+    fn order_book_market_order_rejected_without_trading() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        let order_results = order_book.add_order(NewOrder::new_market(1, "AAPL".to_string(), 100, 'B', 1));
+
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 1 }]);
+    }
+
+    #[test]
+    fn order_book_iceberg_shows_display_qty() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 250, 'S', 1).with_display_qty(100));
+
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "10".to_string(), total_quantity: "100".to_string() });
+    }
+
+    #[test]
+    fn order_book_iceberg_replenishes_behind_level() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 250, 'S', 1).with_display_qty(100));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'S', 101));
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 201));
+
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 3, user_order_id_buy: 201, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
+        assert_eq!(order_results.len(), 2);
+
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 202));
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 3, user_order_id_buy: 202, user_sell: 2, user_order_id_sell: 101, price: 10, qty: 100, .. }));
+
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 203));
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 3, user_order_id_buy: 203, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
+        assert_eq!(order_results[2], OrderResult::TopOfBookChange { side: 'S', price: "10".to_string(), total_quantity: "50".to_string() });
+    }
+
+    #[test]
+    fn order_book_iceberg_cancel_removes_reserve() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 250, 'S', 1).with_display_qty(100));
+        let order_results = order_book.cancel_order(&CancelOrder::new(1, 1));
+
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert!(order_book.is_empty());
+    }
+
+    #[test]
+    fn order_book_post_only_rejected_when_crossing() {
+        let mut order_book = OrderBook::new("AAPL", false);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 12, 100, 'B', 1).with_post_only(PostOnly::Reject));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 1 }]);
+
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 11, 100, 'B', 1).with_post_only(PostOnly::Reject));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 1 }]);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2).with_post_only(PostOnly::Reject));
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
+    }
+
+    #[test]
+    fn order_book_post_only_repriced_when_crossing() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 8, 100, 'B', 102));
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 12, 100, 'B', 1).with_post_only(PostOnly::Reprice));
+
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 1, user_order_id: 1 },
+            OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "100".to_string() }
+        ]);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 7, 100, 'S', 2).with_post_only(PostOnly::Reprice));
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "11".to_string(), total_quantity: "200".to_string() });
+    }
+
+    #[test]
+    fn order_book_hidden_order_matched_but_not_shown() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+        let order_results = order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 50, 'S', 102).with_hidden());
+        assert_eq!(order_results, vec![OrderResult::Acknowledgement { user: 2, user_order_id: 102 }]);
+
+        let new_order = NewOrder::new(1, "AAPL".to_string(), 10, 50, 'B', 1);
+        let time = new_order.time_received;
+        let order_results = order_book.add_order(new_order);
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 1, user_order_id: 1 },
+            OrderResult::Trade { trade_id: 0, symbol: "AAPL".to_string(), user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 102, price: 10, qty: 50, aggressor_side: Some('B'), time }
+        ]);
+    }
+}
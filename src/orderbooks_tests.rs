@@ -137,7 +137,7 @@ mod orderbooks_tests {
         assert_eq!(order_results[3], OrderResult::Acknowledgement { user: 2, user_order_id: 102 });
         assert_eq!(order_results[4], OrderResult::TopOfBookChange { side: 'S', price: "11".to_string(), total_quantity: "100".to_string() });
         assert_eq!(order_results[5], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
-        assert_eq!(order_results[6], OrderResult::Trade { user_buy: 1, user_order_id_buy: 2, user_sell: 2, user_order_id_sell: 102, price: 11, qty: 100 });
+        assert!(matches!(&order_results[6], OrderResult::Trade { user_buy: 1, user_order_id_buy: 2, user_sell: 2, user_order_id_sell: 102, price: 11, qty: 100, .. }));
         assert_eq!(order_results[7], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
         assert_eq!(order_results[8], OrderResult::Acknowledgement { user: 2, user_order_id: 103 });
         assert_eq!(order_results[9], OrderResult::TopOfBookChange { side: 'S', price: "11".to_string(), total_quantity: "100".to_string() });
@@ -234,7 +234,7 @@ mod orderbooks_tests {
         assert_eq!(order_results[5], OrderResult::Acknowledgement { user: 2, user_order_id: 102 });
         assert_eq!(order_results[6], OrderResult::TopOfBookChange { side: 'S', price: "11".to_string(), total_quantity: "100".to_string() });
         assert_eq!(order_results[7], OrderResult::Acknowledgement { user: 1, user_order_id: 103 });
-        assert_eq!(order_results[8], OrderResult::Trade { user_buy: 1, user_order_id_buy: 103, user_sell: 2, user_order_id_sell: 102, price: 11, qty: 100 });
+        assert!(matches!(&order_results[8], OrderResult::Trade { user_buy: 1, user_order_id_buy: 103, user_sell: 2, user_order_id_sell: 102, price: 11, qty: 100, .. }));
         assert_eq!(order_results[9], OrderResult::TopOfBookChange { side: 'S', price: "12".to_string(), total_quantity: "100".to_string() });
     }
 
@@ -581,4 +581,190 @@ mod orderbooks_tests {
         assert_eq!(order_results[41], OrderResult::Acknowledgement { user: 1, user_order_id: 3 });
         assert_eq!(order_results[42], OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn flush_all_symbols() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 100, 'S', 2));
+        let order_results = order_books.flush(FlushOrder::all());
+
+        assert_eq!(order_results.len(), 4);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_results[2], OrderResult::Cancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[3], OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_books.cancel_order(CancelOrder::new(1, 1)).len(), 0);
+    }
+
+    #[test]
+    fn flush_symbol() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 100, 'S', 2));
+        let mut order_results = order_books.flush(FlushOrder::new(Some("IBM".to_string()), None, true));
+        order_results.append(&mut order_books.flush(FlushOrder::new(Some("AAPL".to_string()), None, true)));
+
+        assert_eq!(order_results.len(), 2);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_books.cancel_order(CancelOrder::new(1, 1))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 1 });
+    }
+
+    #[test]
+    fn flush_user() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 9, 100, 'B', 101));
+        order_books.add_order(NewOrder::new(2, "MSFT".to_string(), 12, 100, 'S', 102));
+        let order_results = order_books.flush(FlushOrder::new(None, Some(1), false));
+
+        assert_eq!(order_results.len(), 1);
+        assert_eq!(order_results[0], OrderResult::TopOfBookChange { side: 'B', price: "9".to_string(), total_quantity: "100".to_string() });
+        assert_eq!(order_books.cancel_order(CancelOrder::new(2, 102))[0], OrderResult::Acknowledgement { user: 2, user_order_id: 102 });
+    }
+
+    #[test]
+    fn mass_cancel_by_user() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 9, 100, 'B', 101));
+        order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 12, 100, 'S', 2));
+        let order_results = order_books.mass_cancel(MassCancelOrder::by_user(1));
+
+        assert_eq!(order_results.len(), 5);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'B', price: "9".to_string(), total_quantity: "100".to_string() });
+        assert_eq!(order_results[2], OrderResult::Cancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[3], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_results[4], OrderResult::MassCancelReport { user: Some(1), symbol: None, side: None, total_cancelled: 2 });
+        assert_eq!(order_books.symbols(), vec!["IBM".to_string()]);
+    }
+
+    #[test]
+    fn mass_cancel_by_symbol() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 101));
+        order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 12, 100, 'S', 2));
+        let order_results = order_books.mass_cancel(MassCancelOrder::by_symbol("IBM"));
+
+        assert_eq!(order_results.len(), 4);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[1], OrderResult::Cancellation { user: 2, user_order_id: 101 });
+        assert_eq!(order_results[2], OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() });
+        assert_eq!(order_results[3], OrderResult::MassCancelReport { user: None, symbol: Some("IBM".to_string()), side: None, total_cancelled: 2 });
+        assert_eq!(order_books.cancel_order(CancelOrder::new(1, 2))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
+        assert_eq!(order_books.symbols(), vec!["MSFT".to_string()]);
+    }
+
+    #[test]
+    fn mass_cancel_by_user_and_side() {
+        let mut order_books = OrderBooks::new(false);
+
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 100, 'S', 2));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 13, 100, 'S', 101));
+        let order_results = order_books.mass_cancel(MassCancelOrder::by_user_and_side(1, "IBM", 'S'));
+
+        assert_eq!(order_results.len(), 3);
+        assert_eq!(order_results[0], OrderResult::Cancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[1], OrderResult::TopOfBookChange { side: 'S', price: "13".to_string(), total_quantity: "100".to_string() });
+        assert_eq!(order_results[2], OrderResult::MassCancelReport { user: Some(1), symbol: Some("IBM".to_string()), side: Some('S'), total_cancelled: 1 });
+    }
+
+    #[test]
+    fn mass_cancel_nothing_to_cancel() {
+        let mut order_books = OrderBooks::new(false);
+
+        let order_results = order_books.mass_cancel(MassCancelOrder::by_user(1));
+
+        assert_eq!(order_results.len(), 1);
+        assert_eq!(order_results[0], OrderResult::MassCancelReport { user: Some(1), symbol: None, side: None, total_cancelled: 0 });
+    }
+
+    #[test]
+    fn sequence_numbers() {
+        let mut order_books = OrderBooks::new(false);
+
+        let mut sequenced_order_results = order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        sequenced_order_results.append(&mut order_books.process(InputMessage::New(NewOrder::new(1, "MSFT".to_string(), 10, 100, 'B', 2))));
+        sequenced_order_results.append(&mut order_books.process(InputMessage::MassCancel(MassCancelOrder::by_user(1))));
+
+        let sequences: Vec<(u64, Option<String>, Option<u64>, u64)> = sequenced_order_results.iter()
+            .map(|result| (result.sequence, result.symbol.clone(), result.symbol_sequence, result.input_sequence))
+            .collect();
+        assert_eq!(sequences, vec![
+            (1, Some("IBM".to_string()), Some(1), 1),
+            (2, Some("IBM".to_string()), Some(2), 1),
+            (3, Some("MSFT".to_string()), Some(1), 2),
+            (4, Some("MSFT".to_string()), Some(2), 2),
+            (5, Some("IBM".to_string()), Some(3), 3),
+            (6, Some("IBM".to_string()), Some(4), 3),
+            (7, Some("MSFT".to_string()), Some(3), 3),
+            (8, Some("MSFT".to_string()), Some(4), 3),
+            (9, None, None, 3)
+        ]);
+        assert_eq!(sequenced_order_results[8].order_result, OrderResult::MassCancelReport { user: Some(1), symbol: None, side: None, total_cancelled: 2 });
+    }
+
+    #[test]
+    fn sequence_numbers_follow_input_sequence() {
+        let mut order_books = OrderBooks::new(false);
+
+        let first = order_books.process_at(10, chrono::Utc::now(), InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        let second = order_books.process(InputMessage::Cancel(CancelOrder::new(1, 1)));
+
+        assert_eq!(first[0].input_sequence, 10);
+        assert_eq!(second[0].input_sequence, 11);
+        assert_eq!(second[0].sequence, 3);
+    }
+
+    #[test]
+    fn continue_sequences() {
+        let mut order_books = OrderBooks::new(false);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        let mut next_order_books = OrderBooks::new(false);
+        next_order_books.continue_sequences(&order_books);
+        let sequenced_order_results = next_order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+
+        assert_eq!(sequenced_order_results[0].sequence, 3);
+        assert_eq!(sequenced_order_results[0].symbol_sequence, Some(3));
+        assert_eq!(sequenced_order_results[0].input_sequence, 2);
+    }
+
+    #[test]
+    fn trades_numbered_across_symbols() {
+        let mut order_books = OrderBooks::new(true);
+        let time = chrono::Utc::now();
+        order_books.process_at(1, time, InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process_at(2, time, InputMessage::New(NewOrder::new(1, "AAPL".to_string(), 20, 100, 'S', 2)));
+
+        let ibm_results = order_books.process_at(3, time, InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 101)));
+        assert_eq!(ibm_results[1].order_result, OrderResult::Trade {
+            trade_id: 1,
+            symbol: "IBM".to_string(),
+            user_buy: 1,
+            user_order_id_buy: 1,
+            user_sell: 2,
+            user_order_id_sell: 101,
+            price: 10,
+            qty: 100,
+            aggressor_side: Some('S'),
+            time
+        });
+
+        let aapl_results = order_books.process_at(4, time + chrono::Duration::seconds(1), InputMessage::New(NewOrder::new(2, "AAPL".to_string(), 20, 100, 'B', 102)));
+        assert!(matches!(&aapl_results[1].order_result, OrderResult::Trade { trade_id: 2, aggressor_side: Some('B'), .. }));
+
+        let mut next_order_books = OrderBooks::new(true);
+        next_order_books.continue_sequences(&order_books);
+        assert_eq!(next_order_books.next_trade_id(), 3);
+    }
+}
@@ -0,0 +1,327 @@
+//! A compact binary order entry protocol, modelled on OUCH. Every message is framed by a two byte
+//! big-endian length, followed by a one byte message type and fixed-width big-endian fields.
+//! Symbols are eight ASCII bytes padded with spaces, and a price of `u32::MAX` means a market order.
+//!
+//! Client messages:
+//! ```text
+//! 'L' Login         user u64
+//! 'O' EnterOrder    token u64, side u8 ('B' or 'S'), qty u32, symbol [u8; 8], price u32
+//! 'X' CancelOrder   token u64
+//! 'U' ReplaceOrder  token u64, replacement token u64, qty u32, price u32
+//! ```
+//! Server messages:
+//! ```text
+//! 'L' LoginAccepted user u64
+//! 'A' Accepted      token u64, side u8, qty u32, symbol [u8; 8], price u32
+//! 'U' Replaced      replacement token u64, token u64, qty u32, price u32
+//! 'C' Canceled      token u64, decrement qty u32, reason u8
+//! 'E' Executed      token u64, qty u32, price u32, match number u64
+//! 'J' Rejected      token u64, reason u8
+//! ```
+//! A token is the user order id of the order, chosen by the client.
+
+use std::{convert::TryInto, fmt};
+
+/// Reasons the rest of an order was cancelled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CancelReason {
+    /// Cancelled, or replaced, at the client's request
+    UserRequested,
+    /// Removed or reduced by self-trade prevention
+    SelfTrade,
+    /// Removed by the engine, as when a market order finds nothing to trade with, a crossing limit
+    /// order finds no match for its quantity, or the book is flushed
+    System
+}
+
+impl CancelReason {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::UserRequested => b'U',
+            Self::SelfTrade => b'Q',
+            Self::System => b'Z'
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<CancelReason> {
+        match code {
+            b'U' => Some(Self::UserRequested),
+            b'Q' => Some(Self::SelfTrade),
+            b'Z' => Some(Self::System),
+            _ => None
+        }
+    }
+}
+
+/// Reasons an order or replacement was rejected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RejectReason {
+    /// The connection has not logged in
+    NotLoggedIn,
+    /// The token is already used by a working order
+    DuplicateToken,
+    /// The order to replace is not working
+    UnknownOrder,
+    /// The side, quantity or symbol is not valid
+    InvalidOrder,
+    /// The order breaches a risk limit
+    RiskLimit,
+    /// The order book rejected the order, as when the symbol is halted or the price is outside its band
+    Rejected
+}
+
+impl RejectReason {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::NotLoggedIn => b'L',
+            Self::DuplicateToken => b'D',
+            Self::UnknownOrder => b'U',
+            Self::InvalidOrder => b'I',
+            Self::RiskLimit => b'K',
+            Self::Rejected => b'R'
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<RejectReason> {
+        match code {
+            b'L' => Some(Self::NotLoggedIn),
+            b'D' => Some(Self::DuplicateToken),
+            b'U' => Some(Self::UnknownOrder),
+            b'I' => Some(Self::InvalidOrder),
+            b'K' => Some(Self::RiskLimit),
+            b'R' => Some(Self::Rejected),
+            _ => None
+        }
+    }
+}
+
+/// A message from a client
+#[derive(Clone, Debug, PartialEq)]
+pub enum InboundMessage {
+    Login { user: u64 },
+    /// A new order, which is a market order when `price` is `None`
+    EnterOrder { token: u64, side: char, qty: u32, symbol: String, price: Option<u32> },
+    CancelOrder { token: u64 },
+    /// Replace the order `token` with the order `replacement`, for the same symbol and side. The
+    /// replacement is a new order, so it always loses the time priority of `token`.
+    ReplaceOrder { token: u64, replacement: u64, qty: u32, price: Option<u32> }
+}
+
+/// A message to a client
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutboundMessage {
+    LoginAccepted { user: u64 },
+    Accepted { token: u64, side: char, qty: u32, symbol: String, price: Option<u32> },
+    Replaced { replacement: u64, token: u64, qty: u32, price: Option<u32> },
+    /// The order `token` lost `decrement` of its open quantity, which leaves nothing open unless it
+    /// was reduced by self-trade prevention
+    Canceled { token: u64, decrement: u32, reason: CancelReason },
+    Executed { token: u64, qty: u32, price: u32, match_number: u64 },
+    Rejected { token: u64, reason: RejectReason }
+}
+
+/// Reasons bytes could not be read as a message
+#[derive(Debug, PartialEq)]
+pub enum OuchError {
+    UnknownType(u8),
+    /// The message is the wrong length for its type, holding the type and length
+    InvalidLength(u8, usize),
+    /// A field holds a value that cannot be decoded, such as a non-ASCII symbol
+    InvalidField(String)
+}
+
+impl fmt::Display for OuchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownType(message_type) => write!(f, "Unknown message type: {:?}", *message_type as char),
+            Self::InvalidLength(message_type, length) => write!(f, "Invalid length for message type {:?}: {}", *message_type as char, length),
+            Self::InvalidField(field) => write!(f, "Invalid field: {}", field)
+        }
+    }
+}
+
+/// Appends fixed-width big-endian fields
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new(message_type: u8) -> Writer {
+        Writer(vec![message_type])
+    }
+
+    fn u8(mut self, value: u8) -> Writer {
+        self.0.push(value);
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Writer {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Writer {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn symbol(mut self, symbol: &str) -> Writer {
+        let mut field = [b' '; 8];
+        for (byte, symbol_byte) in field.iter_mut().zip(symbol.bytes()) {
+            *byte = symbol_byte;
+        }
+        self.0.extend_from_slice(&field);
+        self
+    }
+}
+
+/// Reads fixed-width big-endian fields from a message whose length has been checked
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> &'a [u8] {
+        let (field, rest) = self.0.split_at(length);
+        self.0 = rest;
+        field
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_be_bytes(self.take(4).try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_be_bytes(self.take(8).try_into().unwrap())
+    }
+
+    fn symbol(&mut self) -> Result<String, OuchError> {
+        let field = self.take(8);
+        if !field.is_ascii() {
+            return Err(OuchError::InvalidField(format!("symbol {:?}", field)));
+        }
+        Ok(String::from_utf8_lossy(field).trim_end().to_string())
+    }
+}
+
+/// The price field for a market order, so that zero stays a valid limit price
+const MARKET_PRICE: u32 = u32::MAX;
+
+/// The price field of `price`, where `MARKET_PRICE` stands for a market order
+fn encode_price(price: Option<u32>) -> u32 {
+    price.unwrap_or(MARKET_PRICE)
+}
+
+fn decode_price(price: u32) -> Option<u32> {
+    Some(price).filter(|price| *price != MARKET_PRICE)
+}
+
+/// Check `payload` is `length` bytes long for its type, returning a reader after the type
+fn fields(payload: &[u8], length: usize) -> Result<Reader<'_>, OuchError> {
+    if payload.len() != length {
+        return Err(OuchError::InvalidLength(payload[0], payload.len()));
+    }
+    Ok(Reader(&payload[1..]))
+}
+
+impl InboundMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Login { user } => Writer::new(b'L').u64(*user),
+            Self::EnterOrder { token, side, qty, symbol, price } =>
+                Writer::new(b'O').u64(*token).u8(*side as u8).u32(*qty).symbol(symbol).u32(encode_price(*price)),
+            Self::CancelOrder { token } => Writer::new(b'X').u64(*token),
+            Self::ReplaceOrder { token, replacement, qty, price } =>
+                Writer::new(b'U').u64(*token).u64(*replacement).u32(*qty).u32(encode_price(*price))
+        }.0
+    }
+
+    /// Read a message from an unframed `payload`
+    pub fn decode(payload: &[u8]) -> Result<InboundMessage, OuchError> {
+        match payload.first() {
+            Some(b'L') => {
+                let mut reader = fields(payload, 9)?;
+                Ok(Self::Login { user: reader.u64() })
+            },
+            Some(b'O') => {
+                let mut reader = fields(payload, 26)?;
+                Ok(Self::EnterOrder { token: reader.u64(), side: reader.u8() as char, qty: reader.u32(), symbol: reader.symbol()?, price: decode_price(reader.u32()) })
+            },
+            Some(b'X') => {
+                let mut reader = fields(payload, 9)?;
+                Ok(Self::CancelOrder { token: reader.u64() })
+            },
+            Some(b'U') => {
+                let mut reader = fields(payload, 25)?;
+                Ok(Self::ReplaceOrder { token: reader.u64(), replacement: reader.u64(), qty: reader.u32(), price: decode_price(reader.u32()) })
+            },
+            Some(message_type) => Err(OuchError::UnknownType(*message_type)),
+            None => Err(OuchError::InvalidLength(0, 0))
+        }
+    }
+}
+
+impl OutboundMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::LoginAccepted { user } => Writer::new(b'L').u64(*user),
+            Self::Accepted { token, side, qty, symbol, price } =>
+                Writer::new(b'A').u64(*token).u8(*side as u8).u32(*qty).symbol(symbol).u32(encode_price(*price)),
+            Self::Replaced { replacement, token, qty, price } =>
+                Writer::new(b'U').u64(*replacement).u64(*token).u32(*qty).u32(encode_price(*price)),
+            Self::Canceled { token, decrement, reason } => Writer::new(b'C').u64(*token).u32(*decrement).u8(reason.code()),
+            Self::Executed { token, qty, price, match_number } => Writer::new(b'E').u64(*token).u32(*qty).u32(*price).u64(*match_number),
+            Self::Rejected { token, reason } => Writer::new(b'J').u64(*token).u8(reason.code())
+        }.0
+    }
+
+    /// Read a message from an unframed `payload`
+    pub fn decode(payload: &[u8]) -> Result<OutboundMessage, OuchError> {
+        match payload.first() {
+            Some(b'L') => {
+                let mut reader = fields(payload, 9)?;
+                Ok(Self::LoginAccepted { user: reader.u64() })
+            },
+            Some(b'A') => {
+                let mut reader = fields(payload, 26)?;
+                Ok(Self::Accepted { token: reader.u64(), side: reader.u8() as char, qty: reader.u32(), symbol: reader.symbol()?, price: decode_price(reader.u32()) })
+            },
+            Some(b'U') => {
+                let mut reader = fields(payload, 25)?;
+                Ok(Self::Replaced { replacement: reader.u64(), token: reader.u64(), qty: reader.u32(), price: decode_price(reader.u32()) })
+            },
+            Some(b'C') => {
+                let mut reader = fields(payload, 14)?;
+                let (token, decrement, code) = (reader.u64(), reader.u32(), reader.u8());
+                let reason = CancelReason::from_code(code).ok_or_else(|| OuchError::InvalidField(format!("cancel reason {:?}", code as char)))?;
+                Ok(Self::Canceled { token, decrement, reason })
+            },
+            Some(b'E') => {
+                let mut reader = fields(payload, 25)?;
+                Ok(Self::Executed { token: reader.u64(), qty: reader.u32(), price: reader.u32(), match_number: reader.u64() })
+            },
+            Some(b'J') => {
+                let mut reader = fields(payload, 10)?;
+                let (token, code) = (reader.u64(), reader.u8());
+                let reason = RejectReason::from_code(code).ok_or_else(|| OuchError::InvalidField(format!("reject reason {:?}", code as char)))?;
+                Ok(Self::Rejected { token, reason })
+            },
+            Some(message_type) => Err(OuchError::UnknownType(*message_type)),
+            None => Err(OuchError::InvalidLength(0, 0))
+        }
+    }
+}
+
+/// Prefix `payload` with its length
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut framed = (payload.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// The payload of the first frame in `buffer` and the number of bytes the frame took up, or `None`
+/// if `buffer` does not yet hold a whole frame
+pub fn read_frame(buffer: &[u8]) -> Option<(&[u8], usize)> {
+    let length = u16::from_be_bytes(buffer.get(..2)?.try_into().unwrap()) as usize;
+    buffer.get(2..2 + length).map(|payload| (payload, 2 + length))
+}
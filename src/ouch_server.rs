@@ -0,0 +1,345 @@
+//! A long-running order entry server speaking the binary protocol in `ouch`. Each connection logs in
+//! as a user and enters, cancels and replaces orders. Messages from every connection are handled by
+//! a single `OrderBooks` in the order they arrive, and acknowledgements, rejects, cancels and fills
+//! are sent back on the connection which entered each order. A replace cancels the order and
+//! enters its replacement as a new order, so it always goes to the back of its price level. The
+//! public results can also be published as a UDP market data feed.
+
+use std::{collections::HashMap, io::{self, Read, Write}, net::{Shutdown, TcpListener, TcpStream}, sync::mpsc::{self, Receiver, RecvTimeoutError, Sender}, thread, time::Duration};
+
+use crate::{itch::FeedMessage, udp_feed::UdpPublisher, ouch::{CancelReason, InboundMessage, OutboundMessage, RejectReason, frame, read_frame}, order::{CancelOrder, InputMessage, NewOrder}, order_books::OrderBooks, order_result::{OrderResult, SequencedOrderResult}, order_status::ExecType};
+
+/// A change requested to an order which is waiting for the order to be cancelled
+enum PendingChange {
+    Cancel,
+    /// Place `replacement`, with token `token`, once the order is cancelled
+    Replace { token: u64, replacement: Box<OuchOrder> }
+}
+
+/// A working order entered through the server
+struct OuchOrder {
+    /// The connection which entered the order
+    connection: u64,
+    symbol: String,
+    /// 'B' or 'S'
+    side: char,
+    qty: u32,
+    /// `None` for a market order
+    price: Option<u32>,
+    /// The quantity still open
+    leaves: u32,
+    /// The token of the order this one replaced
+    replaces: Option<u64>,
+    pending: Option<PendingChange>
+}
+
+impl OuchOrder {
+    /// The `NewOrder` placing this order as `user` with `token`
+    fn new_order(&self, user: u64, token: u64) -> NewOrder {
+        match self.price {
+            Some(price) => NewOrder::new(user, self.symbol.clone(), price as u64, self.qty as u64, self.side, token),
+            None => NewOrder::new_market(user, self.symbol.clone(), self.qty as u64, self.side, token)
+        }
+    }
+}
+
+/// Turns client messages into messages for `OrderBooks` and their results into replies. It does no
+/// I/O itself: `serve` feeds it the messages read from every connection, in arrival order, and
+/// writes out what it returns.
+pub struct OuchEngine {
+    order_books: OrderBooks,
+    /// The user each connection logged in as
+    logins: HashMap<u64, u64>,
+    /// Working orders keyed by user and token
+    orders: HashMap<(u64, u64), OuchOrder>,
+    /// The feed messages of the public results not yet taken, kept once enabled by `with_market_data`
+    market_data: Option<Vec<FeedMessage>>
+}
+
+impl OuchEngine {
+    /// An engine placing orders in `order_books`, which turns on their execution reports
+    pub fn new(mut order_books: OrderBooks) -> OuchEngine {
+        order_books.set_execution_reports(true);
+        OuchEngine { order_books, logins: HashMap::new(), orders: HashMap::new(), market_data: None }
+    }
+
+    /// Keep a feed message for each public result, to be taken by `take_market_data`
+    pub fn with_market_data(mut self) -> OuchEngine {
+        self.market_data = Some(vec![]);
+        self
+    }
+
+    /// The feed messages of the public results since the last call
+    pub fn take_market_data(&mut self) -> Vec<FeedMessage> {
+        self.market_data.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn order_books(&self) -> &OrderBooks {
+        &self.order_books
+    }
+
+    /// Handle `message` from `connection`, returning each reply with the connection to send it on
+    pub fn handle(&mut self, connection: u64, message: InboundMessage) -> Vec<(u64, OutboundMessage)> {
+        if let InboundMessage::Login { user } = message {
+            self.logins.insert(connection, user);
+            return vec![(connection, OutboundMessage::LoginAccepted { user })];
+        }
+        let user = match self.logins.get(&connection) {
+            Some(user) => *user,
+            None => {
+                let token = match message {
+                    InboundMessage::ReplaceOrder { replacement, .. } => replacement,
+                    InboundMessage::EnterOrder { token, .. } | InboundMessage::CancelOrder { token } => token,
+                    InboundMessage::Login { .. } => 0
+                };
+                return vec![(connection, OutboundMessage::Rejected { token, reason: RejectReason::NotLoggedIn })];
+            }
+        };
+        match message {
+            InboundMessage::EnterOrder { token, side, qty, symbol, price } => {
+                let reject = |reason| vec![(connection, OutboundMessage::Rejected { token, reason })];
+                if !matches!(side, 'B' | 'S') || qty == 0 || symbol.is_empty() {
+                    return reject(RejectReason::InvalidOrder);
+                }
+                if self.orders.contains_key(&(user, token)) {
+                    return reject(RejectReason::DuplicateToken);
+                }
+                let order = OuchOrder { connection, symbol, side, qty, price, leaves: qty, replaces: None, pending: None };
+                let new_order = order.new_order(user, token);
+                self.orders.insert((user, token), order);
+                let order_results = self.process(InputMessage::New(new_order));
+                self.replies(order_results)
+            },
+            InboundMessage::CancelOrder { token } => {
+                // Cancels of orders which are not working, or already being changed, are ignored
+                match self.orders.get_mut(&(user, token)) {
+                    Some(order) if order.pending.is_none() => order.pending = Some(PendingChange::Cancel),
+                    _ => return vec![]
+                }
+                self.cancel(user, token).0
+            },
+            InboundMessage::ReplaceOrder { token, replacement, qty, price } => {
+                let reject = |reason| vec![(connection, OutboundMessage::Rejected { token: replacement, reason })];
+                if qty == 0 {
+                    return reject(RejectReason::InvalidOrder);
+                }
+                if replacement != token && self.orders.contains_key(&(user, replacement)) {
+                    return reject(RejectReason::DuplicateToken);
+                }
+                let order = match self.orders.get_mut(&(user, token)) {
+                    Some(order) if order.pending.is_none() => order,
+                    _ => return reject(RejectReason::UnknownOrder)
+                };
+                let replacement_order = OuchOrder { connection, symbol: order.symbol.clone(), side: order.side, qty, price, leaves: qty, replaces: Some(token), pending: None };
+                order.pending = Some(PendingChange::Replace { token: replacement, replacement: Box::new(replacement_order) });
+                let (mut replies, cancelled) = self.cancel(user, token);
+                if !cancelled {
+                    replies.append(&mut reject(RejectReason::UnknownOrder));
+                }
+                replies
+            },
+            InboundMessage::Login { .. } => vec![]
+        }
+    }
+
+    /// Forget the login of `connection` once it has closed. Its orders keep working, but their
+    /// replies are no longer sent anywhere.
+    pub fn disconnect(&mut self, connection: u64) {
+        self.logins.remove(&connection);
+    }
+
+    /// Process `message`, keeping the feed messages of its results if market data is enabled
+    fn process(&mut self, message: InputMessage) -> Vec<SequencedOrderResult> {
+        let order_results = self.order_books.process(message.clone());
+        if let (Some(market_data), Some(time)) = (self.market_data.as_mut(), self.order_books.current_time()) {
+            market_data.extend(order_results.iter().filter_map(|order_result| FeedMessage::from_result(order_result, Some(&message), time)));
+        }
+        order_results
+    }
+
+    /// Cancel the order of `user` with `token`, which has a pending change, returning the replies
+    /// and whether it was cancelled
+    fn cancel(&mut self, user: u64, token: u64) -> (Vec<(u64, OutboundMessage)>, bool) {
+        let order_results = self.process(InputMessage::Cancel(CancelOrder::new(user, token)));
+        let replies = self.replies(order_results);
+        let not_cancelled = self.orders.get_mut(&(user, token)).and_then(|order| order.pending.take()).is_some();
+        (replies, !not_cancelled)
+    }
+
+    /// The replies to the owners of the orders named by `order_results`, placing the replacement of
+    /// any order whose cancellation completes a replace
+    fn replies(&mut self, order_results: Vec<SequencedOrderResult>) -> Vec<(u64, OutboundMessage)> {
+        let mut replies = vec![];
+        let mut reject_reasons = HashMap::new();
+        let mut cancel_reasons = HashMap::new();
+        let mut replacements = vec![];
+        for sequenced_order_result in order_results {
+            match sequenced_order_result.order_result {
+                OrderResult::RiskRejection { user, user_order_id, .. } => {
+                    reject_reasons.insert((user, user_order_id), RejectReason::RiskLimit);
+                },
+                OrderResult::SelfTradeCancellation { user, user_order_id } => {
+                    cancel_reasons.insert((user, user_order_id), CancelReason::SelfTrade);
+                },
+                OrderResult::Trade { trade_id, user_buy, user_order_id_buy, user_sell, user_order_id_sell, price, qty, .. } => {
+                    for key in [(user_buy, user_order_id_buy), (user_sell, user_order_id_sell)] {
+                        if let Some(order) = self.orders.get_mut(&key) {
+                            order.leaves = order.leaves.saturating_sub(qty as u32);
+                            replies.push((order.connection, OutboundMessage::Executed { token: key.1, qty: qty as u32, price: price as u32, match_number: trade_id }));
+                        }
+                    }
+                },
+                OrderResult::ExecutionReport { user, user_order_id, exec_type, state, leaves_qty, .. } => {
+                    let key = (user, user_order_id);
+                    let order = match self.orders.get_mut(&key) {
+                        Some(order) => order,
+                        None => continue
+                    };
+                    match exec_type {
+                        ExecType::New => replies.push((order.connection, match order.replaces {
+                            Some(token) => OutboundMessage::Replaced { replacement: user_order_id, token, qty: order.qty, price: order.price },
+                            None => OutboundMessage::Accepted { token: user_order_id, side: order.side, qty: order.qty, symbol: order.symbol.clone(), price: order.price }
+                        })),
+                        ExecType::Trade => (),
+                        ExecType::Restated => {
+                            let decrement = order.leaves.saturating_sub(leaves_qty as u32);
+                            order.leaves = leaves_qty as u32;
+                            replies.push((order.connection, OutboundMessage::Canceled { token: user_order_id, decrement, reason: CancelReason::SelfTrade }));
+                        },
+                        ExecType::Cancelled | ExecType::Expired => {
+                            let reason = match order.pending.take() {
+                                Some(PendingChange::Replace { token, replacement }) => {
+                                    replacements.push((user, token, replacement));
+                                    self.orders.remove(&key);
+                                    continue;
+                                },
+                                Some(PendingChange::Cancel) => CancelReason::UserRequested,
+                                None => cancel_reasons.remove(&key).unwrap_or(CancelReason::System)
+                            };
+                            replies.push((order.connection, OutboundMessage::Canceled { token: user_order_id, decrement: order.leaves, reason }));
+                        },
+                        ExecType::Rejected => {
+                            let reason = reject_reasons.remove(&key).unwrap_or(RejectReason::Rejected);
+                            replies.push((order.connection, OutboundMessage::Rejected { token: user_order_id, reason }));
+                        }
+                    }
+                    if state.is_terminal() {
+                        self.orders.remove(&key);
+                    }
+                },
+                _ => ()
+            }
+        }
+        for (user, token, replacement) in replacements {
+            let new_order = replacement.new_order(user, token);
+            self.orders.insert((user, token), *replacement);
+            let order_results = self.process(InputMessage::New(new_order));
+            replies.append(&mut self.replies(order_results));
+        }
+        replies
+    }
+}
+
+/// How long the engine thread waits without a message before sending a market data heartbeat
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a connection thread tells the engine thread
+enum Event {
+    /// A connection opened, whose writer thread sends the frames given to this sender
+    Connected(u64, Sender<Vec<u8>>),
+    Message(u64, InboundMessage),
+    Disconnected(u64)
+}
+
+/// Accept connections on `listener` until it fails, reading each on its own thread and handling
+/// every message with `engine` on a single engine thread. When given, `publisher` sends the
+/// market data of `engine`, which should have been made `with_market_data`.
+pub fn serve(listener: TcpListener, engine: OuchEngine, publisher: Option<UdpPublisher>) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new().name("engine_thread".to_string()).spawn(move || engine_func(rx, engine, publisher))?;
+    for (connection, stream) in (1..).zip(listener.incoming()) {
+        let stream = stream?;
+        stream.set_nodelay(true)?;
+        let mut writer_stream = stream.try_clone()?;
+        let (writer_tx, writer_rx) = mpsc::channel::<Vec<u8>>();
+        if tx.send(Event::Connected(connection, writer_tx)).is_err() {
+            break;
+        }
+
+        thread::Builder::new().name("writer_thread".to_string()).spawn(move || {
+            for bytes in writer_rx {
+                if writer_stream.write_all(&bytes).is_err() {
+                    break;
+                }
+            }
+        })?;
+        let tx = tx.clone();
+        thread::Builder::new().name("reader_thread".to_string()).spawn(move || {
+            if let Err(e) = reader_func(connection, &stream, &tx) {
+                eprintln!("Connection {}: {}", connection, e);
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = tx.send(Event::Disconnected(connection));
+        })?;
+    }
+    Ok(())
+}
+
+/// Read frames from `stream` until it closes, sending each message to the engine thread. A message
+/// which cannot be decoded closes the connection.
+fn reader_func(connection: u64, mut stream: &TcpStream, tx: &Sender<Event>) -> io::Result<()> {
+    let mut buffer = vec![];
+    let mut read_buffer = [0; 4096];
+    loop {
+        let read = stream.read(&mut read_buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&read_buffer[..read]);
+        while let Some((payload, consumed)) = read_frame(&buffer) {
+            let message = InboundMessage::decode(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            buffer.drain(..consumed);
+            if tx.send(Event::Message(connection, message)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Handle every event in arrival order, sending replies to the writer thread of their connection
+/// and publishing the market data of each message. A heartbeat is published whenever no message has
+/// arrived for `HEARTBEAT_INTERVAL`.
+fn engine_func(rx: Receiver<Event>, mut engine: OuchEngine, mut publisher: Option<UdpPublisher>) {
+    let mut writers: HashMap<u64, Sender<Vec<u8>>> = HashMap::new();
+    loop {
+        let event = match rx.recv_timeout(HEARTBEAT_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(Err(e)) = publisher.as_mut().map(|publisher| publisher.heartbeat()) {
+                    eprintln!("Market data heartbeat: {}", e);
+                }
+                continue;
+            },
+            Err(RecvTimeoutError::Disconnected) => break
+        };
+        match event {
+            Event::Connected(connection, writer) => {
+                writers.insert(connection, writer);
+            },
+            Event::Message(connection, message) => {
+                for (connection, reply) in engine.handle(connection, message) {
+                    if let Some(writer) = writers.get(&connection) {
+                        let _ = writer.send(frame(&reply.encode()));
+                    }
+                }
+                if let Some(Err(e)) = publisher.as_mut().map(|publisher| publisher.publish(&engine.take_market_data())) {
+                    eprintln!("Market data: {}", e);
+                }
+            },
+            Event::Disconnected(connection) => {
+                writers.remove(&connection);
+                engine.disconnect(connection);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Unit tests for the binary order entry server, driving the engine directly and over localhost
+
+#[cfg(test)]
+mod ouch_server_tests {
+    use std::{io::{Read, Write}, net::{TcpListener, TcpStream}, thread, time::Duration};
+
+    use crate::itch::FeedBody;
+    use crate::ouch::*;
+    use crate::ouch_server::*;
+    use crate::order_books::OrderBooks;
+    use crate::risk::{RiskLimit, RiskManager};
+
+    fn enter(token: u64, side: char, qty: u32, price: u32) -> InboundMessage {
+        InboundMessage::EnterOrder { token, side, qty, symbol: "IBM".to_string(), price: Some(price) }
+    }
+
+    fn logged_in() -> OuchEngine {
+        let mut engine = OuchEngine::new(OrderBooks::new(true));
+        engine.handle(1, InboundMessage::Login { user: 1 });
+        engine.handle(2, InboundMessage::Login { user: 2 });
+        engine
+    }
+
+    #[test]
+    fn login_required() {
+        let mut engine = OuchEngine::new(OrderBooks::new(true));
+        assert_eq!(engine.handle(1, enter(1, 'B', 100, 10)), vec![(1, OutboundMessage::Rejected { token: 1, reason: RejectReason::NotLoggedIn })]);
+        assert_eq!(engine.handle(1, InboundMessage::Login { user: 9 }), vec![(1, OutboundMessage::LoginAccepted { user: 9 })]);
+    }
+
+    #[test]
+    fn fills_go_to_each_originating_connection() {
+        let mut engine = logged_in();
+        assert_eq!(engine.handle(1, enter(1, 'B', 100, 10)), vec![
+            (1, OutboundMessage::Accepted { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(10) })
+        ]);
+
+        assert_eq!(engine.handle(2, enter(1, 'S', 100, 10)), vec![
+            (2, OutboundMessage::Accepted { token: 1, side: 'S', qty: 100, symbol: "IBM".to_string(), price: Some(10) }),
+            (1, OutboundMessage::Executed { token: 1, qty: 100, price: 10, match_number: 1 }),
+            (2, OutboundMessage::Executed { token: 1, qty: 100, price: 10, match_number: 1 })
+        ]);
+        assert!(engine.handle(1, InboundMessage::CancelOrder { token: 1 }).is_empty());
+    }
+
+    #[test]
+    fn cancel_and_replace() {
+        let mut engine = logged_in();
+        engine.handle(1, enter(1, 'B', 100, 10));
+
+        assert_eq!(engine.handle(1, InboundMessage::ReplaceOrder { token: 1, replacement: 2, qty: 80, price: Some(11) }), vec![
+            (1, OutboundMessage::Replaced { replacement: 2, token: 1, qty: 80, price: Some(11) })
+        ]);
+        assert_eq!(engine.order_books().order_status(1, 2).map(|status| status.open_qty), Some(80));
+        assert_eq!(engine.handle(1, InboundMessage::CancelOrder { token: 2 }), vec![
+            (1, OutboundMessage::Canceled { token: 2, decrement: 80, reason: CancelReason::UserRequested })
+        ]);
+        assert_eq!(engine.handle(1, InboundMessage::ReplaceOrder { token: 2, replacement: 3, qty: 80, price: Some(11) }), vec![
+            (1, OutboundMessage::Rejected { token: 3, reason: RejectReason::UnknownOrder })
+        ]);
+    }
+
+    #[test]
+    fn replace_requeues_order() {
+        let mut engine = logged_in();
+        engine.handle(1, enter(1, 'B', 100, 10));
+        engine.handle(2, enter(1, 'B', 80, 10));
+        engine.handle(1, InboundMessage::ReplaceOrder { token: 1, replacement: 2, qty: 80, price: Some(10) });
+
+        engine.handle(2, enter(2, 'S', 80, 10));
+        assert_eq!(engine.order_books().order_status(1, 2).map(|status| status.open_qty), Some(80));
+        assert_eq!(engine.order_books().order_status(2, 1).map(|status| status.open_qty), Some(0));
+    }
+
+    #[test]
+    fn rejects_and_system_cancels() {
+        let mut order_books = OrderBooks::new(true);
+        let mut risk_manager = RiskManager::new();
+        risk_manager.set_limit(None, RiskLimit::MaxOrderQty, Some(500));
+        order_books.set_risk_manager(risk_manager);
+        let mut engine = OuchEngine::new(order_books);
+        engine.handle(1, InboundMessage::Login { user: 1 });
+
+        assert_eq!(engine.handle(1, enter(1, 'X', 100, 10)), vec![(1, OutboundMessage::Rejected { token: 1, reason: RejectReason::InvalidOrder })]);
+        assert_eq!(engine.handle(1, enter(1, 'B', 1000, 10)), vec![(1, OutboundMessage::Rejected { token: 1, reason: RejectReason::RiskLimit })]);
+        engine.handle(1, enter(1, 'B', 100, 10));
+        assert_eq!(engine.handle(1, enter(1, 'B', 100, 10)), vec![(1, OutboundMessage::Rejected { token: 1, reason: RejectReason::DuplicateToken })]);
+
+        let replies = engine.handle(1, InboundMessage::EnterOrder { token: 2, side: 'S', qty: 50, symbol: "AAPL".to_string(), price: None });
+        assert_eq!(replies.last(), Some(&(1, OutboundMessage::Canceled { token: 2, decrement: 50, reason: CancelReason::System })));
+    }
+
+    #[test]
+    fn market_data() {
+        let mut engine = OuchEngine::new(OrderBooks::new(true)).with_market_data();
+        engine.handle(1, InboundMessage::Login { user: 1 });
+        engine.handle(1, enter(1, 'B', 100, 10));
+        engine.handle(1, InboundMessage::ReplaceOrder { token: 1, replacement: 2, qty: 80, price: Some(11) });
+        engine.handle(1, enter(3, 'X', 100, 10));
+
+        let bodies: Vec<FeedBody> = engine.take_market_data().into_iter().map(|message| message.body).collect();
+        assert_eq!(bodies, vec![
+            FeedBody::AddOrder { user: 1, user_order_id: 1, side: 'B', qty: 100, price: Some(10) },
+            FeedBody::TopOfBook { side: 'B', price: Some(10), total_qty: Some(100) },
+            FeedBody::OrderCancel { user: 1, user_order_id: 1 },
+            FeedBody::TopOfBook { side: 'B', price: None, total_qty: None },
+            FeedBody::AddOrder { user: 1, user_order_id: 2, side: 'B', qty: 80, price: Some(11) },
+            FeedBody::TopOfBook { side: 'B', price: Some(11), total_qty: Some(80) }
+        ]);
+        assert!(engine.take_market_data().is_empty());
+        assert!(logged_in().take_market_data().is_empty());
+    }
+
+    fn send(stream: &mut TcpStream, message: InboundMessage) {
+        stream.write_all(&frame(&message.encode())).unwrap();
+    }
+
+    /// Read `count` messages from `stream`
+    fn receive(stream: &mut TcpStream, count: usize) -> Vec<OutboundMessage> {
+        let mut buffer = vec![];
+        let mut messages = vec![];
+        let mut read_buffer = [0; 256];
+        while messages.len() < count {
+            let read = stream.read(&mut read_buffer).unwrap();
+            assert!(read > 0, "Connection closed");
+            buffer.extend_from_slice(&read_buffer[..read]);
+            while let Some((payload, consumed)) = read_frame(&buffer) {
+                messages.push(OutboundMessage::decode(payload).unwrap());
+                buffer.drain(..consumed);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn clients_over_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, OuchEngine::new(OrderBooks::new(true)), None));
+
+        let mut buyer = TcpStream::connect(address).unwrap();
+        let mut seller = TcpStream::connect(address).unwrap();
+        for stream in [&buyer, &seller] {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        }
+        send(&mut buyer, InboundMessage::Login { user: 1 });
+        send(&mut buyer, enter(1, 'B', 100, 10));
+        assert_eq!(receive(&mut buyer, 2)[1], OutboundMessage::Accepted { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(10) });
+
+        send(&mut seller, InboundMessage::Login { user: 2 });
+        send(&mut seller, enter(7, 'S', 100, 10));
+        assert_eq!(receive(&mut seller, 3)[2], OutboundMessage::Executed { token: 7, qty: 100, price: 10, match_number: 1 });
+        assert_eq!(receive(&mut buyer, 1), vec![OutboundMessage::Executed { token: 1, qty: 100, price: 10, match_number: 1 }]);
+
+        buyer.write_all(&frame(b"?")).unwrap();
+        assert_eq!(buyer.read(&mut [0; 16]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,76 @@
+//! Unit tests for the binary order entry protocol
+
+#[cfg(test)]
+mod ouch_tests {
+    use crate::ouch::*;
+
+    #[test]
+    fn inbound_round_trip() {
+        let messages = vec![
+            InboundMessage::Login { user: 7 },
+            InboundMessage::EnterOrder { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(10) },
+            InboundMessage::EnterOrder { token: 2, side: 'S', qty: 50, symbol: "AAPL".to_string(), price: None },
+            InboundMessage::CancelOrder { token: 1 },
+            InboundMessage::ReplaceOrder { token: 1, replacement: 3, qty: 80, price: Some(11) }
+        ];
+        for message in messages {
+            assert_eq!(InboundMessage::decode(&message.encode()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn outbound_round_trip() {
+        let messages = vec![
+            OutboundMessage::LoginAccepted { user: 7 },
+            OutboundMessage::Accepted { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(10) },
+            OutboundMessage::Replaced { replacement: 3, token: 1, qty: 80, price: None },
+            OutboundMessage::Canceled { token: 1, decrement: 20, reason: CancelReason::SelfTrade },
+            OutboundMessage::Executed { token: 1, qty: 100, price: 10, match_number: 4 },
+            OutboundMessage::Rejected { token: 1, reason: RejectReason::RiskLimit }
+        ];
+        for message in messages {
+            assert_eq!(OutboundMessage::decode(&message.encode()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn enter_order_layout() {
+        let encoded = InboundMessage::EnterOrder { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(10) }.encode();
+
+        assert_eq!(encoded.len(), 26);
+        assert_eq!(&encoded[..10], &[b'O', 0, 0, 0, 0, 0, 0, 0, 1, b'B']);
+        assert_eq!(&encoded[14..22], b"IBM     ");
+        assert_eq!(&encoded[22..], &[0, 0, 0, 10]);
+    }
+
+    #[test]
+    fn zero_price_is_limit() {
+        let limit = InboundMessage::EnterOrder { token: 1, side: 'B', qty: 100, symbol: "IBM".to_string(), price: Some(0) };
+        let market = InboundMessage::EnterOrder { token: 2, side: 'B', qty: 100, symbol: "IBM".to_string(), price: None };
+
+        assert_eq!(&limit.encode()[22..], &[0, 0, 0, 0]);
+        assert_eq!(&market.encode()[22..], &[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(InboundMessage::decode(&limit.encode()), Ok(limit));
+        assert_eq!(InboundMessage::decode(&market.encode()), Ok(market));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(InboundMessage::decode(b"Q"), Err(OuchError::UnknownType(b'Q')));
+        assert_eq!(InboundMessage::decode(b"X123"), Err(OuchError::InvalidLength(b'X', 4)));
+        assert!(OutboundMessage::decode(&[b'J', 0, 0, 0, 0, 0, 0, 0, 1, b'?']).is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let mut buffer = frame(&InboundMessage::CancelOrder { token: 1 }.encode());
+        buffer.extend(frame(&InboundMessage::Login { user: 2 }.encode()));
+
+        assert_eq!(read_frame(&buffer[..1]), None);
+        assert_eq!(read_frame(&buffer[..10]), None);
+        let (payload, consumed) = read_frame(&buffer).unwrap();
+        assert_eq!((InboundMessage::decode(payload), consumed), (Ok(InboundMessage::CancelOrder { token: 1 }), 11));
+        let (payload, _) = read_frame(&buffer[consumed..]).unwrap();
+        assert_eq!(InboundMessage::decode(payload), Ok(InboundMessage::Login { user: 2 }));
+    }
+}
@@ -0,0 +1,99 @@
+//! Pegged orders, whose price follows the top of the book rather than being fixed when they are
+//! placed
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// The price a pegged order follows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PegType {
+    /// The best price on the order's own side: the best bid for buys and the best ask for sells
+    Primary,
+    /// The best price on the opposite side: the best ask for buys and the best bid for sells
+    Market,
+    /// Halfway between the best bid and best ask, rounded down for buys and up for sells
+    Midpoint
+}
+
+impl FromStr for PegType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(Self::Primary),
+            "market" => Ok(Self::Market),
+            "midpoint" => Ok(Self::Midpoint),
+            _ => Err(format!("Unknown peg type: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for PegType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Primary => write!(f, "primary"),
+            Self::Market => write!(f, "market"),
+            Self::Midpoint => write!(f, "midpoint")
+        }
+    }
+}
+
+/// How a pegged order is priced from the best bid and best ask
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peg {
+    pub peg_type: PegType,
+    /// Ticks added to the pegged price for buys and taken from it for sells, so a positive offset
+    /// is more aggressive and a negative offset more passive
+    pub offset: i64,
+    /// The highest price a pegged buy, or the lowest price a pegged sell, may reach
+    pub limit: Option<u64>
+}
+
+impl Peg {
+    pub fn new(peg_type: PegType, offset: i64, limit: Option<u64>) -> Peg {
+        Peg { peg_type, offset, limit }
+    }
+
+    /// The price of a pegged `side` order given the best bid and best ask, or `None` if a price
+    /// it follows is missing or the offset leaves no valid price
+    pub fn price(&self, side: char, best_bid: Option<u64>, best_ask: Option<u64>) -> Option<u64> {
+        let reference = match (self.peg_type, side) {
+            (PegType::Primary, 'B') | (PegType::Market, 'S') => best_bid?,
+            (PegType::Primary, _) | (PegType::Market, _) => best_ask?,
+            (PegType::Midpoint, 'B') => (best_bid? + best_ask?) / 2,
+            (PegType::Midpoint, _) => (best_bid? + best_ask? + 1) / 2
+        };
+        let offset = if side == 'B' { self.offset } else { -self.offset };
+        let price = u64::try_from(reference as i64 + offset).ok().filter(|price| *price > 0)?;
+        Some(match (self.limit, side) {
+            (Some(limit), 'B') => price.min(limit),
+            (Some(limit), _) => price.max(limit),
+            (None, _) => price
+        })
+    }
+}
+
+impl fmt::Display for Peg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = self.limit.map_or("-".to_string(), |limit| limit.to_string());
+        write!(f, "{}:{}:{}", self.peg_type, self.offset, limit)
+    }
+}
+
+impl FromStr for Peg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Unknown peg: \"{}\"", s);
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() != 3 {
+            return Err(invalid());
+        }
+        let limit = match fields[2] {
+            "-" => None,
+            limit => Some(limit.parse::<u64>().map_err(|_| invalid())?)
+        };
+        Ok(Peg::new(fields[0].parse()?, fields[1].parse().map_err(|_| invalid())?, limit))
+    }
+}
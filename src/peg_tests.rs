@@ -0,0 +1,108 @@
+//! Unit tests for pegged orders and their repricing in `OrderBook`
+
+#[cfg(test)]
+mod peg_tests {
+    use crate::order_book::OrderBook;
+    use crate::peg::*;
+    use crate::order::*;
+    use crate::order_result::*;
+
+    fn order_book_with_spread(bid: u64, ask: u64) -> OrderBook {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), ask, 100, 'S', 101));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), bid, 100, 'B', 102));
+        order_book
+    }
+
+    #[test]
+    fn parse_peg() {
+        assert_eq!("midpoint:0:-".parse::<Peg>(), Ok(Peg::new(PegType::Midpoint, 0, None)));
+        assert_eq!("primary:-2:105".parse::<Peg>(), Ok(Peg::new(PegType::Primary, -2, Some(105))));
+        assert_eq!(Peg::new(PegType::Market, 1, Some(9)).to_string(), "market:1:9");
+        assert!("primary:0".parse::<Peg>().is_err());
+        assert!("best:0:-".parse::<Peg>().is_err());
+    }
+
+    #[test]
+    fn peg_price() {
+        assert_eq!(Peg::new(PegType::Primary, 0, None).price('B', Some(9), Some(13)), Some(9));
+        assert_eq!(Peg::new(PegType::Primary, 0, None).price('S', Some(9), Some(13)), Some(13));
+        assert_eq!(Peg::new(PegType::Market, -1, None).price('B', Some(9), Some(13)), Some(12));
+        assert_eq!(Peg::new(PegType::Market, -1, None).price('S', Some(9), Some(13)), Some(10));
+        assert_eq!(Peg::new(PegType::Midpoint, 0, None).price('B', Some(9), Some(12)), Some(10));
+        assert_eq!(Peg::new(PegType::Midpoint, 0, None).price('S', Some(9), Some(12)), Some(11));
+        assert_eq!(Peg::new(PegType::Primary, 2, Some(10)).price('B', Some(9), Some(13)), Some(10));
+        assert_eq!(Peg::new(PegType::Primary, 2, Some(12)).price('S', Some(9), Some(13)), Some(12));
+        assert_eq!(Peg::new(PegType::Midpoint, 0, None).price('B', None, Some(13)), None);
+        assert_eq!(Peg::new(PegType::Primary, -9, None).price('B', Some(9), Some(13)), None);
+    }
+
+    #[test]
+    fn pegged_order_priced_on_entry() {
+        let mut order_book = order_book_with_spread(9, 13);
+
+        let order_results = order_book.add_order(NewOrder::new_pegged(1, "AAPL".to_string(), Peg::new(PegType::Midpoint, 0, None), 100, 'B', 1));
+
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 1, user_order_id: 1 },
+            OrderResult::TopOfBookChange { side: 'B', price: "11".to_string(), total_quantity: "100".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn pegged_order_rejected_without_reference_price() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        let order_results = order_book.add_order(NewOrder::new_pegged(1, "AAPL".to_string(), Peg::new(PegType::Primary, 0, None), 100, 'B', 1));
+
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 1 }]);
+    }
+
+    #[test]
+    fn pegged_order_follows_top_of_book() {
+        let mut order_book = order_book_with_spread(9, 11);
+        order_book.add_order(NewOrder::new_pegged(1, "AAPL".to_string(), Peg::new(PegType::Primary, 0, None), 100, 'B', 1));
+
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 201));
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 3, user_order_id: 201 },
+            OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "100".to_string() },
+            OrderResult::PeggedOrderRepriced { user: 1, user_order_id: 1, price: 10 },
+            OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "200".to_string() }
+        ]);
+
+        let order_results = order_book.cancel_order(&CancelOrder::new(3, 201));
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 3, user_order_id: 201 },
+            OrderResult::TopOfBookChange { side: 'B', price: "10".to_string(), total_quantity: "100".to_string() },
+            OrderResult::PeggedOrderRepriced { user: 1, user_order_id: 1, price: 9 },
+            OrderResult::TopOfBookChange { side: 'B', price: "9".to_string(), total_quantity: "200".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn repriced_order_loses_time_priority() {
+        let mut order_book = order_book_with_spread(9, 11);
+        order_book.add_order(NewOrder::new_pegged(1, "AAPL".to_string(), Peg::new(PegType::Primary, 0, None), 100, 'B', 1));
+        order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 201));
+
+        let order_results = order_book.add_order(NewOrder::new(4, "AAPL".to_string(), 10, 100, 'S', 301));
+
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 3, user_order_id_buy: 201, user_sell: 4, user_order_id_sell: 301, price: 10, qty: 100, .. }));
+        assert!(order_results.contains(&OrderResult::PeggedOrderRepriced { user: 1, user_order_id: 1, price: 9 }));
+    }
+
+    #[test]
+    fn repriced_order_does_not_cross() {
+        let mut order_book = order_book_with_spread(9, 20);
+        order_book.add_order(NewOrder::new_pegged(1, "AAPL".to_string(), Peg::new(PegType::Primary, 9, None), 100, 'B', 1));
+
+        let order_results = order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 12, 50, 'B', 201));
+
+        assert_eq!(order_results, vec![
+            OrderResult::Acknowledgement { user: 3, user_order_id: 201 },
+            OrderResult::PeggedOrderRepriced { user: 1, user_order_id: 1, price: 19 },
+            OrderResult::TopOfBookChange { side: 'B', price: "19".to_string(), total_quantity: "100".to_string() }
+        ]);
+    }
+}
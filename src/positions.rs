@@ -0,0 +1,189 @@
+//! Position and profit and loss tracking for each user and symbol, built from the results published
+//! by `OrderBooks`. Trades move positions and realize profit and loss, while trades and top of book
+//! changes give the mark price that open positions are valued at.
+
+use std::{collections::{BTreeMap, HashMap}, fmt, str::FromStr};
+
+use crate::order_result::{OrderResult, SequencedOrderResult};
+
+/// The price open positions are marked to for unrealized profit and loss
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MarkPrice {
+    /// The price of the latest trade in the symbol
+    LastTrade,
+    /// Halfway between the best bid and ask, or the last trade price while either side is empty
+    Mid
+}
+
+impl FromStr for MarkPrice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last-trade" => Ok(Self::LastTrade),
+            "mid" => Ok(Self::Mid),
+            _ => Err(format!("Unknown mark price: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for MarkPrice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LastTrade => write!(f, "last-trade"),
+            Self::Mid => write!(f, "mid")
+        }
+    }
+}
+
+/// A user's net position in one symbol, long when positive and short when negative, with the
+/// average price it was opened at and the profit and loss realized by reducing it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub net_qty: i64,
+    pub average_price: f64,
+    pub realized_pnl: f64
+}
+
+impl Position {
+    /// Apply a fill of `qty` at `price`, which is positive for a buy and negative for a sell. A fill
+    /// against the position realizes profit and loss on the quantity it closes, and any quantity
+    /// left over opens a new position at `price`.
+    pub fn fill(&mut self, qty: i64, price: u64) {
+        let price = price as f64;
+        if self.net_qty == 0 || self.net_qty.signum() == qty.signum() {
+            let open_qty = self.net_qty.unsigned_abs() as f64;
+            self.average_price = (self.average_price * open_qty + price * qty.unsigned_abs() as f64) / (open_qty + qty.unsigned_abs() as f64);
+        } else {
+            let closed_qty = qty.unsigned_abs().min(self.net_qty.unsigned_abs()) as f64;
+            self.realized_pnl += closed_qty * (price - self.average_price) * self.net_qty.signum() as f64;
+            if qty.unsigned_abs() > self.net_qty.unsigned_abs() {
+                self.average_price = price;
+            } else if qty.unsigned_abs() == self.net_qty.unsigned_abs() {
+                self.average_price = 0.0;
+            }
+        }
+        self.net_qty += qty;
+    }
+
+    /// The profit or loss of the open position if it were closed at `mark_price`, which is zero
+    /// without a mark price
+    pub fn unrealized_pnl(&self, mark_price: Option<f64>) -> f64 {
+        mark_price.map_or(0.0, |mark_price| (mark_price - self.average_price) * self.net_qty as f64)
+    }
+}
+
+/// The latest prices seen for a symbol
+#[derive(Clone, Copy, Debug, Default)]
+struct Quote {
+    best_bid: Option<u64>,
+    best_ask: Option<u64>,
+    last_trade_price: Option<u64>
+}
+
+/// One row of the position report: a user's position in a symbol, valued at `mark_price`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionReport {
+    pub user: u64,
+    pub symbol: String,
+    pub position: Position,
+    pub mark_price: Option<f64>,
+    pub unrealized_pnl: f64
+}
+
+impl fmt::Display for PositionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mark_price = self.mark_price.map_or("-".to_string(), format_amount);
+        write!(f, "user {}, {}: position {}, average price {}, mark price {}, realized {}, unrealized {}", self.user, self.symbol, self.position.net_qty,
+            format_amount(self.position.average_price), mark_price, format_amount(self.position.realized_pnl), format_amount(self.unrealized_pnl))
+    }
+}
+
+/// Format `amount` to two decimal places, without a sign on amounts that round to zero
+fn format_amount(amount: f64) -> String {
+    format!("{:.2}", (amount * 100.0).round() / 100.0 + 0.0)
+}
+
+/// Maintains every user's position in every symbol they have traded, from the results of
+/// `OrderBooks` passed to `record`
+pub struct PositionTracker {
+    mark_price: MarkPrice,
+    /// key is user and symbol
+    positions: BTreeMap<(u64, String), Position>,
+    quotes: HashMap<String, Quote>
+}
+
+impl PositionTracker {
+    pub fn new(mark_price: MarkPrice) -> PositionTracker {
+        PositionTracker { mark_price, positions: BTreeMap::new(), quotes: HashMap::new() }
+    }
+
+    /// Update positions and prices from a result. Trades between two orders of the same user leave
+    /// their position unchanged.
+    pub fn record(&mut self, sequenced_order_result: &SequencedOrderResult) {
+        match &sequenced_order_result.order_result {
+            OrderResult::Trade { symbol, user_buy, user_sell, price, qty, .. } => {
+                if user_buy != user_sell {
+                    self.positions.entry((*user_buy, symbol.clone())).or_default().fill(*qty as i64, *price);
+                    self.positions.entry((*user_sell, symbol.clone())).or_default().fill(-(*qty as i64), *price);
+                }
+                self.quotes.entry(symbol.clone()).or_default().last_trade_price = Some(*price);
+            },
+            OrderResult::TopOfBookChange { side, price, .. } => {
+                let symbol = match &sequenced_order_result.symbol {
+                    Some(symbol) => symbol,
+                    None => return
+                };
+                let quote = self.quotes.entry(symbol.clone()).or_default();
+                let price = price.parse::<u64>().ok();
+                if *side == 'B' {
+                    quote.best_bid = price;
+                } else {
+                    quote.best_ask = price;
+                }
+            },
+            _ => ()
+        }
+    }
+
+    /// Update positions and prices from each of `sequenced_order_results`
+    pub fn record_all(&mut self, sequenced_order_results: &[SequencedOrderResult]) {
+        for sequenced_order_result in sequenced_order_results {
+            self.record(sequenced_order_result);
+        }
+    }
+
+    pub fn position(&self, user: u64, symbol: &str) -> Option<&Position> {
+        self.positions.get(&(user, symbol.to_string()))
+    }
+
+    /// The price positions in `symbol` are valued at, or `None` if it has not traded
+    pub fn mark_price(&self, symbol: &str) -> Option<f64> {
+        let quote = self.quotes.get(symbol)?;
+        match (self.mark_price, quote.best_bid, quote.best_ask) {
+            (MarkPrice::Mid, Some(best_bid), Some(best_ask)) => Some((best_bid + best_ask) as f64 / 2.0),
+            _ => quote.last_trade_price.map(|last_trade_price| last_trade_price as f64)
+        }
+    }
+
+    /// The profit or loss of `user`'s open position in `symbol` at its mark price
+    pub fn unrealized_pnl(&self, user: u64, symbol: &str) -> f64 {
+        self.position(user, symbol).map_or(0.0, |position| position.unrealized_pnl(self.mark_price(symbol)))
+    }
+
+    /// The realized and unrealized profit and loss of `user` across every symbol
+    pub fn user_pnl(&self, user: u64) -> (f64, f64) {
+        self.positions.iter().filter(|((position_user, _), _)| *position_user == user)
+            .fold((0.0, 0.0), |(realized_pnl, unrealized_pnl), ((_, symbol), position)| {
+                (realized_pnl + position.realized_pnl, unrealized_pnl + position.unrealized_pnl(self.mark_price(symbol)))
+            })
+    }
+
+    /// Every position, including closed ones, by user and then symbol
+    pub fn report(&self) -> Vec<PositionReport> {
+        self.positions.iter().map(|((user, symbol), position)| {
+            let mark_price = self.mark_price(symbol);
+            PositionReport { user: *user, symbol: symbol.clone(), position: *position, mark_price, unrealized_pnl: position.unrealized_pnl(mark_price) }
+        }).collect()
+    }
+}
@@ -0,0 +1,81 @@
+//! Unit tests for position and P&L tracking
+
+#[cfg(test)]
+mod positions_tests {
+    use crate::positions::*;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+
+    fn tracked(order_books: &mut OrderBooks, positions: &mut PositionTracker, new_order: NewOrder) {
+        positions.record_all(&order_books.process(InputMessage::New(new_order)));
+    }
+
+    #[test]
+    fn parse_mark_price() {
+        assert_eq!("mid".parse::<MarkPrice>(), Ok(MarkPrice::Mid));
+        assert_eq!(MarkPrice::LastTrade.to_string(), "last-trade");
+        assert!("close".parse::<MarkPrice>().is_err());
+    }
+
+    #[test]
+    fn fills_average_and_realize() {
+        let mut position = Position::default();
+        position.fill(100, 10);
+        position.fill(100, 12);
+        assert_eq!(position, Position { net_qty: 200, average_price: 11.0, realized_pnl: 0.0 });
+
+        position.fill(-50, 15);
+        assert_eq!(position, Position { net_qty: 150, average_price: 11.0, realized_pnl: 200.0 });
+        assert_eq!(position.unrealized_pnl(Some(10.0)), -150.0);
+
+        position.fill(-200, 9);
+        assert_eq!(position, Position { net_qty: -50, average_price: 9.0, realized_pnl: -100.0 });
+
+        position.fill(50, 8);
+        assert_eq!(position, Position { net_qty: 0, average_price: 0.0, realized_pnl: -50.0 });
+    }
+
+    #[test]
+    fn positions_from_trades() {
+        let mut order_books = OrderBooks::new(true);
+        let mut positions = PositionTracker::new(MarkPrice::LastTrade);
+        tracked(&mut order_books, &mut positions, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        tracked(&mut order_books, &mut positions, NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2));
+        tracked(&mut order_books, &mut positions, NewOrder::new(2, "IBM".to_string(), 12, 100, 'S', 3));
+        tracked(&mut order_books, &mut positions, NewOrder::new(3, "IBM".to_string(), 12, 100, 'B', 4));
+
+        assert_eq!(positions.position(1, "IBM"), Some(&Position { net_qty: -100, average_price: 10.0, realized_pnl: 0.0 }));
+        assert_eq!(positions.position(2, "IBM"), Some(&Position { net_qty: 0, average_price: 0.0, realized_pnl: 200.0 }));
+        assert_eq!(positions.mark_price("IBM"), Some(12.0));
+        assert_eq!(positions.unrealized_pnl(1, "IBM"), -200.0);
+        assert_eq!(positions.user_pnl(2), (200.0, 0.0));
+        assert_eq!(positions.report().len(), 3);
+        assert_eq!(positions.report()[0].to_string(), "user 1, IBM: position -100, average price 10.00, mark price 12.00, realized 0.00, unrealized -200.00");
+    }
+
+    #[test]
+    fn mid_mark_price() {
+        let mut order_books = OrderBooks::new(true);
+        let mut positions = PositionTracker::new(MarkPrice::Mid);
+        tracked(&mut order_books, &mut positions, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        tracked(&mut order_books, &mut positions, NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 2));
+        tracked(&mut order_books, &mut positions, NewOrder::new(3, "IBM".to_string(), 11, 100, 'B', 3));
+        assert_eq!(positions.mark_price("IBM"), Some(10.0));
+
+        tracked(&mut order_books, &mut positions, NewOrder::new(3, "IBM".to_string(), 14, 100, 'S', 4));
+        assert_eq!(positions.mark_price("IBM"), Some(12.5));
+        assert_eq!(positions.unrealized_pnl(2, "IBM"), 250.0);
+        assert_eq!(positions.mark_price("AAPL"), None);
+    }
+
+    #[test]
+    fn self_trade_leaves_position_unchanged() {
+        let mut order_books = OrderBooks::new(true);
+        let mut positions = PositionTracker::new(MarkPrice::LastTrade);
+        tracked(&mut order_books, &mut positions, NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1));
+        tracked(&mut order_books, &mut positions, NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(positions.position(1, "IBM"), None);
+        assert_eq!(positions.mark_price("IBM"), Some(10.0));
+    }
+}
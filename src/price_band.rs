@@ -0,0 +1,140 @@
+//! Protection against orders priced far from the market and against sudden price moves: price bands
+//! reject orders outside a percentage of a reference price, and a circuit breaker interrupts
+//! trading when trade prices move too far too quickly
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Duration;
+
+/// The prices an order may have, within `percent` of a reference price. The reference is the last
+/// trade price, or `reference_price` before the first trade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceBand {
+    pub percent: u64,
+    pub reference_price: Option<u64>
+}
+
+impl PriceBand {
+    pub fn new(percent: u64) -> PriceBand {
+        PriceBand { percent, reference_price: None }
+    }
+
+    /// Use `reference_price` until the first trade
+    pub fn with_reference_price(mut self, reference_price: u64) -> PriceBand {
+        self.reference_price = Some(reference_price);
+        self
+    }
+
+    /// The lowest and highest prices allowed, or `None` if there is no reference price yet. The
+    /// band is rounded down, so a band too narrow for a whole tick allows only the reference price.
+    pub fn limits(&self, last_trade_price: Option<u64>) -> Option<(u64, u64)> {
+        let reference_price = last_trade_price.or(self.reference_price)?;
+        let width = u64::try_from(u128::from(reference_price) * u128::from(self.percent) / 100).unwrap_or(u64::MAX);
+        Some((reference_price.saturating_sub(width), reference_price.saturating_add(width)))
+    }
+
+    /// Whether an order at `price` is inside the band, which it always is without a reference price
+    pub fn allows(&self, price: u64, last_trade_price: Option<u64>) -> bool {
+        self.limits(last_trade_price).map_or(true, |(low, high)| price >= low && price <= high)
+    }
+}
+
+impl fmt::Display for PriceBand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reference_price = self.reference_price.map_or("-".to_string(), |reference_price| reference_price.to_string());
+        write!(f, "{}:{}", self.percent, reference_price)
+    }
+}
+
+impl FromStr for PriceBand {
+    type Err = String;
+
+    /// Parse `percent` or `percent:reference_price`, where '-' means no reference price
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Unknown price band: \"{}\"", s);
+        let mut fields = s.split(':');
+        let price_band = PriceBand::new(fields.next().unwrap_or_default().parse().map_err(|_| invalid())?);
+        let price_band = match fields.next() {
+            None | Some("-") => price_band,
+            Some(reference_price) => price_band.with_reference_price(reference_price.parse().map_err(|_| invalid())?)
+        };
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(price_band)
+    }
+}
+
+/// What a tripped circuit breaker does to trading in the `OrderBook`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakerAction {
+    /// Halt trading until the cooldown ends
+    Halt,
+    /// Collect orders in a volatility auction, uncrossed when the cooldown ends
+    Auction
+}
+
+impl FromStr for BreakerAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halt" => Ok(Self::Halt),
+            "auction" => Ok(Self::Auction),
+            _ => Err(format!("Unknown circuit breaker action: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for BreakerAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Halt => write!(f, "halt"),
+            Self::Auction => write!(f, "auction")
+        }
+    }
+}
+
+/// Interrupts continuous trading when a trade price differs by more than `percent` from any trade
+/// within the preceding `window`, resuming continuous trading once `cooldown` has passed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitBreaker {
+    pub percent: u64,
+    pub window: Duration,
+    pub cooldown: Duration,
+    pub action: BreakerAction
+}
+
+impl CircuitBreaker {
+    pub fn new(percent: u64, window: Duration, cooldown: Duration, action: BreakerAction) -> CircuitBreaker {
+        CircuitBreaker { percent, window, cooldown, action }
+    }
+
+    /// Whether a trade at `price` moved too far from an earlier trade at `earlier_price`
+    pub fn is_tripped_by(&self, price: u64, earlier_price: u64) -> bool {
+        u128::from(price.abs_diff(earlier_price)) * 100 > u128::from(earlier_price) * u128::from(self.percent)
+    }
+}
+
+impl fmt::Display for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.percent, self.window.num_seconds(), self.cooldown.num_seconds(), self.action)
+    }
+}
+
+impl FromStr for CircuitBreaker {
+    type Err = String;
+
+    /// Parse `percent:window_seconds:cooldown_seconds:action`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Unknown circuit breaker: \"{}\"", s);
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() != 4 {
+            return Err(invalid());
+        }
+        let seconds = |field: &str| field.parse::<i64>().ok().filter(|seconds| *seconds >= 0).map(Duration::seconds).ok_or_else(invalid);
+        Ok(CircuitBreaker::new(fields[0].parse().map_err(|_| invalid())?, seconds(fields[1])?, seconds(fields[2])?, fields[3].parse()?))
+    }
+}
@@ -0,0 +1,139 @@
+//! Unit tests for price bands and circuit breakers
+
+#[cfg(test)]
+mod price_band_tests {
+    use crate::price_band::*;
+    use crate::order_book::OrderBook;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+    use crate::order_result::*;
+    use crate::trading_status::TradingStatus;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn start_time() -> DateTime<Utc> {
+        "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn order_books_with_breaker(action: BreakerAction) -> OrderBooks {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_circuit_breaker(Some(CircuitBreaker::new(10, Duration::seconds(60), Duration::seconds(300), action)));
+        order_books
+    }
+
+    /// Trade 100 IBM at `price` between users 1 and 2, using order ids from `first_id`
+    fn trade_at(order_books: &mut OrderBooks, input_sequence: u64, time: DateTime<Utc>, price: u64, first_id: u64) -> Vec<OrderResult> {
+        order_books.process_at(input_sequence, time, InputMessage::New(NewOrder::new(1, "IBM".to_string(), price, 100, 'S', first_id)));
+        let results = order_books.process_at(input_sequence + 1, time, InputMessage::New(NewOrder::new(2, "IBM".to_string(), price, 100, 'B', first_id + 1)));
+        results.into_iter().map(|sequenced_order_result| sequenced_order_result.order_result).collect()
+    }
+
+    #[test]
+    fn parse_price_band_and_breaker() {
+        assert_eq!("10".parse::<PriceBand>(), Ok(PriceBand::new(10)));
+        assert_eq!("10:100".parse::<PriceBand>(), Ok(PriceBand::new(10).with_reference_price(100)));
+        assert_eq!(PriceBand::new(5).to_string(), "5:-");
+        assert!("10:100:1".parse::<PriceBand>().is_err());
+        assert_eq!("10:60:300:halt".parse::<CircuitBreaker>(), Ok(CircuitBreaker::new(10, Duration::seconds(60), Duration::seconds(300), BreakerAction::Halt)));
+        assert_eq!(CircuitBreaker::new(5, Duration::seconds(30), Duration::seconds(120), BreakerAction::Auction).to_string(), "5:30:120:auction");
+        assert!("10:60:300:pause".parse::<CircuitBreaker>().is_err());
+    }
+
+    #[test]
+    fn band_limits() {
+        let price_band = PriceBand::new(10).with_reference_price(100);
+
+        assert_eq!(price_band.limits(None), Some((90, 110)));
+        assert_eq!(price_band.limits(Some(50)), Some((45, 55)));
+        assert_eq!(PriceBand::new(10).limits(None), None);
+        assert!(PriceBand::new(10).allows(1_000_000, None));
+        assert!(!price_band.allows(111, None));
+    }
+
+    #[test]
+    fn order_outside_band_rejected() {
+        let mut order_book = OrderBook::new("IBM", true);
+        order_book.set_price_band(Some(PriceBand::new(10).with_reference_price(100)));
+
+        let order_results = order_book.add_order(NewOrder::new(1, "IBM".to_string(), 111, 100, 'B', 1));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 1 }]);
+
+        let order_results = order_book.add_order(NewOrder::new_stop_limit(1, "IBM".to_string(), 100, 80, 100, 'S', 2));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 2 }]);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "IBM".to_string(), 110, 100, 'B', 3));
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 3 });
+    }
+
+    #[test]
+    fn band_follows_last_trade() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_price_band(PriceBandOrder::new(None, Some(PriceBand::new(10).with_reference_price(100))));
+        trade_at(&mut order_books, 1, start_time(), 105, 1);
+
+        assert_eq!(order_books.get_order_book("IBM").unwrap().last_trade_price(), Some(105));
+        let order_results = order_books.add_order(NewOrder::new(3, "IBM".to_string(), 116, 100, 'B', 5));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 3, user_order_id: 5 }]);
+        let order_results = order_books.add_order(NewOrder::new(3, "IBM".to_string(), 115, 100, 'B', 6));
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 3, user_order_id: 6 });
+    }
+
+    #[test]
+    fn breaker_halts_and_resumes() {
+        let mut order_books = order_books_with_breaker(BreakerAction::Halt);
+        trade_at(&mut order_books, 1, start_time(), 100, 1);
+
+        let order_results = trade_at(&mut order_books, 3, start_time() + Duration::seconds(30), 111, 3);
+        assert_eq!(order_results.last(), Some(&OrderResult::TradingStatusChange { status: TradingStatus::Halted }));
+
+        let results = order_books.process_at(5, start_time() + Duration::seconds(100), InputMessage::New(NewOrder::new(3, "IBM".to_string(), 100, 100, 'B', 5)));
+        assert_eq!(results[0].order_result, OrderResult::Rejection { user: 3, user_order_id: 5 });
+
+        let results = order_books.process_at(6, start_time() + Duration::seconds(330), InputMessage::New(NewOrder::new(3, "IBM".to_string(), 100, 100, 'B', 6)));
+        assert_eq!(results[0].symbol, Some("IBM".to_string()));
+        assert_eq!(results[0].order_result, OrderResult::TradingStatusChange { status: TradingStatus::Continuous });
+        assert_eq!(results[1].order_result, OrderResult::Acknowledgement { user: 3, user_order_id: 6 });
+    }
+
+    #[test]
+    fn breaker_ignores_moves_outside_window() {
+        let mut order_books = order_books_with_breaker(BreakerAction::Halt);
+        trade_at(&mut order_books, 1, start_time(), 100, 1);
+
+        let order_results = trade_at(&mut order_books, 3, start_time() + Duration::seconds(61), 111, 3);
+
+        assert!(!order_results.contains(&OrderResult::TradingStatusChange { status: TradingStatus::Halted }));
+    }
+
+    #[test]
+    fn breaker_starts_volatility_auction() {
+        let mut order_books = order_books_with_breaker(BreakerAction::Auction);
+        trade_at(&mut order_books, 1, start_time(), 100, 1);
+        let order_results = trade_at(&mut order_books, 3, start_time() + Duration::seconds(1), 89, 3);
+        assert!(order_results.contains(&OrderResult::TradingStatusChange { status: TradingStatus::Auction }));
+
+        order_books.process_at(5, start_time() + Duration::seconds(2), InputMessage::New(NewOrder::new(3, "IBM".to_string(), 95, 100, 'B', 5)));
+        order_books.process_at(6, start_time() + Duration::seconds(3), InputMessage::New(NewOrder::new(4, "IBM".to_string(), 94, 100, 'S', 6)));
+        let results = order_books.process_at(7, start_time() + Duration::seconds(301), InputMessage::Cancel(CancelOrder::new(9, 9)));
+
+        assert_eq!(results[0].order_result, OrderResult::TradingStatusChange { status: TradingStatus::Continuous });
+        assert!(matches!(&results[1].order_result, OrderResult::Trade { user_buy: 3, user_order_id_buy: 5, user_sell: 4, user_order_id_sell: 6, price: 94, qty: 100, .. }));
+    }
+
+    #[test]
+    fn band_and_last_trade_survive_mass_cancel_and_flush() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_price_band(PriceBandOrder::new(Some("AAPL"), Some(PriceBand::new(5).with_reference_price(100))));
+        order_books.add_order(NewOrder::new(1, "AAPL".to_string(), 100, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 3));
+        order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 20, 100, 'B', 4));
+
+        order_books.mass_cancel(MassCancelOrder::by_user(1));
+        assert_eq!(order_books.symbols(), vec!["AAPL".to_string(), "IBM".to_string()]);
+
+        order_books.flush(FlushOrder::all());
+        assert_eq!(order_books.symbols(), vec!["AAPL".to_string(), "IBM".to_string()]);
+        assert_eq!(order_books.get_order_book("AAPL").unwrap().price_band(), Some(PriceBand::new(5).with_reference_price(100)));
+        assert_eq!(order_books.get_order_book("IBM").unwrap().last_trade_price(), Some(10));
+    }
+}
@@ -0,0 +1,251 @@
+//! Pre-trade risk checks, which reject new orders breaching a user's limits before they reach an
+//! `OrderBook`.
+//!
+//! Limits can be loaded from a CSV file with one row per limit, where a blank or '-' user sets the
+//! default limit for users without their own:
+//! ```text
+//! user, limit, value
+//! ```
+//! For example:
+//! ```text
+//! -, max-order-qty, 1000
+//! 1, max-open-orders, 5
+//! ```
+
+use std::{collections::{BTreeMap, HashMap, VecDeque}, fmt, fs::File, io::Read, path::Path, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use csv::StringRecord;
+
+use crate::order::NewOrder;
+
+/// The kinds of limit a user may be given, each of which is also the reason an order breaching it
+/// is rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RiskLimit {
+    /// The largest quantity of a single order
+    MaxOrderQty,
+    /// The largest price times quantity of a single order
+    MaxOrderNotional,
+    /// The most orders a user may have open across all symbols, including stop and queued orders
+    MaxOpenOrders,
+    /// The largest position in a symbol, long or short, if every open order on the same side as the
+    /// new order filled
+    MaxNetPosition,
+    /// The largest absolute position in a symbol plus the quantity of every open order in it
+    MaxGrossPosition,
+    /// The most new orders and cancels a user may send in any one second
+    MaxMessagesPerSecond
+}
+
+impl FromStr for RiskLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max-order-qty" => Ok(Self::MaxOrderQty),
+            "max-order-notional" => Ok(Self::MaxOrderNotional),
+            "max-open-orders" => Ok(Self::MaxOpenOrders),
+            "max-net-position" => Ok(Self::MaxNetPosition),
+            "max-gross-position" => Ok(Self::MaxGrossPosition),
+            "max-messages-per-second" => Ok(Self::MaxMessagesPerSecond),
+            _ => Err(format!("Unknown risk limit: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for RiskLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MaxOrderQty => write!(f, "max-order-qty"),
+            Self::MaxOrderNotional => write!(f, "max-order-notional"),
+            Self::MaxOpenOrders => write!(f, "max-open-orders"),
+            Self::MaxNetPosition => write!(f, "max-net-position"),
+            Self::MaxGrossPosition => write!(f, "max-gross-position"),
+            Self::MaxMessagesPerSecond => write!(f, "max-messages-per-second")
+        }
+    }
+}
+
+/// What a user already has at risk when placing a new order: their open orders across all symbols,
+/// and their position and open quantity on each side in the order's symbol
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Exposure {
+    pub open_orders: u64,
+    pub net_position: i64,
+    pub open_buy_qty: u64,
+    pub open_sell_qty: u64
+}
+
+/// The limits of one user, or the default limits, where a missing limit is not checked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RiskLimits {
+    limits: BTreeMap<RiskLimit, u64>
+}
+
+impl RiskLimits {
+    pub fn new() -> RiskLimits {
+        RiskLimits::default()
+    }
+
+    /// Set `limit` to `value`, or remove it when `None`
+    pub fn set(&mut self, limit: RiskLimit, value: Option<u64>) {
+        match value {
+            Some(value) => self.limits.insert(limit, value),
+            None => self.limits.remove(&limit)
+        };
+    }
+
+    pub fn get(&self, limit: RiskLimit) -> Option<u64> {
+        self.limits.get(&limit).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    /// Check `new_order`, valued at `price`, against these limits given the user's `exposure`,
+    /// returning the first limit it breaches. A notional too large to represent breaches any
+    /// notional limit.
+    pub fn check(&self, new_order: &NewOrder, price: u64, exposure: &Exposure) -> Result<(), RiskLimit> {
+        let qty = new_order.qty;
+        let net_after = if new_order.side == 'B' {
+            exposure.net_position + (exposure.open_buy_qty + qty) as i64
+        } else {
+            exposure.net_position - (exposure.open_sell_qty + qty) as i64
+        };
+        let gross_after = exposure.net_position.unsigned_abs() + exposure.open_buy_qty + exposure.open_sell_qty + qty;
+        let breaches = [
+            (RiskLimit::MaxOrderQty, Some(qty)),
+            (RiskLimit::MaxOrderNotional, price.checked_mul(qty)),
+            (RiskLimit::MaxOpenOrders, Some(exposure.open_orders + 1)),
+            (RiskLimit::MaxNetPosition, Some(net_after.unsigned_abs())),
+            (RiskLimit::MaxGrossPosition, Some(gross_after))
+        ];
+        for (limit, value) in breaches {
+            if self.get(limit).map_or(false, |max| value.map_or(true, |value| value > max)) {
+                return Err(limit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Holds every user's risk limits, with defaults for users without their own, along with the recent
+/// messages of each user rate limited by `RiskLimit::MaxMessagesPerSecond`
+#[derive(Default)]
+pub struct RiskManager {
+    default_limits: RiskLimits,
+    user_limits: HashMap<u64, RiskLimits>,
+    /// The engine time of each user's messages within the last second, oldest first
+    message_times: HashMap<u64, VecDeque<DateTime<Utc>>>
+}
+
+impl RiskManager {
+    pub fn new() -> RiskManager {
+        RiskManager::default()
+    }
+
+    /// Set `limit` for `user`, or the default `limit` when `user` is `None`, removing it when `value`
+    /// is `None`
+    pub fn set_limit(&mut self, user: Option<u64>, limit: RiskLimit, value: Option<u64>) {
+        match user {
+            Some(user) => self.user_limits.entry(user).or_default().set(limit, value),
+            None => self.default_limits.set(limit, value)
+        }
+    }
+
+    /// The limits that apply to `user`: their own where set, and the defaults otherwise
+    pub fn limits(&self, user: u64) -> RiskLimits {
+        let mut limits = self.default_limits.clone();
+        if let Some(user_limits) = self.user_limits.get(&user) {
+            limits.limits.extend(user_limits.limits.iter());
+        }
+        limits
+    }
+
+    /// Every limit set, as user, limit and value, with the defaults first and then by user
+    pub fn limit_rows(&self) -> Vec<(Option<u64>, RiskLimit, u64)> {
+        let mut users: Vec<&u64> = self.user_limits.keys().collect();
+        users.sort();
+        let mut rows: Vec<(Option<u64>, RiskLimit, u64)> = self.default_limits.limits.iter().map(|(limit, value)| (None, *limit, *value)).collect();
+        for user in users {
+            rows.extend(self.user_limits[user].limits.iter().map(|(limit, value)| (Some(*user), *limit, *value)));
+        }
+        rows
+    }
+
+    /// Count a message from `user` at `time`, returning whether it is within their message rate
+    pub fn record_message(&mut self, user: u64, time: DateTime<Utc>) -> bool {
+        let max_messages = match self.limits(user).get(RiskLimit::MaxMessagesPerSecond) {
+            Some(max_messages) => max_messages,
+            None => return true
+        };
+        let message_times = self.message_times.entry(user).or_default();
+        while message_times.front().map_or(false, |message_time| *message_time <= time - Duration::seconds(1)) {
+            message_times.pop_front();
+        }
+        message_times.push_back(time);
+        message_times.len() as u64 <= max_messages
+    }
+}
+
+/// Reasons risk limits could not be loaded
+#[derive(Debug)]
+pub enum RiskConfigError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    InvalidRow(String)
+}
+
+impl fmt::Display for RiskConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Risk limits I/O error: {}", e),
+            Self::Csv(e) => write!(f, "Risk limits CSV error: {}", e),
+            Self::InvalidRow(row) => write!(f, "Invalid risk limits row: \"{}\"", row)
+        }
+    }
+}
+
+impl From<std::io::Error> for RiskConfigError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<csv::Error> for RiskConfigError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+/// Read risk limits from `reader`, one `user, limit, value` row per limit
+pub fn read_risk_limits<R: Read>(reader: R) -> Result<RiskManager, RiskConfigError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let mut risk_manager = RiskManager::new();
+    for record in reader.records() {
+        let row = record?;
+        let invalid = || RiskConfigError::InvalidRow(row_to_string(&row));
+        if row.len() != 3 {
+            return Err(invalid());
+        }
+        let user = match row.get(0).unwrap() {
+            "" | "-" => None,
+            user => Some(user.parse::<u64>().map_err(|_| invalid())?)
+        };
+        let limit = row.get(1).unwrap().parse::<RiskLimit>().map_err(|_| invalid())?;
+        let value = row.get(2).unwrap().parse::<u64>().map_err(|_| invalid())?;
+        risk_manager.set_limit(user, limit, Some(value));
+    }
+    Ok(risk_manager)
+}
+
+/// Read risk limits from the file at `path`
+pub fn load_risk_limits<P: AsRef<Path>>(path: P) -> Result<RiskManager, RiskConfigError> {
+    read_risk_limits(File::open(path)?)
+}
+
+fn row_to_string(row: &StringRecord) -> String {
+    row.iter().collect::<Vec<&str>>().join(", ")
+}
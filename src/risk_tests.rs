@@ -0,0 +1,135 @@
+//! Unit tests for pre-trade risk checks in `OrderBooks`
+
+#[cfg(test)]
+mod risk_tests {
+    use crate::risk::*;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+    use crate::order_result::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn start_time() -> DateTime<Utc> {
+        "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap()
+    }
+
+    fn order_books_with_limit(user: Option<u64>, limit: RiskLimit, value: u64) -> OrderBooks {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_risk_limit(RiskLimitOrder::new(user, limit, Some(value)));
+        order_books
+    }
+
+    fn risk_rejection(user: u64, user_order_id: u64, reason: RiskLimit) -> Vec<OrderResult> {
+        vec![OrderResult::RiskRejection { user, user_order_id, reason }]
+    }
+
+    #[test]
+    fn parse_limit() {
+        assert_eq!("max-open-orders".parse::<RiskLimit>(), Ok(RiskLimit::MaxOpenOrders));
+        assert_eq!(RiskLimit::MaxMessagesPerSecond.to_string(), "max-messages-per-second");
+        assert!("max-orders".parse::<RiskLimit>().is_err());
+    }
+
+    #[test]
+    fn risk_rejection_format() {
+        assert_eq!(risk_rejection(1, 7, RiskLimit::MaxOrderQty)[0].to_string(), "R, 1, 7, max-order-qty");
+    }
+
+    #[test]
+    fn user_limits_override_defaults() {
+        let mut risk_manager = RiskManager::new();
+        risk_manager.set_limit(None, RiskLimit::MaxOrderQty, Some(100));
+        risk_manager.set_limit(Some(1), RiskLimit::MaxOrderQty, Some(500));
+        risk_manager.set_limit(Some(1), RiskLimit::MaxOpenOrders, Some(2));
+
+        assert_eq!(risk_manager.limits(1).get(RiskLimit::MaxOrderQty), Some(500));
+        assert_eq!(risk_manager.limits(2).get(RiskLimit::MaxOrderQty), Some(100));
+        assert_eq!(risk_manager.limits(2).get(RiskLimit::MaxOpenOrders), None);
+        assert_eq!(risk_manager.limit_rows(), vec![
+            (None, RiskLimit::MaxOrderQty, 100),
+            (Some(1), RiskLimit::MaxOrderQty, 500),
+            (Some(1), RiskLimit::MaxOpenOrders, 2)
+        ]);
+    }
+
+    #[test]
+    fn max_order_qty_and_notional() {
+        let mut order_books = order_books_with_limit(None, RiskLimit::MaxOrderQty, 100);
+        order_books.set_risk_limit(RiskLimitOrder::new(None, RiskLimit::MaxOrderNotional, Some(1000)));
+
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 5, 101, 'B', 1)), risk_rejection(1, 1, RiskLimit::MaxOrderQty));
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 11, 100, 'B', 2)), risk_rejection(1, 2, RiskLimit::MaxOrderNotional));
+        assert!(order_books.symbols().is_empty());
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 3))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 3 });
+    }
+
+    #[test]
+    fn positions_survive_mass_cancel_and_flush() {
+        let mut order_books = order_books_with_limit(Some(1), RiskLimit::MaxNetPosition, 150);
+        order_books.get_or_create_order_book("IBM").restore_position(1, 100);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 50, 'B', 1));
+
+        order_books.mass_cancel(MassCancelOrder::by_user(1));
+        order_books.flush(FlushOrder::all());
+
+        assert_eq!(order_books.get_order_book("IBM").unwrap().position(1), 100);
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2)), risk_rejection(1, 2, RiskLimit::MaxNetPosition));
+    }
+
+    #[test]
+    fn notional_overflow_rejected() {
+        let mut order_books = order_books_with_limit(None, RiskLimit::MaxOrderNotional, u64::MAX);
+
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), u64::MAX, 2, 'B', 1)), risk_rejection(1, 1, RiskLimit::MaxOrderNotional));
+    }
+
+    #[test]
+    fn max_open_orders_across_symbols() {
+        let mut order_books = order_books_with_limit(Some(1), RiskLimit::MaxOpenOrders, 2);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new_stop(1, "AAPL".to_string(), 20, 100, 'B', 2));
+
+        assert_eq!(order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 10, 100, 'B', 3)), risk_rejection(1, 3, RiskLimit::MaxOpenOrders));
+        assert_eq!(order_books.add_order(NewOrder::new(2, "MSFT".to_string(), 10, 100, 'B', 4))[0], OrderResult::Acknowledgement { user: 2, user_order_id: 4 });
+
+        order_books.cancel_order(CancelOrder::new(1, 1));
+        assert_eq!(order_books.add_order(NewOrder::new(1, "MSFT".to_string(), 10, 100, 'B', 5))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 5 });
+    }
+
+    #[test]
+    fn net_and_gross_position() {
+        let mut order_books = order_books_with_limit(Some(1), RiskLimit::MaxNetPosition, 150);
+        order_books.set_risk_limit(RiskLimitOrder::new(Some(1), RiskLimit::MaxGrossPosition, Some(250)));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 1));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 2));
+        assert_eq!(order_books.get_order_book("IBM").unwrap().position(1), 100);
+        assert_eq!(order_books.get_order_book("IBM").unwrap().position(2), -100);
+
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 9, 60, 'B', 3)), risk_rejection(1, 3, RiskLimit::MaxNetPosition));
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 9, 50, 'B', 4))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 4 });
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 150, 'S', 5)), risk_rejection(1, 5, RiskLimit::MaxGrossPosition));
+        assert_eq!(order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 100, 'S', 6))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 6 });
+    }
+
+    #[test]
+    fn max_messages_per_second() {
+        let mut order_books = order_books_with_limit(None, RiskLimit::MaxMessagesPerSecond, 2);
+        let new_order = |user_order_id: u64| InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', user_order_id));
+
+        order_books.process_at(1, start_time(), new_order(1));
+        order_books.process_at(2, start_time() + Duration::milliseconds(100), InputMessage::Cancel(CancelOrder::new(1, 1)));
+        let results = order_books.process_at(3, start_time() + Duration::milliseconds(200), new_order(2));
+        assert_eq!(results[0].order_result, OrderResult::RiskRejection { user: 1, user_order_id: 2, reason: RiskLimit::MaxMessagesPerSecond });
+
+        let results = order_books.process_at(4, start_time() + Duration::milliseconds(1100), new_order(3));
+        assert_eq!(results[0].order_result, OrderResult::Acknowledgement { user: 1, user_order_id: 3 });
+    }
+
+    #[test]
+    fn read_limits_file() {
+        let risk_manager = read_risk_limits("-, max-order-qty, 1000\n1, max-open-orders, 5\n".as_bytes()).unwrap();
+
+        assert_eq!(risk_manager.limits(1).get(RiskLimit::MaxOrderQty), Some(1000));
+        assert_eq!(risk_manager.limits(1).get(RiskLimit::MaxOpenOrders), Some(5));
+        assert!(matches!(read_risk_limits("1, max-orders, 5\n".as_bytes()), Err(RiskConfigError::InvalidRow(_))));
+    }
+}
@@ -0,0 +1,81 @@
+//! Scenario tracking for the CSV runner, where each scenario begins at a `#name:` row and is
+//! summarised once the next scenario begins or the input ends
+
+use std::fmt;
+
+use crate::{order::InputMessage, order_result::OrderResult};
+
+/// Counts of the orders, trades, rejects and cancels seen while running a single scenario
+#[derive(Debug, PartialEq)]
+pub struct ScenarioSummary {
+    pub name: String,
+    pub orders: u64,
+    pub trades: u64,
+    pub rejects: u64,
+    pub cancels: u64
+}
+
+impl ScenarioSummary {
+    pub fn new(name: &str) -> ScenarioSummary {
+        ScenarioSummary { name: name.to_string(), orders: 0, trades: 0, rejects: 0, cancels: 0 }
+    }
+
+    /// Count a processed message according to its kind
+    pub fn record(&mut self, message: &InputMessage, order_results: &[OrderResult]) {
+        match message {
+            InputMessage::New(_) => self.record_new_order(order_results),
+            InputMessage::Cancel(_) => self.record_cancel_order(order_results),
+            InputMessage::MassCancel(_) => self.record_mass_cancel(order_results),
+            InputMessage::Flush(_) | InputMessage::PriceBand(_) | InputMessage::RiskLimit(_) | InputMessage::OrderStatus(_) => (),
+            InputMessage::Auction(_) | InputMessage::TradingStatus(_) => self.record_auction(order_results)
+        }
+    }
+
+    /// Count a new order along with any trades or rejections it produced
+    pub fn record_new_order(&mut self, order_results: &[OrderResult]) {
+        self.orders += 1;
+        for order_result in order_results {
+            match order_result {
+                OrderResult::Trade { .. } => self.trades += 1,
+                OrderResult::Rejection { .. } | OrderResult::RiskRejection { .. } => self.rejects += 1,
+                _ => ()
+            }
+        }
+    }
+
+    /// Count a cancel order, but only if it was acknowledged by one of the `OrderBook`s
+    pub fn record_cancel_order(&mut self, order_results: &[OrderResult]) {
+        if order_results.iter().any(|order_result| matches!(order_result, OrderResult::Acknowledgement { .. })) {
+            self.cancels += 1;
+        }
+    }
+
+    /// Count the trades made by an auction uncross, including one made by resuming continuous
+    /// trading, and by the queued orders entered when trading resumes
+    pub fn record_auction(&mut self, order_results: &[OrderResult]) {
+        self.trades += order_results.iter().filter(|order_result| matches!(order_result, OrderResult::Trade { .. })).count() as u64;
+    }
+
+    /// Count each order cancelled by a mass cancel
+    pub fn record_mass_cancel(&mut self, order_results: &[OrderResult]) {
+        self.cancels += order_results.iter().filter(|order_result| matches!(order_result, OrderResult::Cancellation { .. })).count() as u64;
+    }
+}
+
+impl fmt::Display for ScenarioSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: orders {}, trades {}, rejects {}, cancels {}", self.name, self.orders, self.trades, self.rejects, self.cancels)
+    }
+}
+
+/// Read the scenario name from a `#name:` row, collapsing repeated whitespace
+pub fn parse_scenario_name(row: &str) -> Option<String> {
+    row.strip_prefix("#name:").map(|name| name.split_whitespace().collect::<Vec<&str>>().join(" "))
+}
+
+/// Whether the scenario called `name` was selected on the command line, either by its full name
+/// (Ex. "scenario 7") or by its last word (Ex. "7")
+pub fn is_selected_scenario(name: &str, selected: &str) -> bool {
+    let selected = selected.trim();
+    name == selected || name.split_whitespace().last() == Some(selected)
+}
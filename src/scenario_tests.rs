@@ -0,0 +1,51 @@
+//! Unit tests for `ScenarioSummary` and scenario selection
+
+#[cfg(test)]
+mod scenario_tests {
+    use crate::scenario::*;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+
+    #[test]
+    fn scenario_name() {
+        assert_eq!(parse_scenario_name("#name: scenario 1"), Some("scenario 1".to_string()));
+        assert_eq!(parse_scenario_name("#name: scenario  1"), Some("scenario 1".to_string()));
+        assert_eq!(parse_scenario_name("#descr:balanced book"), None);
+    }
+
+    #[test]
+    fn selected_scenario() {
+        assert!(is_selected_scenario("scenario 7", "7"));
+        assert!(is_selected_scenario("scenario 7", "scenario 7"));
+        assert!(!is_selected_scenario("scenario 17", "7"));
+        assert!(!is_selected_scenario("scenario 7", "1"));
+    }
+
+    #[test]
+    fn summary_counts() {
+        let mut order_books = OrderBooks::new(true);
+        let mut summary = ScenarioSummary::new("scenario 13");
+
+        summary.record_new_order(&order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        summary.record_new_order(&order_books.add_order(NewOrder::new(2, "IBM".to_string(), 11, 100, 'S', 101)));
+        summary.record_new_order(&order_books.add_order(NewOrder::new(1, "IBM".to_string(), 11, 100, 'B', 2)));
+        summary.record_cancel_order(&order_books.cancel_order(CancelOrder::new(1, 1)));
+        summary.record_cancel_order(&order_books.cancel_order(CancelOrder::new(1, 1)));
+
+        assert_eq!(summary, ScenarioSummary { name: "scenario 13".to_string(), orders: 3, trades: 1, rejects: 0, cancels: 1 });
+        assert_eq!(summary.to_string(), "scenario 13: orders 3, trades 1, rejects 0, cancels 1");
+    }
+
+    #[test]
+    fn summary_rejects() {
+        let mut order_books = OrderBooks::new(false);
+        let mut summary = ScenarioSummary::new("scenario 1");
+
+        summary.record_new_order(&order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        summary.record_new_order(&order_books.add_order(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 101)));
+
+        assert_eq!(summary.orders, 2);
+        assert_eq!(summary.rejects, 1);
+        assert_eq!(summary.trades, 0);
+    }
+}
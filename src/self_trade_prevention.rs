@@ -0,0 +1,56 @@
+//! Self-trade prevention modes, applied when an incoming order would otherwise trade against a resting
+//! order from the same user or self-trade group
+
+use std::fmt;
+use std::str::FromStr;
+
+/// What an `OrderBook` does when the match found for a new order belongs to the same user or
+/// self-trade group
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelfTradePrevention {
+    /// Self-trades are matched like any other trade
+    Allow,
+    /// Cancel the incoming order and leave the resting order in the book
+    CancelNewest,
+    /// Cancel the resting order and continue matching the incoming order
+    CancelOldest,
+    /// Cancel both the incoming and resting orders
+    CancelBoth,
+    /// Reduce both orders by the smaller of their quantities, cancelling any order left with none.
+    /// Orders only match at equal shown quantities, so in practice only an iceberg order's reserve
+    /// survives
+    DecrementAndCancel
+}
+
+impl FromStr for SelfTradePrevention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "cancel-newest" => Ok(Self::CancelNewest),
+            "cancel-oldest" => Ok(Self::CancelOldest),
+            "cancel-both" => Ok(Self::CancelBoth),
+            "decrement" => Ok(Self::DecrementAndCancel),
+            _ => Err(format!("Unknown self-trade prevention mode: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for SelfTradePrevention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Allow => write!(f, "allow"),
+            Self::CancelNewest => write!(f, "cancel-newest"),
+            Self::CancelOldest => write!(f, "cancel-oldest"),
+            Self::CancelBoth => write!(f, "cancel-both"),
+            Self::DecrementAndCancel => write!(f, "decrement")
+        }
+    }
+}
+
+/// Two orders are a self-trade when they come from the same user, or when both carry the same
+/// self-trade group
+pub fn is_self_trade(user_a: u64, group_a: Option<u64>, user_b: u64, group_b: Option<u64>) -> bool {
+    user_a == user_b || (group_a.is_some() && group_a == group_b)
+}
@@ -0,0 +1,119 @@
+//! Unit tests for self-trade prevention in `OrderBook`
+
+#[cfg(test)]
+mod self_trade_prevention_tests {
+    use crate::order_book::OrderBook;
+    use crate::self_trade_prevention::*;
+    use crate::order::*;
+    use crate::order_result::*;
+
+    fn order_book_with_resting_sell(self_trade_prevention: SelfTradePrevention) -> OrderBook {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_self_trade_prevention(self_trade_prevention);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'S', 1));
+        order_book
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!("cancel-newest".parse::<SelfTradePrevention>(), Ok(SelfTradePrevention::CancelNewest));
+        assert_eq!("decrement".parse::<SelfTradePrevention>(), Ok(SelfTradePrevention::DecrementAndCancel));
+        assert!("cancel-all".parse::<SelfTradePrevention>().is_err());
+    }
+
+    #[test]
+    fn self_trade_key() {
+        assert!(is_self_trade(1, None, 1, None));
+        assert!(is_self_trade(1, Some(7), 2, Some(7)));
+        assert!(!is_self_trade(1, Some(7), 2, None));
+        assert!(!is_self_trade(1, Some(7), 2, Some(8)));
+    }
+
+    #[test]
+    fn allow() {
+        let mut order_book = order_book_with_resting_sell(SelfTradePrevention::Allow);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(order_results.len(), 3);
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 1, user_order_id_buy: 2, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
+    }
+
+    #[test]
+    fn cancel_newest() {
+        let mut order_book = order_book_with_resting_sell(SelfTradePrevention::CancelNewest);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(order_results.len(), 2);
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[1], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_book.cancel_order(&CancelOrder::new(1, 1))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 1 });
+    }
+
+    #[test]
+    fn cancel_oldest_then_match() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_self_trade_prevention(SelfTradePrevention::CancelOldest);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'S', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 11, 100, 'S', 101));
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 11, 100, 'B', 2));
+
+        assert_eq!(order_results.len(), 4);
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[1], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 1 });
+        assert!(matches!(&order_results[2], OrderResult::Trade { user_buy: 1, user_order_id_buy: 2, user_sell: 2, user_order_id_sell: 101, price: 11, qty: 100, .. }));
+        assert_eq!(order_results[3], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+    }
+
+    #[test]
+    fn cancel_both() {
+        let mut order_book = order_book_with_resting_sell(SelfTradePrevention::CancelBoth);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(order_results.len(), 4);
+        assert_eq!(order_results[1], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[2], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[3], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+    }
+
+    #[test]
+    fn decrement_and_cancel() {
+        let mut order_book = order_book_with_resting_sell(SelfTradePrevention::DecrementAndCancel);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(order_results.len(), 4);
+        assert_eq!(order_results[1], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 1 });
+        assert_eq!(order_results[2], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 2 });
+        assert_eq!(order_results[3], OrderResult::TopOfBookChange { side: 'S', price: "-".to_string(), total_quantity: "-".to_string() });
+    }
+
+    #[test]
+    fn decrement_iceberg_reserve() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_self_trade_prevention(SelfTradePrevention::DecrementAndCancel);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 300, 'S', 1).with_display_qty(100));
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+
+        assert_eq!(order_results[1], OrderResult::SelfTradeDecrement { user: 1, user_order_id: 1, remaining_qty: 200 });
+        assert_eq!(order_results[2], OrderResult::SelfTradeCancellation { user: 1, user_order_id: 2 });
+    }
+
+    #[test]
+    fn self_trade_group() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_self_trade_prevention(SelfTradePrevention::CancelNewest);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'S', 1).with_self_trade_group(7));
+
+        let mut order_results = order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'B', 101).with_self_trade_group(7));
+        order_results.append(&mut order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 10, 100, 'B', 201).with_self_trade_group(8)));
+
+        assert_eq!(order_results.len(), 5);
+        assert_eq!(order_results[1], OrderResult::SelfTradeCancellation { user: 2, user_order_id: 101 });
+        assert!(matches!(&order_results[3], OrderResult::Trade { user_buy: 3, user_order_id_buy: 201, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
+    }
+}
@@ -0,0 +1,335 @@
+//! Save the full state of `OrderBooks` to a versioned CSV snapshot and restore it, so that a run can
+//! be resumed or books can be seeded for tests.
+//!
+//! A snapshot holds a version row, a config row with the trading mode and the next result and input
+//! sequence numbers, a row with the next result sequence number of each symbol, one row per resting
+//! order listed in priority order within each price level, and one row per stop order waiting to be
+//! triggered and per queued order, each in the order they were received:
+//! ```text
+//! SNAPSHOT, 1
+//! CONFIG, trading_enabled, self_trade_prevention, halt_policy, session_status, price_band, circuit_breaker, next_trade_id, next_sequence, next_input_sequence
+//! SEQUENCE, symbol, next_symbol_sequence
+//! RISK, user, risk_limit, value
+//! ORDER_STATUS, user, user_order_id, symbol, side, state, original_qty, filled_qty, open_qty, average_price, updated
+//! STATUS, symbol, trading_status
+//! MARKET, symbol, price_band, last_trade_price, breaker_resumes_at
+//! POSITION, symbol, user, position
+//! ORDER, symbol, side, price, user, user_order_id, qty, time_received, self_trade_group, display_qty, reserve_qty, hidden, peg
+//! STOP, symbol, side, price, user, user_order_id, qty, time_received, self_trade_group, order_type, display_qty, post_only, hidden, peg
+//! QUEUED, symbol, side, price, user, user_order_id, qty, time_received, self_trade_group, order_type, display_qty, post_only, hidden, peg
+//! ```
+//! where a `RISK` row holds one risk limit of a user, or a default limit when user is '-', an
+//! `ORDER_STATUS` row holds the lifecycle status of an order still retained, a `POSITION` row holds
+//! a user's position in a symbol when it is not zero, a `STATUS` row marks a symbol whose trading
+//! status differs from the session's, a `MARKET` row holds a symbol's own price band, last trade
+//! price and the end of any circuit breaker interruption, written when any differs from a new
+//! `OrderBook`'s, `qty` of an `ORDER` is the quantity shown in the book and `reserve_qty` the
+//! hidden remainder of an iceberg order. The trades within the circuit breaker window and the
+//! recent messages counted by risk limits are not saved.
+
+use std::{fmt, fs::File, io::{Read, Write}, path::Path};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use csv::StringRecord;
+
+use crate::{order::{ExistingOrder, NewOrder, OrderType, RiskLimitOrder}, order_books::OrderBooks, order_status::OrderStatus, price_band::{CircuitBreaker, PriceBand}, risk::RiskLimit, self_trade_prevention::SelfTradePrevention, trading_status::{HaltPolicy, TradingStatus}};
+
+/// The snapshot format version written by `write_snapshot`
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Reasons a snapshot could not be written or read
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    UnsupportedVersion(String),
+    InvalidRow(String)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Snapshot I/O error: {}", e),
+            Self::Csv(e) => write!(f, "Snapshot CSV error: {}", e),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported snapshot version: \"{}\"", version),
+            Self::InvalidRow(row) => write!(f, "Invalid snapshot row: \"{}\"", row)
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<csv::Error> for SnapshotError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+/// Write every `OrderBook` in `order_books` to `writer`, in symbol order
+pub fn write_snapshot<W: Write>(order_books: &OrderBooks, writer: W) -> Result<(), SnapshotError> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+    writer.write_record(["SNAPSHOT", &SNAPSHOT_VERSION.to_string()])?;
+    writer.write_record([
+        "CONFIG",
+        &order_books.trading_enabled().to_string(),
+        &order_books.self_trade_prevention().to_string(),
+        &order_books.halt_policy().to_string(),
+        &order_books.session_status().to_string(),
+        &optional_to_field(&order_books.default_price_band()),
+        &optional_to_field(&order_books.circuit_breaker()),
+        &order_books.next_trade_id().to_string(),
+        &order_books.next_sequence().to_string(),
+        &order_books.next_input_sequence().to_string()
+    ])?;
+    for (symbol, next_symbol_sequence) in order_books.next_symbol_sequences() {
+        writer.write_record(["SEQUENCE".to_string(), symbol, next_symbol_sequence.to_string()])?;
+    }
+    for (user, limit, value) in order_books.risk_manager().limit_rows() {
+        writer.write_record(["RISK".to_string(), optional_to_field(&user), limit.to_string(), value.to_string()])?;
+    }
+    for order_status in order_books.order_statuses().statuses() {
+        writer.write_record([
+            "ORDER_STATUS".to_string(),
+            order_status.user.to_string(),
+            order_status.user_order_id.to_string(),
+            order_status.symbol.clone(),
+            order_status.side.to_string(),
+            order_status.state.to_string(),
+            order_status.original_qty.to_string(),
+            order_status.filled_qty.to_string(),
+            order_status.open_qty.to_string(),
+            order_status.average_price.to_string(),
+            order_status.updated.to_rfc3339_opts(SecondsFormat::Nanos, true)
+        ])?;
+    }
+
+    for symbol in order_books.symbols() {
+        let order_book = order_books.get_order_book(&symbol).unwrap();
+        if order_book.trading_status() != order_books.session_status() {
+            writer.write_record(["STATUS", &symbol, &order_book.trading_status().to_string()])?;
+        }
+        if order_book.price_band() != order_books.default_price_band() || order_book.last_trade_price().is_some() || order_book.breaker_resumes_at().is_some() {
+            writer.write_record([
+                "MARKET".to_string(),
+                symbol.clone(),
+                optional_to_field(&order_book.price_band()),
+                optional_to_field(&order_book.last_trade_price()),
+                optional_to_field(&order_book.breaker_resumes_at().map(|time| time.to_rfc3339_opts(SecondsFormat::Nanos, true)))
+            ])?;
+        }
+        for (user, position) in order_book.positions() {
+            writer.write_record(["POSITION".to_string(), symbol.clone(), user.to_string(), position.to_string()])?;
+        }
+        for side in ['B', 'S'] {
+            for existing_orders in order_book.resting_orders(side).values() {
+                for existing_order in existing_orders {
+                    writer.write_record([
+                        "ORDER".to_string(),
+                        symbol.clone(),
+                        side.to_string(),
+                        existing_order.price.to_string(),
+                        existing_order.user.to_string(),
+                        existing_order.user_order_id.to_string(),
+                        existing_order.qty.to_string(),
+                        existing_order.time_received.to_rfc3339_opts(SecondsFormat::Nanos, true),
+                        optional_to_field(&existing_order.self_trade_group),
+                        optional_to_field(&existing_order.display_qty),
+                        existing_order.reserve_qty.to_string(),
+                        existing_order.hidden.to_string(),
+                        optional_to_field(&existing_order.peg)
+                    ])?;
+                }
+            }
+        }
+        for stop_order in order_book.stop_orders() {
+            writer.write_record(new_order_to_fields("STOP", stop_order))?;
+        }
+        for queued_order in order_book.queued_orders() {
+            writer.write_record(new_order_to_fields("QUEUED", queued_order))?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Rebuild `OrderBooks` from a snapshot written by `write_snapshot`
+pub fn read_snapshot<R: Read>(reader: R) -> Result<OrderBooks, SnapshotError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let mut records = reader.records();
+
+    let version_row = records.next().ok_or_else(|| SnapshotError::InvalidRow(String::new()))??;
+    if version_row.get(0) != Some("SNAPSHOT") {
+        return Err(SnapshotError::InvalidRow(row_to_string(&version_row)));
+    }
+    if version_row.get(1) != Some(SNAPSHOT_VERSION.to_string().as_str()) {
+        return Err(SnapshotError::UnsupportedVersion(version_row.get(1).unwrap_or_default().to_string()));
+    }
+
+    let config_row = records.next().ok_or_else(|| SnapshotError::InvalidRow(String::new()))??;
+    if config_row.get(0) != Some("CONFIG") || config_row.len() != 10 {
+        return Err(SnapshotError::InvalidRow(row_to_string(&config_row)));
+    }
+    let trading_enabled = parse_field::<bool>(&config_row, 1)?;
+    let self_trade_prevention = parse_field::<SelfTradePrevention>(&config_row, 2)?;
+
+    let mut order_books = OrderBooks::new(trading_enabled);
+    order_books.set_self_trade_prevention(self_trade_prevention);
+    order_books.set_halt_policy(parse_field::<HaltPolicy>(&config_row, 3)?);
+    order_books.restore_session_status(parse_field::<TradingStatus>(&config_row, 4)?);
+    order_books.set_default_price_band(parse_optional_field::<PriceBand>(&config_row, 5)?);
+    order_books.set_circuit_breaker(parse_optional_field::<CircuitBreaker>(&config_row, 6)?);
+    order_books.restore_next_trade_id(parse_field(&config_row, 7)?);
+    order_books.restore_sequences(parse_field(&config_row, 8)?, parse_field(&config_row, 9)?);
+
+    for record in records {
+        let row = record?;
+        match (row.get(0), row.len()) {
+            (Some("SEQUENCE"), 3) => order_books.restore_symbol_sequence(row.get(1).unwrap(), parse_field(&row, 2)?),
+            (Some("STATUS"), 3) => {
+                let trading_status = parse_field::<TradingStatus>(&row, 2)?;
+                order_books.get_or_create_order_book(row.get(1).unwrap()).restore_trading_status(trading_status);
+            },
+            (Some("RISK"), 4) => {
+                let limit = parse_field::<RiskLimit>(&row, 2)?;
+                order_books.set_risk_limit(RiskLimitOrder::new(parse_optional_field(&row, 1)?, limit, Some(parse_field(&row, 3)?)));
+            },
+            (Some("ORDER_STATUS"), 11) => order_books.restore_order_status(OrderStatus {
+                user: parse_field(&row, 1)?,
+                user_order_id: parse_field(&row, 2)?,
+                symbol: row.get(3).unwrap().to_string(),
+                side: parse_side(&row, 4)?,
+                state: parse_field(&row, 5)?,
+                original_qty: parse_field(&row, 6)?,
+                filled_qty: parse_field(&row, 7)?,
+                open_qty: parse_field(&row, 8)?,
+                average_price: parse_field(&row, 9)?,
+                updated: parse_time(&row, 10)?
+            }),
+            (Some("POSITION"), 4) => {
+                let (user, position) = (parse_field(&row, 2)?, parse_field(&row, 3)?);
+                order_books.get_or_create_order_book(row.get(1).unwrap()).restore_position(user, position);
+            },
+            (Some("MARKET"), 5) => {
+                let breaker_resumes_at = match row.get(4) {
+                    Some("-") => None,
+                    _ => Some(parse_time(&row, 4)?)
+                };
+                let order_book = order_books.get_or_create_order_book(row.get(1).unwrap());
+                order_book.set_price_band(parse_optional_field(&row, 2)?);
+                order_book.restore_market_state(parse_optional_field(&row, 3)?, breaker_resumes_at);
+            },
+            (Some("ORDER"), 13) => {
+                let existing_order = ExistingOrder {
+                    user: parse_field(&row, 4)?,
+                    price: parse_field(&row, 3)?,
+                    qty: parse_field(&row, 6)?,
+                    user_order_id: parse_field(&row, 5)?,
+                    time_received: parse_time(&row, 7)?,
+                    self_trade_group: parse_optional_field(&row, 8)?,
+                    display_qty: parse_optional_field(&row, 9)?,
+                    reserve_qty: parse_field(&row, 10)?,
+                    hidden: parse_field(&row, 11)?,
+                    peg: parse_optional_field(&row, 12)?
+                };
+                order_books.get_or_create_order_book(row.get(1).unwrap()).restore_order(parse_side(&row, 2)?, existing_order);
+            },
+            (Some("STOP"), 14) => {
+                let stop_order = parse_new_order(&row)?;
+                if !matches!(stop_order.order_type, OrderType::Stop { .. } | OrderType::StopLimit { .. }) {
+                    return Err(SnapshotError::InvalidRow(row_to_string(&row)));
+                }
+                order_books.get_or_create_order_book(row.get(1).unwrap()).restore_stop_order(stop_order);
+            },
+            (Some("QUEUED"), 14) => {
+                let queued_order = parse_new_order(&row)?;
+                order_books.get_or_create_order_book(row.get(1).unwrap()).restore_queued_order(queued_order);
+            },
+            _ => return Err(SnapshotError::InvalidRow(row_to_string(&row)))
+        }
+    }
+
+    Ok(order_books)
+}
+
+/// Write a snapshot of `order_books` to the file at `path`, replacing it if it exists
+pub fn save_snapshot<P: AsRef<Path>>(order_books: &OrderBooks, path: P) -> Result<(), SnapshotError> {
+    write_snapshot(order_books, File::create(path)?)
+}
+
+/// Rebuild `OrderBooks` from the snapshot file at `path`
+pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<OrderBooks, SnapshotError> {
+    read_snapshot(File::open(path)?)
+}
+
+/// Write a waiting order, which has not yet been entered into the book, as a row tagged `kind`
+fn new_order_to_fields(kind: &str, new_order: &NewOrder) -> [String; 14] {
+    [
+        kind.to_string(),
+        new_order.symbol.clone(),
+        new_order.side.to_string(),
+        new_order.price.to_string(),
+        new_order.user.to_string(),
+        new_order.user_order_id.to_string(),
+        new_order.qty.to_string(),
+        new_order.time_received.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        optional_to_field(&new_order.self_trade_group),
+        new_order.order_type.to_string(),
+        optional_to_field(&new_order.display_qty),
+        optional_to_field(&new_order.post_only),
+        new_order.hidden.to_string(),
+        optional_to_field(&new_order.peg)
+    ]
+}
+
+/// Read a waiting order written by `new_order_to_fields`
+fn parse_new_order(row: &StringRecord) -> Result<NewOrder, SnapshotError> {
+    Ok(NewOrder {
+        time_received: parse_time(row, 7)?,
+        self_trade_group: parse_optional_field(row, 8)?,
+        order_type: parse_field(row, 9)?,
+        display_qty: parse_optional_field(row, 10)?,
+        post_only: parse_optional_field(row, 11)?,
+        hidden: parse_field(row, 12)?,
+        peg: parse_optional_field(row, 13)?,
+        ..NewOrder::new(parse_field(row, 4)?, row.get(1).unwrap().to_string(), parse_field(row, 3)?, parse_field(row, 6)?, parse_side(row, 2)?, parse_field(row, 5)?)
+    })
+}
+
+fn parse_field<T: std::str::FromStr>(row: &StringRecord, index: usize) -> Result<T, SnapshotError> {
+    row.get(index)
+        .and_then(|field| field.parse::<T>().ok())
+        .ok_or_else(|| SnapshotError::InvalidRow(row_to_string(row)))
+}
+
+fn parse_side(row: &StringRecord, index: usize) -> Result<char, SnapshotError> {
+    match parse_field::<char>(row, index)? {
+        side @ ('B' | 'S') => Ok(side),
+        _ => Err(SnapshotError::InvalidRow(row_to_string(row)))
+    }
+}
+
+fn parse_time(row: &StringRecord, index: usize) -> Result<DateTime<Utc>, SnapshotError> {
+    DateTime::parse_from_rfc3339(row.get(index).unwrap_or_default())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| SnapshotError::InvalidRow(row_to_string(row)))
+}
+
+fn parse_optional_field<T: std::str::FromStr>(row: &StringRecord, index: usize) -> Result<Option<T>, SnapshotError> {
+    match row.get(index) {
+        Some("-") => Ok(None),
+        _ => parse_field::<T>(row, index).map(Some)
+    }
+}
+
+fn optional_to_field<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or("-".to_string(), |value| value.to_string())
+}
+
+fn row_to_string(row: &StringRecord) -> String {
+    row.iter().collect::<Vec<&str>>().join(", ")
+}
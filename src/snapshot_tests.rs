@@ -0,0 +1,272 @@
+//! Unit tests for saving and restoring `OrderBooks` snapshots
+
+#[cfg(test)]
+mod snapshot_tests {
+    use crate::order_books::OrderBooks;
+    use crate::self_trade_prevention::SelfTradePrevention;
+    use crate::snapshot::*;
+    use crate::peg::*;
+    use crate::order::*;
+    use crate::order_result::*;
+    use crate::trading_status::*;
+    use crate::price_band::PriceBand;
+    use crate::risk::RiskLimit;
+
+    fn snapshot_to_string(order_books: &OrderBooks) -> String {
+        let mut buffer = vec![];
+        write_snapshot(order_books, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn sample_order_books() -> OrderBooks {
+        let mut order_books = OrderBooks::new(true);
+        order_books.set_self_trade_prevention(SelfTradePrevention::CancelNewest);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+        order_books.add_order(NewOrder::new(2, "IBM".to_string(), 12, 100, 'S', 101).with_self_trade_group(7));
+        order_books.add_order(NewOrder::new(3, "IBM".to_string(), 12, 100, 'S', 201));
+        order_books.add_order(NewOrder::new(1, "AAPL".to_string(), 9, 50, 'B', 2));
+        order_books
+    }
+
+    #[test]
+    fn snapshot_format() {
+        let snapshot = snapshot_to_string(&sample_order_books());
+        let lines: Vec<&str> = snapshot.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "SNAPSHOT,1");
+        assert_eq!(lines[1], "CONFIG,true,cancel-newest,reject,continuous,-,-,1,1,1");
+        assert!(lines[2].starts_with("ORDER,AAPL,B,9,1,2,50,"));
+        assert!(lines[3].starts_with("ORDER,IBM,B,10,1,1,100,"));
+        assert!(lines[4].starts_with("ORDER,IBM,S,12,2,101,100,"));
+        assert!(lines[4].ends_with(",7,-,0,false,-"));
+        assert!(lines[5].starts_with("ORDER,IBM,S,12,3,201,100,"));
+        assert!(lines[5].ends_with(",-,-,0,false,-"));
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let order_books = sample_order_books();
+        let snapshot = snapshot_to_string(&order_books);
+
+        let restored = read_snapshot(snapshot.as_bytes()).unwrap();
+
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+        assert!(restored.trading_enabled());
+        assert_eq!(restored.self_trade_prevention(), SelfTradePrevention::CancelNewest);
+    }
+
+    #[test]
+    fn restored_books_keep_priority() {
+        let snapshot = snapshot_to_string(&sample_order_books());
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+
+        let order_results = restored.add_order(NewOrder::new(4, "IBM".to_string(), 12, 100, 'B', 301));
+
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 4, user_order_id_buy: 301, user_sell: 2, user_order_id_sell: 101, price: 12, qty: 100, .. }));
+        assert_eq!(order_results[2], OrderResult::TopOfBookChange { side: 'S', price: "12".to_string(), total_quantity: "100".to_string() });
+        assert_eq!(restored.cancel_order(CancelOrder::new(1, 2))[0], OrderResult::Acknowledgement { user: 1, user_order_id: 2 });
+    }
+
+    #[test]
+    fn stop_orders_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.add_order(NewOrder::new_stop(5, "IBM".to_string(), 12, 100, 'B', 401));
+        order_books.add_order(NewOrder::new_stop_limit(5, "IBM".to_string(), 9, 8, 50, 'S', 402).with_self_trade_group(3));
+        let snapshot = snapshot_to_string(&order_books);
+        let lines: Vec<&str> = snapshot.lines().collect();
+
+        assert_eq!(lines.len(), 8);
+        assert!(lines[6].starts_with("STOP,IBM,B,0,5,401,100,"));
+        assert!(lines[6].ends_with(",-,stop:12,-,-,false,-"));
+        assert!(lines[7].starts_with("STOP,IBM,S,8,5,402,50,"));
+        assert!(lines[7].ends_with(",3,stop-limit:9,-,-,false,-"));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+
+        let order_results = restored.add_order(NewOrder::new(4, "IBM".to_string(), 12, 100, 'B', 301));
+        assert!(order_results.contains(&OrderResult::StopTriggered { user: 5, user_order_id: 401 }));
+    }
+
+    #[test]
+    fn iceberg_orders_round_trip() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 250, 'S', 1).with_display_qty(100));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().nth(2).unwrap().starts_with("ORDER,IBM,S,10,1,1,100,"));
+        assert!(snapshot.lines().nth(2).unwrap().ends_with(",-,100,150,false,-"));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+
+        let order_results = restored.add_order(NewOrder::new(2, "IBM".to_string(), 10, 100, 'B', 101));
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 2, user_order_id_buy: 101, user_sell: 1, user_order_id_sell: 1, price: 10, qty: 100, .. }));
+        let snapshot = snapshot_to_string(&restored);
+        assert_eq!(snapshot.lines().nth(2), Some("MARKET,IBM,-,10,-"));
+        assert_eq!(snapshot.lines().nth(3), Some("POSITION,IBM,1,-100"));
+        assert_eq!(snapshot.lines().nth(4), Some("POSITION,IBM,2,100"));
+        assert!(snapshot.lines().nth(5).unwrap().ends_with(",-,100,50,false,-"));
+    }
+
+    #[test]
+    fn order_flags_round_trip() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'S', 1).with_hidden());
+        order_books.add_order(NewOrder::new_stop_limit(1, "IBM".to_string(), 12, 12, 100, 'B', 2).with_post_only(PostOnly::Reprice).with_hidden());
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().nth(2).unwrap().ends_with(",true,-"));
+        assert!(snapshot.lines().nth(3).unwrap().ends_with(",reprice,true,-"));
+        assert_eq!(snapshot_to_string(&read_snapshot(snapshot.as_bytes()).unwrap()), snapshot);
+    }
+
+    #[test]
+    fn pegged_orders_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.add_order(NewOrder::new_pegged(5, "IBM".to_string(), Peg::new(PegType::Midpoint, -1, Some(10)), 100, 'B', 401));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().any(|line| line.starts_with("ORDER,IBM,B,10,5,401,100,") && line.ends_with(",midpoint:-1:10")));
+        assert_eq!(snapshot_to_string(&read_snapshot(snapshot.as_bytes()).unwrap()), snapshot);
+    }
+
+    #[test]
+    fn auction_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.auction(AuctionOrder::start("IBM"));
+        order_books.add_order(NewOrder::new(4, "IBM".to_string(), 12, 50, 'B', 301));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().any(|line| line == "STATUS,IBM,auction"));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+        assert!(restored.get_order_book("IBM").unwrap().is_in_auction());
+        assert!(!restored.get_order_book("AAPL").unwrap().is_in_auction());
+
+        let order_results = restored.auction(AuctionOrder::uncross("IBM"));
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 4, user_order_id_buy: 301, user_sell: 2, user_order_id_sell: 101, price: 12, qty: 50, .. }));
+    }
+
+    #[test]
+    fn trading_status_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.set_halt_policy(HaltPolicy::Queue);
+        order_books.set_trading_status(TradingStatusOrder::all(TradingStatus::Halted));
+        order_books.set_trading_status(TradingStatusOrder::new(Some("AAPL"), TradingStatus::Closed));
+        order_books.add_order(NewOrder::new(4, "IBM".to_string(), 12, 100, 'B', 301));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().any(|line| line == "CONFIG,true,cancel-newest,queue,halted,-,-,1,1,1"));
+        assert!(snapshot.lines().any(|line| line == "STATUS,AAPL,closed"));
+        assert!(snapshot.lines().any(|line| line.starts_with("QUEUED,IBM,B,12,4,301,100,")));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+
+        let order_results = restored.set_trading_status(TradingStatusOrder::new(Some("IBM"), TradingStatus::Continuous));
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 4, user_order_id_buy: 301, user_sell: 2, user_order_id_sell: 101, price: 12, qty: 100, .. }));
+    }
+
+    #[test]
+    fn price_band_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.set_default_price_band(Some(PriceBand::new(20)));
+        order_books.set_circuit_breaker(Some("10:60:300:auction".parse().unwrap()));
+        order_books.set_price_band(PriceBandOrder::new(Some("AAPL"), Some(PriceBand::new(5).with_reference_price(9))));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().any(|line| line == "CONFIG,true,cancel-newest,reject,continuous,20:-,10:60:300:auction,1,1,1"));
+        assert!(snapshot.lines().any(|line| line == "MARKET,AAPL,5:9,-,-"));
+
+        let restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+        assert_eq!(restored.get_order_book("IBM").unwrap().price_band(), Some(PriceBand::new(20)));
+    }
+
+    #[test]
+    fn risk_limits_and_positions_round_trip() {
+        let mut order_books = sample_order_books();
+        order_books.set_risk_limit(RiskLimitOrder::new(None, RiskLimit::MaxOrderQty, Some(1000)));
+        order_books.set_risk_limit(RiskLimitOrder::new(Some(1), RiskLimit::MaxOpenOrders, Some(5)));
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 12, 100, 'B', 3));
+        let snapshot = snapshot_to_string(&order_books);
+
+        assert!(snapshot.lines().any(|line| line == "RISK,-,max-order-qty,1000"));
+        assert!(snapshot.lines().any(|line| line == "RISK,1,max-open-orders,5"));
+        assert!(snapshot.lines().any(|line| line == "POSITION,IBM,1,100"));
+        assert!(snapshot.lines().any(|line| line == "POSITION,IBM,2,-100"));
+
+        let restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+        assert_eq!(restored.risk_manager().limits(1).get(RiskLimit::MaxOpenOrders), Some(5));
+        assert_eq!(restored.get_order_book("IBM").unwrap().position(2), -100);
+    }
+
+    #[test]
+    fn trade_ids_continue_after_restore() {
+        let mut order_books = sample_order_books();
+        order_books.add_order(NewOrder::new(4, "IBM".to_string(), 12, 100, 'B', 301));
+        let snapshot = snapshot_to_string(&order_books);
+        assert!(snapshot.lines().nth(1).unwrap().ends_with(",2,1,1"));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        let order_results = restored.add_order(NewOrder::new(4, "IBM".to_string(), 12, 100, 'B', 302));
+        assert!(matches!(&order_results[1], OrderResult::Trade { trade_id: 2, user_order_id_sell: 201, .. }));
+    }
+
+    #[test]
+    fn order_statuses_round_trip() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::New(NewOrder::new(2, "IBM".to_string(), 10, 100, 'S', 1)));
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 9, 50, 'B', 2)));
+        let snapshot = snapshot_to_string(&order_books);
+        assert!(snapshot.lines().nth(3).unwrap().starts_with("ORDER_STATUS,1,1,IBM,B,filled,100,100,0,10,"));
+
+        let restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        assert_eq!(restored.order_status(1, 2), order_books.order_status(1, 2));
+        assert_eq!(snapshot_to_string(&restored), snapshot);
+    }
+
+    #[test]
+    fn empty_snapshot() {
+        let snapshot = snapshot_to_string(&OrderBooks::new(false));
+
+        let restored = read_snapshot(snapshot.as_bytes()).unwrap();
+
+        assert!(restored.symbols().is_empty());
+        assert!(!restored.trading_enabled());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let result = read_snapshot("SNAPSHOT,2\nCONFIG,true,allow\n".as_bytes());
+
+        assert!(matches!(result, Err(SnapshotError::UnsupportedVersion(version)) if version == "2"));
+    }
+
+    #[test]
+    fn invalid_order_row() {
+        let result = read_snapshot("SNAPSHOT,1\nCONFIG,true,allow,reject,continuous,-,-,1,1,1\nORDER,IBM,X,10,1,1,100,2022-01-01T00:00:00Z,-\n".as_bytes());
+
+        assert!(matches!(result, Err(SnapshotError::InvalidRow(_))));
+    }
+
+    #[test]
+    fn sequences_continue_after_restore() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1)));
+        order_books.process(InputMessage::New(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2)));
+        let snapshot = snapshot_to_string(&order_books);
+        assert_eq!(snapshot.lines().nth(1), Some("CONFIG,true,allow,reject,continuous,-,-,1,5,3"));
+        assert_eq!(snapshot.lines().nth(2), Some("SEQUENCE,AAPL,3"));
+        assert_eq!(snapshot.lines().nth(3), Some("SEQUENCE,IBM,3"));
+
+        let mut restored = read_snapshot(snapshot.as_bytes()).unwrap();
+        let order_results = restored.process(InputMessage::New(NewOrder::new(1, "IBM".to_string(), 9, 100, 'B', 3)));
+        assert_eq!((order_results[0].sequence, order_results[0].symbol_sequence, order_results[0].input_sequence), (5, Some(3), 3));
+    }
+}
@@ -0,0 +1,78 @@
+//! The trading status of a symbol through its trading session, which decides whether its
+//! `OrderBook` accepts, queues, collects or rejects new orders
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The phase of the trading session a symbol is in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TradingStatus {
+    /// Before the book opens, when new orders are queued until it moves to an auction or continuous
+    /// trading
+    PreOpen,
+    /// A call auction collecting orders without matching them
+    Auction,
+    /// Normal trading, where new orders match as they arrive
+    Continuous,
+    /// Trading is suspended; new orders are rejected or queued according to the `HaltPolicy`
+    Halted,
+    /// The session is over and new orders are rejected
+    Closed
+}
+
+impl FromStr for TradingStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pre-open" => Ok(Self::PreOpen),
+            "auction" => Ok(Self::Auction),
+            "continuous" => Ok(Self::Continuous),
+            "halted" => Ok(Self::Halted),
+            "closed" => Ok(Self::Closed),
+            _ => Err(format!("Unknown trading status: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for TradingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PreOpen => write!(f, "pre-open"),
+            Self::Auction => write!(f, "auction"),
+            Self::Continuous => write!(f, "continuous"),
+            Self::Halted => write!(f, "halted"),
+            Self::Closed => write!(f, "closed")
+        }
+    }
+}
+
+/// What a halted `OrderBook` does with new orders. Cancels are accepted either way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HaltPolicy {
+    /// Reject new orders while halted
+    Reject,
+    /// Acknowledge new orders and hold them until trading resumes
+    Queue
+}
+
+impl FromStr for HaltPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "queue" => Ok(Self::Queue),
+            _ => Err(format!("Unknown halt policy: \"{}\"", s))
+        }
+    }
+}
+
+impl fmt::Display for HaltPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reject => write!(f, "reject"),
+            Self::Queue => write!(f, "queue")
+        }
+    }
+}
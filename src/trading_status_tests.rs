@@ -0,0 +1,124 @@
+//! Unit tests for trading statuses in `OrderBook` and `OrderBooks`
+
+#[cfg(test)]
+mod trading_status_tests {
+    use crate::trading_status::*;
+    use crate::order_book::OrderBook;
+    use crate::order_books::OrderBooks;
+    use crate::order::*;
+    use crate::order_result::*;
+    use chrono::Utc;
+
+    fn status_change(status: TradingStatus) -> OrderResult {
+        OrderResult::TradingStatusChange { status }
+    }
+
+    #[test]
+    fn parse_status_and_policy() {
+        assert_eq!("pre-open".parse::<TradingStatus>(), Ok(TradingStatus::PreOpen));
+        assert_eq!("halted".parse::<TradingStatus>(), Ok(TradingStatus::Halted));
+        assert_eq!(TradingStatus::Continuous.to_string(), "continuous");
+        assert!("open".parse::<TradingStatus>().is_err());
+        assert_eq!("queue".parse::<HaltPolicy>(), Ok(HaltPolicy::Queue));
+        assert!("drop".parse::<HaltPolicy>().is_err());
+    }
+
+    #[test]
+    fn status_change_published_once() {
+        let mut order_book = OrderBook::new("AAPL", true);
+
+        assert_eq!(order_book.set_trading_status(TradingStatus::Halted, Utc::now()), vec![status_change(TradingStatus::Halted)]);
+        assert_eq!(order_book.set_trading_status(TradingStatus::Halted, Utc::now()), vec![]);
+        assert_eq!(order_book.trading_status(), TradingStatus::Halted);
+    }
+
+    #[test]
+    fn halted_book_rejects_orders_but_accepts_cancels() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+        order_book.set_trading_status(TradingStatus::Halted, Utc::now());
+
+        let order_results = order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'S', 2));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 2, user_order_id: 2 }]);
+
+        let order_results = order_book.cancel_order(&CancelOrder::new(1, 1));
+        assert_eq!(order_results[0], OrderResult::Acknowledgement { user: 1, user_order_id: 1 });
+    }
+
+    #[test]
+    fn halted_book_queues_orders_until_resumed() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_halt_policy(HaltPolicy::Queue);
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+        order_book.set_trading_status(TradingStatus::Halted, Utc::now());
+
+        let order_results = order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'S', 2));
+        assert_eq!(order_results, vec![OrderResult::Acknowledgement { user: 2, user_order_id: 2 }]);
+        order_book.add_order(NewOrder::new(3, "AAPL".to_string(), 11, 100, 'S', 3));
+        order_book.cancel_order(&CancelOrder::new(3, 3));
+
+        let time = Utc::now();
+        let order_results = order_book.set_trading_status(TradingStatus::Continuous, time);
+        assert_eq!(order_results, vec![
+            status_change(TradingStatus::Continuous),
+            OrderResult::Trade { trade_id: 0, symbol: "AAPL".to_string(), user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 100, aggressor_side: Some('S'), time },
+            OrderResult::TopOfBookChange { side: 'B', price: "-".to_string(), total_quantity: "-".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn pre_open_orders_enter_the_opening_auction() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_trading_status(TradingStatus::PreOpen, Utc::now());
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 11, 100, 'B', 1));
+        order_book.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'S', 2));
+        assert!(order_book.resting_orders('B').is_empty());
+
+        let order_results = order_book.set_trading_status(TradingStatus::Auction, Utc::now());
+        assert_eq!(order_results[0], status_change(TradingStatus::Auction));
+        assert_eq!(order_results.last(), Some(&OrderResult::AuctionIndicative { price: Some(10), matched_qty: 100, imbalance_qty: 0, imbalance_side: None }));
+
+        let order_results = order_book.set_trading_status(TradingStatus::Continuous, Utc::now());
+        assert!(matches!(&order_results[1], OrderResult::Trade { user_buy: 1, user_order_id_buy: 1, user_sell: 2, user_order_id_sell: 2, price: 10, qty: 100, .. }));
+    }
+
+    #[test]
+    fn closing_cancels_queued_orders() {
+        let mut order_book = OrderBook::new("AAPL", true);
+        order_book.set_trading_status(TradingStatus::PreOpen, Utc::now());
+        order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 1));
+
+        let order_results = order_book.set_trading_status(TradingStatus::Closed, Utc::now());
+        assert_eq!(order_results, vec![
+            status_change(TradingStatus::Closed),
+            OrderResult::Cancellation { user: 1, user_order_id: 1 }
+        ]);
+
+        let order_results = order_book.add_order(NewOrder::new(1, "AAPL".to_string(), 10, 100, 'B', 2));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 1, user_order_id: 2 }]);
+    }
+
+    #[test]
+    fn session_status_applies_to_new_books() {
+        let mut order_books = OrderBooks::new(true);
+        order_books.add_order(NewOrder::new(1, "IBM".to_string(), 10, 100, 'B', 1));
+
+        let results = order_books.process(InputMessage::TradingStatus(TradingStatusOrder::all(TradingStatus::Closed)));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].symbol, Some("IBM".to_string()));
+        assert_eq!(results[0].order_result, status_change(TradingStatus::Closed));
+
+        let order_results = order_books.add_order(NewOrder::new(2, "AAPL".to_string(), 10, 100, 'B', 2));
+        assert_eq!(order_results, vec![OrderResult::Rejection { user: 2, user_order_id: 2 }]);
+    }
+
+    #[test]
+    fn halted_book_survives_flush() {
+        let mut order_books = OrderBooks::new(true);
+
+        order_books.set_trading_status(TradingStatusOrder::new(Some("IBM"), TradingStatus::Halted));
+        order_books.flush(FlushOrder::all());
+
+        assert_eq!(order_books.get_order_book("IBM").unwrap().trading_status(), TradingStatus::Halted);
+    }
+}
@@ -0,0 +1,470 @@
+//! Publishes the binary market data feed in `itch` over UDP, to a multicast group or a unicast
+//! address, in sequenced packets modelled on MoldUDP64. A TCP replay service lets a subscriber which
+//! joined late or missed packets ask for ranges of messages again, or for a snapshot of the books.
+//!
+//! Every message published is numbered from 1 by the feed, separately from the result sequence in
+//! its header, which skips results left out of the feed. A packet is the feed sequence number of its
+//! first message (u64) and a count of messages (u16), followed by each message framed by its two
+//! byte big-endian length. A packet of no messages is a heartbeat, holding the sequence number of the
+//! next message to be published.
+//!
+//! Requests to the replay service, and its responses, are framed by a two byte big-endian length:
+//! ```text
+//! 'R' Retransmit  first sequence u64, count u16       answered with 'P' frames then 'E'
+//! 'S' Snapshot                                         answered with 'M' frames then 'E'
+//! 'P' Packet      a packet of retransmitted messages
+//! 'M' Message     a message of the snapshot
+//! 'E' End         the sequence number of the next message to be published (u64)
+//! ```
+
+use std::{collections::BTreeMap, convert::TryInto, io::{self, Write}, net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket}, ops::Range, sync::{Arc, Mutex}, thread};
+
+use crate::{feed_book::FeedBooks, itch::{FeedError, FeedMessage, read_payload, write_payload}};
+
+/// The most bytes in one packet, so that a packet fits in a typical Ethernet frame
+pub const MAX_PACKET_LENGTH: usize = 1400;
+/// The most messages one retransmit request can ask for
+pub const MAX_RETRANSMIT_COUNT: u16 = 1000;
+/// The length of the header every packet starts with
+const PACKET_HEADER_LENGTH: usize = 10;
+
+/// A sequenced packet of feed messages
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    /// The feed sequence number of the first message, or of the next message to be published for a
+    /// heartbeat
+    pub sequence: u64,
+    pub messages: Vec<FeedMessage>
+}
+
+impl Packet {
+    pub fn heartbeat(sequence: u64) -> Packet {
+        Packet { sequence, messages: vec![] }
+    }
+
+    /// `messages` numbered from `sequence`, split into as few packets as fit in `MAX_PACKET_LENGTH`,
+    /// failing if a message cannot be encoded
+    pub fn split(sequence: u64, messages: &[FeedMessage]) -> Result<Vec<Packet>, FeedError> {
+        let mut packets: Vec<Packet> = vec![];
+        let mut length = MAX_PACKET_LENGTH;
+        for (message_sequence, message) in (sequence..).zip(messages) {
+            let message_length = 2 + message.encode()?.len();
+            match packets.last_mut() {
+                Some(packet) if length + message_length <= MAX_PACKET_LENGTH => packet.messages.push(message.clone()),
+                _ => {
+                    packets.push(Packet { sequence: message_sequence, messages: vec![message.clone()] });
+                    length = PACKET_HEADER_LENGTH;
+                }
+            }
+            length += message_length;
+        }
+        Ok(packets)
+    }
+
+    /// Each message with its feed sequence number
+    pub fn sequenced(&self) -> impl Iterator<Item = (u64, &FeedMessage)> {
+        (self.sequence..).zip(self.messages.iter())
+    }
+
+    /// The sequence number following the last message of the packet
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence + self.messages.len() as u64
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, FeedError> {
+        let mut bytes = self.sequence.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&(self.messages.len() as u16).to_be_bytes());
+        for message in &self.messages {
+            let payload = message.encode()?;
+            bytes.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&payload);
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Packet, FeedError> {
+        if bytes.len() < PACKET_HEADER_LENGTH {
+            return Err(FeedError::InvalidPacket(format!("{} bytes is shorter than a header", bytes.len())));
+        }
+        let sequence = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let count = u16::from_be_bytes(bytes[8..10].try_into().unwrap());
+        let mut messages = vec![];
+        let mut offset = PACKET_HEADER_LENGTH;
+        for _ in 0..count {
+            let length = bytes.get(offset..offset + 2).map(|length| u16::from_be_bytes(length.try_into().unwrap()) as usize);
+            let payload = length.and_then(|length| bytes.get(offset + 2..offset + 2 + length))
+                .ok_or_else(|| FeedError::InvalidPacket(format!("truncated after {} of {} messages", messages.len(), count)))?;
+            messages.push(FeedMessage::decode(payload)?);
+            offset += 2 + payload.len();
+        }
+        if offset != bytes.len() {
+            return Err(FeedError::InvalidPacket(format!("{} bytes after {} messages", bytes.len() - offset, count)));
+        }
+        Ok(Packet { sequence, messages })
+    }
+}
+
+/// Every message published on a feed, numbered from 1, and the books they build. It is shared by
+/// a publisher and the replay service answering for it.
+#[derive(Debug, Default)]
+pub struct FeedHistory {
+    messages: Vec<FeedMessage>,
+    books: FeedBooks
+}
+
+impl FeedHistory {
+    pub fn new() -> FeedHistory {
+        FeedHistory { messages: vec![], books: FeedBooks::new() }
+    }
+
+    /// The sequence number of the next message to be published
+    pub fn next_sequence(&self) -> u64 {
+        self.messages.len() as u64 + 1
+    }
+
+    /// Add `messages` to the history, returning the sequence number of the first
+    pub fn append(&mut self, messages: &[FeedMessage]) -> u64 {
+        let sequence = self.next_sequence();
+        for message in messages {
+            self.books.apply(message);
+            self.messages.push(message.clone());
+        }
+        sequence
+    }
+
+    /// The messages numbered `range` which have been published
+    pub fn messages(&self, range: Range<u64>) -> &[FeedMessage] {
+        let end = (range.end.saturating_sub(1) as usize).min(self.messages.len());
+        let start = (range.start.saturating_sub(1) as usize).min(end);
+        &self.messages[start..end]
+    }
+
+    /// The books built by every message published
+    pub fn books(&self) -> &FeedBooks {
+        &self.books
+    }
+}
+
+/// Sends feed messages over UDP in sequenced packets, keeping them in a `FeedHistory` for the
+/// replay service
+pub struct UdpPublisher {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    history: Arc<Mutex<FeedHistory>>,
+    /// When set, every packet whose number is a multiple of this is not sent
+    drop_every: Option<u64>,
+    packets_sent: u64
+}
+
+impl UdpPublisher {
+    /// A publisher sending to `destination`, a multicast group or a unicast address such as one on
+    /// loopback
+    pub fn new(destination: SocketAddr) -> io::Result<UdpPublisher> {
+        let socket = UdpSocket::bind(if destination.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        Ok(UdpPublisher { socket, destination, history: Arc::new(Mutex::new(FeedHistory::new())), drop_every: None, packets_sent: 0 })
+    }
+
+    /// Leave out every `drop_every`th packet, so that the gap recovery of subscribers can be tried
+    /// out locally. Dropped packets are still kept for retransmission.
+    pub fn with_dropped_packets(mut self, drop_every: u64) -> UdpPublisher {
+        self.drop_every = Some(drop_every).filter(|drop_every| *drop_every > 0);
+        self
+    }
+
+    /// The history to serve replay requests from
+    pub fn history(&self) -> Arc<Mutex<FeedHistory>> {
+        Arc::clone(&self.history)
+    }
+
+    /// Number `messages` and send them. Nothing is published if any of them cannot be encoded.
+    pub fn publish(&mut self, messages: &[FeedMessage]) -> Result<(), FeedError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let packets = {
+            let mut history = self.history.lock().unwrap();
+            let packets = Packet::split(history.next_sequence(), messages)?;
+            history.append(messages);
+            packets
+        };
+        for packet in packets {
+            self.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Send a heartbeat, which lets subscribers notice messages missed at the end of the feed
+    pub fn heartbeat(&mut self) -> Result<(), FeedError> {
+        let sequence = self.history.lock().unwrap().next_sequence();
+        self.send(&Packet::heartbeat(sequence))
+    }
+
+    fn send(&mut self, packet: &Packet) -> Result<(), FeedError> {
+        self.packets_sent += 1;
+        if self.drop_every.map_or(false, |drop_every| self.packets_sent % drop_every == 0) {
+            return Ok(());
+        }
+        self.socket.send_to(&packet.encode()?, self.destination)?;
+        Ok(())
+    }
+}
+
+/// A socket receiving the packets sent to `address`, joining it on every interface if it is a
+/// multicast group
+pub fn subscribe(address: SocketAddr) -> io::Result<UdpSocket> {
+    match address {
+        SocketAddr::V4(address_v4) if address_v4.ip().is_multicast() => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, address_v4.port()))?;
+            socket.join_multicast_v4(address_v4.ip(), &Ipv4Addr::UNSPECIFIED)?;
+            Ok(socket)
+        },
+        SocketAddr::V6(address_v6) if address_v6.ip().is_multicast() => {
+            let socket = UdpSocket::bind(("::", address_v6.port()))?;
+            socket.join_multicast_v6(address_v6.ip(), 0)?;
+            Ok(socket)
+        },
+        _ => UdpSocket::bind(address)
+    }
+}
+
+/// A request to the replay service
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayRequest {
+    /// Send the `count` messages from `sequence` again, or as many of them as have been published
+    Retransmit { sequence: u64, count: u16 },
+    /// Send the books built by every message published so far
+    Snapshot
+}
+
+impl ReplayRequest {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Retransmit { sequence, count } => {
+                let mut bytes = vec![b'R'];
+                bytes.extend_from_slice(&sequence.to_be_bytes());
+                bytes.extend_from_slice(&count.to_be_bytes());
+                bytes
+            },
+            Self::Snapshot => vec![b'S']
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<ReplayRequest, FeedError> {
+        match bytes {
+            [b'R', rest @ ..] if rest.len() == 10 => Ok(Self::Retransmit { sequence: u64::from_be_bytes(rest[..8].try_into().unwrap()), count: u16::from_be_bytes(rest[8..].try_into().unwrap()) }),
+            [b'S'] => Ok(Self::Snapshot),
+            [message_type, ..] if matches!(message_type, b'R' | b'S') => Err(FeedError::InvalidLength(*message_type, bytes.len())),
+            [message_type, ..] => Err(FeedError::UnknownType(*message_type)),
+            [] => Err(FeedError::InvalidLength(0, 0))
+        }
+    }
+}
+
+/// A response from the replay service
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayResponse {
+    Packet(Packet),
+    SnapshotMessage(FeedMessage),
+    /// The end of a response, holding the sequence number of the next message to be published
+    End { next_sequence: u64 }
+}
+
+impl ReplayResponse {
+    pub fn encode(&self) -> Result<Vec<u8>, FeedError> {
+        let (response_type, mut payload) = match self {
+            Self::Packet(packet) => (b'P', packet.encode()?),
+            Self::SnapshotMessage(message) => (b'M', message.encode()?),
+            Self::End { next_sequence } => (b'E', next_sequence.to_be_bytes().to_vec())
+        };
+        payload.insert(0, response_type);
+        Ok(payload)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<ReplayResponse, FeedError> {
+        match bytes {
+            [b'P', packet @ ..] => Ok(Self::Packet(Packet::decode(packet)?)),
+            [b'M', message @ ..] => Ok(Self::SnapshotMessage(FeedMessage::decode(message)?)),
+            [b'E', next_sequence @ ..] => Ok(Self::End { next_sequence: u64::from_be_bytes(next_sequence.try_into().map_err(|_| FeedError::InvalidLength(b'E', bytes.len()))?) }),
+            [response_type, ..] => Err(FeedError::UnknownType(*response_type)),
+            [] => Err(FeedError::InvalidLength(0, 0))
+        }
+    }
+}
+
+/// Answer replay requests on `listener` from `history` until it fails, each connection on its own
+/// thread
+pub fn serve_replay(listener: TcpListener, history: Arc<Mutex<FeedHistory>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let history = Arc::clone(&history);
+        thread::Builder::new().name("replay_thread".to_string()).spawn(move || {
+            if let Err(e) = replay_func(stream, &history) {
+                eprintln!("Replay connection: {}", e);
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Answer requests from `stream` until it closes. A request which cannot be decoded closes the
+/// connection.
+fn replay_func(mut stream: TcpStream, history: &Mutex<FeedHistory>) -> io::Result<()> {
+    while let Some(payload) = read_payload(&mut stream)? {
+        let invalid_data = |e: FeedError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+        let request = ReplayRequest::decode(&payload).map_err(invalid_data)?;
+        let mut responses = vec![];
+        let next_sequence = {
+            let history = history.lock().unwrap();
+            match request {
+                ReplayRequest::Retransmit { sequence, count } => {
+                    let sequence = sequence.max(1);
+                    let count = count.min(MAX_RETRANSMIT_COUNT) as u64;
+                    responses.extend(Packet::split(sequence, history.messages(sequence..sequence + count)).map_err(invalid_data)?.into_iter().map(ReplayResponse::Packet));
+                },
+                ReplayRequest::Snapshot => responses.extend(history.books().snapshot().into_iter().map(ReplayResponse::SnapshotMessage))
+            }
+            history.next_sequence()
+        };
+        responses.push(ReplayResponse::End { next_sequence });
+
+        let mut bytes = vec![];
+        for response in responses {
+            write_payload(&mut bytes, &response.encode().map_err(invalid_data)?)?;
+        }
+        stream.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+/// A connection to the replay service
+pub struct ReplayClient {
+    stream: TcpStream
+}
+
+impl ReplayClient {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<ReplayClient> {
+        Ok(ReplayClient { stream: TcpStream::connect(address)? })
+    }
+
+    /// The messages numbered `range` which have been published, each with its sequence number
+    pub fn retransmit(&mut self, range: Range<u64>) -> Result<Vec<(u64, FeedMessage)>, FeedError> {
+        let mut messages = vec![];
+        let mut sequence = range.start;
+        while sequence < range.end {
+            let count = (range.end - sequence).min(MAX_RETRANSMIT_COUNT as u64) as u16;
+            let (responses, next_sequence) = self.request(&ReplayRequest::Retransmit { sequence, count })?;
+            let received = messages.len();
+            for response in responses {
+                match response {
+                    ReplayResponse::Packet(packet) => messages.extend(packet.sequenced().map(|(sequence, message)| (sequence, message.clone()))),
+                    _ => return Err(FeedError::InvalidPacket("snapshot message in a retransmission".to_string()))
+                }
+            }
+            sequence = messages.last().map_or(sequence, |(sequence, _)| sequence + 1);
+            if sequence >= next_sequence || messages.len() == received {
+                break;
+            }
+        }
+        Ok(messages)
+    }
+
+    /// A snapshot of the books, and the sequence number of the first message not reflected in it
+    pub fn snapshot(&mut self) -> Result<(Vec<FeedMessage>, u64), FeedError> {
+        let (responses, next_sequence) = self.request(&ReplayRequest::Snapshot)?;
+        let snapshot = responses.into_iter().map(|response| match response {
+            ReplayResponse::SnapshotMessage(message) => Ok(message),
+            _ => Err(FeedError::InvalidPacket("packet in a snapshot".to_string()))
+        }).collect::<Result<Vec<FeedMessage>, FeedError>>()?;
+        Ok((snapshot, next_sequence))
+    }
+
+    /// Send `request`, returning the responses up to the end and the sequence number the end holds
+    fn request(&mut self, request: &ReplayRequest) -> Result<(Vec<ReplayResponse>, u64), FeedError> {
+        write_payload(&mut self.stream, &request.encode())?;
+        let mut responses = vec![];
+        loop {
+            let payload = read_payload(&mut self.stream)?.ok_or_else(|| FeedError::InvalidPacket("replay service closed the connection".to_string()))?;
+            match ReplayResponse::decode(&payload)? {
+                ReplayResponse::End { next_sequence } => return Ok((responses, next_sequence)),
+                response => responses.push(response)
+            }
+        }
+    }
+}
+
+/// Rebuilds books from packets, applying messages strictly in sequence. Messages which arrive after
+/// a gap are held until the gap is filled by retransmission or skipped by a snapshot.
+#[derive(Debug)]
+pub struct FeedSubscriber {
+    books: FeedBooks,
+    next_sequence: u64,
+    /// One past the highest sequence number known to have been published
+    known_end: u64,
+    pending: BTreeMap<u64, FeedMessage>
+}
+
+impl Default for FeedSubscriber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeedSubscriber {
+    pub fn new() -> FeedSubscriber {
+        FeedSubscriber { books: FeedBooks::new(), next_sequence: 1, known_end: 1, pending: BTreeMap::new() }
+    }
+
+    pub fn books(&self) -> &FeedBooks {
+        &self.books
+    }
+
+    /// The sequence number of the next message to apply
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// The sequence numbers missed before the next message held or the end of the feed, if any
+    pub fn gap(&self) -> Option<Range<u64>> {
+        let end = self.pending.keys().next().copied().unwrap_or(self.known_end);
+        Some(self.next_sequence..end).filter(|gap| !gap.is_empty())
+    }
+
+    /// Take the messages of `packet`, returning those applied, in sequence
+    pub fn receive(&mut self, packet: &Packet) -> Vec<FeedMessage> {
+        self.known_end = self.known_end.max(packet.next_sequence());
+        self.hold(packet.sequenced().map(|(sequence, message)| (sequence, message.clone())))
+    }
+
+    /// Take retransmitted messages, returning those applied, in sequence
+    pub fn recover(&mut self, messages: Vec<(u64, FeedMessage)>) -> Vec<FeedMessage> {
+        if let Some((sequence, _)) = messages.last() {
+            self.known_end = self.known_end.max(sequence + 1);
+        }
+        self.hold(messages.into_iter())
+    }
+
+    /// Replace the books with `snapshot`, which reflects every message before `next_sequence`, and
+    /// apply the messages held after it, returning those applied
+    pub fn restore(&mut self, snapshot: &[FeedMessage], next_sequence: u64) -> Vec<FeedMessage> {
+        self.books = FeedBooks::from_snapshot(snapshot);
+        self.next_sequence = next_sequence;
+        self.known_end = self.known_end.max(next_sequence);
+        self.pending = self.pending.split_off(&next_sequence);
+        self.hold(std::iter::empty())
+    }
+
+    fn hold<I: Iterator<Item = (u64, FeedMessage)>>(&mut self, messages: I) -> Vec<FeedMessage> {
+        for (sequence, message) in messages {
+            if sequence >= self.next_sequence {
+                self.pending.insert(sequence, message);
+            }
+        }
+        let mut applied = vec![];
+        while let Some(message) = self.pending.remove(&self.next_sequence) {
+            self.books.apply(&message);
+            applied.push(message);
+            self.next_sequence += 1;
+        }
+        applied
+    }
+}
@@ -0,0 +1,148 @@
+//! Unit tests for the UDP market data feed, its replay service and gap recovery
+
+#[cfg(test)]
+mod udp_feed_tests {
+    use std::{net::TcpListener, thread, time::Duration};
+
+    use chrono::{DateTime, Utc};
+
+    use crate::itch::*;
+    use crate::udp_feed::*;
+
+    /// `count` orders adding to IBM, each with the next sequence number
+    fn add_orders(count: u64) -> Vec<FeedMessage> {
+        (1..=count).map(|user_order_id| FeedMessage {
+            sequence: user_order_id,
+            time: "2022-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap(),
+            symbol: Some("IBM".to_string()),
+            body: FeedBody::AddOrder { user: 1, user_order_id, side: 'B', qty: 100, price: Some(10) }
+        }).collect()
+    }
+
+    #[test]
+    fn packets() {
+        let messages = add_orders(50);
+        let packets = Packet::split(7, &messages).unwrap();
+
+        assert_eq!(packets.len(), 3);
+        assert!(packets.iter().all(|packet| packet.encode().unwrap().len() <= MAX_PACKET_LENGTH));
+        assert_eq!(packets.iter().map(|packet| (packet.sequence, packet.messages.len())).collect::<Vec<(u64, usize)>>(), vec![(7, 23), (30, 23), (53, 4)]);
+        for packet in packets {
+            assert_eq!(Packet::decode(&packet.encode().unwrap()).unwrap(), packet);
+        }
+        assert_eq!(Packet::decode(&Packet::heartbeat(57).encode().unwrap()).unwrap(), Packet::heartbeat(57));
+
+        let encoded = Packet::split(1, &messages[..2]).unwrap().remove(0).encode().unwrap();
+        assert!(matches!(Packet::decode(&encoded[..encoded.len() - 1]), Err(FeedError::InvalidPacket(_))));
+        assert!(matches!(Packet::decode(&encoded[..9]), Err(FeedError::InvalidPacket(_))));
+    }
+
+    #[test]
+    fn replay_messages() {
+        let requests = vec![ReplayRequest::Retransmit { sequence: 3, count: 2 }, ReplayRequest::Snapshot];
+        for request in requests {
+            assert_eq!(ReplayRequest::decode(&request.encode()).unwrap(), request);
+        }
+        let responses = vec![
+            ReplayResponse::Packet(Packet { sequence: 3, messages: add_orders(2) }),
+            ReplayResponse::SnapshotMessage(add_orders(1).remove(0)),
+            ReplayResponse::End { next_sequence: 5 }
+        ];
+        for response in responses {
+            assert_eq!(ReplayResponse::decode(&response.encode().unwrap()).unwrap(), response);
+        }
+        assert!(matches!(ReplayRequest::decode(b"R1"), Err(FeedError::InvalidLength(b'R', 2))));
+        assert!(matches!(ReplayResponse::decode(b"Z"), Err(FeedError::UnknownType(b'Z'))));
+    }
+
+    #[test]
+    fn history() {
+        let mut history = FeedHistory::new();
+        assert_eq!(history.append(&add_orders(3)), 1);
+        assert_eq!(history.append(&add_orders(2)), 4);
+
+        assert_eq!(history.next_sequence(), 6);
+        assert_eq!(history.messages(2..4), &add_orders(3)[1..3]);
+        assert_eq!(history.messages(5..100).len(), 1);
+        assert!(history.messages(9..12).is_empty());
+        assert_eq!(history.books().book("IBM").unwrap().orders().count(), 3);
+    }
+
+    #[test]
+    fn subscriber_holds_messages_after_a_gap() {
+        let messages = add_orders(6);
+        let mut subscriber = FeedSubscriber::new();
+        assert_eq!(subscriber.receive(&Packet { sequence: 1, messages: messages[..2].to_vec() }).len(), 2);
+        assert_eq!(subscriber.receive(&Packet { sequence: 5, messages: messages[4..].to_vec() }), vec![]);
+        assert_eq!(subscriber.gap(), Some(3..5));
+
+        let recovered = subscriber.recover(vec![(3, messages[2].clone()), (4, messages[3].clone())]);
+        assert_eq!(recovered, messages[2..].to_vec());
+        assert_eq!((subscriber.gap(), subscriber.next_sequence()), (None, 7));
+
+        subscriber.receive(&Packet::heartbeat(9));
+        assert_eq!(subscriber.gap(), Some(7..9));
+        subscriber.receive(&Packet { sequence: 9, messages: add_orders(1) });
+        assert_eq!(subscriber.gap(), Some(7..9));
+    }
+
+    #[test]
+    fn subscriber_restores_a_snapshot() {
+        let mut history = FeedHistory::new();
+        history.append(&add_orders(4));
+        let mut subscriber = FeedSubscriber::new();
+        subscriber.receive(&Packet { sequence: 5, messages: vec![add_orders(5).remove(4)] });
+        subscriber.receive(&Packet { sequence: 3, messages: vec![add_orders(3).remove(2)] });
+        history.append(&[add_orders(5).remove(4)]);
+
+        let applied = subscriber.restore(&history.books().snapshot()[..4], 5);
+        assert_eq!(applied.len(), 1);
+        assert_eq!((subscriber.gap(), subscriber.next_sequence()), (None, 6));
+        assert_eq!(subscriber.books(), history.books());
+    }
+
+    #[test]
+    fn unencodable_message_not_published() {
+        let socket = subscribe("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut publisher = UdpPublisher::new(socket.local_addr().unwrap()).unwrap();
+        let mut messages = add_orders(2);
+        messages[1].time = "1969-12-31T23:59:59Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert!(matches!(publisher.publish(&messages), Err(FeedError::InvalidField(_))));
+        assert_eq!(publisher.history().lock().unwrap().next_sequence(), 1);
+    }
+
+    #[test]
+    fn recovery_over_localhost() {
+        let socket = subscribe("127.0.0.1:0".parse().unwrap()).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut publisher = UdpPublisher::new(socket.local_addr().unwrap()).unwrap().with_dropped_packets(2);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let replay_address = listener.local_addr().unwrap();
+        let history = publisher.history();
+        thread::spawn(move || serve_replay(listener, history));
+
+        let messages = add_orders(60);
+        for chunk in messages.chunks(10) {
+            publisher.publish(chunk).unwrap();
+        }
+        publisher.heartbeat().unwrap();
+
+        let mut replay = ReplayClient::connect(replay_address).unwrap();
+        let mut subscriber = FeedSubscriber::new();
+        let mut buffer = [0; 2048];
+        for _ in 0..4 {
+            let length = socket.recv(&mut buffer).unwrap();
+            subscriber.receive(&Packet::decode(&buffer[..length]).unwrap());
+            while let Some(gap) = subscriber.gap() {
+                subscriber.recover(replay.retransmit(gap).unwrap());
+            }
+        }
+        assert_eq!(subscriber.next_sequence(), 61);
+        assert_eq!(subscriber.books(), publisher.history().lock().unwrap().books());
+
+        let (snapshot, next_sequence) = replay.snapshot().unwrap();
+        assert_eq!((snapshot.len(), next_sequence), (60, 61));
+        assert_eq!(replay.retransmit(58..70).unwrap().iter().map(|(sequence, _)| *sequence).collect::<Vec<u64>>(), vec![58, 59, 60]);
+    }
+}